@@ -0,0 +1,92 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use m_lang::interpreter::Interpreter;
+use m_lang::lexer::Lexer;
+use m_lang::parser::Parser;
+
+// A program exercising most of the syntax, used for the lexing and parsing benchmarks
+const SAMPLE: &str = r#"
+fn area(width: number, height: number): number {
+    return width * height
+}
+
+transformer scale(factor) {
+    return applied * factor
+}
+
+let shapes = [{name: "square", size: 4}, {name: "wide", size: 9}]
+for shape in shapes {
+    let size = shape["size"].scale(2)
+    if size > 10 {
+        print(format("{}: {}", shape["name"], area(size, size)))
+    } else {
+        print(shape["name"] + " is small")
+    }
+}
+
+let total = 0
+let i = 0
+while i < 100 {
+    total = total + i % 7
+    i = i + 1
+}
+"#;
+
+fn parse(source: &str) -> m_lang::ast::Expr {
+    let tokens = Lexer::new(source).tokenize().unwrap();
+    Parser::new(tokens).parse().unwrap()
+}
+
+fn run(c: &mut Criterion, name: &str, source: &str) {
+    let program = parse(source);
+    c.bench_function(name, |b| {
+        b.iter(|| Interpreter::new().evaluate(black_box(&program)).unwrap())
+    });
+}
+
+fn lexing(c: &mut Criterion) {
+    let source = SAMPLE.repeat(20);
+    c.bench_function("lex", |b| b.iter(|| Lexer::new(black_box(&source)).tokenize().unwrap()));
+}
+
+fn parsing(c: &mut Criterion) {
+    let source = SAMPLE.repeat(20);
+    let tokens = Lexer::new(&source).tokenize().unwrap();
+    c.bench_function("parse", |b| b.iter(|| Parser::new(black_box(tokens.clone())).parse().unwrap()));
+}
+
+fn function_calls(c: &mut Criterion) {
+    let source = "
+fn fib(n) {
+    if n < 2 { n } else { fib(n - 1) + fib(n - 2) }
+}
+fib(15)
+";
+    run(c, "call fib(15)", source);
+}
+
+fn loops(c: &mut Criterion) {
+    let source = "
+let total = 0
+for i in range(0, 10000) {
+    total = total + i
+}
+let j = 0
+while j < 10000 {
+    j = j + 1
+}
+";
+    run(c, "loop 10000", source);
+}
+
+fn string_building(c: &mut Criterion) {
+    let source = "
+let s = \"\"
+for i in range(0, 10000) {
+    s = s + \"line \" + i
+}
+";
+    run(c, "append 10000", source);
+}
+
+criterion_group!(benches, lexing, parsing, function_calls, loops, string_building);
+criterion_main!(benches);
@@ -0,0 +1,69 @@
+#![no_main]
+
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use libfuzzer_sys::fuzz_target;
+use m_lang::host::Host;
+use m_lang::interpreter::Interpreter;
+use m_lang::lexer::Lexer;
+use m_lang::parser::Parser;
+
+// Drops output and refuses input and every file operation, so fuzzed programs
+// can't touch the machine running them
+struct Sandbox;
+
+fn denied<T>() -> io::Result<T> {
+    Err(io::Error::new(io::ErrorKind::PermissionDenied, "not available while fuzzing"))
+}
+
+impl Host for Sandbox {
+    fn write(&self, _text: &str) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn read_line(&self) -> Result<String, String> {
+        Err("input() is not available while fuzzing".to_string())
+    }
+
+    fn read_file(&self, _path: &Path) -> io::Result<Vec<u8>> {
+        denied()
+    }
+
+    fn write_file(&self, _path: &Path, _bytes: &[u8]) -> io::Result<()> {
+        denied()
+    }
+
+    fn list_dir(&self, _path: &Path) -> io::Result<Vec<String>> {
+        denied()
+    }
+
+    fn create_dir(&self, _path: &Path) -> io::Result<()> {
+        denied()
+    }
+
+    fn remove_file(&self, _path: &Path) -> io::Result<()> {
+        denied()
+    }
+
+    fn canonicalize(&self, _path: &Path) -> io::Result<PathBuf> {
+        denied()
+    }
+}
+
+// Any program that parses runs to a value or an error. Programs that sleep
+// are skipped, since they only make the fuzzer slow; endless loops are caught
+// by libFuzzer's -timeout.
+fuzz_target!(|source: &str| {
+    if source.contains("sleep") {
+        return;
+    }
+    let Ok(program) = Lexer::new(source).tokenize().and_then(|tokens| Parser::new(tokens).parse()) else {
+        return;
+    };
+
+    let mut interpreter = Interpreter::new();
+    interpreter.set_host(Arc::new(Sandbox));
+    interpreter.set_seed(0);
+    let _ = interpreter.evaluate(&program);
+});
@@ -0,0 +1,9 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use m_lang::lexer::Lexer;
+
+// Any text lexes into tokens and errors, ending with EOF
+fuzz_target!(|source: &str| {
+    for _ in Lexer::new(source) {}
+});
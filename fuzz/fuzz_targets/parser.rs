@@ -0,0 +1,12 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use m_lang::lexer::Lexer;
+use m_lang::parser::Parser;
+
+// Any text either parses or gives an error
+fuzz_target!(|source: &str| {
+    if let Ok(tokens) = Lexer::new(source).tokenize() {
+        let _ = Parser::new(tokens).parse();
+    }
+});
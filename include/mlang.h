@@ -0,0 +1,34 @@
+/* C interface to the M interpreter, built with `cargo build --release --features ffi`
+ * (the library is target/release/libm_lang.so, .dylib or .dll). */
+#ifndef MLANG_H
+#define MLANG_H
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct MLang MLang;
+
+/* Creates an interpreter; free it with mlang_free(). Variables persist between
+ * calls to mlang_eval(). */
+MLang *mlang_new(void);
+
+/* Runs a program. Returns 0 on success and -1 on failure. */
+int mlang_eval(MLang *mlang, const char *source);
+
+/* The message of the last failed mlang_eval(), or NULL. Owned by the interpreter. */
+const char *mlang_error(const MLang *mlang);
+
+/* A variable's value as JSON, or NULL if it is undefined or has no JSON form.
+ * Free it with mlang_free_string(). */
+char *mlang_get_var(const MLang *mlang, const char *name);
+
+void mlang_free_string(char *string);
+
+void mlang_free(MLang *mlang);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
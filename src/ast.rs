@@ -1,13 +1,35 @@
+use std::fmt;
+use std::sync::Arc;
+use serde::{Deserialize, Serialize};
+use crate::printer;
+use crate::symbol::Symbol;
 use crate::token::Token;
 
 // AST Node types
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub enum Expr {
+    Int(i64),
+    // An integer literal too large for an i64, as its decimal digits. Only
+    // parsed with the `bigint` feature.
+    BigInt(String),
     Number(f64),
     String(String),
     Boolean(bool),
+    #[default]
+    Nil,
     Array(Vec<Expr>),
-    Variable(String),
+    // `(a, b)`, or `(a,)` with one element
+    Tuple(Vec<Expr>),
+    Map(Vec<(String, Expr)>),
+    Variable(Symbol),
+    // A variable naming a parameter of the function or transformer it is in,
+    // or a `let` at the top of its body, bound by the parser to that name's
+    // slot in the scope of the call, `depth` block scopes out
+    Local {
+        name: Symbol,
+        depth: usize,
+        slot: usize,
+    },
     Binary {
         left: Box<Expr>,
         operator: Token,
@@ -17,21 +39,57 @@ pub enum Expr {
         operator: Token,
         right: Box<Expr>,
     },
+    // Declarations, assignments and returns remember where they are written, for
+    // the linter's warnings
+    Let {
+        name: Symbol,
+        value: Option<Box<Expr>>,
+        line: usize,
+        column: usize,
+        // `pub let x` (or `pub x = ...`): exported by the file that declares it
+        public: bool,
+    },
     Assign {
-        name: String,
+        name: Symbol,
         value: Box<Expr>,
+        line: usize,
+        column: usize,
+    },
+    // `(a, b) = value` assigns the elements of a tuple or array to the variables
+    TupleAssign {
+        names: Vec<Symbol>,
+        value: Box<Expr>,
+        line: usize,
+        column: usize,
     },
     Call {
-        callee: String,
+        callee: Symbol,
         arguments: Vec<Expr>,
     },
     Function {
-        name: String,
-        params: Vec<String>,
-        body: Vec<Expr>,
+        // None for a function expression, `fn(x) { ... }`, which defines no name
+        name: Option<Symbol>,
+        params: Arc<[Symbol]>,
+        // Names declared with `let` at the top of the body, whose slots follow
+        // those of the parameters
+        locals: Arc<[Symbol]>,
+        // Optional annotations, only used by the type checker
+        param_types: Vec<Option<String>>,
+        return_type: Option<String>,
+        body: Arc<[Expr]>,
+        // `async fn`: calls run the body as a task and return it
+        is_async: bool,
+        // Text of the `///` comments written before the declaration
+        doc: Option<String>,
+        // `pub fn`: exported by the file that defines it
+        public: bool,
     },
+    // `await expr` waits for a task and evaluates to its result
+    Await(Box<Expr>),
     Return {
         value: Option<Box<Expr>>,
+        line: usize,
+        column: usize,
     },
     Block(Vec<Expr>),
     If {
@@ -40,29 +98,198 @@ pub enum Expr {
         else_branch: Option<Box<Expr>>,
     },
     For {
-        variable: String,
+        variable: Symbol,
+        // `for a, b in ...` binds the two elements of each pair (or a map's key and value)
+        second: Option<Symbol>,
         iterable: Box<Expr>,
+        // `for i in 0..10 step 2`: only valid for ranges
+        step: Option<Box<Expr>>,
         body: Box<Expr>,
+        label: Option<String>,
     },
     Index {
         object: Box<Expr>,
         index: Box<Expr>,
     },
+    // `object[index] = value`: changes the array or map in place
+    IndexAssign {
+        object: Box<Expr>,
+        index: Box<Expr>,
+        value: Box<Expr>,
+    },
     While {
         condition: Box<Expr>,
         body: Box<Expr>,
+        label: Option<String>,
+    },
+    // `do { } while condition`: the condition is checked after each run of the
+    // body, in the body's scope
+    DoWhile {
+        body: Box<Expr>,
+        condition: Box<Expr>,
+        label: Option<String>,
+    },
+    // `loop { }` repeats until a `break`, whose value the loop evaluates to
+    Loop {
+        body: Box<Expr>,
+        label: Option<String>,
+    },
+    // A labeled `break` or `continue` targets the enclosing loop with that
+    // label (`outer: for ...`) instead of the innermost one
+    Break {
+        label: Option<String>,
+        value: Option<Box<Expr>>,
+    },
+    Continue {
+        label: Option<String>,
     },
     Transformer {
-        name: String,
-        params: Vec<String>,
-        body: Vec<Expr>,
+        name: Symbol,
+        // What the applied value is called in the body: `n` in
+        // `transformer n.square()`, and `applied` when no name is given
+        receiver: Option<Symbol>,
+        params: Arc<[Symbol]>,
+        locals: Arc<[Symbol]>,
+        body: Arc<[Expr]>,
+        doc: Option<String>,
+        public: bool,
     },
     Apply {
         object: Box<Expr>,
-        transformer: String,
+        transformer: Symbol,
         arguments: Vec<Expr>,
+        // `?.` application: evaluates to nil instead of applying when the object is nil
+        safe: bool,
+    },
+    // `object.name` without parentheses: a map entry, or else the transformer
+    // `name` applied with no arguments
+    Member {
+        object: Box<Expr>,
+        name: Symbol,
+        safe: bool,
     },
     Use {
         path: String,
     },
+    // `use strict` turns on a mode for the rest of the run
+    Pragma {
+        name: String,
+    },
+    // `assert(condition)` or `assert_eq(actual, expected)`, remembering where the
+    // call is written so a failure can point at it
+    Assert {
+        callee: String,
+        arguments: Vec<Expr>,
+        line: usize,
+        column: usize,
+    },
+}
+
+impl Expr {
+    /// Writes the expression (or, for a block, the program) as M source that
+    /// parses back to the same tree. Comments other than doc comments and the
+    /// original layout aren't kept, and strings containing `"` can't be written.
+    pub fn to_source(&self) -> String {
+        let mut source = printer::print(self);
+        if !source.is_empty() {
+            source.push('\n');
+        }
+        source
+    }
+
+    /// The line and column of a statement that records where it starts: a
+    /// `let`, an assignment, a `return` or an assertion.
+    pub fn position(&self) -> Option<(usize, usize)> {
+        match self {
+            Expr::Let { line, column, .. }
+            | Expr::Assign { line, column, .. }
+            | Expr::TupleAssign { line, column, .. }
+            | Expr::Return { line, column, .. }
+            | Expr::Assert { line, column, .. } => Some((*line, *column)),
+            _ => None,
+        }
+    }
+
+    // Moves the expressions this one is made of onto `parts`, leaving it
+    // without any
+    fn take_parts(&mut self, parts: &mut Vec<Expr>) {
+        let mut take = |expr: &mut Box<Expr>| parts.push(std::mem::take(&mut **expr));
+        match self {
+            Expr::Binary { left, right, .. } | Expr::Index { object: left, index: right } => {
+                take(left);
+                take(right);
+            },
+            Expr::Unary { right: expr, .. }
+            | Expr::Assign { value: expr, .. }
+            | Expr::TupleAssign { value: expr, .. }
+            | Expr::Await(expr)
+            | Expr::Loop { body: expr, .. }
+            | Expr::Member { object: expr, .. } => take(expr),
+            Expr::Let { value, .. } | Expr::Return { value, .. } | Expr::Break { value, .. } => value.iter_mut().for_each(take),
+            Expr::If { condition, then_branch, else_branch } => {
+                take(condition);
+                take(then_branch);
+                else_branch.iter_mut().for_each(take);
+            },
+            Expr::For { iterable, step, body, .. } => {
+                take(iterable);
+                step.iter_mut().for_each(&mut take);
+                take(body);
+            },
+            Expr::IndexAssign { object, index, value } => {
+                take(object);
+                take(index);
+                take(value);
+            },
+            Expr::While { condition, body, .. } | Expr::DoWhile { body, condition, .. } => {
+                take(condition);
+                take(body);
+            },
+            Expr::Apply { object, arguments, .. } => {
+                take(object);
+                parts.append(arguments);
+            },
+            Expr::Array(elements) | Expr::Tuple(elements) | Expr::Block(elements) => parts.append(elements),
+            Expr::Call { arguments, .. } | Expr::Assert { arguments, .. } => parts.append(arguments),
+            Expr::Map(entries) => parts.extend(entries.drain(..).map(|(_, value)| value)),
+            // A body still shared with a function value is freed with the last copy
+            Expr::Function { body, .. } | Expr::Transformer { body, .. } => {
+                if let Some(body) = Arc::get_mut(body) {
+                    parts.extend(body.iter_mut().map(std::mem::take));
+                }
+            },
+            Expr::Int(_)
+            | Expr::BigInt(_)
+            | Expr::Number(_)
+            | Expr::String(_)
+            | Expr::Boolean(_)
+            | Expr::Nil
+            | Expr::Variable(_)
+            | Expr::Local { .. }
+            | Expr::Continue { .. }
+            | Expr::Use { .. }
+            | Expr::Pragma { .. } => {},
+        }
+    }
+}
+
+// Freeing an expression frees the ones it's made of, and so on down the tree.
+// Doing that recursively would overflow the stack for deeply nested code, so
+// the parts are taken out first and freed from a list, one at a time.
+impl Drop for Expr {
+    fn drop(&mut self) {
+        let mut parts = Vec::new();
+        self.take_parts(&mut parts);
+        while let Some(mut part) = parts.pop() {
+            part.take_parts(&mut parts);
+        }
+    }
+}
+
+/// The expression as M source, as to_source() writes it but without the final
+/// newline.
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&printer::print(self))
+    }
 }
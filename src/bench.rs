@@ -0,0 +1,37 @@
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+// `m_lang bench script.m --iterations N`: runs a whole program (lexing, parsing
+// and evaluation, with a fresh interpreter each time) repeatedly and reports how
+// long the runs took. Anything the program prints is printed on every run.
+pub fn run(file_path: &Path, iterations: usize, mut run_once: impl FnMut()) {
+    let mut timings = Vec::with_capacity(iterations);
+    for _ in 0..iterations {
+        let start = Instant::now();
+        run_once();
+        timings.push(start.elapsed());
+    }
+
+    println!("{}", report(file_path, &mut timings));
+}
+
+fn report(file_path: &Path, timings: &mut [Duration]) -> String {
+    timings.sort();
+    let count = timings.len() as f64;
+    let millis: Vec<f64> = timings.iter().map(|timing| timing.as_secs_f64() * 1000.0).collect();
+
+    let mean = millis.iter().sum::<f64>() / count;
+    let variance = millis.iter().map(|ms| (ms - mean).powi(2)).sum::<f64>() / count;
+    let middle = millis.len() / 2;
+    let median = if millis.len().is_multiple_of(2) { (millis[middle - 1] + millis[middle]) / 2.0 } else { millis[middle] };
+
+    [
+        format!("Benchmark: {} ({} iterations)", file_path.display(), timings.len()),
+        format!("  mean:   {:.3} ms", mean),
+        format!("  median: {:.3} ms", median),
+        format!("  min:    {:.3} ms", millis[0]),
+        format!("  max:    {:.3} ms", millis[millis.len() - 1]),
+        format!("  stddev: {:.3} ms", variance.sqrt()),
+    ]
+    .join("\n")
+}
@@ -0,0 +1,137 @@
+// Integers too large for an i64. Integer arithmetic that would overflow moves
+// to a BigInt instead, and a result that fits an i64 again comes back as a
+// plain Int, so each whole number has a single representation.
+//
+// The arithmetic comes from num-bigint, enabled by the `bigint` feature.
+// Without it BigInt has no values and overflow stays an error.
+use std::fmt;
+use crate::token::TokenType;
+use crate::value::Value;
+
+/// An integer outside the range of an i64 (see `Value::BigInt`).
+#[cfg(feature = "bigint")]
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct BigInt(num_bigint::BigInt);
+
+/// An integer outside the range of an i64 (see `Value::BigInt`).
+#[cfg(not(feature = "bigint"))]
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum BigInt {}
+
+#[cfg(feature = "bigint")]
+impl BigInt {
+    /// The nearest float, or an infinity beyond the range of f64.
+    pub fn to_f64(&self) -> f64 {
+        use num_traits::ToPrimitive;
+        self.0.to_f64().unwrap_or(f64::NAN)
+    }
+
+    pub(crate) fn negate(&self) -> Value {
+        normalize(-&self.0)
+    }
+}
+
+#[cfg(not(feature = "bigint"))]
+impl BigInt {
+    /// The nearest float, or an infinity beyond the range of f64.
+    pub fn to_f64(&self) -> f64 {
+        match *self {}
+    }
+
+    pub(crate) fn negate(&self) -> Value {
+        match *self {}
+    }
+}
+
+impl fmt::Display for BigInt {
+    #[cfg(feature = "bigint")]
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+
+    #[cfg(not(feature = "bigint"))]
+    fn fmt(&self, _f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {}
+    }
+}
+
+#[cfg(feature = "bigint")]
+fn normalize(n: num_bigint::BigInt) -> Value {
+    match i64::try_from(&n) {
+        Ok(n) => Value::Int(n),
+        Err(_) => Value::BigInt(BigInt(n)),
+    }
+}
+
+#[cfg(feature = "bigint")]
+fn integer(value: &Value) -> Option<num_bigint::BigInt> {
+    match value {
+        Value::Int(n) => Some((*n).into()),
+        Value::BigInt(n) => Some(n.0.clone()),
+        _ => None,
+    }
+}
+
+// Applies an arithmetic or comparison operator to two integers, either of which
+// may be a BigInt. Returns None for other operands and operators, which work as
+// they do for floats.
+#[cfg(feature = "bigint")]
+pub(crate) fn operate(operator: TokenType, left: &Value, right: &Value) -> Option<Result<Value, String>> {
+    use num_traits::{Euclid, Signed, Zero};
+
+    let (l, r) = (integer(left)?, integer(right)?);
+    Some(match operator {
+        TokenType::Plus => Ok(normalize(l + r)),
+        TokenType::Minus => Ok(normalize(l - r)),
+        TokenType::Multiply => Ok(normalize(l * r)),
+        TokenType::IntDivide if r.is_zero() => Err("Division by zero".to_string()),
+        // Floored, like `~/` and `%` on other numbers. That only differs from
+        // Euclidean division for a negative divisor that doesn't divide evenly.
+        TokenType::IntDivide => {
+            let (quotient, remainder) = (l.div_euclid(&r), l.rem_euclid(&r));
+            Ok(normalize(if r.is_negative() && !remainder.is_zero() { quotient - 1 } else { quotient }))
+        },
+        TokenType::Modulo if r.is_zero() => Err("Modulo by zero".to_string()),
+        TokenType::Modulo => {
+            let remainder = l.rem_euclid(&r);
+            Ok(normalize(if r.is_negative() && !remainder.is_zero() { remainder + r } else { remainder }))
+        },
+        TokenType::LessThan => Ok(Value::Boolean(l < r)),
+        TokenType::LessThanEqual => Ok(Value::Boolean(l <= r)),
+        TokenType::GreaterThan => Ok(Value::Boolean(l > r)),
+        TokenType::GreaterThanEqual => Ok(Value::Boolean(l >= r)),
+        _ => return None,
+    })
+}
+
+#[cfg(not(feature = "bigint"))]
+pub(crate) fn operate(_operator: TokenType, _left: &Value, _right: &Value) -> Option<Result<Value, String>> {
+    None
+}
+
+// Parses a string of decimal digits, with an optional sign, too long for an i64
+#[cfg(feature = "bigint")]
+pub(crate) fn parse(text: &str) -> Option<Value> {
+    let digits = text.strip_prefix(['-', '+']).unwrap_or(text);
+    if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    text.parse().ok().map(normalize)
+}
+
+#[cfg(not(feature = "bigint"))]
+pub(crate) fn parse(_text: &str) -> Option<Value> {
+    None
+}
+
+// Parses the digits of an integer literal too large for an i64, written in
+// the given radix
+#[cfg(feature = "bigint")]
+pub(crate) fn parse_radix(digits: &str, radix: u32) -> Option<Value> {
+    num_bigint::BigInt::parse_bytes(digits.as_bytes(), radix).map(normalize)
+}
+
+#[cfg(not(feature = "bigint"))]
+pub(crate) fn parse_radix(_digits: &str, _radix: u32) -> Option<Value> {
+    None
+}
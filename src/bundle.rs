@@ -0,0 +1,173 @@
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Component, Path, PathBuf};
+use serde::{Deserialize, Serialize};
+use m_lang::host::{Host, StdHost};
+use m_lang::lexer::Lexer;
+use m_lang::module;
+use m_lang::token::TokenType;
+
+// `m_lang bundle script.m -o tool`: copies the interpreter binary and appends the
+// program and every file it imports, followed by a trailer holding their
+// length. When a binary finds a trailer on itself at startup, it runs the
+// embedded program instead of reading its arguments.
+const MAGIC: &[u8; 8] = b"MLBUNDLE";
+const TRAILER_LEN: u64 = 16;
+
+#[derive(Serialize, Deserialize)]
+pub struct Bundle {
+    pub name: String,
+    pub main: String,
+    // Imported files, by their path relative to the base path the bundle was made with
+    modules: Vec<(PathBuf, String)>,
+    // The directories `use` searches, relative to the base path in the same way
+    pub module_paths: Vec<PathBuf>,
+}
+
+pub fn create(file_path: &Path, base_path: &Path, module_paths: &[PathBuf], output_path: &Path) -> Result<(), String> {
+    let main = fs::read_to_string(file_path).map_err(|e| format!("Error reading file: {}", e))?;
+
+    let mut modules = Vec::new();
+    collect_imports(&main, base_path, module_paths, &mut modules)?;
+
+    let bundle = Bundle {
+        name: file_path.display().to_string(),
+        main,
+        modules,
+        module_paths: module_paths.iter().map(|path| relative(path, base_path)).collect(),
+    };
+    let payload = serde_json::to_vec(&bundle).map_err(|e| e.to_string())?;
+
+    let exe = std::env::current_exe().map_err(|e| format!("Can't find the interpreter binary: {}", e))?;
+    let mut binary = fs::read(&exe).map_err(|e| format!("Error reading {}: {}", exe.display(), e))?;
+    // Bundling from a bundled binary replaces its program
+    if let Some(len) = payload_len(&binary).filter(|len| len + TRAILER_LEN <= binary.len() as u64) {
+        binary.truncate(binary.len() - (len + TRAILER_LEN) as usize);
+    }
+
+    binary.extend_from_slice(&payload);
+    binary.extend_from_slice(&(payload.len() as u64).to_le_bytes());
+    binary.extend_from_slice(MAGIC);
+    fs::write(output_path, binary).map_err(|e| format!("Error writing file: {}", e))?;
+
+    // Keep the interpreter's permissions, so the bundle is executable too
+    let permissions = fs::metadata(&exe).map_err(|e| e.to_string())?.permissions();
+    fs::set_permissions(output_path, permissions).map_err(|e| format!("Error writing file: {}", e))
+}
+
+// Adds the files a source imports, and the files those import, that aren't in
+// `modules` yet. Paths are resolved against `base_path` and then the module
+// directories, as the interpreter does.
+fn collect_imports(source: &str, base_path: &Path, module_paths: &[PathBuf], modules: &mut Vec<(PathBuf, String)>) -> Result<(), String> {
+    let tokens = Lexer::new(source).tokenize()?;
+    let imports = tokens.windows(2).filter_map(|pair| match (&pair[0].token_type, &pair[1].token_type) {
+        (TokenType::Use, TokenType::String) => Some(pair[1].literal.clone()),
+        _ => None,
+    });
+
+    for import in imports.collect::<Vec<_>>() {
+        let key = relative(&module::locate(&import, base_path, module_paths), base_path);
+        if modules.iter().any(|(path, _)| *path == key) {
+            continue;
+        }
+
+        let module = fs::read_to_string(base_path.join(&key))
+            .map_err(|e| format!("Failed to read file '{}': {}", import, e))?;
+        modules.push((key, module.clone()));
+        collect_imports(&module, base_path, module_paths, modules)?;
+    }
+    Ok(())
+}
+
+// The bundle attached to the running binary, if it has one
+pub fn embedded() -> Option<Bundle> {
+    let exe = std::env::current_exe().ok()?;
+    let mut file = File::open(exe).ok()?;
+
+    let mut trailer = [0; TRAILER_LEN as usize];
+    file.seek(SeekFrom::End(-(TRAILER_LEN as i64))).ok()?;
+    file.read_exact(&mut trailer).ok()?;
+    let len = payload_len(&trailer)?;
+
+    // A trailer claiming more than the file holds (a corrupt or unrelated file
+    // that happens to end with the magic bytes) isn't trusted
+    let file_len = file.metadata().ok()?.len();
+    let bundled = len.checked_add(TRAILER_LEN).filter(|&bundled| bundled <= file_len)?;
+    let mut payload = vec![0; usize::try_from(len).ok()?];
+    file.seek(SeekFrom::End(-i64::try_from(bundled).ok()?)).ok()?;
+    file.read_exact(&mut payload).ok()?;
+    serde_json::from_slice(&payload).ok()
+}
+
+// The payload length from the trailer at the end of `bytes`
+fn payload_len(bytes: &[u8]) -> Option<u64> {
+    let trailer = bytes.len().checked_sub(TRAILER_LEN as usize).map(|start| &bytes[start..])?;
+    if &trailer[8..] != MAGIC {
+        return None;
+    }
+    Some(u64::from_le_bytes(trailer[..8].try_into().ok()?))
+}
+
+// Removes `.` and `..` without touching the file system, then makes the path
+// relative to `base_path` when it is inside it
+fn relative(path: &Path, base_path: &Path) -> PathBuf {
+    let path = normalize(path);
+    path.strip_prefix(normalize(base_path)).map(Path::to_path_buf).unwrap_or(path)
+}
+
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {},
+            Component::ParentDir if normalized.file_name().is_some() => {
+                normalized.pop();
+            },
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+/// Serves the bundled files to `use`; everything else goes to the real console
+/// and file system.
+pub struct EmbeddedHost {
+    base_path: PathBuf,
+    modules: HashMap<PathBuf, Vec<u8>>,
+}
+
+impl EmbeddedHost {
+    pub fn new(bundle: Bundle, base_path: &Path) -> Self {
+        let modules = bundle.modules.into_iter().map(|(path, source)| (path, source.into_bytes())).collect();
+        EmbeddedHost { base_path: base_path.to_path_buf(), modules }
+    }
+
+    fn embedded(&self, path: &Path) -> Option<&Vec<u8>> {
+        self.modules.get(&relative(path, &self.base_path))
+    }
+}
+
+impl Host for EmbeddedHost {
+    fn write(&self, text: &str) -> Result<(), String> {
+        StdHost.write(text)
+    }
+
+    fn read_line(&self) -> Result<String, String> {
+        StdHost.read_line()
+    }
+
+    fn read_file(&self, path: &Path) -> io::Result<Vec<u8>> {
+        match self.embedded(path) {
+            Some(source) => Ok(source.clone()),
+            None => fs::read(path),
+        }
+    }
+
+    fn canonicalize(&self, path: &Path) -> io::Result<PathBuf> {
+        match self.embedded(path) {
+            Some(_) => Ok(normalize(path)),
+            None => fs::canonicalize(path),
+        }
+    }
+}
@@ -0,0 +1,51 @@
+use std::fs;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::path::{Path, PathBuf};
+use serde::{Deserialize, Serialize};
+use crate::ast::Expr;
+
+// Parsed programs cached next to their source (`lib.m` -> `lib.mc`) when running
+// with --cache. A cache file is only used when it was written by the same
+// interpreter version, for identical source text and with the same -O setting;
+// anything else (including unreadable or corrupt files) falls back to parsing.
+#[derive(Serialize, Deserialize)]
+struct CacheFile<T> {
+    version: String,
+    source_hash: u64,
+    optimized: bool,
+    ast: T,
+}
+
+fn cache_path(source_path: &Path) -> PathBuf {
+    source_path.with_extension("mc")
+}
+
+fn source_hash(source: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    source.hash(&mut hasher);
+    hasher.finish()
+}
+
+pub fn load(source_path: &Path, source: &str, optimized: bool) -> Option<Expr> {
+    let content = fs::read_to_string(cache_path(source_path)).ok()?;
+    let cached: CacheFile<Expr> = serde_json::from_str(&content).ok()?;
+
+    let fresh = cached.version == env!("CARGO_PKG_VERSION")
+        && cached.source_hash == source_hash(source)
+        && cached.optimized == optimized;
+    fresh.then_some(cached.ast)
+}
+
+// Writing is best-effort: a program still runs when its directory is read-only
+pub fn store(source_path: &Path, source: &str, optimized: bool, ast: &Expr) {
+    let cached = CacheFile {
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        source_hash: source_hash(source),
+        optimized,
+        ast,
+    };
+
+    if let Ok(content) = serde_json::to_string(&cached) {
+        let _ = fs::write(cache_path(source_path), content);
+    }
+}
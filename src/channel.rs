@@ -0,0 +1,53 @@
+use std::collections::VecDeque;
+use std::sync::{Condvar, Mutex};
+use crate::value::Value;
+
+// An unbounded multi-producer, multi-consumer queue shared by the sender and
+// receiver values returned from channel(). Closing it lets receivers drain
+// the remaining messages and then stop.
+#[derive(Debug, Default)]
+pub struct Channel {
+    state: Mutex<ChannelState>,
+    ready: Condvar,
+}
+
+#[derive(Debug, Default)]
+struct ChannelState {
+    queue: VecDeque<Value>,
+    closed: bool,
+}
+
+impl Channel {
+    pub fn send(&self, value: Value) -> Result<(), String> {
+        let mut state = self.state.lock().map_err(|_| "Channel is poisoned".to_string())?;
+        if state.closed {
+            return Err("Cannot send on a closed channel".to_string());
+        }
+
+        state.queue.push_back(value);
+        self.ready.notify_one();
+        Ok(())
+    }
+
+    // Blocks until a message arrives; None means the channel is closed and empty
+    pub fn receive(&self) -> Result<Option<Value>, String> {
+        let mut state = self.state.lock().map_err(|_| "Channel is poisoned".to_string())?;
+        loop {
+            if let Some(value) = state.queue.pop_front() {
+                return Ok(Some(value));
+            }
+            if state.closed {
+                return Ok(None);
+            }
+            state = self.ready.wait(state).map_err(|_| "Channel is poisoned".to_string())?;
+        }
+    }
+
+    pub fn close(&self) -> Result<(), String> {
+        let mut state = self.state.lock().map_err(|_| "Channel is poisoned".to_string())?;
+        state.closed = true;
+        // Wake every waiting receiver so they can observe the close
+        self.ready.notify_all();
+        Ok(())
+    }
+}
@@ -0,0 +1,92 @@
+// Exact base-10 numbers made by decimal(), for amounts of money and other values
+// that binary floats can only approximate: decimal("0.1") + decimal("0.2") is
+// exactly 0.3. A decimal keeps the places it was written with ("10.50" prints
+// as 10.50) and holds up to 28 significant digits.
+//
+// Decimals combine with integers but not with floats, which would bring their
+// rounding error along; decimal(x) converts a float by its printed digits.
+use rust_decimal::prelude::ToPrimitive;
+use rust_decimal::{Decimal, RoundingStrategy};
+use crate::token::TokenType;
+use crate::value::Value;
+
+// The decimal() builtin
+pub(crate) fn from_value(value: &Value) -> Result<Value, String> {
+    let decimal = match value {
+        Value::Decimal(d) => *d,
+        Value::Int(n) => Decimal::from(*n),
+        Value::String(s) => Decimal::from_str_exact(s.trim()).map_err(|_| format!("Cannot convert \"{}\" to a decimal", s))?,
+        // Floats and large integers convert through the digits they print as
+        Value::Number(n) if n.is_finite() => parse(&n.to_string())?,
+        Value::BigInt(n) => parse(&n.to_string())?,
+        _ => return Err(format!("Cannot convert {} to a decimal", value.type_name())),
+    };
+    Ok(Value::Decimal(decimal))
+}
+
+fn parse(digits: &str) -> Result<Decimal, String> {
+    Decimal::from_str_exact(digits).map_err(|_| format!("{} doesn't fit in a decimal", digits))
+}
+
+fn operand(value: &Value) -> Option<Decimal> {
+    match value {
+        Value::Decimal(d) => Some(*d),
+        Value::Int(n) => Some(Decimal::from(*n)),
+        _ => None,
+    }
+}
+
+// Applies an arithmetic or comparison operator when either operand is a
+// decimal. Returns None for operators and operands handled as for other values
+// (equality, and `+` with a string).
+pub(crate) fn operate(operator: TokenType, left: &Value, right: &Value) -> Option<Result<Value, String>> {
+    let (l, r) = match (operand(left), operand(right)) {
+        (Some(l), Some(r)) => (l, r),
+        _ if matches!(left, Value::Number(_)) || matches!(right, Value::Number(_)) => {
+            return Some(Err("Cannot combine a decimal with a float; convert the float with decimal() first".to_string()));
+        },
+        _ => return None,
+    };
+
+    let overflow = || "Decimal overflow".to_string();
+    Some(match operator {
+        TokenType::Plus => l.checked_add(r).map(Value::Decimal).ok_or_else(overflow),
+        TokenType::Minus => l.checked_sub(r).map(Value::Decimal).ok_or_else(overflow),
+        TokenType::Multiply => l.checked_mul(r).map(Value::Decimal).ok_or_else(overflow),
+        TokenType::Divide | TokenType::IntDivide if r.is_zero() => Err("Division by zero".to_string()),
+        // Quotients that don't terminate are rounded to 28 significant digits
+        TokenType::Divide => l.checked_div(r).map(Value::Decimal).ok_or_else(overflow),
+        TokenType::IntDivide => l.checked_div(r).map(|q| Value::Decimal(q.floor())).ok_or_else(overflow),
+        TokenType::Modulo if r.is_zero() => Err("Modulo by zero".to_string()),
+        // Like `%` on numbers, the remainder has the sign of the divisor
+        TokenType::Modulo => l.checked_rem(r).map(|m| Value::Decimal(if !m.is_zero() && m.is_sign_negative() != r.is_sign_negative() { m + r } else { m })).ok_or_else(overflow),
+        TokenType::LessThan => Ok(Value::Boolean(l < r)),
+        TokenType::LessThanEqual => Ok(Value::Boolean(l <= r)),
+        TokenType::GreaterThan => Ok(Value::Boolean(l > r)),
+        TokenType::GreaterThanEqual => Ok(Value::Boolean(l >= r)),
+        _ => return None,
+    })
+}
+
+// The nearest float, for to_number()
+pub(crate) fn to_f64(d: Decimal) -> f64 {
+    d.to_f64().unwrap_or(0.0)
+}
+
+// Rounds half away from zero, as round() does for floats. Negative places round
+// to tens, hundreds and so on.
+pub(crate) fn round(d: Decimal, places: i64) -> Result<Decimal, String> {
+    if places >= 0 {
+        return Ok(d.round_dp_with_strategy(places.min(28) as u32, RoundingStrategy::MidpointAwayFromZero));
+    }
+
+    let factor = Decimal::from_i128_with_scale(10i128.pow(places.unsigned_abs().min(28) as u32), 0);
+    d.checked_div(factor)
+        .and_then(|q| q.round_dp_with_strategy(0, RoundingStrategy::MidpointAwayFromZero).checked_mul(factor))
+        .ok_or_else(|| "Decimal overflow".to_string())
+}
+
+// Writes exactly `places` digits after the point
+pub(crate) fn to_fixed(d: Decimal, places: usize) -> String {
+    format!("{:.*}", places, d.round_dp_with_strategy(places.min(28) as u32, RoundingStrategy::MidpointAwayFromZero))
+}
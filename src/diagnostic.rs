@@ -0,0 +1,154 @@
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use m_lang::ast::Expr;
+use m_lang::interpreter::Location;
+use m_lang::lexer::{LexError, Lexer};
+use m_lang::parser::Parser;
+use serde::Serialize;
+
+use crate::style;
+
+// Errors and warnings about a program, printed on stderr as text or, with
+// --json-diagnostics, as one JSON object per line for editors and other tools:
+// {"file": "main.m", "line": 3, "column": 7, "kind": "parse", "message": "..."}
+// The file, line and column are null when they aren't known.
+
+static JSON: AtomicBool = AtomicBool::new(false);
+
+pub fn set_json(json: bool) {
+    JSON.store(json, Ordering::Relaxed);
+}
+
+pub fn is_json() -> bool {
+    JSON.load(Ordering::Relaxed)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Kind {
+    // A character or string the lexer can't read
+    Lex,
+    Parse,
+    Runtime,
+    // A program that can't be translated by `compile`
+    Compile,
+    // The file couldn't be read
+    Io,
+    // A name the program refers to that is never defined, found before running
+    Resolve,
+    // A lint with -W, or an undefined name in a program that calls eval(),
+    // which may define it
+    Warning,
+    // Found by --typecheck
+    Type,
+}
+
+impl Kind {
+    fn name(self) -> &'static str {
+        match self {
+            Kind::Lex => "lex",
+            Kind::Parse => "parse",
+            Kind::Runtime => "runtime",
+            Kind::Compile => "compile",
+            Kind::Io => "io",
+            Kind::Resolve => "resolve",
+            Kind::Warning => "warning",
+            Kind::Type => "type",
+        }
+    }
+
+    // How the message starts when printed as text
+    fn label(self) -> &'static str {
+        match self {
+            Kind::Io => "Error reading file",
+            Kind::Warning => "Warning",
+            Kind::Type => "Type warning",
+            Kind::Lex | Kind::Parse | Kind::Resolve | Kind::Runtime | Kind::Compile => "Error",
+        }
+    }
+}
+
+pub struct Diagnostic {
+    pub kind: Kind,
+    pub file: Option<PathBuf>,
+    pub line: Option<usize>,
+    pub column: Option<usize>,
+    pub message: String,
+}
+
+impl Diagnostic {
+    pub fn new(kind: Kind, file: Option<&Path>, message: impl Into<String>) -> Self {
+        Diagnostic { kind, file: file.map(Path::to_path_buf), line: None, column: None, message: message.into() }
+    }
+
+    // A runtime error, at the statement the interpreter found it in, which
+    // may be in an imported file
+    pub fn runtime(file: Option<&Path>, message: impl Into<String>, location: Option<Location>) -> Self {
+        let diagnostic = Diagnostic::new(Kind::Runtime, file, message);
+        match location {
+            Some(location) => Diagnostic {
+                file: location.file.or(diagnostic.file),
+                line: Some(location.line),
+                column: Some(location.column),
+                ..diagnostic
+            },
+            None => diagnostic,
+        }
+    }
+
+    pub fn report(&self) {
+        if is_json() {
+            let json = Json {
+                file: self.file.as_ref().map(|file| file.display().to_string()),
+                line: self.line,
+                column: self.column,
+                kind: self.kind.name(),
+                message: &self.message,
+            };
+            eprintln!("{}", serde_json::to_string(&json).unwrap_or_default());
+        } else {
+            style::report(&self.to_string());
+        }
+    }
+}
+
+// A diagnostic as it is printed with --json-diagnostics, fields in this order
+#[derive(Serialize)]
+struct Json<'a> {
+    file: Option<String>,
+    line: Option<usize>,
+    column: Option<usize>,
+    kind: &'a str,
+    message: &'a str,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.kind.label(), self.message)
+    }
+}
+
+// Lexes and parses a program, with the position of the first error
+pub fn parse(source: &str, file: Option<&Path>) -> Result<Expr, Diagnostic> {
+    let tokens = Lexer::new(source).collect::<Result<Vec<_>, LexError>>().map_err(|e| Diagnostic {
+        line: Some(e.line),
+        column: Some(e.column),
+        ..Diagnostic::new(Kind::Lex, file, e.to_string())
+    })?;
+
+    let mut parser = Parser::new(tokens);
+    parser.parse().map_err(|e| {
+        let (line, column) = parser.position();
+        Diagnostic { line: Some(line), column: Some(column), ..Diagnostic::new(Kind::Parse, file, e) }
+    })
+}
+
+pub fn read(file: &Path) -> Result<String, Diagnostic> {
+    fs::read_to_string(file).map_err(|e| Diagnostic::new(Kind::Io, Some(file), e.to_string()))
+}
+
+pub fn parse_file(file: &Path) -> Result<Expr, Diagnostic> {
+    read(file).and_then(|source| parse(&source, Some(file)))
+}
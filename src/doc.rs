@@ -0,0 +1,69 @@
+use std::path::Path;
+
+use m_lang::ast::Expr;
+
+// `m_lang doc script.m [--html]`: lists the functions and transformers a file
+// defines at the top level, with their parameters and `///` comments, as
+// Markdown (or HTML). Nothing in the file is run.
+pub fn generate(file_path: &Path, program: &Expr, html: bool) -> String {
+    let items = items(program);
+    let title = file_path.display().to_string();
+
+    if html {
+        let mut out = format!("<h1>{}</h1>\n", escape(&title));
+        for (signature, doc) in &items {
+            out += &format!("<h2><code>{}</code></h2>\n", escape(signature));
+            for paragraph in doc.split("\n\n").filter(|p| !p.trim().is_empty()) {
+                out += &format!("<p>{}</p>\n", escape(paragraph.trim()));
+            }
+        }
+        out
+    } else {
+        let mut out = format!("# {}\n", title);
+        for (signature, doc) in &items {
+            out += &format!("\n## `{}`\n", signature);
+            if !doc.is_empty() {
+                out += &format!("\n{}\n", doc);
+            }
+        }
+        out
+    }
+}
+
+// The signature and doc text of each top-level declaration, in source order.
+// A file that marks declarations `pub` only documents those.
+fn items(program: &Expr) -> Vec<(String, String)> {
+    let statements = match program {
+        Expr::Block(statements) => statements.as_slice(),
+        statement => std::slice::from_ref(statement),
+    };
+    let any_public = statements.iter().any(|statement| matches!(
+        statement,
+        Expr::Function { public: true, .. } | Expr::Transformer { public: true, .. } | Expr::Let { public: true, .. }
+    ));
+
+    statements.iter().filter_map(|statement| match statement {
+        Expr::Function { public: false, .. } | Expr::Transformer { public: false, .. } if any_public => None,
+        Expr::Function { name: Some(name), params, param_types, return_type, is_async, doc, .. } => {
+            let params: Vec<String> = params.iter().zip(param_types).map(|(param, param_type)| match param_type {
+                Some(param_type) => format!("{}: {}", param, param_type),
+                None => param.to_string(),
+            }).collect();
+            let mut signature = format!("{}fn {}({})", if *is_async { "async " } else { "" }, name, params.join(", "));
+            if let Some(return_type) = return_type {
+                signature += &format!(": {}", return_type);
+            }
+            Some((signature, doc.clone().unwrap_or_default()))
+        },
+        Expr::Transformer { name, receiver, params, doc, .. } => {
+            let receiver = receiver.as_ref().map(|receiver| format!("{}.", receiver)).unwrap_or_default();
+            let params: Vec<&str> = params.iter().map(|param| param.as_str()).collect();
+            Some((format!("transformer {}{}({})", receiver, name, params.join(", ")), doc.clone().unwrap_or_default()))
+        },
+        _ => None,
+    }).collect()
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
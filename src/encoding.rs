@@ -0,0 +1,78 @@
+// Base64 (standard alphabet, padded) and hex codecs used by the
+// to_base64/from_base64/to_hex/from_hex transformers.
+
+const BASE64_ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+pub fn to_base64(bytes: &[u8]) -> String {
+    let mut result = String::with_capacity(bytes.len().div_ceil(3) * 4);
+
+    for chunk in bytes.chunks(3) {
+        let b = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+        let group = (b[0] as u32) << 16 | (b[1] as u32) << 8 | b[2] as u32;
+
+        for i in 0..4 {
+            if i <= chunk.len() {
+                let index = (group >> (18 - 6 * i)) & 0x3f;
+                result.push(BASE64_ALPHABET[index as usize] as char);
+            } else {
+                result.push('=');
+            }
+        }
+    }
+
+    result
+}
+
+pub fn from_base64(text: &str) -> Result<Vec<u8>, String> {
+    // Whitespace (e.g. line breaks in wrapped output) is ignored
+    let digits: Vec<u8> = text.bytes().filter(|b| !b.is_ascii_whitespace()).collect();
+    let trimmed = digits.strip_suffix(b"==").or_else(|| digits.strip_suffix(b"=")).unwrap_or(&digits);
+
+    if !digits.len().is_multiple_of(4) {
+        return Err("Invalid base64: length must be a multiple of 4".to_string());
+    }
+
+    let mut result = Vec::with_capacity(trimmed.len() * 3 / 4);
+    let mut group: u32 = 0;
+    let mut bits = 0;
+
+    for &digit in trimmed {
+        let value = match BASE64_ALPHABET.iter().position(|&c| c == digit) {
+            Some(value) => value as u32,
+            None => return Err(format!("Invalid base64 character '{}'", digit as char)),
+        };
+
+        group = group << 6 | value;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            result.push((group >> bits) as u8);
+            group &= (1 << bits) - 1;
+        }
+    }
+
+    Ok(result)
+}
+
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+pub fn from_hex(text: &str) -> Result<Vec<u8>, String> {
+    let digits: Vec<char> = text.chars().collect();
+    if !digits.len().is_multiple_of(2) {
+        return Err("Invalid hex: odd number of digits".to_string());
+    }
+
+    digits
+        .chunks(2)
+        .map(|pair| {
+            let pair: String = pair.iter().collect();
+            // from_str_radix would also accept a leading '+'
+            if !pair.chars().all(|c| c.is_ascii_hexdigit()) {
+                return Err(format!("Invalid hex digits '{}'", pair));
+            }
+            u8::from_str_radix(&pair, 16).map_err(|_| format!("Invalid hex digits '{}'", pair))
+        })
+        .collect()
+}
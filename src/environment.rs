@@ -1,10 +1,57 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::sync::Arc;
+use crate::symbol::Symbol;
 use crate::value::Value;
 
+// The names the scope of a call keeps by position: the parameters (after the
+// receiver, for a transformer), then the names the body declares with `let`
+// at its top level. Made once per definition and shared by its calls.
+#[derive(Debug)]
+pub struct Layout {
+    names: Vec<Symbol>,
+    positions: HashMap<Symbol, usize>,
+    // The file the function is written in, which errors in its calls name
+    file: Option<Arc<Path>>,
+}
+
+impl Layout {
+    pub fn new(names: impl IntoIterator<Item = Symbol>, file: Option<Arc<Path>>) -> Arc<Self> {
+        let names: Vec<Symbol> = names.into_iter().collect();
+        let mut positions = HashMap::new();
+        for (position, name) in names.iter().enumerate() {
+            // A parameter written twice is bound to its first slot
+            positions.entry(*name).or_insert(position);
+        }
+        Arc::new(Layout { names, positions, file })
+    }
+
+    pub fn names(&self) -> &[Symbol] {
+        &self.names
+    }
+
+    pub fn file(&self) -> Option<&Path> {
+        self.file.as_deref()
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Frame {
+    layout: Arc<Layout>,
+    // A slot is None until its name is defined
+    slots: Vec<Option<Value>>,
+    // The scopes the function was defined in, if that was inside a call
+    captured: Option<Arc<Environment>>,
+}
+
 #[derive(Debug)]
 pub struct Environment {
     pub enclosing: Option<Box<Environment>>,
-    pub values: HashMap<String, Value>,
+    pub values: HashMap<Symbol, Value>,
+    // The scope of a call keeps the names of its layout in slots, which the
+    // references the parser bound to them (Expr::Local) index directly. Other
+    // scopes have no frame.
+    frame: Option<Frame>,
 }
 
 impl Environment {
@@ -12,6 +59,7 @@ impl Environment {
         Environment {
             enclosing: None,
             values: HashMap::new(),
+            frame: None,
         }
     }
 
@@ -19,40 +67,245 @@ impl Environment {
         Environment {
             enclosing,
             values: HashMap::new(),
+            frame: None,
+        }
+    }
+
+    // The scope of a call, on top of its caller's scopes, with a slot for each
+    // name in the layout
+    pub fn new_call(enclosing: Environment, layout: Arc<Layout>, captured: Option<Arc<Environment>>) -> Self {
+        Environment {
+            enclosing: Some(Box::new(enclosing)),
+            values: HashMap::new(),
+            frame: Some(Frame { slots: vec![None; layout.names.len()], layout, captured }),
+        }
+    }
+
+    pub fn define(&mut self, name: Symbol, value: Value) {
+        match (self.position(name), self.frame.as_mut()) {
+            (Some(position), Some(frame)) => frame.slots[position] = Some(value),
+            _ => {
+                self.values.insert(name, value);
+            },
+        }
+    }
+
+    // The name's slot in this scope. A name of the layout is only ever kept
+    // in its slot.
+    fn position(&self, name: Symbol) -> Option<usize> {
+        self.frame.as_ref()?.layout.positions.get(&name).copied()
+    }
+
+    // The name's value in this scope alone
+    fn local(&self, name: Symbol) -> Option<&Value> {
+        match (self.position(name), self.frame.as_ref()) {
+            (Some(position), Some(frame)) => frame.slots[position].as_ref(),
+            _ => self.values.get(&name),
+        }
+    }
+
+    fn local_mut(&mut self, name: Symbol) -> Option<&mut Value> {
+        match (self.position(name), self.frame.as_mut()) {
+            (Some(position), Some(frame)) => frame.slots[position].as_mut(),
+            _ => self.values.get_mut(&name),
+        }
+    }
+
+    // Scopes are walked with loops rather than recursion: every function call
+    // adds a scope, so deeply recursive programs build very long chains
+
+    pub fn get(&self, name: Symbol) -> Option<Value> {
+        self.lookup(name).cloned()
+    }
+
+    // The name's value in the nearest scope that binds it, or failing that in
+    // the scopes the running functions were defined in, innermost first
+    fn lookup(&self, name: Symbol) -> Option<&Value> {
+        self.scopes().find_map(|scope| scope.local(name)).or_else(|| {
+            self.scopes()
+                .filter_map(|scope| scope.frame.as_ref()?.captured.as_deref())
+                .find_map(|captured| captured.lookup(name))
+        })
+    }
+
+    // The value of a reference the parser bound to slot `slot` of the call's
+    // scope, `depth` block scopes out. The parser only binds references that
+    // run once their slot is set, so None means the scopes aren't laid out
+    // the way it saw them.
+    pub fn slot(&self, depth: usize, slot: usize) -> Option<&Value> {
+        let mut scope = self;
+        for _ in 0..depth {
+            scope = scope.enclosing.as_deref()?;
         }
+        scope.frame.as_ref()?.slots.get(slot)?.as_ref()
     }
 
-    pub fn define(&mut self, name: String, value: Value) {
-        self.values.insert(name, value);
+    // The layout of the innermost call, if the scopes are inside one
+    pub fn layout(&self) -> Option<&Layout> {
+        self.scopes().find_map(|scope| Some(&*scope.frame.as_ref()?.layout))
     }
 
-    pub fn get(&self, name: &str) -> Option<Value> {
-        if let Some(value) = self.values.get(name) {
-            Some(value.clone())
-        } else if let Some(enclosing) = &self.enclosing {
-            enclosing.get(name)
-        } else {
-            None
+    // The outermost scope, which holds the builtins
+    pub fn outermost(&self) -> &Environment {
+        let mut scope = self;
+        while let Some(enclosing) = scope.enclosing.as_deref() {
+            scope = enclosing;
         }
+        scope
     }
 
-    pub fn assign(&mut self, name: &str, value: Value) -> Result<(), String> {
-        if self.values.contains_key(name) {
-            self.values.insert(name.to_string(), value);
-            Ok(())
-        } else if let Some(enclosing) = &mut self.enclosing {
-            enclosing.assign(name, value)
-        } else {
-            Err(format!("Undefined variable '{}'", name))
+    pub fn outermost_mut(&mut self) -> &mut Environment {
+        let mut scope = self;
+        while scope.enclosing.is_some() {
+            scope = scope.enclosing.as_deref_mut().expect("checked above");
         }
+        scope
+    }
+
+    // The scopes, innermost first
+    pub fn scopes(&self) -> impl Iterator<Item = &Environment> {
+        std::iter::successors(Some(self), |environment| environment.enclosing.as_deref())
+    }
+
+    // The names bound in this scope alone, with their values
+    pub fn bindings(&self) -> impl Iterator<Item = (Symbol, &Value)> {
+        let slots = self.frame.iter().flat_map(|frame| {
+            frame.layout.names.iter().zip(&frame.slots).filter_map(|(name, value)| Some((*name, value.as_ref()?)))
+        });
+        slots.chain(self.values.iter().map(|(name, value)| (*name, value)))
+    }
+
+    // A call works on its own copy of its caller's variables, and of those its
+    // function captured, so a change to one is kept in the call's scope and
+    // ends with the call. Arrays and maps are shared all the same.
+    pub fn get_mut(&mut self, name: Symbol) -> Option<&mut Value> {
+        if let Some((depth, false)) = self.find(name) {
+            return self.scope_mut(depth).local_mut(name);
+        }
+        let value = self.lookup(name)?.clone();
+        let scope = self.call_scope_mut()?;
+        scope.define(name, value);
+        scope.local_mut(name)
+    }
+
+    pub fn assign(&mut self, name: Symbol, value: Value) -> Result<(), String> {
+        match self.find(name) {
+            Some((depth, false)) => *self.scope_mut(depth).local_mut(name).expect("found above") = value,
+            _ if self.lookup(name).is_some() => self.call_scope_mut().expect("found past a call's scope").define(name, value),
+            _ => return Err(format!("Undefined variable '{}'", name)),
+        }
+        Ok(())
+    }
+
+    // How many scopes out the name is bound, and whether that's past the
+    // scope of the innermost call
+    fn find(&self, name: Symbol) -> Option<(usize, bool)> {
+        let mut past_call = false;
+        for (depth, scope) in self.scopes().enumerate() {
+            if scope.local(name).is_some() {
+                return Some((depth, past_call));
+            }
+            past_call |= scope.frame.is_some();
+        }
+        None
+    }
+
+    fn scope_mut(&mut self, depth: usize) -> &mut Environment {
+        let mut scope = self;
+        for _ in 0..depth {
+            scope = scope.enclosing.as_deref_mut().expect("no deeper than the chain");
+        }
+        scope
+    }
+
+    fn call_scope_mut(&mut self) -> Option<&mut Environment> {
+        let mut scope = self;
+        while scope.frame.is_none() {
+            scope = scope.enclosing.as_deref_mut()?;
+        }
+        Some(scope)
+    }
+
+    // Copies of the scopes a function defined here sees, for calls made after
+    // they're gone: the block scopes out to the call it's defined in and that
+    // call's own scope, which keeps what its function captured in turn. None
+    // at the top level, whose scope stays in place; only its block scopes are
+    // copied inside one of those.
+    pub fn capture(&self) -> Option<Environment> {
+        let mut scopes = Vec::new();
+        for scope in self.scopes() {
+            // The top level is the scope just inside the builtins
+            if scope.enclosing.as_deref().is_none_or(|enclosing| enclosing.enclosing.is_none()) {
+                break;
+            }
+            scopes.push(Environment { enclosing: None, values: scope.values.clone(), frame: scope.frame.clone() });
+            if scope.frame.is_some() {
+                break;
+            }
+        }
+        Self::link(scopes)
+    }
+
+    // The binding lookup() finds for each name the scopes bind, including
+    // those captured, sorted by name
+    pub fn visible(&self) -> Vec<(Symbol, &Value)> {
+        let mut seen = HashSet::new();
+        let captured = self.scopes().filter_map(|scope| scope.frame.as_ref()?.captured.as_deref());
+        let mut visible: Vec<_> = self.scopes()
+            .flat_map(Environment::bindings)
+            .chain(captured.flat_map(Environment::visible))
+            .filter(|(name, _)| seen.insert(*name))
+            .collect();
+        visible.sort_by_key(|(name, _)| *name);
+        visible
+    }
+
+    // A copy sharing no arrays or maps with this environment, for code that
+    // runs on its own (tasks and imported files)
+    pub fn deep_copy(&self) -> Self {
+        self.copy_scopes(|scope| Environment {
+            enclosing: None,
+            values: scope.values.iter().map(|(name, value)| (*name, value.deep_copy())).collect(),
+            frame: scope.frame.as_ref().map(|frame| Frame {
+                layout: frame.layout.clone(),
+                slots: frame.slots.iter().map(|value| value.as_ref().map(Value::deep_copy)).collect(),
+                captured: frame.captured.as_deref().map(|captured| Arc::new(captured.deep_copy())),
+            }),
+        })
+    }
+
+    // Rebuilds the chain of scopes from the outermost one inwards, copying each
+    // scope's bindings with `copy`
+    fn copy_scopes(&self, copy: impl Fn(&Environment) -> Environment) -> Self {
+        Self::link(self.scopes().map(copy).collect()).expect("an environment has at least one scope")
+    }
+
+    // Chains scopes given innermost first, each enclosed by the next
+    fn link(scopes: Vec<Environment>) -> Option<Environment> {
+        let mut linked = None;
+        for mut environment in scopes.into_iter().rev() {
+            environment.enclosing = linked.map(Box::new);
+            linked = Some(environment);
+        }
+        linked
     }
 }
 
 impl Clone for Environment {
     fn clone(&self) -> Self {
-        Environment {
-            enclosing: self.enclosing.clone(),
-            values: self.values.clone(),
+        self.copy_scopes(|scope| Environment {
+            enclosing: None,
+            values: scope.values.clone(),
+            frame: scope.frame.clone(),
+        })
+    }
+}
+
+impl Drop for Environment {
+    fn drop(&mut self) {
+        let mut enclosing = self.enclosing.take();
+        while let Some(mut environment) = enclosing {
+            enclosing = environment.enclosing.take();
         }
     }
 }
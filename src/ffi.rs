@@ -0,0 +1,112 @@
+// A C interface for embedding the interpreter, compiled with the `ffi` feature;
+// include/mlang.h declares it. An interpreter keeps its variables between
+// calls to mlang_eval(), like a REPL session. Programs print to standard output.
+use std::ffi::{c_char, c_int, CStr, CString};
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+use crate::interpreter::Interpreter;
+use crate::json;
+use crate::lexer::Lexer;
+use crate::parser::Parser;
+
+pub struct MLang {
+    interpreter: Interpreter,
+    // The message of the last failed mlang_eval(), kept for mlang_error()
+    error: Option<CString>,
+}
+
+/// Creates an interpreter. Free it with mlang_free().
+#[no_mangle]
+pub extern "C" fn mlang_new() -> *mut MLang {
+    Box::into_raw(Box::new(MLang { interpreter: Interpreter::new(), error: None }))
+}
+
+/// Runs a program. Returns 0 on success, or -1 if it failed to parse or run, in
+/// which case mlang_error() describes the problem.
+///
+/// # Safety
+/// `mlang` must come from mlang_new() and `source` must be a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn mlang_eval(mlang: *mut MLang, source: *const c_char) -> c_int {
+    let Some(mlang) = mlang.as_mut() else { return -1 };
+    if source.is_null() {
+        mlang.error = CString::new("Source is null").ok();
+        return -1;
+    }
+    let source = CStr::from_ptr(source).to_string_lossy();
+
+    // A panic must not unwind into the host's C code
+    let interpreter = &mut mlang.interpreter;
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        let tokens = Lexer::new(&source).tokenize()?;
+        let program = Parser::new(tokens).parse()?;
+        interpreter.evaluate(&program)
+    }))
+    .unwrap_or_else(|_| Err("The interpreter panicked".to_string()));
+
+    match result {
+        Ok(_) => {
+            mlang.error = None;
+            0
+        },
+        Err(e) => {
+            // Messages can't contain NUL bytes in C
+            mlang.error = CString::new(e.replace('\0', "")).ok();
+            -1
+        },
+    }
+}
+
+/// The error message of the last failed mlang_eval(), or NULL after a success.
+/// The string belongs to the interpreter and lasts until the next mlang_eval().
+///
+/// # Safety
+/// `mlang` must come from mlang_new().
+#[no_mangle]
+pub unsafe extern "C" fn mlang_error(mlang: *const MLang) -> *const c_char {
+    match mlang.as_ref().and_then(|mlang| mlang.error.as_ref()) {
+        Some(error) => error.as_ptr(),
+        None => ptr::null(),
+    }
+}
+
+/// The value of a variable as JSON text, or NULL if it isn't defined or has no
+/// JSON form. Free the result with mlang_free_string().
+///
+/// # Safety
+/// `mlang` must come from mlang_new() and `name` must be a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn mlang_get_var(mlang: *const MLang, name: *const c_char) -> *mut c_char {
+    let Some(mlang) = mlang.as_ref() else { return ptr::null_mut() };
+    if name.is_null() {
+        return ptr::null_mut();
+    }
+
+    let name = CStr::from_ptr(name).to_string_lossy();
+    mlang.interpreter.get_variable(&name)
+        .and_then(|value| json::to_json(&value).ok())
+        .and_then(|text| CString::new(text).ok())
+        .map_or(ptr::null_mut(), CString::into_raw)
+}
+
+/// Frees a string returned by mlang_get_var(). NULL is ignored.
+///
+/// # Safety
+/// `string` must come from mlang_get_var() and not have been freed already.
+#[no_mangle]
+pub unsafe extern "C" fn mlang_free_string(string: *mut c_char) {
+    if !string.is_null() {
+        drop(CString::from_raw(string));
+    }
+}
+
+/// Frees an interpreter and everything it holds. NULL is ignored.
+///
+/// # Safety
+/// `mlang` must come from mlang_new() and not have been freed already.
+#[no_mangle]
+pub unsafe extern "C" fn mlang_free(mlang: *mut MLang) {
+    if !mlang.is_null() {
+        drop(Box::from_raw(mlang));
+    }
+}
@@ -0,0 +1,190 @@
+use crate::decimal;
+use crate::value::Value;
+
+// A parsed `{:...}` specifier: [[fill]align][+][0][width][.precision]
+struct Spec {
+    fill: char,
+    align: Option<char>,
+    sign: bool,
+    zero: bool,
+    width: usize,
+    precision: Option<usize>,
+}
+
+// Implements the `format()` builtin. Placeholders are `{}` (next argument),
+// `{n}` (argument n) and either form followed by `:spec`; `{{` and `}}` are
+// literal braces.
+pub fn format_string(template: &str, arguments: &[Value]) -> Result<String, String> {
+    let mut result = String::new();
+    let mut chars = template.chars().peekable();
+    let mut next_argument = 0;
+
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                result.push('{');
+            },
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                result.push('}');
+            },
+            '{' => {
+                let mut placeholder = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => placeholder.push(c),
+                        None => return Err("Unclosed '{' in format string".to_string()),
+                    }
+                }
+
+                let (position, spec) = match placeholder.split_once(':') {
+                    Some((position, spec)) => (position, spec),
+                    None => (placeholder.as_str(), ""),
+                };
+
+                let index = if position.is_empty() {
+                    next_argument += 1;
+                    next_argument - 1
+                } else {
+                    position.trim().parse::<usize>()
+                        .map_err(|_| format!("Invalid placeholder '{{{}}}' in format string", placeholder))?
+                };
+
+                let value = arguments.get(index).ok_or_else(|| {
+                    format!("Format string refers to argument {}, but only {} were given", index + 1, arguments.len())
+                })?;
+
+                result.push_str(&format_value(value, &parse_spec(spec)?));
+            },
+            '}' => return Err("Unmatched '}' in format string".to_string()),
+            _ => result.push(c),
+        }
+    }
+
+    Ok(result)
+}
+
+fn parse_spec(spec: &str) -> Result<Spec, String> {
+    let chars: Vec<char> = spec.chars().collect();
+    let mut parsed = Spec { fill: ' ', align: None, sign: false, zero: false, width: 0, precision: None };
+    let mut i = 0;
+
+    let is_align = |c: char| c == '<' || c == '>' || c == '^';
+    if chars.len() >= 2 && is_align(chars[1]) {
+        parsed.fill = chars[0];
+        parsed.align = Some(chars[1]);
+        i = 2;
+    } else if !chars.is_empty() && is_align(chars[0]) {
+        parsed.align = Some(chars[0]);
+        i = 1;
+    }
+
+    if chars.get(i) == Some(&'+') {
+        parsed.sign = true;
+        i += 1;
+    }
+
+    if chars.get(i) == Some(&'0') {
+        parsed.zero = true;
+        i += 1;
+    }
+
+    let start = i;
+    while i < chars.len() && chars[i].is_ascii_digit() {
+        i += 1;
+    }
+    if i > start {
+        parsed.width = parse_count(&chars[start..i]).ok_or_else(|| format!("Invalid width in '{{:{}}}'", spec))?;
+    }
+
+    if chars.get(i) == Some(&'.') {
+        i += 1;
+        let start = i;
+        while i < chars.len() && chars[i].is_ascii_digit() {
+            i += 1;
+        }
+        if i == start {
+            return Err(format!("Missing precision in '{{:{}}}'", spec));
+        }
+        parsed.precision = Some(parse_count(&chars[start..i]).ok_or_else(|| format!("Invalid precision in '{{:{}}}'", spec))?);
+    }
+
+    if i != chars.len() {
+        return Err(format!("Invalid format specifier '{{:{}}}'", spec));
+    }
+
+    Ok(parsed)
+}
+
+// A width or precision, which Rust's formatting (and a sensible amount of
+// padding) limits to 65535
+fn parse_count(digits: &[char]) -> Option<usize> {
+    digits.iter().collect::<String>().parse::<u16>().ok().map(usize::from)
+}
+
+fn format_value(value: &Value, spec: &Spec) -> String {
+    let numeric = value.as_number().is_some() || matches!(value, Value::Decimal(_));
+
+    let mut text = match (value, spec.precision) {
+        (Value::Decimal(d), Some(precision)) => decimal::to_fixed(*d, precision),
+        (_, Some(precision)) if numeric => format!("{:.*}", precision, value.as_number().unwrap_or_default()),
+        // Precision truncates anything that isn't a number
+        (_, Some(precision)) => value.to_string().chars().take(precision).collect(),
+        (_, None) => value.to_string(),
+    };
+
+    if numeric && spec.sign && !text.starts_with('-') {
+        text.insert(0, '+');
+    }
+
+    let length = text.chars().count();
+    if length >= spec.width {
+        return text;
+    }
+    let padding = spec.width - length;
+
+    // Zero padding goes between the sign and the digits
+    if numeric && spec.zero && spec.align.is_none() {
+        let sign_length = if text.starts_with('-') || text.starts_with('+') { 1 } else { 0 };
+        let (sign, digits) = text.split_at(sign_length);
+        return format!("{}{}{}", sign, "0".repeat(padding), digits);
+    }
+
+    // Numbers are right-aligned by default, everything else left-aligned
+    let align = spec.align.unwrap_or(if numeric { '>' } else { '<' });
+    let fill = |count: usize| spec.fill.to_string().repeat(count);
+    match align {
+        '>' => format!("{}{}", fill(padding), text),
+        '^' => format!("{}{}{}", fill(padding / 2), text, fill(padding - padding / 2)),
+        _ => format!("{}{}", text, fill(padding)),
+    }
+}
+
+// Rounds to the given number of decimal places (negative places round to tens,
+// hundreds, ...), with halves rounded away from zero.
+pub fn round_to(n: f64, places: i32) -> f64 {
+    let factor = 10f64.powi(places.abs());
+    if places >= 0 {
+        (n * factor).round() / factor
+    } else {
+        (n / factor).round() * factor
+    }
+}
+
+// Renders a number with the given count of significant digits
+pub fn to_precision(n: f64, digits: usize) -> String {
+    if n == 0.0 || !n.is_finite() {
+        return format!("{:.*}", digits.saturating_sub(1), n);
+    }
+
+    // Scientific formatting does the rounding, which may carry into a new
+    // digit (9.99 -> 1.0e1), so the exponent is read back from it
+    let scientific = format!("{:.*e}", digits - 1, n);
+    let exponent: i64 = scientific.split('e').nth(1).and_then(|e| e.parse().ok()).unwrap_or(0);
+    let places = digits as i64 - 1 - exponent;
+
+    // Digits left of the decimal point beyond the precision are rounded to zero
+    format!("{:.*}", places.max(0) as usize, round_to(n, places as i32))
+}
@@ -0,0 +1,21 @@
+// Digests for the sha256(), md5() and crc32() builtins, returned as lowercase hex.
+// The implementations come from optional crates enabled by the `hashing` feature.
+
+#[cfg(feature = "hashing")]
+pub fn digest(algorithm: &str, bytes: &[u8]) -> Result<String, String> {
+    use md5::Md5;
+    use sha2::{Digest, Sha256};
+    use crate::encoding::to_hex;
+
+    match algorithm {
+        "sha256" => Ok(to_hex(&Sha256::digest(bytes))),
+        "md5" => Ok(to_hex(&Md5::digest(bytes))),
+        "crc32" => Ok(format!("{:08x}", crc32fast::hash(bytes))),
+        _ => Err(format!("Unknown hash algorithm '{}'", algorithm)),
+    }
+}
+
+#[cfg(not(feature = "hashing"))]
+pub fn digest(algorithm: &str, _bytes: &[u8]) -> Result<String, String> {
+    Err(format!("{}() is unavailable: the interpreter was built without the 'hashing' feature", algorithm))
+}
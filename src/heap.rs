@@ -0,0 +1,106 @@
+use std::any::Any;
+use std::cell::RefCell;
+use std::sync::{Arc, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+// A reference to a container (an array or a map) stored on the heap. Cloning a
+// handle shares the container instead of copying it, so a container keeps its
+// identity when it is assigned or passed around. Containers are reference
+// counted and freed when the last handle goes away.
+#[derive(Debug, Default)]
+pub struct Handle<T: 'static>(Option<Arc<RwLock<T>>>);
+
+impl<T> Clone for Handle<T> {
+    fn clone(&self) -> Self {
+        Handle(Some(Arc::clone(self.container())))
+    }
+}
+
+impl<T> Handle<T> {
+    pub fn new(value: T) -> Self {
+        Handle(Some(Arc::new(RwLock::new(value))))
+    }
+
+    // Only None while the handle is being dropped
+    fn container(&self) -> &Arc<RwLock<T>> {
+        self.0.as_ref().expect("handle used after it was dropped")
+    }
+
+    // Guards must not be held while evaluating M code, which may access the same container
+    pub fn borrow(&self) -> RwLockReadGuard<'_, T> {
+        self.container().read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub fn borrow_mut(&self) -> RwLockWriteGuard<'_, T> {
+        self.container().write().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Identifies the container: handles to the same container have the same id.
+    pub fn id(&self) -> usize {
+        Arc::as_ptr(self.container()) as *const () as usize
+    }
+
+    /// Returns true when no other handle refers to the container.
+    pub fn is_unique(&self) -> bool {
+        Arc::strong_count(self.container()) == 1
+    }
+
+    /// Returns true when both handles refer to the same container.
+    pub fn ptr_eq(&self, other: &Handle<T>) -> bool {
+        Arc::ptr_eq(self.container(), other.container())
+    }
+}
+
+impl<T: Clone> Handle<T> {
+    /// Returns a copy of the container's current contents.
+    pub fn get(&self) -> T {
+        self.borrow().clone()
+    }
+}
+
+// Freeing a container drops the values in it, which may hold the last handles to
+// further containers. Freeing those recursively could overflow the stack for
+// deeply nested data, so containers freed while another one is being freed are
+// queued and dropped one at a time instead.
+impl<T: 'static> Drop for Handle<T> {
+    fn drop(&mut self) {
+        if let Some(contents) = self.0.take().and_then(Arc::into_inner) {
+            release(Box::new(contents));
+        }
+    }
+}
+
+thread_local! {
+    // Containers waiting to be freed, while this thread is freeing one
+    static PENDING: RefCell<Option<Vec<Box<dyn Any>>>> = const { RefCell::new(None) };
+}
+
+pub(crate) fn release(contents: Box<dyn Any>) {
+    let mut contents = Some(contents);
+    let queued = PENDING.try_with(|pending| {
+        let mut pending = pending.borrow_mut();
+        match pending.as_mut() {
+            Some(queue) => {
+                queue.extend(contents.take());
+                true
+            },
+            None => {
+                *pending = Some(Vec::new());
+                false
+            },
+        }
+    });
+
+    match queued {
+        // A container further up the stack is being freed; this one waits its turn
+        Ok(true) => {},
+        Ok(false) => {
+            drop(contents);
+            while let Some(next) = PENDING.with(|pending| pending.borrow_mut().as_mut().and_then(Vec::pop)) {
+                drop(next);
+            }
+            PENDING.with(|pending| *pending.borrow_mut() = None);
+        },
+        // The thread is exiting and its queue is already gone
+        Err(_) => drop(contents),
+    }
+}
@@ -0,0 +1,72 @@
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+
+/// Everything a program does outside the interpreter: console output and input,
+/// and files. Programs run with `StdHost` unless the embedder sets another one
+/// (the browser build sends output to JavaScript). The file operations default
+/// to the real file system.
+pub trait Host: Send + Sync {
+    /// Writes program output as it is produced. `print()` passes its text with
+    /// the trailing newline.
+    fn write(&self, text: &str) -> Result<(), String>;
+
+    /// Reads one line for `input()`, without its line ending.
+    fn read_line(&self) -> Result<String, String>;
+
+    fn read_file(&self, path: &Path) -> io::Result<Vec<u8>> {
+        fs::read(path)
+    }
+
+    fn write_file(&self, path: &Path, bytes: &[u8]) -> io::Result<()> {
+        fs::write(path, bytes)
+    }
+
+    /// The names of the entries in a directory, in any order.
+    fn list_dir(&self, path: &Path) -> io::Result<Vec<String>> {
+        fs::read_dir(path)?
+            .map(|entry| entry.map(|entry| entry.file_name().to_string_lossy().into_owned()))
+            .collect()
+    }
+
+    /// Creates a directory along with any missing parents.
+    fn create_dir(&self, path: &Path) -> io::Result<()> {
+        fs::create_dir_all(path)
+    }
+
+    fn remove_file(&self, path: &Path) -> io::Result<()> {
+        fs::remove_file(path)
+    }
+
+    /// The absolute form of a path to an existing file, used to load each
+    /// imported module once however its path is spelled.
+    fn canonicalize(&self, path: &Path) -> io::Result<PathBuf> {
+        fs::canonicalize(path)
+    }
+
+    /// Milliseconds since the Unix epoch for `now()`, or `None` without a
+    /// clock. Defaults to the system clock where there is one.
+    fn now(&self) -> Option<f64> {
+        crate::random::system_clock()
+    }
+}
+
+/// Standard output, standard input and the file system.
+pub struct StdHost;
+
+impl Host for StdHost {
+    fn write(&self, text: &str) -> Result<(), String> {
+        let mut stdout = io::stdout().lock();
+        stdout.write_all(text.as_bytes())
+            .and_then(|()| stdout.flush())
+            .map_err(|e| format!("Failed to write output: {}", e))
+    }
+
+    fn read_line(&self) -> Result<String, String> {
+        let mut input = String::new();
+        io::stdin().lock().read_line(&mut input).map_err(|e| format!("Failed to read input: {}", e))?;
+        // Only the line ending is removed; spaces the user typed are kept
+        let line = input.strip_suffix('\n').unwrap_or(&input);
+        Ok(line.strip_suffix('\r').unwrap_or(line).to_string())
+    }
+}
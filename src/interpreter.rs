@@ -1,43 +1,139 @@
-use std::collections::HashMap;
-use std::fs;
+use std::collections::{BTreeMap, HashMap, VecDeque};
 use std::path::{Path, PathBuf};
-use std::io::{self, Write};
-use crate::token::{Token, TokenType};
-use crate::lexer::Lexer;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError, RwLock};
+use std::thread;
+use std::time::Instant;
+use crate::token::TokenType;
+use crate::lexer::{LexError, Lexer};
 use crate::ast::Expr;
-use crate::value::Value;
-use crate::environment::Environment;
+use crate::symbol::Symbol;
+use crate::value::{AsyncCall, Builtin, NativeFunction, Set, TaskState, Value};
+use crate::environment::{Environment, Layout};
 use crate::parser::Parser;
+use crate::optimizer::Optimizer;
+use crate::cache;
+use crate::module::{self, ModuleRegistry, ModuleScopes};
+use crate::format;
+use crate::encoding;
+use crate::json;
+use crate::snapshot;
+use crate::bigint;
+use crate::decimal;
+use crate::host::{Host, StdHost};
+use crate::stack;
+use crate::random::Random;
+use builtins::BUILTINS;
+
+mod builtins;
+mod evaluation;
+
+// Deepest nesting of function and transformer calls before a program is stopped,
+// which turns runaway recursion into an error
+const MAX_CALL_DEPTH: usize = 1_000;
+
+// `break`, `continue` and `return` travel to the loop or call they belong to as
+// an error, so every expression in between stops; the payload waits here. The
+// error message is only seen when nothing handles the signal.
+// A labeled `break` or `continue` passes through loops with other labels.
+enum Unwind {
+    Break(Option<String>, Value),
+    Continue(Option<String>),
+    Return(Value),
+}
+
+/// Where a runtime error happened: the innermost `let`, assignment, `return`
+/// or assertion it stopped, in the file that statement is written in.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Location {
+    pub file: Option<PathBuf>,
+    pub line: usize,
+    pub column: usize,
+}
+
+// What a loop does after running its body once
+enum Flow {
+    Next(Value),
+    Break(Value),
+}
 
 pub struct Interpreter {
     environment: Environment,
     _globals: Environment,
-    imported_files: Vec<String>,
+    modules: Arc<Mutex<ModuleRegistry>>,
+    // Signalled whenever a module finishes loading or fails to
+    module_loaded: Arc<Condvar>,
+    module_scopes: Arc<RwLock<ModuleScopes>>,
+    // The modules being loaded by the chain of imports that led here, outermost first
+    importing: Vec<PathBuf>,
+    // The file the code being run comes from, if it's known
+    file: Option<Arc<Path>>,
+    // The last error raised and where it happened
+    error_at: Option<(String, Location)>,
+    // The functions subscribed to each event with on(), shared with forks so
+    // imported files can subscribe too
+    handlers: Arc<Mutex<HashMap<String, Vec<Value>>>>,
+    // Calls of async functions that haven't run yet, in the order they were
+    // made. Each runs when it's awaited or while the program sleeps.
+    queued: VecDeque<Arc<Mutex<TaskState>>>,
     base_path: Option<PathBuf>,
+    module_paths: Vec<PathBuf>,
+    // Shared with tasks, so they continue the program's sequence
+    random: Arc<Mutex<Random>>,
+    require_let: bool,
+    strict_conditions: bool,
+    strict: bool,
+    // Arithmetic that produces NaN or an infinity is an error
+    strict_numbers: bool,
+    optimize: bool,
+    cache: bool,
+    // What the program sees with args(): the command-line arguments after `--`
+    args: Vec<String>,
+    call_depth: usize,
+    unwinding: Option<Unwind>,
+    host: Arc<dyn Host>,
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Interpreter {
     pub fn new() -> Self {
+        // The builtins live in the outermost scope, the prelude, where the host
+        // can remove or replace them
         let mut environment = Environment::new();
-        
-        // Add built-in functions
-        environment.define("print".to_string(), Value::Function {
-            params: vec!["message".to_string()],
-            body: vec![],
-        });
-        
-        environment.define("range".to_string(), Value::Function {
-            params: vec!["start".to_string(), "end".to_string()],
-            body: vec![],
-        });
-        
+        for &(name, function) in BUILTINS {
+            environment.define(Symbol::new(name), Value::Builtin(Builtin { name, function }));
+        }
+
         let environment = Environment::new_with_enclosing(Some(Box::new(environment)));
 
         Interpreter {
             environment,
             _globals: Environment::new(),
-            imported_files: Vec::new(),
+            modules: Arc::new(Mutex::new(ModuleRegistry::default())),
+            module_loaded: Arc::default(),
+            module_scopes: Arc::default(),
+            importing: Vec::new(),
+            file: None,
+            error_at: None,
+            handlers: Arc::default(),
+            queued: VecDeque::new(),
             base_path: None,
+            module_paths: Vec::new(),
+            random: Arc::new(Mutex::new(Random::new())),
+            require_let: false,
+            strict_conditions: false,
+            strict: false,
+            strict_numbers: false,
+            optimize: false,
+            cache: false,
+            args: Vec::new(),
+            call_depth: 0,
+            unwinding: None,
+            host: Arc::new(StdHost),
         }
     }
 
@@ -47,660 +143,1983 @@ impl Interpreter {
         interpreter
     }
 
+    /// Enables strict declarations: variables must be introduced with `let`,
+    /// and assigning to an undeclared name is an error instead of defining it.
+    pub fn set_require_let(&mut self, require_let: bool) {
+        self.require_let = require_let;
+    }
+
+    /// Requires the conditions of `if`, `while` and `do` to be booleans instead
+    /// of testing their truthiness.
+    pub fn set_strict_conditions(&mut self, strict_conditions: bool) {
+        self.strict_conditions = strict_conditions;
+    }
+
+    /// Turns silent fallbacks into errors: conversions of values that aren't
+    /// numbers (`"abc".to_number()` is otherwise 0), calls with the wrong number
+    /// of arguments (missing ones are otherwise nil), non-boolean conditions
+    /// and arithmetic that produces NaN or an infinity.
+    pub fn set_strict(&mut self, strict: bool) {
+        self.strict = strict;
+        self.strict_conditions |= strict;
+        self.strict_numbers |= strict;
+    }
+
+    /// Makes arithmetic and number conversions that produce NaN or an infinity
+    /// (`1e308 * 10`, `"nan".to_number()`) errors, instead of values that
+    /// silently spread through later results.
+    pub fn set_strict_numbers(&mut self, strict_numbers: bool) {
+        self.strict_numbers = strict_numbers;
+    }
+
+    /// Names the file the program being run comes from, so that an import of
+    /// it is reported as a cycle instead of loading it again as a module, and
+    /// error_location() names it for errors in its code.
+    pub fn set_main_file(&mut self, path: &Path) {
+        self.importing = self.host.canonicalize(path).into_iter().collect();
+        self.file = Some(path.into());
+    }
+
+    /// Where `error`, just returned by evaluate(), happened, if that's known.
+    /// Taking it leaves none for the next error.
+    pub fn take_error_location(&mut self, error: &str) -> Option<Location> {
+        self.error_at.take().filter(|(message, _)| message == error).map(|(_, location)| location)
+    }
+
+    // Records where an error happened, at the innermost statement with a
+    // position that it passes through on its way out
+    fn locate(&mut self, error: &str, (line, column): (usize, usize)) {
+        if self.unwinding.is_some() || self.error_at.as_ref().is_some_and(|(message, _)| message == error) {
+            return;
+        }
+        // Code in a function is in the file the function was written in
+        let file = self.environment.layout().and_then(Layout::file).or(self.file.as_deref());
+        let location = Location { file: file.map(Path::to_path_buf), line, column };
+        self.error_at = Some((error.to_string(), location));
+    }
+
+    /// Sends console output and input, and file access, through `host` instead
+    /// of the process's standard streams and file system.
+    pub fn set_host(&mut self, host: Arc<dyn Host>) {
+        if let (Some(time), Ok(mut random)) = (host.now(), self.random.lock()) {
+            random.mix(time);
+        }
+        self.host = host;
+    }
+
+    /// Reads and writes `.mc` AST caches for imported files.
+    pub fn set_cache(&mut self, cache: bool) {
+        self.cache = cache;
+    }
+
+    pub fn base_path(&self) -> Option<&Path> {
+        self.base_path.as_deref()
+    }
+
+    /// Directories `use` searches for a file that isn't found relative to the
+    /// base path, in order.
+    pub fn set_module_paths(&mut self, module_paths: Vec<PathBuf>) {
+        self.module_paths = module_paths;
+    }
+
+    pub fn module_paths(&self) -> &[PathBuf] {
+        &self.module_paths
+    }
+
+    /// Makes runs reproducible: random() and random_int() give the same
+    /// sequence for the same seed, and now() counts only the milliseconds the
+    /// program has slept, starting from zero.
+    pub fn set_seed(&mut self, seed: u64) {
+        self.random = Arc::new(Mutex::new(Random::seeded(seed)));
+    }
+
+    /// Runs the optimizer over imported files as well as the main program.
+    pub fn set_optimize(&mut self, optimize: bool) {
+        self.optimize = optimize;
+    }
+
+    /// Sets the command-line arguments the program gets from args().
+    pub fn set_args(&mut self, args: Vec<String>) {
+        self.args = args;
+    }
+
     pub fn evaluate(&mut self, expr: &Expr) -> Result<Value, String> {
-        match expr {
-            Expr::Number(value) => Ok(Value::Number(*value)),
-            Expr::String(value) => Ok(Value::String(value.clone())),
-            Expr::Boolean(value) => Ok(Value::Boolean(*value)),
-            Expr::Array(elements) => {
-                let mut values = Vec::new();
-                for element in elements {
-                    values.push(self.evaluate(element)?);
+        // Expressions are evaluated without recursion (see evaluation.rs), but a
+        // call starts an evaluation of its own, so those still nest
+        stack::guard(|| self.run(expr))
+    }
+
+    // `object[index]`: an element of an array, tuple, bytes or range, or an entry of a map
+    fn index(object_val: Value, index_val: Value) -> Result<Value, String> {
+        match (object_val, index_val) {
+            (Value::Tuple(elements), index @ (Value::Int(_) | Value::Number(_))) => {
+                match index.as_index() {
+                    Some(idx) if idx < elements.len() => Ok(elements[idx].clone()),
+                    _ => Err(format!("Index out of bounds: {}", index)),
                 }
-                Ok(Value::Array(values))
             },
-            Expr::Variable(name) => {
-                match self.environment.get(name) {
-                    Some(value) => Ok(value),
-                    None => Err(format!("Undefined variable: {}", name)),
+            (Value::Array(elements), index @ (Value::Int(_) | Value::Number(_))) => {
+                let elements = elements.borrow();
+                match index.as_index() {
+                    Some(idx) if idx < elements.len() => Ok(elements[idx].clone()),
+                    _ => Err(format!("Index out of bounds: {}", index)),
                 }
             },
-            Expr::Binary { left, operator, right } => self.evaluate_binary(left, operator, right),
-            Expr::Unary { operator, right } => self.evaluate_unary(operator, right),
-            Expr::Assign { name, value } => {
-                let evaluated_value = self.evaluate(value)?;
-
-                if self.environment.get(name).is_some() {
-                    self.environment.assign(name, evaluated_value.clone())?;
-                } else {
-                    self.environment.define(name.clone(), evaluated_value.clone());
+            (Value::Bytes(bytes), index @ (Value::Int(_) | Value::Number(_))) => {
+                match index.as_index() {
+                    Some(idx) if idx < bytes.len() => Ok(Value::Int(bytes[idx] as i64)),
+                    _ => Err(format!("Index out of bounds: {}", index)),
+                }
+            },
+            (Value::String(s), index @ (Value::Int(_) | Value::Number(_))) => {
+                // Indexes count characters, not bytes
+                match index.as_index().and_then(|idx| s.chars().nth(idx)) {
+                    Some(c) => Ok(Value::String(c.to_string().into())),
+                    None => Err(format!("Index out of bounds: {}", index)),
+                }
+            },
+            (Value::Map(entries), Value::String(key)) => {
+                // Missing keys evaluate to nil
+                Ok(entries.borrow().get(&*key).cloned().unwrap_or(Value::Nil))
+            },
+            (Value::Range { start, end }, index @ (Value::Int(_) | Value::Number(_))) => {
+                match index.as_index() {
+                    Some(idx) => match i64::try_from(idx).ok().and_then(|idx| start.checked_add(idx)) {
+                        Some(value) if value < end => Ok(Value::Int(value)),
+                        _ => Err(format!("Index out of bounds: {}", index)),
+                    },
+                    _ => Err(format!("Index out of bounds: {}", index)),
                 }
+            },
+            (Value::Map(_), _) => Err("Map keys must be strings".to_string()),
+            _ => Err("Cannot index non-array type".to_string()),
+        }
+    }
 
-                Ok(evaluated_value)
+    // `object[index] = value`: changes the array or map in place, so every
+    // variable holding the container sees the change
+    fn store_index(object_val: Value, index_val: Value, value: Value) -> Result<Value, String> {
+        // A container holding itself could never be freed (or printed)
+        if value.refers_to(&object_val) {
+            return Err("Cannot store an array or map inside itself".to_string());
+        }
+
+        match (&object_val, &index_val) {
+            (Value::Array(elements), Value::Int(_) | Value::Number(_)) => {
+                let mut elements = elements.borrow_mut();
+                match index_val.as_index() {
+                    Some(idx) if idx < elements.len() => elements[idx] = value.clone(),
+                    _ => return Err(format!("Index out of bounds: {}", index_val)),
+                }
             },
-            Expr::Call { callee, arguments } => self.call(callee, arguments),
-            Expr::Function { name, params, body } => {
-                // Create function value
-                let function = Value::Function {
-                    params: params.clone(),
-                    body: body.clone(),
-                };
-                
-                self.environment.define(name.clone(), function.clone());
-                
-                Ok(function)
+            (Value::Map(entries), Value::String(key)) => {
+                entries.borrow_mut().insert(key.to_string(), value.clone());
             },
-            Expr::Return { value } => {
-                match value {
-                    Some(expr) => {
-                        let result = self.evaluate(expr)?;
-                        return Ok(result);
+            (Value::Map(_), _) => return Err("Map keys must be strings".to_string()),
+            _ => return Err(format!("Cannot assign to an index of {}", object_val.type_name())),
+        }
+
+        Ok(value)
+    }
+
+    // Applies the transformer named `transformer` (a built-in or one in scope) to a
+    // value, with the arguments it was given
+    fn apply(&mut self, object_val: Value, name: Symbol, mut arguments: Vec<Value>) -> Result<Value, String> {
+        let transformer = name.as_str();
+
+        // Handle built-in transformers
+        match transformer {
+            "to_string" => {
+                // Bytes are decoded using an optional encoding argument
+                if let Value::Bytes(bytes) = object_val {
+                    let encoding = match arguments.len() {
+                        0 => "utf8".to_string(),
+                        1 => match arguments.remove(0) {
+                            Value::String(s) => s.to_lowercase(),
+                            _ => return Err("Encoding passed to to_string() must be a string".to_string()),
+                        },
+                        _ => return Err("to_string() takes at most 1 argument".to_string()),
+                    };
+
+                    return match encoding.as_str() {
+                        "utf8" | "utf-8" => String::from_utf8(bytes)
+                            .map(|s| Value::String(s.into()))
+                            .map_err(|_| "Bytes are not valid UTF-8".to_string()),
+                        "ascii" if !bytes.is_ascii() => Err("Bytes are not valid ASCII".to_string()),
+                        // Every byte maps to the code point with the same value
+                        "ascii" | "latin1" | "latin-1" => Ok(Value::String(bytes.iter().map(|&b| b as char).collect::<String>().into())),
+                        _ => Err(format!("Unknown encoding '{}'", encoding)),
+                    };
+                }
+
+                // Convert any value to a string
+                match object_val {
+                    Value::Int(n) => Ok(Value::String(n.to_string().into())),
+                    Value::BigInt(n) => Ok(Value::String(n.to_string().into())),
+                    Value::Decimal(d) => Ok(Value::String(d.to_string().into())),
+                    Value::Number(n) => Ok(Value::String(n.to_string().into())),
+                    Value::String(s) => Ok(Value::String(s)),
+                    Value::Boolean(b) => Ok(Value::String(if b { "true".into() } else { "false".into() })),
+                    Value::Array(arr) => {
+                        let mut result = String::new();
+                        for (i, val) in arr.borrow().iter().enumerate() {
+                            if i > 0 {
+                                result.push_str(", ");
+                            }
+                            match val {
+                                Value::String(s) => result.push_str(s),
+                                _ => result.push_str(&val.to_string()),
+                            }
+                        }
+                        Ok(Value::String(result.into()))
                     },
-                    None => {
-                        return Ok(Value::Nil);
+                    Value::Map(_) | Value::Set(_) | Value::Tuple(_) | Value::Range { .. } | Value::Bytes(_) | Value::Result(_) => Ok(Value::String(object_val.to_string().into())),
+                    Value::Function { .. } | Value::NativeFunction(_) | Value::Builtin(_) => Ok(Value::String("[Function]".into())),
+                    Value::Task(_) | Value::Sender(_) | Value::Receiver(_) | Value::Native(_) => Ok(Value::String(object_val.to_string().into())),
+                    Value::Transformer { .. } | Value::Composed(_) => Ok(Value::String("[Transformer]".into())),
+                    Value::Nil => Ok(Value::String("nil".into())),
+                }
+            },
+            "to_number" => {
+                // Convert a value to a number
+                match object_val {
+                    Value::Int(_) | Value::BigInt(_) | Value::Number(_) => Ok(object_val),
+                    // Decimals become floats, the nearest one to their value
+                    Value::Decimal(d) => Ok(Value::Number(decimal::to_f64(d))),
+                    Value::String(s) => {
+                        match Self::parse_number(&s) {
+                            Some(Value::Number(n)) if self.strict_numbers => Self::finite(n, &format!("\"{}\".to_number()", s)),
+                            Some(n) => Ok(n),
+                            None if self.strict => Err(format!("Cannot convert \"{}\" to a number", s)),
+                            None => {
+                                // Special cases
+                                if &*s == "true" {
+                                    Ok(Value::Number(1.0))
+                                } else {
+                                    Ok(Value::Number(0.0)) // "false" and unparseable strings
+                                }
+                            }
+                        }
                     },
+                    Value::Boolean(b) => Ok(Value::Int(if b { 1 } else { 0 })),
+                    _ if self.strict => Err(format!("Cannot convert {} to a number", object_val.type_name())),
+                    Value::Array(_) | Value::Bytes(_) => Ok(Value::Number(0.0)), // Default for arrays
+                    Value::Map(_) | Value::Set(_) | Value::Tuple(_) | Value::Result(_) => Ok(Value::Number(0.0)),
+                    Value::Range { .. } => Ok(Value::Number(0.0)),
+                    Value::Function { .. } | Value::NativeFunction(_) | Value::Builtin(_) | Value::Native(_) | Value::Task(_) | Value::Sender(_) | Value::Receiver(_) => Ok(Value::Number(0.0)),
+                    Value::Transformer { .. } | Value::Composed(_) => Ok(Value::Number(0.0)),
+                    Value::Nil => Ok(Value::Number(0.0)),
                 }
             },
-            Expr::Index { object, index } => {
-                let object_val = self.evaluate(object)?;
-                let index_val = self.evaluate(index)?;
+            "to_bool" => Ok(Value::Boolean(object_val.is_truthy())),
+            "to_array" => {
+                // Convert a value to an array
+                match object_val {
+                    Value::Array(arr) => Ok(Value::Array(arr)),
+                    // Materialize lazy ranges into real arrays
+                    Value::Range { start, end } => Value::range_to_array(start, end),
+                    Value::Bytes(bytes) => Ok(Value::array(bytes.into_iter().map(|b| Value::Int(b as i64)).collect())),
+                    Value::Set(set) => Ok(Value::array(set.borrow().iter().cloned().collect())),
+                    Value::Tuple(elements) => Ok(Value::array(elements.to_vec())),
+                    _ => Ok(Value::array(vec![object_val])),
+                }
+            },
+            "enumerate" => {
+                // `[a, b].enumerate()` is `[[0, a], [1, b]]`, for `for i, x in ...`
+                if !arguments.is_empty() {
+                    return Err("enumerate() takes no arguments".to_string());
+                }
 
-                match (object_val, index_val) {
-                    (Value::Array(elements), Value::Number(i)) => {
-                        let idx = i as usize;
-                        if idx < elements.len() {
-                            Ok(elements[idx].clone())
-                        } else {
-                            Err(format!("Index out of bounds: {}", idx))
+                match object_val.elements()? {
+                    Some(elements) => Ok(Value::array(
+                        elements.into_iter().enumerate().map(|(i, element)| Value::array(vec![Value::Int(i as i64), element])).collect(),
+                    )),
+                    None => Err(format!("Cannot enumerate {}", object_val.type_name())),
+                }
+            },
+            "to_bytes" => {
+                // Strings are encoded as UTF-8; arrays must hold integers from 0 to 255
+                match object_val {
+                    Value::Bytes(bytes) => Ok(Value::Bytes(bytes)),
+                    Value::String(s) => Ok(Value::Bytes(s.as_bytes().to_vec())),
+                    Value::Array(elements) => {
+                        let mut bytes = Vec::new();
+                        for element in elements.get() {
+                            match element {
+                                Value::Int(n) if (0..=255).contains(&n) => bytes.push(n as u8),
+                                _ => return Err(format!("Cannot convert {} to a byte", element)),
+                            }
                         }
+                        Ok(Value::Bytes(bytes))
                     },
-                    _ => Err("Cannot index non-array type".to_string()),
+                    _ => Err(format!("Cannot convert {} to bytes", object_val.type_name())),
+                }
+            },
+            "to_base64" | "to_hex" => {
+                // Strings are encoded from their UTF-8 bytes
+                let bytes = match object_val {
+                    Value::Bytes(bytes) => bytes,
+                    Value::String(s) => s.as_bytes().to_vec(),
+                    _ => return Err(format!("{}() can only be applied to strings and bytes", transformer)),
+                };
+
+                if transformer == "to_base64" {
+                    Ok(Value::String(encoding::to_base64(&bytes).into()))
+                } else {
+                    Ok(Value::String(encoding::to_hex(&bytes).into()))
                 }
             },
-            Expr::Block(expressions) => {
-                let mut result = Value::Nil;
+            "from_base64" | "from_hex" => {
+                // Decoding yields bytes; use to_string() to get text back
+                let text = match object_val {
+                    Value::String(s) => s,
+                    _ => return Err(format!("{}() can only be applied to strings", transformer)),
+                };
 
-                for expr in expressions {
-                    result = self.evaluate(expr)?;
+                if transformer == "from_base64" {
+                    Ok(Value::Bytes(encoding::from_base64(&text)?))
+                } else {
+                    Ok(Value::Bytes(encoding::from_hex(&text)?))
+                }
+            },
+            "parallel_map" => {
+                // `arr.parallel_map(f)` calls a function (or applies a transformer)
+                // to every element, spreading the work across threads
+                if arguments.len() != 1 {
+                    return Err("parallel_map() takes exactly 1 argument".to_string());
                 }
 
-                Ok(result)
+                let elements = match object_val {
+                    Value::Array(elements) => elements.get(),
+                    Value::Range { start, end } => Value::range_elements(start, end)?,
+                    _ => return Err("parallel_map() can only be applied to arrays".to_string()),
+                };
+
+                let function = arguments.remove(0);
+                if !matches!(function, Value::Function { .. } | Value::NativeFunction(_) | Value::Builtin(_)) && !function.is_transformer() {
+                    return Err("Argument to parallel_map() must be a function or transformer".to_string());
+                }
+
+                self.parallel_map(elements, &function).map(Value::array)
             },
-            Expr::If { condition, then_branch, else_branch } => {
-                let condition_val = self.evaluate(condition)?;
+            "sort_by" | "sort_key" => {
+                // `arr.sort_by(compare)` orders the elements by a function of two
+                // elements; `arr.sort_key(key)` by a function of one, comparing
+                // the keys with `<`. Both give a new array, and equal elements
+                // keep their order.
+                if arguments.len() != 1 {
+                    return Err(format!("{}() takes exactly 1 argument", transformer));
+                }
+
+                let Some(elements) = object_val.elements()? else {
+                    return Err(format!("Cannot sort {}", object_val.type_name()));
+                };
+
+                let function = arguments.remove(0);
+                if !matches!(function, Value::Function { .. } | Value::NativeFunction(_) | Value::Builtin(_)) && !function.is_transformer() {
+                    return Err(format!("Argument to {}() must be a function or transformer", transformer));
+                }
+
+                if transformer == "sort_by" {
+                    let sorted = self.merge_sort(elements, &mut |interpreter, a, b| {
+                        match interpreter.call_with(&function, a.clone(), vec![b.clone()])? {
+                            Value::Int(n) => Ok(n < 0),
+                            Value::Number(n) => Ok(n < 0.0),
+                            Value::Decimal(d) => Ok(d.is_sign_negative() && !d.is_zero()),
+                            Value::BigInt(n) => Ok(n.to_f64() < 0.0),
+                            other => Err(format!("Comparator passed to sort_by() must return a number, got {}", other.type_name())),
+                        }
+                    })?;
+                    return Ok(Value::array(sorted));
+                }
 
-                match condition_val {
-                    Value::Boolean(true) => self.evaluate(then_branch),
-                    Value::Boolean(false) => else_branch.as_ref().map_or(Ok(Value::Nil), |branch| self.evaluate(branch)),
-                    _ => Err("Condition must be a boolean value".to_string()),
+                // Each key is worked out once
+                let mut keyed = Vec::with_capacity(elements.len());
+                for element in elements {
+                    keyed.push((self.call_with(&function, element.clone(), Vec::new())?, element));
                 }
+                let sorted = self.merge_sort(keyed, &mut |interpreter, (a, _), (b, _)| {
+                    match interpreter.operate(a.clone(), TokenType::LessThan, b.clone()) {
+                        Ok(Value::Boolean(less)) => Ok(less),
+                        Ok(other) => Err(format!("Comparing keys in sort_key() gave {}, not a boolean", other.type_name())),
+                        Err(_) => Err(format!("sort_key() cannot compare {} with {}", a.type_name(), b.type_name())),
+                    }
+                })?;
+                Ok(Value::array(sorted.into_iter().map(|(_, element)| element).collect()))
             },
-            Expr::For { variable, iterable, body } => {
-                let iterable_val = self.evaluate(iterable)?;
+            // Aggregates; other values fall through to transformers of the same name
+            "sum" | "avg" | "min" | "max" if matches!(object_val, Value::Array(_) | Value::Set(_) | Value::Tuple(_) | Value::Range { .. }) => {
+                if !arguments.is_empty() {
+                    return Err(format!("{}() takes no arguments", transformer));
+                }
 
-                match iterable_val {
-                    Value::Array(elements) => {
-                        let mut result = Value::Nil;
+                let elements = object_val.elements()?.unwrap_or_default();
+                if let Some((index, element)) = elements.iter().enumerate()
+                    .find(|(_, element)| !matches!(element, Value::Int(_) | Value::BigInt(_) | Value::Number(_) | Value::Decimal(_))) {
+                    return Err(format!("{}() needs numbers, got {} at index {}", transformer, element.type_name(), index));
+                }
+                if elements.is_empty() && transformer != "sum" {
+                    return Err(format!("Cannot take {}() of an empty {}", transformer, object_val.type_name()));
+                }
+
+                let mut elements = elements.into_iter();
+                match transformer {
+                    "sum" | "avg" => {
+                        let count = elements.len() as i64;
+                        let mut total = elements.next().unwrap_or(Value::Int(0));
                         for element in elements {
-                            let mut environment = Environment::new_with_enclosing(Some(Box::new(self.environment.clone())));
-                            environment.define(variable.clone(), element.clone());
-                            self.environment = environment;
-                            result = self.evaluate(body)?;
-                            self.environment = *self.environment.enclosing.clone().unwrap();
+                            total = self.operate(total, TokenType::Plus, element)?;
+                        }
+                        if transformer == "sum" {
+                            return Ok(total);
                         }
-                        Ok(result)
+                        self.operate(total, TokenType::Divide, Value::Int(count))
                     },
-                    Value::String(s) => {
-                        // Make strings iterable by character
-                        let mut result = Value::Nil;
-                        for c in s.chars() {
-                            let mut environment = Environment::new_with_enclosing(Some(Box::new(self.environment.clone())));
-                            environment.define(variable.clone(), Value::String(c.to_string()));
-                            self.environment = environment;
-                            result = self.evaluate(body)?;
-                            self.environment = *self.environment.enclosing.clone().unwrap();
+                    // The first of equal extremes wins
+                    _ => {
+                        let better = match transformer {
+                            "min" => TokenType::LessThan,
+                            _ => TokenType::GreaterThan,
+                        };
+                        let mut best = elements.next().unwrap_or(Value::Nil);
+                        for element in elements {
+                            if matches!(self.operate(element.clone(), better, best.clone())?, Value::Boolean(true)) {
+                                best = element;
+                            }
                         }
-                        Ok(result)
+                        Ok(best)
                     },
-                    _ => Err(format!("Cannot iterate over non-iterable value: {:?}", iterable_val)),
                 }
             },
-            Expr::While { condition, body } => {
-                loop {
-                    let condition_val = self.evaluate(condition)?;
-                    
-                    match condition_val {
-                        Value::Boolean(true) => {
-                            self.evaluate(body)?;
-                        },
-                        Value::Boolean(false) => {
-                            break;
-                        },
-                        _ => return Err("Condition must be a boolean value".to_string()),
-                    }
+            // Results; other values fall through to transformers of the same name
+            "is_ok" | "is_err" | "unwrap" | "unwrap_or" | "unwrap_err" if matches!(object_val, Value::Result(_)) => {
+                let expected = if transformer == "unwrap_or" { 1 } else { 0 };
+                if arguments.len() != expected {
+                    return Err(match expected {
+                        0 => format!("{}() takes no arguments", transformer),
+                        _ => format!("{}() takes exactly 1 argument", transformer),
+                    });
+                }
+
+                let Value::Result(result) = object_val else {
+                    unreachable!("matched a result above");
+                };
+                match (transformer, result.into_inner()) {
+                    ("is_ok", result) => Ok(Value::Boolean(result.is_ok())),
+                    ("is_err", result) => Ok(Value::Boolean(result.is_err())),
+                    ("unwrap" | "unwrap_or", Ok(value)) | ("unwrap_err", Err(value)) => Ok(value),
+                    ("unwrap_or", Err(_)) => Ok(arguments.remove(0)),
+                    (_, Ok(value)) => Err(format!("unwrap_err() of ok({})", value)),
+                    (_, Err(error)) => Err(format!("unwrap() of err({})", error)),
                 }
-                
-                Ok(Value::Nil)
             },
-            Expr::Transformer { name, params, body } => {
-                let transformer = Value::Transformer {
-                    params: params.clone(),
-                    body: body.clone(),
+            "group_by" | "unique" | "flatten" | "chunk" => {
+                let expected = if matches!(transformer, "group_by" | "chunk") { 1 } else { 0 };
+                if arguments.len() != expected {
+                    return Err(match expected {
+                        0 => format!("{}() takes no arguments", transformer),
+                        _ => format!("{}() takes exactly 1 argument", transformer),
+                    });
+                }
+
+                let Some(elements) = object_val.elements()? else {
+                    return Err(format!("Cannot apply {}() to {}", transformer, object_val.type_name()));
                 };
-                
-                self.environment.define(name.clone(), transformer.clone());
-                
-                Ok(transformer)
-            },
-            Expr::Apply { object, transformer, arguments } => {
-                let object_val = self.evaluate(object)?;
-                
-                // Handle built-in transformers
-                match transformer.as_str() {
-                    "to_string" => {
-                        // Convert any value to a string
-                        match object_val {
-                            Value::Number(n) => Ok(Value::String(n.to_string())),
-                            Value::String(s) => Ok(Value::String(s)),
-                            Value::Boolean(b) => Ok(Value::String(if b { "true".to_string() } else { "false".to_string() })),
-                            Value::Array(arr) => {
-                                let mut result = String::new();
-                                for (i, val) in arr.iter().enumerate() {
-                                    if i > 0 {
-                                        result.push_str(", ");
-                                    }
-                                    match val {
-                                        Value::String(s) => result.push_str(s),
-                                        _ => result.push_str(&val.to_string()),
-                                    }
-                                }
-                                Ok(Value::String(result))
-                            },
-                            Value::Function { .. } => Ok(Value::String("[Function]".to_string())),
-                            Value::Transformer { .. } => Ok(Value::String("[Transformer]".to_string())),
-                            Value::Nil => Ok(Value::String("nil".to_string())),
+
+                match transformer {
+                    // `arr.group_by(f)` maps each key f returns to the elements it
+                    // was returned for, in their original order
+                    "group_by" => {
+                        let function = arguments.remove(0);
+                        if !matches!(function, Value::Function { .. } | Value::NativeFunction(_) | Value::Builtin(_)) && !function.is_transformer() {
+                            return Err("Argument to group_by() must be a function or transformer".to_string());
+                        }
+
+                        let mut groups: BTreeMap<String, Vec<Value>> = BTreeMap::new();
+                        for element in elements {
+                            match self.call_with(&function, element.clone(), Vec::new())? {
+                                Value::String(key) => groups.entry(key.to_string()).or_default().push(element),
+                                key => return Err(format!("group_by() keys must be strings, got {}; convert them with to_string()", key.type_name())),
+                            }
                         }
+                        Ok(Value::map(groups.into_iter().map(|(key, group)| (key, Value::array(group))).collect()))
                     },
-                    "to_number" => {
-                        // Convert a value to a number
-                        match object_val {
-                            Value::Number(n) => Ok(Value::Number(n)),
-                            Value::String(s) => {
-                                // Try to parse the string as a number
-                                match s.parse::<f64>() {
-                                    Ok(n) => Ok(Value::Number(n)),
-                                    Err(_) => {
-                                        // Special cases
-                                        if s == "true" {
-                                            Ok(Value::Number(1.0))
-                                        } else if s == "false" {
-                                            Ok(Value::Number(0.0))
-                                        } else {
-                                            Ok(Value::Number(0.0)) // Default for unparseable strings
-                                        }
-                                    }
-                                }
-                            },
-                            Value::Boolean(b) => Ok(Value::Number(if b { 1.0 } else { 0.0 })),
-                            Value::Array(_) => Ok(Value::Number(0.0)), // Default for arrays
-                            Value::Function { .. } => Ok(Value::Number(0.0)),
-                            Value::Transformer { .. } => Ok(Value::Number(0.0)),
-                            Value::Nil => Ok(Value::Number(0.0)),
+                    // Keeps the first of each group of equal (`==`) elements
+                    "unique" => {
+                        let mut seen = Set::default();
+                        let mut unique: Vec<Value> = Vec::new();
+                        for element in elements {
+                            // Containers and NaN can't go in a set, so are compared one by one
+                            let new = match seen.insert(element.clone()) {
+                                Ok(new) => new,
+                                Err(_) => !unique.contains(&element),
+                            };
+                            if new {
+                                unique.push(element);
+                            }
+                        }
+                        Ok(Value::array(unique))
+                    },
+                    // Spreads out arrays and tuples nested one level deep
+                    "flatten" => {
+                        let mut flat = Vec::with_capacity(elements.len());
+                        for element in elements {
+                            match element {
+                                Value::Array(inner) => flat.extend(inner.get()),
+                                Value::Tuple(inner) => flat.extend(inner.iter().cloned()),
+                                element => flat.push(element),
+                            }
                         }
+                        Ok(Value::array(flat))
+                    },
+                    // Arrays of `n` elements each; the last may be shorter
+                    _ => {
+                        let size = match arguments.remove(0).as_index() {
+                            Some(size) if size > 0 => size,
+                            _ => return Err("Size passed to chunk() must be a positive integer".to_string()),
+                        };
+                        Ok(Value::array(elements.chunks(size).map(|chunk| Value::array(chunk.to_vec())).collect()))
+                    },
+                }
+            },
+            "slice" => {
+                // `x.slice(start, end)` returns the elements from start up to (not including) end
+                if arguments.is_empty() || arguments.len() > 2 {
+                    return Err("slice() takes 1 or 2 arguments".to_string());
+                }
+
+                let length = match &object_val {
+                    Value::Bytes(bytes) => bytes.len(),
+                    Value::Array(elements) => elements.borrow().len(),
+                    Value::String(s) => s.chars().count(),
+                    _ => return Err(format!("Cannot slice {}", object_val.type_name())),
+                };
+
+                let mut bounds = Vec::new();
+                for argument in arguments {
+                    match argument.as_index() {
+                        Some(bound) => bounds.push(bound),
+                        None => return Err("Arguments to slice() must be non-negative integers".to_string()),
+                    }
+                }
+                let start = bounds[0];
+                let end = bounds.get(1).copied().unwrap_or(length);
+                if start > end || end > length {
+                    return Err(format!("Slice {}..{} out of bounds for length {}", start, end, length));
+                }
+
+                match object_val {
+                    Value::Bytes(bytes) => Ok(Value::Bytes(bytes[start..end].to_vec())),
+                    Value::Array(elements) => Ok(Value::array(elements.borrow()[start..end].to_vec())),
+                    Value::String(s) => Ok(Value::String(s.chars().skip(start).take(end - start).collect::<String>().into())),
+                    _ => unreachable!(),
+                }
+            },
+            "trim" | "upper" | "lower" => {
+                if !arguments.is_empty() {
+                    return Err(format!("{}() takes no arguments", transformer));
+                }
+
+                match object_val {
+                    Value::String(s) => Ok(Value::String(match transformer {
+                        "trim" => s.trim().to_string(),
+                        "upper" => s.to_uppercase(),
+                        _ => s.to_lowercase(),
+                    }.into())),
+                    _ => Err(format!("Cannot apply {}() to {}", transformer, object_val.type_name())),
+                }
+            },
+            "split" => {
+                // `s.split(sep)` returns the pieces between separators; an empty
+                // separator splits a string into its characters
+                if arguments.len() != 1 {
+                    return Err("split() takes exactly 1 argument".to_string());
+                }
+
+                let separator = match arguments.remove(0) {
+                    Value::String(separator) => separator,
+                    _ => return Err("Separator passed to split() must be a string".to_string()),
+                };
+                let s = match object_val {
+                    Value::String(s) => s,
+                    _ => return Err(format!("Cannot apply split() to {}", object_val.type_name())),
+                };
+
+                let pieces: Vec<Value> = if separator.is_empty() {
+                    s.chars().map(|c| Value::String(c.to_string().into())).collect()
+                } else {
+                    s.split(&*separator).map(|piece| Value::String(piece.into())).collect()
+                };
+                Ok(Value::array(pieces))
+            },
+            "join" => {
+                // `arr.join(sep)` undoes split(): the elements with a separator
+                // between each pair. Other values join as `+` would write them.
+                if arguments.len() != 1 {
+                    return Err("join() takes exactly 1 argument".to_string());
+                }
+
+                let separator = match arguments.remove(0) {
+                    Value::String(separator) => separator,
+                    _ => return Err("Separator passed to join() must be a string".to_string()),
+                };
+                let elements = match object_val {
+                    Value::Array(_) | Value::Set(_) | Value::Tuple(_) | Value::Range { .. } => object_val.elements()?.unwrap_or_default(),
+                    _ => return Err(format!("Cannot apply join() to {}", object_val.type_name())),
+                };
+
+                let pieces: Vec<String> = elements.iter().map(Value::to_string).collect();
+                Ok(Value::String(pieces.join(&separator).into()))
+            },
+            "lines" => {
+                // `s.lines()` splits at "\n" or "\r\n"; a final line ending doesn't
+                // start another line, so "a\nb\n" has the lines "a" and "b"
+                if !arguments.is_empty() {
+                    return Err("lines() takes no arguments".to_string());
+                }
+
+                match object_val {
+                    Value::String(s) => Ok(Value::array(s.lines().map(|line| Value::String(line.into())).collect())),
+                    _ => Err(format!("Cannot apply lines() to {}", object_val.type_name())),
+                }
+            },
+            // Strings are sequences of Unicode scalar values ("characters"):
+            // `len()`, `chars()`, indexing and slice() all count in those, and
+            // `bytes()` gives the UTF-8 encoding
+            "chars" | "bytes" => {
+                if !arguments.is_empty() {
+                    return Err(format!("{}() takes no arguments", transformer));
+                }
+
+                match object_val {
+                    Value::String(s) if transformer == "chars" => Ok(Value::array(s.chars().map(|c| Value::String(c.to_string().into())).collect())),
+                    Value::String(s) => Ok(Value::Bytes(s.as_bytes().to_vec())),
+                    _ => Err(format!("Cannot apply {}() to {}", transformer, object_val.type_name())),
+                }
+            },
+            "len" => {
+                if !arguments.is_empty() {
+                    return Err("len() takes no arguments".to_string());
+                }
+
+                let length = match &object_val {
+                    Value::String(s) => s.chars().count(),
+                    Value::Array(elements) => elements.borrow().len(),
+                    Value::Bytes(bytes) => bytes.len(),
+                    Value::Map(entries) => entries.borrow().len(),
+                    Value::Set(set) => set.borrow().len(),
+                    Value::Tuple(elements) => elements.len(),
+                    // A range can be longer than the largest Int
+                    Value::Range { start, end } => {
+                        let length = (*end as i128 - *start as i128).max(0);
+                        return match i64::try_from(length) {
+                            Ok(length) => Ok(Value::Int(length)),
+                            Err(_) => bigint::parse(&length.to_string())
+                                .ok_or_else(|| format!("The length of {} is too large for an integer", object_val)),
+                        };
                     },
-                    "to_bool" => {
-                        // Convert a value to a boolean
+                    _ => return Err(format!("Cannot apply len() to {}", object_val.type_name())),
+                };
+                Ok(Value::Int(length as i64))
+            },
+            "parse_number" => {
+                // Parse a string to a number
+                match object_val {
+                    Value::String(s) => match Self::parse_number(&s) {
+                        Some(Value::Number(n)) if self.strict_numbers => Self::finite(n, &format!("\"{}\".parse_number()", s)),
+                        Some(n) => Ok(n),
+                        None if self.strict => Err(format!("Cannot parse \"{}\" as a number", s)),
+                        None => Ok(Value::Number(0.0)), // Default for unparseable strings
+                    },
+                    Value::Int(_) | Value::BigInt(_) | Value::Number(_) => Ok(object_val),
+                    _ if self.strict => Err(format!("Cannot parse {} as a number", object_val.type_name())),
+                    _ => Ok(Value::Number(0.0)),
+                }
+            },
+            "parse_bool" => {
+                // Parse a string to a boolean
+                match object_val {
+                    Value::String(s) if self.strict && !matches!(&*s, "true" | "1" | "yes" | "false" | "0" | "no") => {
+                        Err(format!("Cannot parse \"{}\" as a boolean", s))
+                    },
+                    Value::String(s) => {
+                        Ok(Value::Boolean(matches!(&*s, "true" | "1" | "yes")))
+                    },
+                    Value::Boolean(b) => Ok(Value::Boolean(b)),
+                    _ => {
+                        // Use the to_bool logic for other types
                         match object_val {
+                            Value::Int(n) => Ok(Value::Boolean(n != 0)),
                             Value::Number(n) => Ok(Value::Boolean(n != 0.0)),
-                            Value::String(s) => {
-                                // Empty string, "false", and "0" are false, everything else is true
-                                Ok(Value::Boolean(!(s.is_empty() || s == "false" || s == "0")))
-                            },
-                            Value::Boolean(b) => Ok(Value::Boolean(b)),
-                            Value::Array(arr) => Ok(Value::Boolean(!arr.is_empty())),
-                            Value::Function { .. } => Ok(Value::Boolean(true)),
-                            Value::Transformer { .. } => Ok(Value::Boolean(true)),
+                            Value::Array(arr) => Ok(Value::Boolean(!arr.borrow().is_empty())),
+                            Value::Map(entries) => Ok(Value::Boolean(!entries.borrow().is_empty())),
+                            Value::Set(set) => Ok(Value::Boolean(!set.borrow().is_empty())),
+                            Value::Range { start, end } => Ok(Value::Boolean(start < end)),
+                            Value::Function { .. } | Value::Task(_) | Value::Sender(_) | Value::Receiver(_) => Ok(Value::Boolean(true)),
+                            Value::Native(_) | Value::NativeFunction(_) | Value::Builtin(_) => Ok(Value::Boolean(true)),
+                            Value::Transformer { .. } | Value::Composed(_) => Ok(Value::Boolean(true)),
                             Value::Nil => Ok(Value::Boolean(false)),
+                            _ => Ok(Value::Boolean(false)), // Default case
                         }
                     },
-                    "to_array" => {
-                        // Convert a value to an array
-                        match object_val {
-                            Value::Array(arr) => Ok(Value::Array(arr)),
-                            _ => Ok(Value::Array(vec![object_val])),
-                        }
+                }
+            },
+            // Type tests
+            "is_number" => Ok(Value::Boolean(matches!(object_val, Value::Int(_) | Value::BigInt(_) | Value::Number(_) | Value::Decimal(_)))),
+            "is_integer" => Ok(Value::Boolean(matches!(object_val, Value::Int(_) | Value::BigInt(_)))),
+            "is_string" => Ok(Value::Boolean(matches!(object_val, Value::String(_)))),
+            "is_boolean" => Ok(Value::Boolean(matches!(object_val, Value::Boolean(_)))),
+            "is_array" => Ok(Value::Boolean(matches!(object_val, Value::Array(_)))),
+            "is_map" => Ok(Value::Boolean(matches!(object_val, Value::Map(_)))),
+            "is_set" => Ok(Value::Boolean(matches!(object_val, Value::Set(_)))),
+            "is_tuple" => Ok(Value::Boolean(matches!(object_val, Value::Tuple(_)))),
+            "is_function" => Ok(Value::Boolean(matches!(object_val, Value::Function { .. } | Value::NativeFunction(_) | Value::Builtin(_)))),
+            "is_transformer" => Ok(Value::Boolean(object_val.is_transformer())),
+            "is_nil" => Ok(Value::Boolean(matches!(object_val, Value::Nil))),
+            // Integers are always finite; only floats can be NaN or infinite
+            "is_nan" => Ok(Value::Boolean(matches!(object_val, Value::Number(n) if n.is_nan()))),
+            "is_infinite" => Ok(Value::Boolean(matches!(object_val, Value::Number(n) if n.is_infinite()))),
+            "is_finite" => Ok(Value::Boolean(match object_val {
+                Value::Int(_) | Value::BigInt(_) | Value::Decimal(_) => true,
+                Value::Number(n) => n.is_finite(),
+                _ => false,
+            })),
+            // Number formatting
+            "round" | "to_fixed" | "to_precision" => {
+                let n = match &object_val {
+                    Value::Int(n) => *n as f64,
+                    Value::Number(n) => *n,
+                    Value::Decimal(d) => decimal::to_f64(*d),
+                    _ => return Err(format!("{}() can only be applied to numbers", transformer)),
+                };
+
+                let digits = match arguments.len() {
+                    0 if transformer == "round" => 0,
+                    1 => match arguments.remove(0) {
+                        Value::Int(d) => d,
+                        _ => return Err(format!("Argument to {}() must be an integer", transformer)),
                     },
-                    "parse_number" => {
-                        // Parse a string to a number
-                        match object_val {
-                            Value::String(s) => {
-                                match s.parse::<f64>() {
-                                    Ok(n) => Ok(Value::Number(n)),
-                                    Err(_) => Ok(Value::Number(0.0)), // Default for unparseable strings
-                                }
-                            },
-                            Value::Number(n) => Ok(Value::Number(n)),
-                            _ => Ok(Value::Number(0.0)),
+                    _ => return Err(format!("{}() takes exactly 1 argument", transformer)),
+                };
+
+                match transformer {
+                    "round" => {
+                        // Decimals round exactly and stay decimals
+                        if let Value::Decimal(d) = object_val {
+                            return decimal::round(d, digits).map(Value::Decimal);
                         }
-                    },
-                    "parse_bool" => {
-                        // Parse a string to a boolean
-                        match object_val {
-                            Value::String(s) => {
-                                Ok(Value::Boolean(s == "true" || s == "1" || s == "yes"))
-                            },
-                            Value::Boolean(b) => Ok(Value::Boolean(b)),
-                            _ => {
-                                // Use the to_bool logic for other types
-                                match object_val {
-                                    Value::Number(n) => Ok(Value::Boolean(n != 0.0)),
-                                    Value::Array(arr) => Ok(Value::Boolean(!arr.is_empty())),
-                                    Value::Function { .. } => Ok(Value::Boolean(true)),
-                                    Value::Transformer { .. } => Ok(Value::Boolean(true)),
-                                    Value::Nil => Ok(Value::Boolean(false)),
-                                    _ => Ok(Value::Boolean(false)), // Default case
-                                }
-                            },
+                        let rounded = format::round_to(n, digits.clamp(-308, 308) as i32);
+                        // Rounding to a whole number gives an integer
+                        if digits <= 0 && rounded.abs() < i64::MAX as f64 {
+                            Ok(Value::Int(rounded as i64))
+                        } else {
+                            Ok(Value::Number(rounded))
                         }
                     },
-                    "to_json" => {
-                        // Convert a value to its JSON string representation
-                        match object_val {
-                            Value::String(s) => Ok(Value::String(format!("\"{}\"", s))),
-                            Value::Number(n) => Ok(Value::String(n.to_string())),
-                            Value::Boolean(b) => Ok(Value::String(if b { "true".to_string() } else { "false".to_string() })),
-                            Value::Array(arr) => {
-                                let mut result = String::from("[");
-                                for (i, val) in arr.iter().enumerate() {
-                                    if i > 0 {
-                                        result.push_str(",");
-                                    }
-                                    
-                                    // Recursively convert each item to JSON
-                                    let json_val = match val {
-                                        Value::String(s) => format!("\"{}\"", s),
-                                        Value::Number(n) => n.to_string(),
-                                        Value::Boolean(b) => if *b { "true".to_string() } else { "false".to_string() },
-                                        Value::Array(_) => "[...]".to_string(), // Simplified for nested arrays
-                                        _ => "null".to_string(),
-                                    };
-                                    
-                                    result.push_str(&json_val);
-                                }
-                                result.push_str("]");
-                                Ok(Value::String(result))
-                            },
-                            Value::Function { .. } => Ok(Value::String("null".to_string())),
-                            Value::Transformer { .. } => Ok(Value::String("null".to_string())),
-                            Value::Nil => Ok(Value::String("null".to_string())),
+                    "to_fixed" => {
+                        if !(0..=100).contains(&digits) {
+                            return Err("to_fixed() digits must be between 0 and 100".to_string());
+                        }
+                        if let Value::Decimal(d) = object_val {
+                            return Ok(Value::String(decimal::to_fixed(d, digits as usize).into()));
                         }
+                        Ok(Value::String(format!("{:.*}", digits as usize, format::round_to(n, digits as i32)).into()))
                     },
                     _ => {
-                        // Look up the transformer in the environment
-                        if let Some(Value::Transformer { params, body }) = self.environment.get(transformer) {
-                            // Create a new environment for the transformer execution
-                            let mut env = Environment::new_with_enclosing(Some(Box::new(self.environment.clone())));
-                            
-                            // Define the special 'applied' variable with the object value
-                            env.define("applied".to_string(), object_val.clone());
-                            
-                            // Define parameters
-                            for (i, param) in params.iter().enumerate() {
-                                let arg_value = if i < arguments.len() {
-                                    self.evaluate(&arguments[i])?
-                                } else {
-                                    Value::Nil
-                                };
-                                
-                                env.define(param.clone(), arg_value);
-                            }
-                            
-                            // Save the current environment
-                            let old_env = self.environment.clone();
-                            
-                            // Set the new environment
-                            self.environment = env;
-                            
-                            // Execute the transformer body
-                            let mut result = Value::Nil;
-                            
-                            for expr in body.iter() {
-                                result = self.evaluate(expr)?;
-                                
-                                // Handle return statements
-                                if let Expr::Return { .. } = expr {
-                                    break;
-                                }
-                            }
-                            
-                            // Restore the old environment
-                            self.environment = old_env;
-                            
-                            // Update the original object with the result
-                            if let Expr::Variable(name) = &**object {
-                                self.environment.assign(name, result.clone())?;
-                            }
-                            
-                            Ok(result)
-                        } else {
-                            Err(format!("Undefined transformer '{}'", transformer))
+                        if !(1..=100).contains(&digits) {
+                            return Err("to_precision() digits must be between 1 and 100".to_string());
                         }
-                    }
+                        Ok(Value::String(format::to_precision(n, digits as usize).into()))
+                    },
                 }
             },
-            Expr::Use { path } => {
-                // Check if file has already been imported to prevent circular imports
-                if self.imported_files.contains(path) {
-                    return Ok(Value::Nil); // Skip already imported files
+            // Maps become objects and nested values convert at any depth
+            "to_json" => Ok(Value::String(json::to_json(&object_val)?.into())),
+            "to_json_pretty" => Ok(Value::String(json::to_json_pretty(&object_val)?.into())),
+            "from_json" => match object_val {
+                Value::String(text) => Ok(Value::result(json::from_json(&text))),
+                _ => Err("from_json() can only be applied to strings".to_string()),
+            },
+            // Set operations; other values fall through to transformers of the same name
+            "add" | "contains" | "union" | "intersect" | "difference" if matches!(object_val, Value::Set(_)) => {
+                if arguments.len() != 1 {
+                    return Err(format!("{}() takes exactly 1 argument", transformer));
                 }
-                
-                // Add to imported files list
-                self.imported_files.push(path.clone());
-                
-                // Resolve the path
-                let file_path = if let Some(base_path) = &self.base_path {
-                    base_path.join(path)
-                } else {
-                    PathBuf::from(path)
-                };
-                
-                // Read the file
-                let content = match fs::read_to_string(&file_path) {
-                    Ok(content) => content,
-                    Err(e) => return Err(format!("Failed to read file '{}': {}", file_path.display(), e)),
+
+                let Value::Set(set) = &object_val else { unreachable!() };
+                let argument = arguments.remove(0);
+                match transformer {
+                    // Adds to the set in place, and gives the set back for chaining
+                    "add" => {
+                        set.borrow_mut().insert(argument)?;
+                        Ok(object_val)
+                    },
+                    "contains" => Ok(Value::Boolean(set.borrow().contains(&argument))),
+                    _ => {
+                        let other = match argument {
+                            Value::Set(other) => other.get(),
+                            values => Self::set_of(&values)?,
+                        };
+                        let set = set.borrow();
+                        Ok(Value::set(match transformer {
+                            "union" => set.union(&other),
+                            "intersect" => set.intersection(&other),
+                            _ => set.difference(&other),
+                        }))
+                    },
+                }
+            },
+            "then" => {
+                // Compose two transformers: `a.then(b)` applies a, then b
+                if arguments.len() != 1 {
+                    return Err("then() takes exactly 1 argument".to_string());
+                }
+
+                let next = arguments.remove(0);
+                if !object_val.is_transformer() || !next.is_transformer() {
+                    return Err("then() can only compose transformers".to_string());
+                }
+
+                // Flatten nested compositions into a single list of stages
+                let mut stages = match object_val {
+                    Value::Composed(stages) => stages,
+                    transformer => vec![transformer],
                 };
-                
+                match next {
+                    Value::Composed(next_stages) => stages.extend(next_stages),
+                    transformer => stages.push(transformer),
+                }
+
+                Ok(Value::Composed(stages))
+            },
+            "apply" => {
+                // Apply a transformer held in a value: `x.apply(t, args...)`
+                if arguments.is_empty() {
+                    return Err("apply() takes a transformer and its arguments".to_string());
+                }
+
+                let transformer_val = arguments.remove(0);
+                self.apply_transformer(transformer_val, object_val, arguments)
+            },
+            _ => {
+                // Look up the transformer in the environment
+                match self.environment.get(name) {
+                    Some(transformer_val) if transformer_val.is_transformer() => {
+                        // Application is pure; `x .= t()` is the form that stores the result
+                        self.apply_transformer(transformer_val, object_val, arguments)
+                    },
+                    _ => Err(format!("Undefined transformer '{}'", transformer)),
+                }
+            }
+        }
+    }
+
+    // Applies a transformer value (a defined transformer or a composition) to an object
+    fn apply_transformer(&mut self, transformer: Value, object_val: Value, arguments: Vec<Value>) -> Result<Value, String> {
+        match transformer {
+            Value::Transformer { receiver, params, layout, body } => {
+                if self.call_depth >= MAX_CALL_DEPTH {
+                    return Err(format!("Maximum call depth of {} exceeded", MAX_CALL_DEPTH));
+                }
+
+                self.check_arity("Transformer", params.len(), arguments.len())?;
+
+                // Run the body in a scope of its own on top of the current one,
+                // with the slots the parser laid out for it
+                self.enter_call(layout, None);
+                self.define_module_scope(&body);
+
+                // The object is `applied`, unless the transformer names it
+                self.environment.define(receiver, object_val);
+
+                // Define parameters
+                let mut arguments = arguments.into_iter();
+                for param in params.iter() {
+                    self.environment.define(*param, arguments.next().unwrap_or(Value::Nil));
+                }
+
+                self.call_depth += 1;
+
+                // Execute the transformer body
+                let result = self.run_body(body.iter());
+
+                self.call_depth -= 1;
+                self.leave_scope();
+
+                result
+            },
+            Value::Composed(stages) => {
+                if !arguments.is_empty() {
+                    return Err("Composed transformers take no arguments".to_string());
+                }
+
+                // Feed each stage's result into the next one
+                let mut result = object_val;
+                for stage in stages {
+                    result = self.apply_transformer(stage, result, Vec::new())?;
+                }
+
+                Ok(result)
+            },
+            _ => Err(format!("Cannot apply non-transformer value: {}", transformer)),
+        }
+    }
+
+    fn lock_random(&self) -> Result<MutexGuard<'_, Random>, String> {
+        self.random.lock().map_err(|_| "Random number generator is poisoned".to_string())
+    }
+
+    fn lock_modules(&self) -> Result<MutexGuard<'_, ModuleRegistry>, String> {
+        self.modules.lock().map_err(|_| "Module registry is poisoned".to_string())
+    }
+
+    fn canonical_module_path(&self, path: &str) -> Result<PathBuf, String> {
+        let file_path = self.resolve_path(path);
+        let error = match self.host.canonicalize(&file_path) {
+            Ok(canonical) => return Ok(canonical),
+            Err(e) => format!("Failed to read file '{}': {}", file_path.display(), e),
+        };
+
+        // Then the module directories, in order
+        self.module_paths
+            .iter()
+            .find_map(|dir| self.host.canonicalize(&dir.join(path)).ok())
+            .ok_or(error)
+    }
+
+    // `use path`: loads the module once and defines its exports in the current scope
+    fn import(&mut self, path: &str) -> Result<(), String> {
+        let module_path = self.canonical_module_path(path)?;
+
+        // A file still running further up this chain, the main file included, imports itself
+        if self.importing.contains(&module_path) {
+            return Err(self.import_cycle(&module_path, &[]));
+        }
+
+        // Modules are loaded once; later imports reuse their exports
+        let exports = loop {
+            let mut modules = self.lock_modules()?;
+            match modules.get(&module_path).map(|module| module.exports.clone()) {
+                Some(Some(exports)) => break exports,
+                // Another task is loading it, so wait until it has. If it failed,
+                // this import loads it again and reports the error itself.
+                Some(None) => {
+                    if let Some(cycle) = self.waiting_cycle(&modules, &module_path) {
+                        return Err(cycle);
+                    }
+                    let loading = self.importing.last().cloned().unwrap_or_default();
+                    modules.wait_for(&loading, Some(module_path.clone()));
+                    let mut modules = self.module_loaded.wait(modules).map_err(|_| "Module registry is poisoned".to_string())?;
+                    modules.wait_for(&loading, None);
+                },
+                None => {
+                    modules.start(module_path.clone());
+                    drop(modules);
+                    break self.load_module(&module_path)?;
+                },
+            }
+        };
+
+        for (name, value) in exports {
+            self.environment.define(name, value);
+        }
+        Ok(())
+    }
+
+    // Runs a module whose registry entry has been started, and records its
+    // exports, or forgets it if it fails so that a later `use` tries again
+    fn load_module(&mut self, module_path: &Path) -> Result<Vec<(Symbol, Value)>, String> {
+        let loaded = self.run_module(module_path);
+        let mut modules = self.lock_modules()?;
+        match &loaded {
+            Ok(exports) => modules.finish(module_path, exports.clone()),
+            Err(_) => {
+                modules.remove(module_path);
+            },
+        }
+        drop(modules);
+        self.module_loaded.notify_all();
+        loaded
+    }
+
+    // Parses and runs a module, returning its exports
+    fn run_module(&mut self, module_path: &Path) -> Result<Vec<(Symbol, Value)>, String> {
+        // Read the file
+        let content = match self.host.read_file(module_path).map(String::from_utf8) {
+            Ok(Ok(content)) => content,
+            Ok(Err(e)) => return Err(format!("Failed to read file '{}': {}", module_path.display(), e)),
+            Err(e) => return Err(format!("Failed to read file '{}': {}", module_path.display(), e)),
+        };
+
+        let cached = if self.cache { cache::load(module_path, &content, self.optimize) } else { None };
+        let ast = match cached {
+            Some(ast) => ast,
+            None => {
                 // Tokenize
                 let lexer = Lexer::new(&content);
-                let tokens = match lexer.tokenize() {
+                let tokens = match lexer.collect::<Result<Vec<_>, LexError>>() {
                     Ok(tokens) => tokens,
-                    Err(e) => return Err(format!("Failed to tokenize file '{}': {}", file_path.display(), e)),
+                    Err(e) => {
+                        let error = format!("Failed to tokenize file '{}': {}", module_path.display(), e);
+                        return Err(self.failed_in(module_path, (e.line, e.column), error));
+                    },
                 };
-                
+
                 // Parse
                 let mut parser = Parser::new(tokens);
                 let ast = match parser.parse() {
                     Ok(ast) => ast,
-                    Err(e) => return Err(format!("Failed to parse file '{}': {}", file_path.display(), e)),
-                };
-                
-                // Create a new interpreter with the same environment
-                let mut file_interpreter = Interpreter {
-                    environment: self.environment.clone(),
-                    _globals: self._globals.clone(),
-                    imported_files: self.imported_files.clone(),
-                    base_path: if let Some(base_path) = &self.base_path {
-                        Some(base_path.clone())
-                    } else {
-                        // If the file has a parent directory, use that as the base path
-                        file_path.parent().map(|p| p.to_path_buf())
+                    Err(e) => {
+                        let error = format!("Failed to parse file '{}': {}", module_path.display(), e);
+                        return Err(self.failed_in(module_path, parser.position(), error));
                     },
                 };
-                
-                // Evaluate the imported file
-                match file_interpreter.evaluate(&ast) {
-                    Ok(_) => {
-                        // Copy all variables and functions from the file's environment to our environment
-                        for (name, value) in file_interpreter.get_variables() {
-                            self.environment.define(name, value);
-                        }
-                        Ok(Value::Nil)
+
+                let ast = if self.optimize { Optimizer::optimize(ast) } else { ast };
+                if self.cache {
+                    cache::store(module_path, &content, self.optimize, &ast);
+                }
+                ast
+            },
+        };
+
+
+        // Run the module in a scope of its own, which sees only the builtins:
+        // what the importer has defined can't change what the module does
+        let mut file_interpreter = self.fork();
+        file_interpreter.environment = Environment::new_with_enclosing(Some(Box::new(self.environment.outermost().clone())));
+        file_interpreter.importing.push(module_path.to_path_buf());
+        file_interpreter.file = Some(module_path.into());
+        if file_interpreter.base_path.is_none() {
+            // If the file has a parent directory, use that as the base path
+            file_interpreter.base_path = module_path.parent().map(|p| p.to_path_buf());
+        }
+
+        if let Err(e) = file_interpreter.evaluate(&ast) {
+            let error = format!("Error evaluating file '{}': {}", module_path.display(), e);
+            // The error happened in the module, so it keeps the position found there
+            if let Some(location) = file_interpreter.take_error_location(&e) {
+                self.error_at = Some((error.clone(), location));
+            }
+            return Err(error);
+        }
+
+        // Export everything bound in the module's scope, in order of name: its
+        // own definitions, however they were made, and what its imports exported
+        let mut exports: Vec<(Symbol, Value)> = std::mem::take(&mut file_interpreter.environment.values).into_iter().collect();
+        exports.sort_by_key(|(name, _)| *name);
+
+        // A module that marks definitions `pub` exports only those. The rest stay
+        // visible to the module's own functions and transformers when they run.
+        // A reloaded module's new definitions replace those of the old version.
+        let mut bodies = Vec::new();
+        let mut private: Arc<[(Symbol, Value)]> = Arc::default();
+        if let Some(public) = module::public_names(&ast) {
+            let (public, hidden): (Vec<_>, Vec<_>) = exports.into_iter().partition(|(name, _)| public.contains(name));
+            private = hidden.into();
+            let defined = module::top_level_names(&ast);
+            for (name, value) in public.iter().chain(private.iter()) {
+                if let (true, Value::Function { body, .. } | Value::Transformer { body, .. }) = (defined.contains(name), value) {
+                    bodies.push(body.clone());
+                }
+            }
+            exports = public;
+        }
+        self.module_scopes.write().unwrap_or_else(PoisonError::into_inner).replace(module_path, bodies, private);
+
+        Ok(exports)
+    }
+
+    // An error found reading a module, at a position in its file
+    fn failed_in(&mut self, module_path: &Path, (line, column): (usize, usize), error: String) -> String {
+        let location = Location { file: Some(module_path.to_path_buf()), line, column };
+        self.error_at = Some((error.clone(), location));
+        error
+    }
+
+    // Describes the chain of imports from a module back to itself, through
+    // `others` (modules other tasks are loading) when the cycle leaves this chain
+    fn import_cycle(&self, module_path: &Path, others: &[PathBuf]) -> String {
+        let start = self.importing.iter().position(|path| path == module_path).unwrap_or(0);
+        let chain: Vec<String> = self.importing[start..]
+            .iter()
+            .chain(others)
+            .chain(std::iter::once(&module_path.to_path_buf()))
+            .map(|path| {
+                let relative = self.base_path.as_deref().and_then(|base_path| path.strip_prefix(base_path).ok());
+                relative.unwrap_or(path).display().to_string()
+            })
+            .collect();
+        format!("Import cycle: {}", chain.join(" -> "))
+    }
+
+    // Waiting for another task to load `module_path` would never end if that
+    // task is itself waiting, perhaps through further tasks, for a module this
+    // chain is loading. Returns that cycle.
+    fn waiting_cycle(&self, modules: &ModuleRegistry, module_path: &Path) -> Option<String> {
+        let mut others = vec![module_path.to_path_buf()];
+        let mut waiting_for = modules.get(module_path)?.waiting_for.clone();
+        while let Some(path) = waiting_for {
+            if self.importing.contains(&path) {
+                return Some(self.import_cycle(&path, &others));
+            }
+            // Tasks waiting for each other without this one
+            if others.contains(&path) {
+                return None;
+            }
+            waiting_for = modules.get(&path).and_then(|module| module.waiting_for.clone());
+            others.push(path);
+        }
+        None
+    }
+
+    // Relative paths in `use` and file builtins resolve against the interpreter's base path
+    fn resolve_path(&self, path: &str) -> PathBuf {
+        match &self.base_path {
+            Some(base_path) => base_path.join(path),
+            None => PathBuf::from(path),
+        }
+    }
+
+    // Missing arguments are nil and extra ones are ignored, unless in strict mode
+    fn check_arity(&self, what: &str, expected: usize, given: usize) -> Result<(), String> {
+        if self.strict && expected != given {
+            let plural = if expected == 1 { "" } else { "s" };
+            return Err(format!("{} expects {} argument{}, got {}", what, expected, plural, given));
+        }
+        Ok(())
+    }
+
+    // Decides whether a condition holds: booleans as they are, other values by
+    // their truthiness unless conditions are strict
+    fn condition(&self, value: Value) -> Result<bool, String> {
+        match value {
+            Value::Boolean(b) => Ok(b),
+            _ if self.strict_conditions => Err(format!("Condition must be a boolean value, got {}", value.type_name())),
+            _ => Ok(value.is_truthy()),
+        }
+    }
+
+    // The variables a `for` loop binds to one element; with two variables the
+    // element must be a pair
+    fn loop_bindings(variable: Symbol, second: Option<Symbol>, element: Value) -> Result<Vec<(Symbol, Value)>, String> {
+        let Some(second) = second else {
+            return Ok(vec![(variable, element)]);
+        };
+
+        match &element {
+            Value::Array(pair) if pair.borrow().len() == 2 => {
+                let pair = pair.get();
+                Ok(vec![(variable, pair[0].clone()), (second, pair[1].clone())])
+            },
+            Value::Tuple(pair) if pair.len() == 2 => {
+                Ok(vec![(variable, pair[0].clone()), (second, pair[1].clone())])
+            },
+            _ => Err(format!("Cannot unpack {} into '{}, {}'", element, variable, second)),
+        }
+    }
+
+    // Runs the body of a function or transformer, which evaluates to its last
+    // expression unless it returns earlier
+    fn run_body<'e>(&mut self, body: impl Iterator<Item = &'e Expr>) -> Result<Value, String> {
+        let mut result = Value::Nil;
+        for expr in body {
+            match self.evaluate(expr) {
+                Ok(value) => result = value,
+                Err(e) => {
+                    return match self.unwinding.take() {
+                        Some(Unwind::Return(value)) => Ok(value),
+                        // A `break` or `continue` can't leave the function
+                        _ => Err(e),
+                    };
+                },
+            }
+        }
+        Ok(result)
+    }
+
+    // Starts a `break`, `continue` or `return` on its way out
+    fn unwind(&mut self, signal: Unwind, message: &str) -> String {
+        self.unwinding = Some(signal);
+        message.to_string()
+    }
+
+    // Decides how the loop labeled `label` continues after its body evaluated to `result`
+    fn flow(&mut self, result: Result<Value, String>, label: &Option<String>) -> Result<Flow, String> {
+        let targets = |target: &Option<String>| target.is_none() || target == label;
+
+        match result {
+            Ok(value) => Ok(Flow::Next(value)),
+            Err(e) => match self.unwinding.take() {
+                Some(Unwind::Break(target, value)) if targets(&target) => Ok(Flow::Break(value)),
+                Some(Unwind::Continue(target)) if targets(&target) => Ok(Flow::Next(Value::Nil)),
+                // A `return`, or a signal for an outer loop, keeps going
+                signal => {
+                    self.unwinding = signal;
+                    Err(e)
+                },
+            },
+        }
+    }
+
+    // Pushes a block scope with the given bindings. Definitions made inside the
+    // block disappear when it's left, while assignments to names from enclosing
+    // scopes persist because they update those scopes in place.
+    fn enter_scope(&mut self, bindings: Vec<(Symbol, Value)>) {
+        let enclosing = std::mem::replace(&mut self.environment, Environment::new());
+        self.environment = Environment::new_with_enclosing(Some(Box::new(enclosing)));
+
+        for (name, value) in bindings {
+            self.environment.define(name, value);
+        }
+    }
+
+    // Pushes the scope of a call onto the current chain. Nothing is copied:
+    // the call's changes to its caller's variables are kept in its own scope.
+    fn enter_call(&mut self, layout: Arc<Layout>, captured: Option<Arc<Environment>>) {
+        let caller = std::mem::replace(&mut self.environment, Environment::new());
+        self.environment = Environment::new_call(caller, layout, captured);
+    }
+
+    // Pops the innermost scope, a block's or a call's
+    fn leave_scope(&mut self) {
+        let mut scope = std::mem::replace(&mut self.environment, Environment::new());
+        self.environment = *scope.enclosing.take().expect("scope without an enclosing environment");
+    }
+
+    // Assigns to a variable, defining it in the current scope if it doesn't exist
+    fn store(&mut self, name: Symbol, value: Value) -> Result<(), String> {
+        if self.environment.get(name).is_some() {
+            self.environment.assign(name, value)
+        } else if self.require_let {
+            Err(format!("Assignment to undeclared variable '{}' (declare it with 'let')", name))
+        } else {
+            self.environment.define(name, value);
+            Ok(())
+        }
+    }
+
+    // Finishes `s = s + x + ...` (see appending()) once every operand could be
+    // appended: in place, if the variable still holds the string or array it
+    // started with and nothing else shares it
+    fn append_in_place(&mut self, name: Symbol, current: Value, additions: Vec<Value>) -> Value {
+        // The variable must still hold the same value after evaluating the operands
+        match (self.environment.get_mut(name), &current) {
+            (Some(Value::String(text)), Value::String(original)) if text.ptr_eq(original) => {
+                drop(current);
+                match text.get_mut() {
+                    Some(string) => Self::push_strings(string, &additions),
+                    None => {
+                        let mut string = text.to_string();
+                        Self::push_strings(&mut string, &additions);
+                        *text = string.into();
                     },
-                    Err(e) => Err(format!("Error evaluating file '{}': {}", file_path.display(), e)),
                 }
+                Value::String(text.clone())
+            },
+            (Some(Value::Array(elements)), Value::Array(original)) if elements.ptr_eq(original) => {
+                drop(current);
+                // An array no other value refers to can't be told apart from a new one
+                if !elements.is_unique() {
+                    return Self::concatenate(&Value::Array(elements.clone()), &additions);
+                }
+                // Extending the vector reuses its spare capacity
+                let mut contents = elements.borrow_mut();
+                for addition in &additions {
+                    if let Value::Array(addition) = addition {
+                        contents.extend(addition.get());
+                    }
+                }
+                drop(contents);
+                Value::Array(elements.clone())
+            },
+            _ => Self::concatenate(&current, &additions),
+        }
+    }
+
+    // Adds values to a string or array as `+` does, leaving it unchanged
+    fn concatenate(value: &Value, additions: &[Value]) -> Value {
+        match value {
+            Value::Array(elements) => {
+                let mut elements = elements.get();
+                for addition in additions {
+                    if let Value::Array(addition) = addition {
+                        elements.extend(addition.get());
+                    }
+                }
+                Value::array(elements)
+            },
+            value => {
+                let mut string = value.to_string();
+                Self::push_strings(&mut string, additions);
+                Value::String(string.into())
             },
         }
     }
 
-    fn evaluate_binary(&mut self, left: &Expr, operator: &Token, right: &Expr) -> Result<Value, String> {
-        let left_val = self.evaluate(left)?;
-        let right_val = self.evaluate(right)?;
+    // The number a string holds: an integer if it is one, otherwise a float
+    fn parse_number(s: &str) -> Option<Value> {
+        if let Ok(n) = s.parse::<i64>() {
+            return Some(Value::Int(n));
+        }
+        if let Some(n) = bigint::parse(s) {
+            return Some(n);
+        }
+        s.parse::<f64>().ok().map(Value::Number)
+    }
+
+    // The distinct elements of an array, range, string or bytes value, or a copy of a set
+    fn set_of(values: &Value) -> Result<Set, String> {
+        match values.elements()? {
+            Some(elements) => Set::from_values(elements),
+            None => Err(format!("Cannot make a set from {}", values.type_name())),
+        }
+    }
 
-        match operator.token_type {
+    fn push_strings(string: &mut String, additions: &[Value]) {
+        for addition in additions {
+            match addition {
+                Value::String(addition) => string.push_str(addition),
+                addition => string.push_str(&addition.to_string()),
+            }
+        }
+    }
+
+    // Applies a binary operator to operands that have already been evaluated
+    fn operate(&mut self, left_val: Value, operator: TokenType, right_val: Value) -> Result<Value, String> {
+        // Maps can define their own operators through specially named transformers
+        if matches!(left_val, Value::Map(_)) || matches!(right_val, Value::Map(_)) {
+            if let Some(result) = self.overloaded_operator(operator, &left_val, &right_val)? {
+                return Ok(result);
+            }
+        }
+
+        if matches!(left_val, Value::BigInt(_)) || matches!(right_val, Value::BigInt(_)) {
+            if let Some(result) = bigint::operate(operator, &left_val, &right_val) {
+                return result;
+            }
+        }
+        if matches!(left_val, Value::Decimal(_)) || matches!(right_val, Value::Decimal(_)) {
+            if let Some(result) = decimal::operate(operator, &left_val, &right_val) {
+                return result;
+            }
+        }
+
+        let result = match operator {
             // Arithmetic operators
             TokenType::Plus => {
                 match (&left_val, &right_val) {
-                    (Value::Number(l), Value::Number(r)) => Ok(Value::Number(l + r)),
-                    (Value::String(l), Value::String(r)) => Ok(Value::String(l.clone() + r)),
-                    (Value::String(l), _) => Ok(Value::String(l.clone() + &right_val.to_string())),
-                    (_, Value::String(r)) => Ok(Value::String(left_val.to_string() + r)),
+                    (Value::Int(l), Value::Int(r)) => l.checked_add(*r).map(Value::Int).map_or_else(|| Self::overflow(operator, &left_val, &right_val), Ok),
+                    (Value::String(l), Value::String(r)) => Ok(Value::String(format!("{}{}", l, r).into())),
+                    (Value::String(l), _) => Ok(Value::String(format!("{}{}", l, right_val).into())),
+                    (_, Value::String(r)) => Ok(Value::String((left_val.to_string() + r).into())),
                     (Value::Array(l), Value::Array(r)) => {
-                        let mut elements = l.clone();
-                        elements.extend(r.clone());
-                        Ok(Value::Array(elements))
+                        // Concatenation builds a new array; neither operand is changed
+                        let mut elements = l.get();
+                        elements.extend(r.get());
+                        Ok(Value::array(elements))
+                    },
+                    _ => match Self::float_operands(&left_val, &right_val) {
+                        Some((l, r)) => Ok(Value::Number(l + r)),
+                        None => Err(format!("Invalid operands for operator: {:?}", operator)),
                     },
-                    _ => Err(format!("Invalid operands for operator: {:?}", operator.token_type)),
                 }
             },
             TokenType::Minus => {
                 match (&left_val, &right_val) {
-                    (Value::Number(l), Value::Number(r)) => Ok(Value::Number(l - r)),
-                    _ => Err(format!("Invalid operands for operator: {:?}", operator.token_type)),
+                    (Value::Int(l), Value::Int(r)) => l.checked_sub(*r).map(Value::Int).map_or_else(|| Self::overflow(operator, &left_val, &right_val), Ok),
+                    _ => match Self::float_operands(&left_val, &right_val) {
+                        Some((l, r)) => Ok(Value::Number(l - r)),
+                        None => Err(format!("Invalid operands for operator: {:?}", operator)),
+                    },
                 }
             },
             TokenType::Multiply => {
                 match (&left_val, &right_val) {
-                    (Value::Number(l), Value::Number(r)) => Ok(Value::Number(l * r)),
-                    _ => Err(format!("Invalid operands for operator: {:?}", operator.token_type)),
+                    (Value::Int(l), Value::Int(r)) => l.checked_mul(*r).map(Value::Int).map_or_else(|| Self::overflow(operator, &left_val, &right_val), Ok),
+                    _ => match Self::float_operands(&left_val, &right_val) {
+                        Some((l, r)) => Ok(Value::Number(l * r)),
+                        None => Err(format!("Invalid operands for operator: {:?}", operator)),
+                    },
                 }
             },
             TokenType::Divide => {
-                match (&left_val, &right_val) {
-                    (Value::Number(l), Value::Number(r)) => {
-                        if *r == 0.0 {
+                // `/` is always true division and produces a float, even for two integers
+                match Self::float_operands(&left_val, &right_val) {
+                    Some((l, r)) => {
+                        if r == 0.0 {
                             Err("Division by zero".to_string())
                         } else {
                             Ok(Value::Number(l / r))
                         }
                     },
-                    _ => Err(format!("Invalid operands for operator: {:?}", operator.token_type)),
+                    None => Err(format!("Invalid operands for operator: {:?}", operator)),
+                }
+            },
+            TokenType::IntDivide => {
+                // `~/` is floored division; it stays an integer when both operands are integers
+                match (&left_val, &right_val) {
+                    (Value::Int(_), Value::Int(0)) => Err("Division by zero".to_string()),
+                    (Value::Int(l), Value::Int(r)) => Self::floored_divide(*l, *r).map(Value::Int).map_or_else(|| Self::overflow(operator, &left_val, &right_val), Ok),
+                    _ => match Self::float_operands(&left_val, &right_val) {
+                        Some((_, 0.0)) => Err("Division by zero".to_string()),
+                        Some((l, r)) => Ok(Value::Number((l / r).floor())),
+                        None => Err(format!("Invalid operands for operator: {:?}", operator)),
+                    },
                 }
             },
             TokenType::Modulo => {
                 match (&left_val, &right_val) {
-                    (Value::Number(l), Value::Number(r)) => {
-                        if *r == 0.0 {
-                            Err("Modulo by zero".to_string())
-                        } else {
-                            // Use the rem_euclid method for proper floating-point modulo
-                            let result = l.rem_euclid(*r);
-                            Ok(Value::Number(result))
-                        }
+                    (Value::Int(_), Value::Int(0)) => Err("Modulo by zero".to_string()),
+                    (Value::Int(l), Value::Int(r)) => Self::floored_modulo(*l, *r).map(Value::Int).map_or_else(|| Self::overflow(operator, &left_val, &right_val), Ok),
+                    _ => match Self::float_operands(&left_val, &right_val) {
+                        Some((l, r)) => {
+                            if r == 0.0 {
+                                Err("Modulo by zero".to_string())
+                            } else {
+                                // The remainder of floored division, with the divisor's sign
+                                let remainder = l % r;
+                                let result = if remainder != 0.0 && (remainder < 0.0) != (r < 0.0) { remainder + r } else { remainder };
+                                Ok(Value::Number(result))
+                            }
+                        },
+                        None => Err(format!("Invalid operands for operator: {:?}", operator)),
                     },
-                    _ => Err(format!("Invalid operands for operator: {:?}", operator.token_type)),
                 }
             },
             // Comparison operators
             TokenType::LessThan => {
                 match (&left_val, &right_val) {
-                    (Value::Number(l), Value::Number(r)) => Ok(Value::Boolean(l < r)),
-                    _ => Err(format!("Invalid operands for operator: {:?}", operator.token_type)),
+                    (Value::Int(l), Value::Int(r)) => Ok(Value::Boolean(l < r)),
+                    (Value::String(l), Value::String(r)) => Ok(Value::Boolean(l < r)),
+                    _ => match Self::float_operands(&left_val, &right_val) {
+                        Some((l, r)) => Ok(Value::Boolean(l < r)),
+                        None => Err(format!("Invalid operands for operator: {:?}", operator)),
+                    },
                 }
             },
             TokenType::LessThanEqual => {
                 match (&left_val, &right_val) {
-                    (Value::Number(l), Value::Number(r)) => Ok(Value::Boolean(l <= r)),
-                    _ => Err(format!("Invalid operands for operator: {:?}", operator.token_type)),
+                    (Value::Int(l), Value::Int(r)) => Ok(Value::Boolean(l <= r)),
+                    (Value::String(l), Value::String(r)) => Ok(Value::Boolean(l <= r)),
+                    _ => match Self::float_operands(&left_val, &right_val) {
+                        Some((l, r)) => Ok(Value::Boolean(l <= r)),
+                        None => Err(format!("Invalid operands for operator: {:?}", operator)),
+                    },
                 }
             },
             TokenType::GreaterThan => {
                 match (&left_val, &right_val) {
-                    (Value::Number(l), Value::Number(r)) => Ok(Value::Boolean(l > r)),
-                    _ => Err(format!("Invalid operands for operator: {:?}", operator.token_type)),
+                    (Value::Int(l), Value::Int(r)) => Ok(Value::Boolean(l > r)),
+                    (Value::String(l), Value::String(r)) => Ok(Value::Boolean(l > r)),
+                    _ => match Self::float_operands(&left_val, &right_val) {
+                        Some((l, r)) => Ok(Value::Boolean(l > r)),
+                        None => Err(format!("Invalid operands for operator: {:?}", operator)),
+                    },
                 }
             },
             TokenType::GreaterThanEqual => {
                 match (&left_val, &right_val) {
-                    (Value::Number(l), Value::Number(r)) => Ok(Value::Boolean(l >= r)),
-                    _ => Err(format!("Invalid operands for operator: {:?}", operator.token_type)),
-                }
-            },
-            TokenType::EqualEqual => {
-                match (&left_val, &right_val) {
-                    (Value::Number(l), Value::Number(r)) => Ok(Value::Boolean(l == r)),
-                    (Value::String(l), Value::String(r)) => Ok(Value::Boolean(l == r)),
-                    (Value::Boolean(l), Value::Boolean(r)) => Ok(Value::Boolean(l == r)),
-                    (Value::Nil, Value::Nil) => Ok(Value::Boolean(true)),
-                    _ => Ok(Value::Boolean(false)),
-                }
-            },
-            TokenType::BangEqual => {
-                match (&left_val, &right_val) {
-                    (Value::Number(l), Value::Number(r)) => Ok(Value::Boolean(l != r)),
-                    (Value::String(l), Value::String(r)) => Ok(Value::Boolean(l != r)),
-                    (Value::Boolean(l), Value::Boolean(r)) => Ok(Value::Boolean(l != r)),
-                    (Value::Nil, Value::Nil) => Ok(Value::Boolean(false)),
-                    _ => Ok(Value::Boolean(true)),
+                    (Value::Int(l), Value::Int(r)) => Ok(Value::Boolean(l >= r)),
+                    (Value::String(l), Value::String(r)) => Ok(Value::Boolean(l >= r)),
+                    _ => match Self::float_operands(&left_val, &right_val) {
+                        Some((l, r)) => Ok(Value::Boolean(l >= r)),
+                        None => Err(format!("Invalid operands for operator: {:?}", operator)),
+                    },
                 }
             },
+            TokenType::EqualEqual => Ok(Value::Boolean(left_val == right_val)),
+            TokenType::BangEqual => Ok(Value::Boolean(left_val != right_val)),
             // Logical operators
             TokenType::And => {
                 match (&left_val, &right_val) {
                     (Value::Boolean(l), Value::Boolean(r)) => Ok(Value::Boolean(*l && *r)),
-                    _ => Err(format!("Invalid operands for operator: {:?}", operator.token_type)),
+                    _ => Err(format!("Invalid operands for operator: {:?}", operator)),
                 }
             },
             TokenType::Or => {
                 match (&left_val, &right_val) {
                     (Value::Boolean(l), Value::Boolean(r)) => Ok(Value::Boolean(*l || *r)),
-                    _ => Err(format!("Invalid operands for operator: {:?}", operator.token_type)),
+                    _ => Err(format!("Invalid operands for operator: {:?}", operator)),
                 }
             },
-            _ => Err(format!("Unknown operator: {:?}", operator.token_type)),
+            _ => Err(format!("Unknown operator: {:?}", operator)),
+        };
+
+        match result {
+            Ok(Value::Number(n)) if self.strict_numbers => Self::finite(n, &format!("The result of {}", operator.operator_text().unwrap_or("an operator"))),
+            result => result,
+        }
+    }
+
+    // Integer arithmetic that doesn't fit an i64 continues as a BigInt, when
+    // the interpreter is built with them
+    fn overflow(operator: TokenType, left: &Value, right: &Value) -> Result<Value, String> {
+        bigint::operate(operator, left, right).unwrap_or_else(|| Err("Integer overflow".to_string()))
+    }
+
+    // With --strict-numbers, NaN and infinities are errors rather than results
+    fn finite(n: f64, what: &str) -> Result<Value, String> {
+        if n.is_nan() {
+            Err(format!("{} is NaN", what))
+        } else if n.is_infinite() {
+            Err(format!("{} is infinite", what))
+        } else {
+            Ok(Value::Number(n))
+        }
+    }
+
+    // Dispatches an operator to a user-defined operator transformer such as
+    // `transformer __add(other)`, with the left operand as `applied`. Returns
+    // None when no transformer is defined, so the built-in behavior applies.
+    fn overloaded_operator(&mut self, operator: TokenType, left: &Value, right: &Value) -> Result<Option<Value>, String> {
+        let name = match operator {
+            TokenType::Plus => "__add",
+            TokenType::Minus => "__sub",
+            TokenType::Multiply => "__mul",
+            TokenType::Divide => "__div",
+            TokenType::IntDivide => "__intdiv",
+            TokenType::Modulo => "__mod",
+            TokenType::EqualEqual => "__eq",
+            TokenType::BangEqual => "__ne",
+            TokenType::LessThan => "__lt",
+            TokenType::LessThanEqual => "__le",
+            TokenType::GreaterThan => "__gt",
+            TokenType::GreaterThanEqual => "__ge",
+            _ => return Ok(None),
+        };
+
+        if let Some(transformer) = Symbol::existing(name).and_then(|name| self.environment.get(name)).filter(Value::is_transformer) {
+            return self.apply_transformer(transformer, left.clone(), vec![right.clone()]).map(Some);
+        }
+
+        // Without a dedicated __ne, != is the negation of __eq
+        if operator == TokenType::BangEqual {
+            return match self.overloaded_operator(TokenType::EqualEqual, left, right)? {
+                Some(Value::Boolean(equal)) => Ok(Some(Value::Boolean(!equal))),
+                Some(_) => Err("__eq must return a boolean".to_string()),
+                None => Ok(None),
+            };
         }
+
+        Ok(None)
+    }
+
+    // Promotes a pair of numeric operands (integers or floats) to floats
+    fn float_operands(left: &Value, right: &Value) -> Option<(f64, f64)> {
+        Some((left.as_number()?, right.as_number()?))
+    }
+
+    // `~/` rounds the quotient down and `%` is the matching remainder, which
+    // has the sign of the divisor: `7 ~/ -2` is -4 and `7 % -2` is -1. None
+    // when the result overflows.
+    fn floored_divide(l: i64, r: i64) -> Option<i64> {
+        let quotient = l.checked_div(r)?;
+        Some(if l % r != 0 && (l < 0) != (r < 0) { quotient - 1 } else { quotient })
     }
 
-    fn evaluate_unary(&mut self, operator: &Token, right: &Expr) -> Result<Value, String> {
-        let right_val = self.evaluate(right)?;
+    fn floored_modulo(l: i64, r: i64) -> Option<i64> {
+        let remainder = l.checked_rem(r)?;
+        Some(if remainder != 0 && (remainder < 0) != (r < 0) { remainder + r } else { remainder })
+    }
 
-        match operator.token_type {
+    // Applies a unary operator to an operand that has already been evaluated
+    fn unary(operator: TokenType, right_val: Value) -> Result<Value, String> {
+        match operator {
             // Negation
             TokenType::Minus => match right_val {
+                Value::Int(n) => n.checked_neg().map(Value::Int).map_or_else(|| Self::overflow(TokenType::Minus, &Value::Int(0), &right_val), Ok),
+                Value::BigInt(n) => Ok(n.negate()),
+                Value::Decimal(d) => Ok(Value::Decimal(-d)),
                 Value::Number(n) => Ok(Value::Number(-n)),
-                _ => Err(format!("Invalid operand for unary operator: {:?}", operator.token_type)),
+                _ => Err(format!("Invalid operand for unary operator: {:?}", operator)),
             },
             // Logical NOT
             TokenType::Not => match right_val {
                 Value::Boolean(b) => Ok(Value::Boolean(!b)),
-                _ => Err(format!("Invalid operand for unary operator: {:?}", operator.token_type)),
+                _ => Err(format!("Invalid operand for unary operator: {:?}", operator)),
             },
-            _ => Err(format!("Unknown unary operator: {:?}", operator.token_type)),
+            _ => Err(format!("Unknown unary operator: {:?}", operator)),
         }
     }
 
-    fn call(&mut self, callee: &str, arguments: &[Expr]) -> Result<Value, String> {
-        // Handle built-in functions
-        if callee == "print" {
-            if arguments.len() != 1 {
-                return Err("print() takes exactly 1 argument".to_string());
-            }
+    // Runs a function body with its parameters bound; missing arguments are nil
+    fn call_function(&mut self, params: &[Symbol], layout: &Arc<Layout>, body: &[Expr], captured: Option<&Arc<Environment>>, arguments: Vec<Value>) -> Result<Value, String> {
+        if self.call_depth >= MAX_CALL_DEPTH {
+            return Err(format!("Maximum call depth of {} exceeded", MAX_CALL_DEPTH));
+        }
 
-            let value = self.evaluate(&arguments[0])?;
+        self.check_arity("Function", params.len(), arguments.len())?;
 
-            // Print without quotes for strings
-            match &value {
-                Value::String(s) => println!("{}", s),
-                _ => println!("{}", value),
-            }
+        // Run the body in a scope of its own on top of the current one, with
+        // the slots the parser laid out for it
+        self.enter_call(layout.clone(), captured.cloned());
+        self.define_module_scope(body);
 
-            return Ok(Value::Nil);
-        } else if callee == "input" {
-            if arguments.len() != 1 {
-                return Err("input() takes exactly 1 argument".to_string());
-            }
+        // Define parameters
+        let mut arguments = arguments.into_iter();
+        for param in params {
+            self.environment.define(*param, arguments.next().unwrap_or(Value::Nil));
+        }
 
-            let prompt = match self.evaluate(&arguments[0])? {
-                Value::String(s) => s,
-                _ => return Err("Argument to input() must be a string".to_string()),
-            };
+        self.call_depth += 1;
 
-            // Print the prompt without a newline
-            print!("{}", prompt);
-            io::stdout().flush().unwrap(); // Ensure the prompt is displayed immediately
-
-            // Read user input
-            let mut input = String::new();
-            match io::stdin().read_line(&mut input) {
-                Ok(_) => {
-                    // Trim the trailing newline
-                    let input = input.trim_end().to_string();
-                    return Ok(Value::String(input));
-                },
-                Err(e) => return Err(format!("Failed to read input: {}", e)),
-            }
-        } else if callee == "range" {
-            if arguments.len() != 2 {
-                return Err("range() takes exactly 2 arguments".to_string());
-            }
+        // Execute the function body
+        let result = self.run_body(body.iter());
 
-            let start = match self.evaluate(&arguments[0])? {
-                Value::Number(n) => n as i32,
-                _ => return Err("First argument to range() must be a number".to_string()),
-            };
+        self.call_depth -= 1;
+        self.leave_scope();
 
-            let end = match self.evaluate(&arguments[1])? {
-                Value::Number(n) => n as i32,
-                _ => return Err("Second argument to range() must be a number".to_string()),
-            };
+        result
+    }
 
-            let mut elements = Vec::new();
-            for i in start..end {
-                elements.push(Value::Number(i as f64));
+    // Gives a call of a function from a module that uses `pub` the module's
+    // private definitions; its parameters are defined afterwards, over them
+    fn define_module_scope(&mut self, body: &[Expr]) {
+        let scopes = self.module_scopes.read().unwrap_or_else(PoisonError::into_inner);
+        if let Some(scope) = scopes.get(body) {
+            for (name, value) in scope.iter() {
+                self.environment.define(*name, value.clone());
             }
+        }
+    }
+
+    // Starts running a function on a new thread and returns its task handle
+    fn spawn_task(&self, params: Arc<[Symbol]>, layout: Arc<Layout>, body: Arc<[Expr]>, captured: Option<Arc<Environment>>, arguments: Vec<Value>) -> Result<Value, String> {
+        // The task runs in its own interpreter over a copy of the current
+        // environment, so it can't change variables seen by other tasks
+        let mut task_interpreter = self.fork();
+        let captured = captured.map(|captured| Arc::new(captured.deep_copy()));
+        let arguments: Vec<Value> = arguments.iter().map(Value::deep_copy).collect();
+        let handle = thread::Builder::new()
+            .spawn(move || task_interpreter.call_function(&params, &layout, &body, captured.as_ref(), arguments))
+            .map_err(|e| format!("Failed to spawn task: {}", e))?;
+
+        Ok(Value::Task(Arc::new(Mutex::new(TaskState::Running(handle)))))
+    }
+
+    // Makes the task for a call of an async function. The call waits in the
+    // queue until it's awaited or the program sleeps, and then runs on this
+    // interpreter's thread.
+    fn queue_call(&mut self, params: Arc<[Symbol]>, layout: Arc<Layout>, body: Arc<[Expr]>, captured: Option<Arc<Environment>>, arguments: Vec<Value>) -> Value {
+        let call = AsyncCall { environment: self.environment.clone(), params, layout, body, captured, arguments };
+        let task = Arc::new(Mutex::new(TaskState::Queued(Box::new(call))));
+        self.queued.push_back(task.clone());
+        Value::Task(task)
+    }
 
-            return Ok(Value::Array(elements));
+    // Runs queued async calls in the order they were made until there are none
+    // left or the deadline passes. A call that sleeps runs the ones after it in
+    // the meantime, so calls waiting on timers overlap.
+    fn run_queued(&mut self, deadline: Instant) {
+        while Instant::now() < deadline {
+            let Some(task) = self.queued.pop_front() else { break };
+            // Already run if it was awaited; otherwise its result, or its
+            // error, is kept for whoever awaits it
+            let _ = self.join_task(&task);
+            self.error_at = None;
         }
+    }
 
-        // Look up the function in the environment
-        if let Some(Value::Function { params, body }) = self.environment.get(callee) {
-            // Create a new environment for the function execution
-            let mut env = Environment::new_with_enclosing(Some(Box::new(self.environment.clone())));
+    // Waits for a task to finish, running it first if it's a queued async call.
+    // Other joins of a task on a thread wait on the lock until the result is
+    // cached.
+    fn join_task(&mut self, task: &Mutex<TaskState>) -> Result<Value, String> {
+        let mut state = task.lock().map_err(|_| "Task state is poisoned".to_string())?;
+        let result = match std::mem::replace(&mut *state, TaskState::Started) {
+            TaskState::Running(handle) => handle.join().unwrap_or_else(|_| Err("Task panicked".to_string())),
+            TaskState::Queued(call) => {
+                // Unlocked while it runs, so the call can see its own state
+                drop(state);
+                let result = self.run_async_call(*call);
+                state = task.lock().map_err(|_| "Task state is poisoned".to_string())?;
+                result
+            },
+            // Its caller is paused in sleep() until the current call returns
+            TaskState::Started => return Err("Cannot await a task that is waiting for this one to finish".to_string()),
+            TaskState::Finished(result) => result,
+        };
+        *state = TaskState::Finished(result.clone());
 
-            // Define parameters
-            for (i, param) in params.iter().enumerate() {
-                let arg_value = if i < arguments.len() {
-                    self.evaluate(&arguments[i])?
-                } else {
-                    Value::Nil
-                };
+        result.map_err(|e| format!("Task failed: {}", e))
+    }
+
+    fn run_async_call(&mut self, call: AsyncCall) -> Result<Value, String> {
+        let caller = std::mem::replace(&mut self.environment, call.environment);
+        let result = self.call_function(&call.params, &call.layout, &call.body, call.captured.as_ref(), call.arguments);
+        self.environment = caller;
+        result
+    }
 
-                env.define(param.clone(), arg_value);
+    // Calls a function with the given arguments, or applies a transformer to the
+    // first of them with the rest as its arguments
+    fn call_with(&mut self, function: &Value, first: Value, mut rest: Vec<Value>) -> Result<Value, String> {
+        if function.is_transformer() {
+            return self.apply_transformer(function.clone(), first, rest);
+        }
+        rest.insert(0, first);
+        self.call_value(function, rest)
+    }
+
+    // A stable merge sort for comparisons that call back into M code: `less`
+    // may fail, and needn't be consistent, without the sort panicking
+    fn merge_sort<T>(&mut self, mut items: Vec<T>, less: &mut dyn FnMut(&mut Self, &T, &T) -> Result<bool, String>) -> Result<Vec<T>, String> {
+        if items.len() < 2 {
+            return Ok(items);
+        }
+
+        let right = items.split_off(items.len() / 2);
+        let mut left = self.merge_sort(items, less)?.into_iter().peekable();
+        let mut right = self.merge_sort(right, less)?.into_iter().peekable();
+        let mut merged = Vec::with_capacity(left.len() + right.len());
+        while let (Some(l), Some(r)) = (left.peek(), right.peek()) {
+            // Taking from the left unless the right is strictly less keeps ties in order
+            let next = if less(self, r, l)? { right.next() } else { left.next() };
+            merged.extend(next);
+        }
+        merged.extend(left);
+        merged.extend(right);
+        Ok(merged)
+    }
+
+    // Splits the elements into one contiguous chunk per worker thread, each with
+    // its own forked interpreter, and reassembles the results in order
+    fn parallel_map(&self, elements: Vec<Value>, function: &Value) -> Result<Vec<Value>, String> {
+        let workers = thread::available_parallelism().map_or(4, |n| n.get());
+        let chunk_size = elements.len().div_ceil(workers).max(1);
+
+        thread::scope(|scope| {
+            let handles: Vec<_> = elements
+                .chunks(chunk_size)
+                .map(|chunk| {
+                    let mut worker = self.fork();
+                    scope.spawn(move || {
+                        let mut results = Vec::with_capacity(chunk.len());
+                        for element in chunk {
+                            let result = match function {
+                                Value::Function { params, layout, body, captured, .. } => worker.call_function(params, layout, body, captured.as_ref(), vec![element.deep_copy()])?,
+                                Value::NativeFunction(native) => (native.function)(vec![element.deep_copy()])?,
+                                Value::Builtin(builtin) => (builtin.function)(&mut worker, vec![element.deep_copy()])?,
+                                transformer => worker.apply_transformer(transformer.clone(), element.deep_copy(), Vec::new())?,
+                            };
+                            results.push(result);
+                        }
+                        Ok(results)
+                    })
+                })
+                .collect();
+
+            let mut results = Vec::with_capacity(elements.len());
+            for handle in handles {
+                let chunk: Result<Vec<Value>, String> = handle.join().unwrap_or_else(|_| Err("Worker thread panicked".to_string()));
+                results.extend(chunk?);
             }
+            Ok(results)
+        })
+    }
 
-            // Save the current environment
-            let old_env = self.environment.clone();
+    // Creates an independent interpreter over a deep copy of this one's variables,
+    // used to run spawned tasks on other threads and imported files
+    fn fork(&self) -> Interpreter {
+        Interpreter {
+            environment: self.environment.deep_copy(),
+            _globals: self._globals.clone(),
+            modules: self.modules.clone(),
+            module_loaded: self.module_loaded.clone(),
+            module_scopes: self.module_scopes.clone(),
+            importing: self.importing.clone(),
+            file: self.file.clone(),
+            error_at: None,
+            handlers: self.handlers.clone(),
+            // Async calls run in the interpreter that made them
+            queued: VecDeque::new(),
+            base_path: self.base_path.clone(),
+            module_paths: self.module_paths.clone(),
+            random: self.random.clone(),
+            require_let: self.require_let,
+            strict_conditions: self.strict_conditions,
+            strict: self.strict,
+            strict_numbers: self.strict_numbers,
+            optimize: self.optimize,
+            cache: self.cache,
+            args: self.args.clone(),
+            // Tasks and imported files count their calls from zero
+            call_depth: 0,
+            unwinding: None,
+            host: self.host.clone(),
+        }
+    }
 
-            // Set the new environment
-            self.environment = env;
+    /// Makes a Rust function callable from M code as `name(...)`, replacing any
+    /// variable, function or builtin with that name, so a host can add its own
+    /// builtins or e.g. send print() to a log. It receives the evaluated
+    /// arguments, however many were passed. Host objects go in and out as
+    /// Value::native() values, which M code can hold but not look inside.
+    pub fn register_function<F>(&mut self, name: &str, function: F)
+    where
+        F: Fn(Vec<Value>) -> Result<Value, String> + Send + Sync + 'static,
+    {
+        // Registered functions sit beside the builtins, so imported files see them too
+        let function = NativeFunction { name: name.into(), function: Arc::new(function) };
+        let name = Symbol::new(name);
+        self.environment.values.remove(&name);
+        self.environment.outermost_mut().define(name, Value::NativeFunction(function));
+    }
 
-            // Execute the function body
-            let mut result = Value::Nil;
+    /// Removes a builtin such as input() or a function added with
+    /// register_function(), so calling it is an error, in this interpreter and
+    /// the files it imports. Returns false if there was nothing by that name.
+    pub fn remove_builtin(&mut self, name: &str) -> bool {
+        Symbol::existing(name).is_some_and(|name| self.environment.outermost_mut().values.remove(&name).is_some())
+    }
+
+    /// Subscribes a function to an event, as `on("event", f)` does in M code.
+    /// Handlers run in the order they were added each time the event is
+    /// emitted.
+    pub fn on(&mut self, event: &str, handler: Value) -> Result<(), String> {
+        if !matches!(handler, Value::Function { .. } | Value::NativeFunction(_) | Value::Builtin(_)) {
+            return Err(format!("An event handler must be a function, not {}", handler.type_name()));
+        }
+
+        let mut handlers = self.handlers.lock().map_err(|_| "Event handlers are poisoned".to_string())?;
+        handlers.entry(event.to_string()).or_default().push(handler);
+        Ok(())
+    }
 
-            for expr in body.iter() {
-                result = self.evaluate(expr)?;
+    /// Calls every function subscribed to an event with the arguments, and
+    /// returns their results. The first handler to fail stops the rest, and
+    /// an event nothing is subscribed to does nothing.
+    pub fn emit(&mut self, event: &str, arguments: Vec<Value>) -> Result<Vec<Value>, String> {
+        // Copied out, so handlers can subscribe while the event is going on
+        let handlers = match self.handlers.lock() {
+            Ok(handlers) => handlers.get(event).cloned().unwrap_or_default(),
+            Err(_) => return Err("Event handlers are poisoned".to_string()),
+        };
+        handlers.iter().map(|handler| self.call_value(handler, arguments.clone())).collect()
+    }
 
-                // Handle return statements
-                if let Expr::Return { .. } = expr {
-                    break;
+    // Calls a function value the way a call by name would, ignoring extra arguments
+    fn call_value(&mut self, function: &Value, arguments: Vec<Value>) -> Result<Value, String> {
+        match function {
+            Value::Function { params, layout, body, is_async, captured } => {
+                if *is_async {
+                    Ok(self.queue_call(params.clone(), layout.clone(), body.clone(), captured.clone(), arguments))
+                } else {
+                    self.call_function(params, layout, body, captured.as_ref(), arguments)
                 }
+            },
+            Value::NativeFunction(native) => (native.function)(arguments),
+            Value::Builtin(builtin) => (builtin.function)(self, arguments),
+            _ => Err(format!("Cannot call {}", function.type_name())),
+        }
+    }
+
+    /// Saves the variables, functions and transformers defined at the top level
+    /// as JSON text, for load_state() to restore later, e.g. in another process.
+    /// Containers shared between variables stay shared; tasks, channels and
+    /// host objects can't be saved. Functions added with register_function()
+    /// are left out, for the host to register again.
+    pub fn save_state(&self) -> Result<String, String> {
+        let mut bindings: Vec<(Symbol, Value)> = self.environment.values.clone().into_iter()
+            .filter(|(_, value)| !matches!(value, Value::NativeFunction(_) | Value::Builtin(_)))
+            .collect();
+        bindings.sort_by_key(|(name, _)| *name);
+        snapshot::save(&bindings)
+    }
+
+    /// Defines everything saved by save_state(), replacing variables with the
+    /// same names.
+    pub fn load_state(&mut self, state: &str) -> Result<(), String> {
+        for (name, value) in snapshot::load(state)? {
+            self.environment.define(name, value);
+        }
+        Ok(())
+    }
+
+    /// Runs a module imported with `use` again, e.g. after its file changed, and
+    /// replaces the definitions it exported with the new ones: names it no
+    /// longer exports are removed and the rest of the program's state is left
+    /// as it is. If the new version fails to load, the old one stays in place.
+    /// Files that only the module imports are not reloaded.
+    pub fn reload_module(&mut self, path: &str) -> Result<(), String> {
+        let module_path = self.canonical_module_path(path)?;
+        let mut modules = self.lock_modules()?;
+        let old = match modules.get(&module_path) {
+            Some(module) if module.exports.is_some() => modules.remove(&module_path).expect("module is registered"),
+            Some(_) => return Err(format!("Module '{}' is still loading", path)),
+            None => return Err(format!("Module '{}' hasn't been imported", path)),
+        };
+        modules.start(module_path.clone());
+        drop(modules);
+
+        let exports = match self.load_module(&module_path) {
+            Ok(exports) => exports,
+            Err(e) => {
+                self.lock_modules()?.insert(module_path, old);
+                self.module_loaded.notify_all();
+                return Err(e);
+            },
+        };
+
+        // The new definitions are only swapped in once the module loaded
+        for (name, _) in old.exports.unwrap_or_default() {
+            if !exports.iter().any(|(exported, _)| *exported == name) {
+                self.environment.values.remove(&name);
             }
+        }
+        for (name, value) in exports {
+            self.environment.define(name, value);
+        }
+        Ok(())
+    }
 
-            // Restore the old environment
-            self.environment = old_env;
+    /// Returns the value of a variable visible at the top level, if it is defined.
+    pub fn get_variable(&self, name: &str) -> Option<Value> {
+        self.environment.get(Symbol::existing(name)?)
+    }
 
-            Ok(result)
-        } else {
-            Err(format!("Undefined function '{}'", callee))
+    /// Defines a variable in the current scope (the top level outside of a
+    /// call), replacing any value it had.
+    pub fn set_variable(&mut self, name: &str, value: Value) {
+        self.environment.define(Symbol::new(name), value);
+    }
+
+    /// Every variable, function and transformer visible from the current scope,
+    /// sorted by name. Where scopes define the same name, the innermost binding
+    /// is the one listed. Builtins are left out.
+    pub fn globals(&self) -> impl Iterator<Item = (&str, &Value)> {
+        let mut bindings = BTreeMap::new();
+        for scope in self.scopes().collect::<Vec<_>>().into_iter().rev() {
+            bindings.extend(scope);
         }
+        bindings.into_iter()
     }
 
-    #[allow(dead_code)]
-    pub fn get_variables(&self) -> HashMap<String, Value> {
-        self.environment.values.clone()
+    /// The bindings of each scope in the current chain, innermost first (the
+    /// running function's, then its caller's, out to the top level), each
+    /// sorted by name. Builtins are left out.
+    pub fn scopes(&self) -> impl Iterator<Item = Vec<(&str, &Value)>> {
+        let mut scopes: Vec<_> = self.environment.scopes().collect();
+        // The outermost scope holds the builtins and the host's functions
+        scopes.pop();
+        scopes.into_iter().map(|scope| {
+            let mut bindings: Vec<_> = scope.bindings().map(|(name, value)| (name.as_str(), value)).collect();
+            bindings.sort_by_key(|(name, _)| *name);
+            bindings
+        })
     }
 
-    // Commented out unused method
-    // pub fn get_variable(&self, name: &str) -> Option<&Value> {
-    //     self.environment.values.get(name)
-    // }
+    /// The names of the builtins and the host's functions, sorted. These are
+    /// the bindings globals() and scopes() leave out.
+    pub fn builtins(&self) -> impl Iterator<Item = &str> {
+        let mut names: Vec<_> = self.environment.outermost().values.keys().map(Symbol::as_str).collect();
+        names.sort_unstable();
+        names.into_iter()
+    }
+
+    /// A copy of the bindings listed by globals().
+    #[deprecated(note = "use globals(), which borrows instead of copying")]
+    pub fn get_variables(&self) -> HashMap<String, Value> {
+        self.globals().map(|(name, value)| (name.to_string(), value.clone())).collect()
+    }
 }
\ No newline at end of file
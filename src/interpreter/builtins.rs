@@ -0,0 +1,612 @@
+// The functions every program starts with. new() defines them in the outermost
+// scope, where the host can remove them or replace them with its own.
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+use crate::lexer::Lexer;
+use crate::parser::Parser;
+use crate::optimizer::Optimizer;
+use crate::symbol::Symbol;
+use crate::value::{BuiltinFn, Outcome, Set, Value};
+use crate::channel::Channel;
+use crate::format::format_string;
+use crate::json;
+use crate::decimal;
+use crate::hashing;
+use super::Interpreter;
+
+pub(super) const BUILTINS: &[(&str, BuiltinFn)] = &[
+    ("print", |interpreter, arguments| write(interpreter, arguments, "\n")),
+    ("write", |interpreter, arguments| write(interpreter, arguments, "")),
+    ("print_raw", |interpreter, arguments| write(interpreter, arguments, "")),
+    ("format", format),
+    ("input", input),
+    ("typeof", type_of),
+    ("range", range),
+    ("read_bytes", read_bytes),
+    ("write_bytes", write_bytes),
+    ("sha256", |_, arguments| digest("sha256", arguments)),
+    ("md5", |_, arguments| digest("md5", arguments)),
+    ("crc32", |_, arguments| digest("crc32", arguments)),
+    ("list_dir", list_dir),
+    ("mkdir", mkdir),
+    ("remove_file", remove_file),
+    ("path_join", path_join),
+    ("basename", |_, arguments| path_part("basename", arguments)),
+    ("extension", |_, arguments| path_part("extension", arguments)),
+    ("spawn", spawn),
+    ("join", join),
+    ("sleep", sleep),
+    ("channel", channel),
+    ("send", send),
+    ("receive", receive),
+    ("close", close),
+    ("push", push),
+    ("zip", zip),
+    ("array_of", array_of),
+    ("set", set),
+    ("ord", ord),
+    ("chr", chr),
+    ("eval", eval),
+    ("globals", globals),
+    ("defined", defined),
+    ("fn_params", fn_params),
+    ("from_json", from_json),
+    ("on", on),
+    ("random", random),
+    ("random_int", random_int),
+    ("now", now),
+    ("decimal", |_, arguments| decimal(arguments)),
+    ("try_parse_number", try_parse_number),
+    ("ok", |_, arguments| result(arguments, true)),
+    ("err", |_, arguments| result(arguments, false)),
+    ("try", attempt),
+    ("args", args),
+];
+
+// Arguments are joined with spaces; strings are printed without quotes.
+// write() and print_raw() omit the trailing newline.
+fn write(interpreter: &mut Interpreter, arguments: Vec<Value>, end: &str) -> Result<Value, String> {
+    let parts: Vec<String> = arguments.iter().map(Value::to_string).collect();
+    interpreter.host.write(&(parts.join(" ") + end))?;
+    Ok(Value::Nil)
+}
+
+fn format(_: &mut Interpreter, arguments: Vec<Value>) -> Result<Value, String> {
+    let Some((template, values)) = arguments.split_first() else {
+        return Err("format() takes at least 1 argument".to_string());
+    };
+
+    match template {
+        Value::String(template) => Ok(Value::String(format_string(template, values)?.into())),
+        _ => Err("First argument to format() must be a string".to_string()),
+    }
+}
+
+fn input(interpreter: &mut Interpreter, arguments: Vec<Value>) -> Result<Value, String> {
+    if arguments.len() != 1 {
+        return Err("input() takes exactly 1 argument".to_string());
+    }
+
+    let Value::String(prompt) = &arguments[0] else {
+        return Err("Argument to input() must be a string".to_string());
+    };
+
+    // Print the prompt without a newline, then read the reply without its line ending
+    interpreter.host.write(prompt)?;
+    Ok(Value::String(interpreter.host.read_line()?.into()))
+}
+
+fn type_of(_: &mut Interpreter, arguments: Vec<Value>) -> Result<Value, String> {
+    if arguments.len() != 1 {
+        return Err("typeof() takes exactly 1 argument".to_string());
+    }
+    Ok(Value::String(arguments[0].type_name().into()))
+}
+
+fn range(_: &mut Interpreter, arguments: Vec<Value>) -> Result<Value, String> {
+    if arguments.len() != 2 {
+        return Err("range() takes exactly 2 arguments".to_string());
+    }
+
+    let start = match arguments[0] {
+        Value::Int(n) => n,
+        Value::Number(n) => n as i64,
+        _ => return Err("First argument to range() must be a number".to_string()),
+    };
+
+    let end = match arguments[1] {
+        Value::Int(n) => n,
+        Value::Number(n) => n as i64,
+        _ => return Err("Second argument to range() must be a number".to_string()),
+    };
+
+    // Ranges are lazy; elements are produced on demand by `for` and indexing
+    Ok(Value::Range { start, end })
+}
+
+// The file builtins give err(message) when the operation fails, as for a
+// missing file, so scripts can handle it; wrong arguments still stop the program
+fn read_bytes(interpreter: &mut Interpreter, arguments: Vec<Value>) -> Result<Value, String> {
+    if arguments.len() != 1 {
+        return Err("read_bytes() takes exactly 1 argument".to_string());
+    }
+
+    let Value::String(path) = &arguments[0] else {
+        return Err("Argument to read_bytes() must be a string".to_string());
+    };
+
+    let file_path = interpreter.resolve_path(path);
+    Ok(Value::result(match interpreter.host.read_file(&file_path) {
+        Ok(bytes) => Ok(Value::Bytes(bytes)),
+        Err(e) => Err(format!("Failed to read file '{}': {}", file_path.display(), e)),
+    }))
+}
+
+fn write_bytes(interpreter: &mut Interpreter, arguments: Vec<Value>) -> Result<Value, String> {
+    if arguments.len() != 2 {
+        return Err("write_bytes() takes exactly 2 arguments".to_string());
+    }
+
+    let Value::String(path) = &arguments[0] else {
+        return Err("First argument to write_bytes() must be a string".to_string());
+    };
+
+    let Value::Bytes(bytes) = &arguments[1] else {
+        return Err("Second argument to write_bytes() must be bytes".to_string());
+    };
+
+    let file_path = interpreter.resolve_path(path);
+    Ok(Value::result(match interpreter.host.write_file(&file_path, bytes) {
+        Ok(()) => Ok(Value::Nil),
+        Err(e) => Err(format!("Failed to write file '{}': {}", file_path.display(), e)),
+    }))
+}
+
+fn digest(algorithm: &str, arguments: Vec<Value>) -> Result<Value, String> {
+    if arguments.len() != 1 {
+        return Err(format!("{}() takes exactly 1 argument", algorithm));
+    }
+
+    // Strings are hashed as their UTF-8 bytes
+    let bytes = match &arguments[0] {
+        Value::Bytes(bytes) => bytes.clone(),
+        Value::String(s) => s.as_bytes().to_vec(),
+        _ => return Err(format!("Argument to {}() must be a string or bytes", algorithm)),
+    };
+
+    Ok(Value::String(hashing::digest(algorithm, &bytes)?.into()))
+}
+
+fn from_json(_: &mut Interpreter, arguments: Vec<Value>) -> Result<Value, String> {
+    if arguments.len() != 1 {
+        return Err("from_json() takes exactly 1 argument".to_string());
+    }
+
+    match &arguments[0] {
+        Value::String(text) => Ok(Value::result(json::from_json(text))),
+        _ => Err("Argument to from_json() must be a string".to_string()),
+    }
+}
+
+// The single string argument of a path builtin such as basename(path)
+fn path_argument(callee: &str, arguments: Vec<Value>) -> Result<String, String> {
+    if arguments.len() != 1 {
+        return Err(format!("{}() takes exactly 1 argument", callee));
+    }
+
+    match &arguments[0] {
+        Value::String(s) => Ok(s.to_string()),
+        _ => Err(format!("Argument to {}() must be a string", callee)),
+    }
+}
+
+fn list_dir(interpreter: &mut Interpreter, arguments: Vec<Value>) -> Result<Value, String> {
+    let path = path_argument("list_dir", arguments)?;
+    let dir_path = interpreter.resolve_path(&path);
+
+    let mut names = match interpreter.host.list_dir(&dir_path) {
+        Ok(names) => names,
+        Err(e) => return Ok(Value::result(Err(format!("Failed to list directory '{}': {}", dir_path.display(), e)))),
+    };
+
+    // Entry names are sorted so scripts behave the same on every platform
+    names.sort();
+
+    Ok(Value::result(Ok(Value::array(names.into_iter().map(|name| Value::String(name.into())).collect()))))
+}
+
+fn mkdir(interpreter: &mut Interpreter, arguments: Vec<Value>) -> Result<Value, String> {
+    let path = path_argument("mkdir", arguments)?;
+    let dir_path = interpreter.resolve_path(&path);
+
+    // Missing parent directories are created too
+    Ok(Value::result(match interpreter.host.create_dir(&dir_path) {
+        Ok(()) => Ok(Value::Nil),
+        Err(e) => Err(format!("Failed to create directory '{}': {}", dir_path.display(), e)),
+    }))
+}
+
+fn remove_file(interpreter: &mut Interpreter, arguments: Vec<Value>) -> Result<Value, String> {
+    let path = path_argument("remove_file", arguments)?;
+    let file_path = interpreter.resolve_path(&path);
+
+    Ok(Value::result(match interpreter.host.remove_file(&file_path) {
+        Ok(()) => Ok(Value::Nil),
+        Err(e) => Err(format!("Failed to remove file '{}': {}", file_path.display(), e)),
+    }))
+}
+
+fn path_join(_: &mut Interpreter, arguments: Vec<Value>) -> Result<Value, String> {
+    if arguments.is_empty() {
+        return Err("path_join() takes at least 1 argument".to_string());
+    }
+
+    let mut path = PathBuf::new();
+    for argument in &arguments {
+        match argument {
+            Value::String(s) => path.push(&**s),
+            _ => return Err("Arguments to path_join() must be strings".to_string()),
+        }
+    }
+
+    Ok(Value::String(path.to_string_lossy().into_owned().into()))
+}
+
+// basename() and extension(); paths without a file name or extension give nil
+fn path_part(callee: &str, arguments: Vec<Value>) -> Result<Value, String> {
+    let path = path_argument(callee, arguments)?;
+    let path = Path::new(&path);
+
+    let part = if callee == "basename" { path.file_name() } else { path.extension() };
+    Ok(part.map_or(Value::Nil, |part| Value::String(part.to_string_lossy().into_owned().into())))
+}
+
+// `on("event", f)` calls f each time the host emits the event
+fn on(interpreter: &mut Interpreter, arguments: Vec<Value>) -> Result<Value, String> {
+    let [event, handler] = <[Value; 2]>::try_from(arguments)
+        .map_err(|_| "on() takes an event name and a function".to_string())?;
+
+    let Value::String(event) = event else {
+        return Err("First argument to on() must be a string".to_string());
+    };
+    interpreter.on(&event, handler)?;
+    Ok(Value::Nil)
+}
+
+fn spawn(interpreter: &mut Interpreter, mut arguments: Vec<Value>) -> Result<Value, String> {
+    if arguments.is_empty() {
+        return Err("spawn() takes a function and its arguments".to_string());
+    }
+
+    let Value::Function { params, layout, body, captured, .. } = arguments.remove(0) else {
+        return Err("First argument to spawn() must be a function".to_string());
+    };
+
+    interpreter.spawn_task(params, layout, body, captured, arguments)
+}
+
+fn join(interpreter: &mut Interpreter, arguments: Vec<Value>) -> Result<Value, String> {
+    if arguments.len() != 1 {
+        return Err("join() takes exactly 1 argument".to_string());
+    }
+
+    match &arguments[0] {
+        Value::Task(task) => interpreter.join_task(task),
+        _ => Err("Argument to join() must be a task".to_string()),
+    }
+}
+
+fn sleep(interpreter: &mut Interpreter, arguments: Vec<Value>) -> Result<Value, String> {
+    if arguments.len() != 1 {
+        return Err("sleep() takes exactly 1 argument".to_string());
+    }
+
+    let milliseconds = match arguments[0].as_number() {
+        Some(ms) if ms >= 0.0 && ms.is_finite() => ms,
+        _ => return Err("Argument to sleep() must be a non-negative number of milliseconds".to_string()),
+    };
+
+    // Queued async calls run while this waits
+    let deadline = Instant::now() + Duration::from_secs_f64(milliseconds / 1000.0);
+    interpreter.run_queued(deadline);
+    thread::sleep(deadline.saturating_duration_since(Instant::now()));
+    interpreter.lock_random()?.slept(milliseconds);
+    Ok(Value::Nil)
+}
+
+// `decimal("10.05")`: an exact base-10 number
+fn decimal(arguments: Vec<Value>) -> Result<Value, String> {
+    if arguments.len() != 1 {
+        return Err("decimal() takes exactly 1 argument".to_string());
+    }
+    decimal::from_value(&arguments[0])
+}
+
+// The number a string holds, or nil when it doesn't hold one, so input can be
+// checked; unlike parse_number() it never falls back to 0
+fn try_parse_number(interpreter: &mut Interpreter, arguments: Vec<Value>) -> Result<Value, String> {
+    if arguments.len() != 1 {
+        return Err("try_parse_number() takes exactly 1 argument".to_string());
+    }
+
+    match &arguments[0] {
+        Value::String(s) => match Interpreter::parse_number(s) {
+            Some(Value::Number(n)) if interpreter.strict_numbers && !n.is_finite() => Ok(Value::Nil),
+            Some(n) => Ok(n),
+            None => Ok(Value::Nil),
+        },
+        value @ (Value::Int(_) | Value::BigInt(_) | Value::Number(_)) => Ok(value.clone()),
+        _ => Ok(Value::Nil),
+    }
+}
+
+// `ok(value)` and `err(error)`
+fn result(arguments: Vec<Value>, ok: bool) -> Result<Value, String> {
+    let [value] = <[Value; 1]>::try_from(arguments)
+        .map_err(|_| format!("{}() takes exactly 1 argument", if ok { "ok" } else { "err" }))?;
+    Ok(Value::Result(Outcome::new(if ok { Ok(value) } else { Err(value) })))
+}
+
+// `try(f, args...)` calls f, giving ok(result), or err(message) if it fails,
+// so any function can fail without stopping the program
+fn attempt(interpreter: &mut Interpreter, mut arguments: Vec<Value>) -> Result<Value, String> {
+    if arguments.is_empty() {
+        return Err("try() takes a function and its arguments".to_string());
+    }
+
+    let function = arguments.remove(0);
+    if !matches!(function, Value::Function { .. } | Value::NativeFunction(_) | Value::Builtin(_)) && !function.is_transformer() {
+        return Err(format!("First argument to try() must be a function or transformer, got {}", function.type_name()));
+    }
+
+    let mut arguments = arguments.into_iter();
+    let result = match arguments.next() {
+        Some(first) => interpreter.call_with(&function, first, arguments.collect()),
+        None => interpreter.call_value(&function, Vec::new()),
+    };
+    Ok(Value::Result(Outcome::new(result.map_err(|message| {
+        // A `break` that escaped the function ends here too
+        interpreter.unwinding = None;
+        Value::String(message.into())
+    }))))
+}
+
+fn random(interpreter: &mut Interpreter, arguments: Vec<Value>) -> Result<Value, String> {
+    if !arguments.is_empty() {
+        return Err("random() takes no arguments".to_string());
+    }
+    Ok(Value::Number(interpreter.lock_random()?.next_float()))
+}
+
+// `random_int(1, 7)` rolls a die: the end is excluded, as in `range`
+fn random_int(interpreter: &mut Interpreter, arguments: Vec<Value>) -> Result<Value, String> {
+    if arguments.len() != 2 {
+        return Err("random_int() takes exactly 2 arguments".to_string());
+    }
+
+    match (&arguments[0], &arguments[1]) {
+        (&Value::Int(start), &Value::Int(end)) if start < end => Ok(Value::Int(interpreter.lock_random()?.next_int(start, end))),
+        (Value::Int(_), Value::Int(_)) => Err("random_int() needs a start less than its end".to_string()),
+        _ => Err("Arguments to random_int() must be integers".to_string()),
+    }
+}
+
+fn now(interpreter: &mut Interpreter, arguments: Vec<Value>) -> Result<Value, String> {
+    if !arguments.is_empty() {
+        return Err("now() takes no arguments".to_string());
+    }
+    let clock = interpreter.host.now();
+    match interpreter.lock_random()?.now(clock) {
+        Some(time) => Ok(Value::Number(time)),
+        None => Err("now() needs a clock, and this host has none".to_string()),
+    }
+}
+
+fn args(interpreter: &mut Interpreter, arguments: Vec<Value>) -> Result<Value, String> {
+    if !arguments.is_empty() {
+        return Err("args() takes no arguments".to_string());
+    }
+    Ok(Value::array(interpreter.args.iter().map(|arg| Value::String(arg.as_str().into())).collect()))
+}
+
+fn channel(_: &mut Interpreter, arguments: Vec<Value>) -> Result<Value, String> {
+    if !arguments.is_empty() {
+        return Err("channel() takes no arguments".to_string());
+    }
+
+    let channel = Arc::new(Channel::default());
+    Ok(Value::array(vec![Value::Sender(channel.clone()), Value::Receiver(channel)]))
+}
+
+fn send(_: &mut Interpreter, arguments: Vec<Value>) -> Result<Value, String> {
+    if arguments.len() != 2 {
+        return Err("send() takes exactly 2 arguments".to_string());
+    }
+
+    let Value::Sender(channel) = &arguments[0] else {
+        return Err("First argument to send() must be a sender".to_string());
+    };
+
+    // The receiver gets its own copy of any arrays and maps in the message
+    channel.send(arguments[1].deep_copy())?;
+    Ok(Value::Nil)
+}
+
+fn receive(_: &mut Interpreter, arguments: Vec<Value>) -> Result<Value, String> {
+    if arguments.len() != 1 {
+        return Err("receive() takes exactly 1 argument".to_string());
+    }
+
+    // Waits for the next message; a closed, empty channel gives nil
+    match &arguments[0] {
+        Value::Receiver(channel) => Ok(channel.receive()?.unwrap_or(Value::Nil)),
+        _ => Err("Argument to receive() must be a receiver".to_string()),
+    }
+}
+
+fn close(_: &mut Interpreter, arguments: Vec<Value>) -> Result<Value, String> {
+    if arguments.len() != 1 {
+        return Err("close() takes exactly 1 argument".to_string());
+    }
+
+    match &arguments[0] {
+        Value::Sender(channel) => channel.close()?,
+        _ => return Err("Argument to close() must be a sender".to_string()),
+    }
+    Ok(Value::Nil)
+}
+
+// Appends to the array in place rather than building a new one
+fn push(_: &mut Interpreter, arguments: Vec<Value>) -> Result<Value, String> {
+    if arguments.len() != 2 {
+        return Err("push() takes exactly 2 arguments".to_string());
+    }
+
+    let (array, value) = (&arguments[0], &arguments[1]);
+    let Value::Array(elements) = array else {
+        return Err("First argument to push() must be an array".to_string());
+    };
+    if value.refers_to(array) {
+        return Err("Cannot store an array or map inside itself".to_string());
+    }
+
+    elements.borrow_mut().push(value.clone());
+    Ok(Value::Nil)
+}
+
+// Pairs up elements at the same position, stopping at the shorter input
+fn zip(_: &mut Interpreter, arguments: Vec<Value>) -> Result<Value, String> {
+    if arguments.len() != 2 {
+        return Err("zip() takes exactly 2 arguments".to_string());
+    }
+
+    let mut inputs = Vec::new();
+    for value in &arguments {
+        match value.elements()? {
+            Some(elements) => inputs.push(elements),
+            None => return Err(format!("Cannot zip {}", value.type_name())),
+        }
+    }
+
+    let second = inputs.pop().unwrap_or_default();
+    let first = inputs.pop().unwrap_or_default();
+    Ok(Value::array(first.into_iter().zip(second).map(|(a, b)| Value::array(vec![a, b])).collect()))
+}
+
+fn array_of(_: &mut Interpreter, arguments: Vec<Value>) -> Result<Value, String> {
+    let [size, default] = <[Value; 2]>::try_from(arguments)
+        .map_err(|_| "array_of() takes exactly 2 arguments".to_string())?;
+
+    let size = match size {
+        Value::Int(n) if n >= 0 => n as usize,
+        Value::Int(_) => return Err("array_of() size cannot be negative".to_string()),
+        _ => return Err("First argument to array_of() must be an integer".to_string()),
+    };
+
+    let mut elements = Vec::new();
+    elements.try_reserve_exact(size).map_err(|_| format!("Cannot make an array of {} elements", size))?;
+    // Each element gets its own copy of an array or map, so changing one leaves the rest alone
+    match default {
+        Value::Array(_) | Value::Map(_) => elements.extend((0..size).map(|_| default.deep_copy())),
+        _ => elements.resize(size, default),
+    }
+    Ok(Value::array(elements))
+}
+
+// `set()` is empty; `set(values)` takes the elements of an array, range, string or set
+fn set(_: &mut Interpreter, arguments: Vec<Value>) -> Result<Value, String> {
+    let set = match arguments.as_slice() {
+        [] => Set::default(),
+        [values] => Interpreter::set_of(values)?,
+        _ => return Err("set() takes at most 1 argument".to_string()),
+    };
+    Ok(Value::set(set))
+}
+
+// Runs M source in the current scope, so it sees and defines the caller's
+// variables, and gives the value of its last expression
+fn eval(interpreter: &mut Interpreter, arguments: Vec<Value>) -> Result<Value, String> {
+    if arguments.len() != 1 {
+        return Err("eval() takes exactly 1 argument".to_string());
+    }
+
+    let source = match &arguments[0] {
+        Value::String(source) => source,
+        value => return Err(format!("eval() takes a string of M code, got {}", value.type_name())),
+    };
+    let tokens = Lexer::new(source).tokenize().map_err(|e| format!("In eval(): {}", e))?;
+    let program = Parser::new(tokens).parse().map_err(|e| format!("In eval(): {}", e))?;
+    let program = if interpreter.optimize { Optimizer::optimize(program) } else { program };
+
+    // The code is a program of its own: a `return` or `break` in it can't
+    // leave the function or loop that called eval(), and its errors are
+    // placed at the call rather than at lines of the string
+    interpreter.evaluate(&program).inspect_err(|_| {
+        interpreter.unwinding = None;
+        interpreter.error_at = None;
+    })
+}
+
+fn globals(interpreter: &mut Interpreter, arguments: Vec<Value>) -> Result<Value, String> {
+    if !arguments.is_empty() {
+        return Err("globals() takes no arguments".to_string());
+    }
+    Ok(Value::map(interpreter.globals().map(|(name, value)| (name.to_string(), value.clone())).collect()))
+}
+
+// Whether a name can be used here: a variable, function or transformer in
+// scope, or a builtin function
+fn defined(interpreter: &mut Interpreter, arguments: Vec<Value>) -> Result<Value, String> {
+    if arguments.len() != 1 {
+        return Err("defined() takes exactly 1 argument".to_string());
+    }
+
+    match &arguments[0] {
+        Value::String(name) => Ok(Value::Boolean(Symbol::existing(name).and_then(|name| interpreter.environment.get(name)).is_some())),
+        value => Err(format!("defined() takes a name as a string, got {}", value.type_name())),
+    }
+}
+
+fn fn_params(_: &mut Interpreter, arguments: Vec<Value>) -> Result<Value, String> {
+    if arguments.len() != 1 {
+        return Err("fn_params() takes exactly 1 argument".to_string());
+    }
+
+    match &arguments[0] {
+        Value::Function { params, .. } | Value::Transformer { params, .. } => {
+            Ok(Value::array(params.iter().map(|param| Value::String(param.as_str().into())).collect()))
+        },
+        Value::NativeFunction(native) => Err(format!("The parameters of native function '{}' aren't known", native.name)),
+        Value::Builtin(builtin) => Err(format!("The parameters of builtin '{}' aren't known", builtin.name)),
+        value => Err(format!("fn_params() takes a function or transformer, got {}", value.type_name())),
+    }
+}
+
+// The code point of a one-character string
+fn ord(_: &mut Interpreter, arguments: Vec<Value>) -> Result<Value, String> {
+    if arguments.len() != 1 {
+        return Err("ord() takes exactly 1 argument".to_string());
+    }
+
+    match &arguments[0] {
+        Value::String(s) if s.chars().count() == 1 => Ok(Value::Int(s.chars().next().map_or(0, |c| c as i64))),
+        Value::String(s) => Err(format!("ord() takes a single character, got a string of {} characters", s.chars().count())),
+        value => Err(format!("ord() takes a single character, got {}", value.type_name())),
+    }
+}
+
+// The one-character string for a code point
+fn chr(_: &mut Interpreter, arguments: Vec<Value>) -> Result<Value, String> {
+    if arguments.len() != 1 {
+        return Err("chr() takes exactly 1 argument".to_string());
+    }
+
+    let value = &arguments[0];
+    match value.as_index().and_then(|n| u32::try_from(n).ok()).and_then(char::from_u32) {
+        Some(c) => Ok(Value::String(c.to_string().into())),
+        None => Err(format!("{} is not a Unicode code point", value)),
+    }
+}
@@ -0,0 +1,953 @@
+// Evaluation runs on an explicit stack of work instead of by recursion in Rust,
+// so deeply nested expressions can't overflow the thread's stack. A step either
+// starts an expression, pushing the steps it's made of, or continues one with
+// the values of its operands, which the steps before it left on a stack of
+// values. Only calls (and eval() and imports) start an evaluation of their own,
+// and the call depth limit bounds how far those nest.
+//
+// An error unwinds the work that's left (see recover()), leaving the scopes
+// entered on the way, until a loop takes the `break` or `continue` it signals
+// or the evaluation ends with it.
+
+use std::collections::{btree_map, BTreeMap};
+use std::sync::Arc;
+use std::vec;
+use crate::ast::Expr;
+use crate::channel::Channel;
+use crate::environment::Layout;
+use crate::symbol::Symbol;
+use crate::token::{Token, TokenType};
+use crate::value::{Text, Value};
+use crate::bigint;
+use super::{Flow, Interpreter, Unwind};
+
+enum Step<'e> {
+    // Evaluates an expression, leaving its value on the value stack
+    Evaluate(&'e Expr),
+    // Where the expression below it is written, for an error passing through it
+    Locate((usize, usize)),
+    // The values of a collection's elements, in order
+    Array(usize),
+    Tuple(usize),
+    Map(&'e [(String, Expr)]),
+    Binary(TokenType),
+    // `??` only evaluates its right-hand side when the left-hand side is nil
+    Coalesce(&'e Expr),
+    Unary(TokenType),
+    Let(Symbol),
+    Assign(Symbol),
+    TupleAssign(&'e [Symbol]),
+    Append(Box<Append<'e>>),
+    // A function and how many arguments it's called with
+    Call(Value, usize),
+    Await,
+    Return,
+    Break(&'e Option<String>),
+    Index,
+    // A map entry on the path to the container an assignment stores into
+    Field(Symbol),
+    IndexAssign,
+    // The statements of a block after the one just run
+    Statements(&'e [Expr]),
+    If(&'e Expr, Option<&'e Expr>),
+    LeaveScope,
+    // A `for` loop whose iterable (and step) are being evaluated
+    ForStart(&'e Expr),
+    // Loops waiting for their body or condition. A body records how many
+    // values were stacked when it started, for a `break` to go back to.
+    For(Box<ForLoop<'e>>),
+    WhileCondition(Repeat<'e>),
+    WhileBody(Repeat<'e>, usize),
+    DoBody(Repeat<'e>, usize),
+    // Runs in the scope of the body before it
+    DoCondition(Repeat<'e>),
+    LoopBody(&'e Expr, &'e Option<String>, usize),
+    // An application waiting for the object, then for the arguments, which
+    // are stacked on top of it
+    Apply {
+        transformer: Symbol,
+        arguments: &'e [Expr],
+        safe: bool,
+    },
+    ApplyWith(Symbol, usize),
+    Member {
+        name: Symbol,
+        safe: bool,
+    },
+    AssertEqual(Assertion<'e>),
+    Compare(&'e Token, Assertion<'e>),
+    AssertCondition(Assertion<'e>),
+    // A failed assertion, waiting for its message
+    AssertMessage(String),
+}
+
+struct Evaluation<'e> {
+    // What's left to do, the next step last
+    work: Vec<Step<'e>>,
+    values: Vec<Value>,
+}
+
+impl<'e> Evaluation<'e> {
+    fn evaluate(&mut self, expr: &'e Expr) {
+        self.work.push(Step::Evaluate(expr));
+    }
+
+    // Evaluates the expressions in order, stacking their values in that order
+    fn evaluate_all(&mut self, exprs: impl DoubleEndedIterator<Item = &'e Expr>) {
+        self.work.extend(exprs.rev().map(Step::Evaluate));
+    }
+
+    fn push(&mut self, value: Value) {
+        self.values.push(value);
+    }
+
+    fn pop(&mut self) -> Value {
+        self.values.pop().expect("a step's operands are on the value stack")
+    }
+
+    fn pop_n(&mut self, count: usize) -> Vec<Value> {
+        self.values.split_off(self.values.len() - count)
+    }
+}
+
+// The parts of a `while` or `do` loop
+#[derive(Clone, Copy)]
+struct Repeat<'e> {
+    condition: &'e Expr,
+    body: &'e Expr,
+    label: &'e Option<String>,
+}
+
+// A `for` loop part way through its elements
+struct ForLoop<'e> {
+    variable: Symbol,
+    second: Option<Symbol>,
+    body: &'e Expr,
+    label: &'e Option<String>,
+    elements: Elements,
+    // The value of the last run of the body
+    result: Value,
+    base: usize,
+}
+
+// What a `for` loop has left to visit
+enum Elements {
+    // The elements an array, set or tuple had when the loop started
+    Values(vec::IntoIter<Value>),
+    // A map's entries, as pairs when the loop binds two variables
+    Entries(btree_map::IntoIter<String, Value>, bool),
+    Range {
+        next: Option<i64>,
+        end: i64,
+        step: i64,
+    },
+    Bytes(vec::IntoIter<u8>),
+    Messages(Arc<Channel>),
+    // The characters of a string from a byte offset on
+    Chars(Text, usize),
+}
+
+impl Elements {
+    fn next(&mut self) -> Result<Option<Value>, String> {
+        Ok(match self {
+            Elements::Values(values) => values.next(),
+            // With two variables, the second one gets the entry's value
+            Elements::Entries(entries, pairs) => entries.next().map(|(key, value)| match pairs {
+                true => Value::array(vec![Value::String(key.into()), value]),
+                false => Value::String(key.into()),
+            }),
+            // Ranges are consumed lazily, one element per iteration. A negative
+            // step counts down from start to just above end.
+            Elements::Range { next, end, step } => match *next {
+                Some(i) if (*step > 0 && i < *end) || (*step < 0 && i > *end) => {
+                    *next = i.checked_add(*step);
+                    Some(Value::Int(i))
+                },
+                _ => None,
+            },
+            Elements::Bytes(bytes) => bytes.next().map(|byte| Value::Int(byte as i64)),
+            // Receives messages until the channel is closed and drained
+            Elements::Messages(channel) => channel.receive()?,
+            Elements::Chars(text, offset) => text[*offset..].chars().next().map(|c| {
+                *offset += c.len_utf8();
+                Value::String(c.to_string().into())
+            }),
+        })
+    }
+}
+
+// `s = s + x + ...` part way through its operands (see Interpreter::appending)
+struct Append<'e> {
+    name: Symbol,
+    // The variable's value before the operands were evaluated
+    current: Value,
+    // The operands still to evaluate, the next one last
+    operands: Vec<&'e Expr>,
+    additions: Vec<Value>,
+    // The sum worked out as usual, once an operand can't be appended
+    sum: Option<Value>,
+}
+
+#[derive(Clone, Copy)]
+struct Assertion<'e> {
+    message: Option<&'e Expr>,
+    line: usize,
+    column: usize,
+}
+
+// Strings are quoted so that "1" and 1 can be told apart
+fn describe(value: &Value) -> String {
+    match value {
+        Value::String(s) => format!("{:?}", s),
+        _ => value.to_string(),
+    }
+}
+
+impl Interpreter {
+    pub(super) fn run(&mut self, expr: &Expr) -> Result<Value, String> {
+        let mut evaluation = Evaluation { work: vec![Step::Evaluate(expr)], values: Vec::new() };
+        while let Some(step) = evaluation.work.pop() {
+            if let Err(error) = self.step(step, &mut evaluation) {
+                self.recover(error, &mut evaluation)?;
+            }
+        }
+        Ok(evaluation.pop())
+    }
+
+    fn step<'e>(&mut self, step: Step<'e>, ev: &mut Evaluation<'e>) -> Result<(), String> {
+        match step {
+            Step::Evaluate(expr) => return self.start(expr, ev),
+            Step::Locate(_) => {},
+            Step::Array(count) => {
+                let values = ev.pop_n(count);
+                ev.push(Value::array(values));
+            },
+            Step::Tuple(count) => {
+                let values = ev.pop_n(count);
+                ev.push(Value::Tuple(values.into()));
+            },
+            Step::Map(entries) => {
+                let mut values = BTreeMap::new();
+                for ((key, _), value) in entries.iter().zip(ev.pop_n(entries.len())) {
+                    values.insert(key.clone(), value);
+                }
+                ev.push(Value::map(values));
+            },
+            Step::Binary(operator) => {
+                let right = ev.pop();
+                let left = ev.pop();
+                let value = self.operate(left, operator, right)?;
+                ev.push(value);
+            },
+            Step::Coalesce(right) => match ev.pop() {
+                Value::Nil => ev.evaluate(right),
+                value => ev.push(value),
+            },
+            Step::Unary(operator) => {
+                let right = ev.pop();
+                ev.push(Self::unary(operator, right)?);
+            },
+            Step::Let(name) => {
+                // A declaration always binds in the current scope, shadowing outer names
+                let value = ev.pop();
+                self.environment.define(name, value.clone());
+                ev.push(value);
+            },
+            Step::Assign(name) => {
+                let value = ev.pop();
+                self.store(name, value.clone())?;
+                ev.push(value);
+            },
+            Step::TupleAssign(names) => {
+                let value = ev.pop();
+                let elements = match &value {
+                    Value::Tuple(elements) => Some(elements.to_vec()),
+                    Value::Array(elements) => Some(elements.get()),
+                    _ => None,
+                };
+                let elements = match elements {
+                    Some(elements) if elements.len() == names.len() => elements,
+                    _ => return Err(format!("Cannot unpack {} into ({})", value, names.iter().map(Symbol::as_str).collect::<Vec<_>>().join(", "))),
+                };
+
+                for (&name, element) in names.iter().zip(elements) {
+                    self.store(name, element)?;
+                }
+                ev.push(value);
+            },
+            Step::Append(append) => return self.append(append, ev),
+            Step::Call(function, count) => {
+                let arguments = ev.pop_n(count);
+                let value = self.call_value(&function, arguments)?;
+                ev.push(value);
+            },
+            // Awaiting anything other than a task just gives the value back
+            Step::Await => match ev.pop() {
+                Value::Task(task) => {
+                    let value = self.join_task(&task)?;
+                    ev.push(value);
+                },
+                value => ev.push(value),
+            },
+            Step::Return => {
+                let value = ev.pop();
+                return Err(self.unwind(Unwind::Return(value), "'return' outside of a function"));
+            },
+            Step::Break(label) => {
+                let value = ev.pop();
+                return Err(self.unwind(Unwind::Break(label.clone(), value), "'break' outside of a loop"));
+            },
+            Step::Index => {
+                let index = ev.pop();
+                let object = ev.pop();
+                ev.push(Self::index(object, index)?);
+            },
+            Step::Field(name) => match ev.pop() {
+                Value::Map(entries) => {
+                    let value = entries.borrow().get(name.as_str()).cloned().ok_or_else(|| format!("Map has no entry '{}'", name))?;
+                    ev.push(value);
+                },
+                other => return Err(format!("Cannot assign to a field of {}", other.type_name())),
+            },
+            Step::IndexAssign => {
+                let value = ev.pop();
+                let index = ev.pop();
+                let object = ev.pop();
+                ev.push(Self::store_index(object, index, value)?);
+            },
+            // Earlier statements' values are dropped as soon as they are made, so
+            // they don't keep strings shared (see append_in_place)
+            Step::Statements(statements) => {
+                if let Some((next, rest)) = statements.split_first() {
+                    drop(ev.pop());
+                    ev.work.push(Step::Statements(rest));
+                    ev.evaluate(next);
+                }
+            },
+            Step::If(then_branch, else_branch) => {
+                let condition = ev.pop();
+                let branch = if self.condition(condition)? { Some(then_branch) } else { else_branch };
+                match branch {
+                    Some(branch) => {
+                        self.enter_scope(Vec::new());
+                        ev.work.push(Step::LeaveScope);
+                        ev.evaluate(branch);
+                    },
+                    None => ev.push(Value::Nil),
+                }
+            },
+            Step::LeaveScope => self.leave_scope(),
+            Step::ForStart(expr) => return self.start_for(expr, ev),
+            Step::For(mut state) => {
+                self.leave_scope();
+                state.result = ev.pop();
+                return self.iterate(state, ev);
+            },
+            Step::WhileCondition(repeat) => {
+                let condition = ev.pop();
+                if self.condition(condition)? {
+                    self.enter_body(|base| Step::WhileBody(repeat, base), repeat.body, Vec::new(), ev);
+                } else {
+                    ev.push(Value::Nil);
+                }
+            },
+            Step::WhileBody(repeat, _) => {
+                self.leave_scope();
+                drop(ev.pop());
+                ev.work.push(Step::WhileCondition(repeat));
+                ev.evaluate(repeat.condition);
+            },
+            // The condition can see the variables defined by the body
+            Step::DoBody(repeat, _) => {
+                drop(ev.pop());
+                ev.work.push(Step::DoCondition(repeat));
+                ev.evaluate(repeat.condition);
+            },
+            Step::DoCondition(repeat) => {
+                self.leave_scope();
+                let condition = ev.pop();
+                if self.condition(condition)? {
+                    self.enter_body(|base| Step::DoBody(repeat, base), repeat.body, Vec::new(), ev);
+                } else {
+                    ev.push(Value::Nil);
+                }
+            },
+            Step::LoopBody(body, label, _) => {
+                self.leave_scope();
+                drop(ev.pop());
+                self.enter_body(|base| Step::LoopBody(body, label, base), body, Vec::new(), ev);
+            },
+            Step::Apply { transformer, arguments, safe } => {
+                let object = ev.pop();
+
+                // `x?.t()` skips the application entirely when x is nil
+                if safe && matches!(object, Value::Nil) {
+                    ev.push(Value::Nil);
+                    return Ok(());
+                }
+
+                // The default of unwrap_or() is only evaluated when it is needed
+                if let (Value::Result(result), "unwrap_or", [default]) = (&object, transformer.as_str(), arguments) {
+                    match &**result {
+                        Ok(value) => ev.push(value.clone()),
+                        Err(_) => ev.evaluate(default),
+                    }
+                    return Ok(());
+                }
+
+                ev.push(object);
+                ev.work.push(Step::ApplyWith(transformer, arguments.len()));
+                ev.evaluate_all(arguments.iter());
+            },
+            Step::ApplyWith(transformer, count) => {
+                let arguments = ev.pop_n(count);
+                let object = ev.pop();
+                let value = self.apply(object, transformer, arguments)?;
+                ev.push(value);
+            },
+            Step::Member { name, safe } => {
+                let object = ev.pop();
+
+                if safe && matches!(object, Value::Nil) {
+                    ev.push(Value::Nil);
+                    return Ok(());
+                }
+
+                // A map's own entries take precedence over transformers
+                let entry = match &object {
+                    Value::Map(entries) => Some(entries.borrow().get(name.as_str()).cloned()),
+                    _ => None,
+                };
+                let value = match entry {
+                    Some(Some(value)) => value,
+                    entry => {
+                        let is_map = entry.is_some();
+                        let undefined = format!("Undefined transformer '{}'", name);
+                        self.apply(object, name, Vec::new()).map_err(|e| {
+                            if is_map && e == undefined { format!("Map has no entry or transformer named '{}'", name) } else { e }
+                        })?
+                    },
+                };
+                ev.push(value);
+            },
+            Step::AssertEqual(assertion) => {
+                let expected = ev.pop();
+                let actual = ev.pop();
+                let outcome = self.operate(actual.clone(), TokenType::EqualEqual, expected.clone())?;
+                let detail = format!("expected {}, got {}", describe(&expected), describe(&actual));
+                return self.check(outcome, detail, assertion, ev);
+            },
+            // A failed comparison shows both operands
+            Step::Compare(operator, assertion) => {
+                let right = ev.pop();
+                let left = ev.pop();
+                let detail = format!("{} {} {}", describe(&left), operator.literal, describe(&right));
+                let outcome = self.operate(left, operator.token_type, right)?;
+                return self.check(outcome, detail, assertion, ev);
+            },
+            Step::AssertCondition(assertion) => {
+                let outcome = ev.pop();
+                let detail = format!("condition was {}", describe(&outcome));
+                return self.check(outcome, detail, assertion, ev);
+            },
+            Step::AssertMessage(error) => {
+                let message = ev.pop();
+                return Err(format!("{} ({})", error, message));
+            },
+        }
+        Ok(())
+    }
+
+    // Starts evaluating an expression: one without operands is evaluated at
+    // once, others push the steps that evaluate their operands and use them
+    fn start<'e>(&mut self, expr: &'e Expr, ev: &mut Evaluation<'e>) -> Result<(), String> {
+        if let Some(position) = expr.position() {
+            ev.work.push(Step::Locate(position));
+        }
+
+        match expr {
+            Expr::Int(value) => ev.push(Value::Int(*value)),
+            Expr::BigInt(digits) => ev.push(bigint::parse(digits).ok_or_else(|| format!("Integer literal '{}' is too large", digits))?),
+            Expr::Number(value) => ev.push(Value::Number(*value)),
+            Expr::String(value) => ev.push(Value::String(value.clone().into())),
+            Expr::Boolean(value) => ev.push(Value::Boolean(*value)),
+            Expr::Nil => ev.push(Value::Nil),
+            Expr::Array(elements) => {
+                ev.work.push(Step::Array(elements.len()));
+                ev.evaluate_all(elements.iter());
+            },
+            Expr::Tuple(elements) => {
+                ev.work.push(Step::Tuple(elements.len()));
+                ev.evaluate_all(elements.iter());
+            },
+            Expr::Map(entries) => {
+                ev.work.push(Step::Map(entries));
+                ev.evaluate_all(entries.iter().map(|(_, value)| value));
+            },
+            Expr::Variable(name) => match self.environment.get(*name) {
+                Some(value) => ev.push(value),
+                None => return Err(format!("Undefined variable: {}", name)),
+            },
+            Expr::Local { name, depth, slot } => match self.environment.slot(*depth, *slot) {
+                Some(value) => ev.push(value.clone()),
+                None => return Err(format!("Undefined variable: {}", name)),
+            },
+            Expr::Binary { left, operator, right } => {
+                if operator.token_type == TokenType::QuestionQuestion {
+                    ev.work.push(Step::Coalesce(right));
+                } else {
+                    ev.work.push(Step::Binary(operator.token_type));
+                    ev.evaluate(right);
+                }
+                ev.evaluate(left);
+            },
+            Expr::Unary { operator, right } => {
+                ev.work.push(Step::Unary(operator.token_type));
+                ev.evaluate(right);
+            },
+            Expr::Let { name, value, .. } => match value {
+                Some(value) => {
+                    ev.work.push(Step::Let(*name));
+                    ev.evaluate(value);
+                },
+                None => {
+                    self.environment.define(*name, Value::Nil);
+                    ev.push(Value::Nil);
+                },
+            },
+            Expr::Assign { name, value, .. } => match self.appending(*name, value) {
+                Some(append) => return self.next_addition(append, ev),
+                None => {
+                    ev.work.push(Step::Assign(*name));
+                    ev.evaluate(value);
+                },
+            },
+            Expr::TupleAssign { names, value, .. } => {
+                ev.work.push(Step::TupleAssign(names));
+                ev.evaluate(value);
+            },
+            Expr::Call { callee, arguments } => {
+                // Builtins are looked up like any other function, so a definition in M
+                // code shadows the builtin of the same name, and a variable can alias one.
+                // Extra arguments are still evaluated, for their side effects and for --strict.
+                match self.environment.get(*callee) {
+                    Some(function @ (Value::Function { .. } | Value::NativeFunction(_) | Value::Builtin(_))) => {
+                        ev.work.push(Step::Call(function, arguments.len()));
+                        ev.evaluate_all(arguments.iter());
+                    },
+                    _ => return Err(format!("Undefined function '{}'", callee)),
+                }
+            },
+            Expr::Await(task) => {
+                ev.work.push(Step::Await);
+                ev.evaluate(task);
+            },
+            Expr::Function { name, params, locals, body, is_async, .. } => {
+                let layout = Layout::new(params.iter().chain(locals.iter()).copied(), self.file.clone());
+
+                // A function defined in a call keeps copies of the scopes it sees
+                // there, for when it's called after the call has returned
+                let captured = self.environment.capture().map(|mut scopes| {
+                    // It can call itself by name from there. Such a call runs inside
+                    // a call of this value, which has the scopes, so the copy needs none.
+                    if let Some(name) = name {
+                        let itself = Value::Function { params: params.clone(), layout: layout.clone(), body: body.clone(), is_async: *is_async, captured: None };
+                        scopes.define(*name, itself);
+                    }
+                    Arc::new(scopes)
+                });
+
+                let function = Value::Function {
+                    params: params.clone(),
+                    layout,
+                    body: body.clone(),
+                    is_async: *is_async,
+                    captured,
+                };
+
+                if let Some(name) = name {
+                    self.environment.define(*name, function.clone());
+                }
+
+                ev.push(function);
+            },
+            Expr::Transformer { name, receiver, params, locals, body, .. } => {
+                let receiver = receiver.unwrap_or_else(|| Symbol::new("applied"));
+                let transformer = Value::Transformer {
+                    receiver,
+                    params: params.clone(),
+                    layout: Layout::new(
+                        std::iter::once(receiver).chain(params.iter().copied()).chain(locals.iter().copied()),
+                        self.file.clone(),
+                    ),
+                    body: body.clone(),
+                };
+
+                self.environment.define(*name, transformer.clone());
+
+                ev.push(transformer);
+            },
+            Expr::Return { value, .. } => match value {
+                Some(value) => {
+                    ev.work.push(Step::Return);
+                    ev.evaluate(value);
+                },
+                None => return Err(self.unwind(Unwind::Return(Value::Nil), "'return' outside of a function")),
+            },
+            Expr::Break { label, value } => match value {
+                Some(value) => {
+                    ev.work.push(Step::Break(label));
+                    ev.evaluate(value);
+                },
+                None => return Err(self.unwind(Unwind::Break(label.clone(), Value::Nil), "'break' outside of a loop")),
+            },
+            Expr::Continue { label } => return Err(self.unwind(Unwind::Continue(label.clone()), "'continue' outside of a loop")),
+            Expr::Index { object, index } => {
+                ev.work.push(Step::Index);
+                ev.evaluate(index);
+                ev.evaluate(object);
+            },
+            Expr::IndexAssign { object, index, value } => {
+                ev.work.push(Step::IndexAssign);
+                ev.evaluate(value);
+                ev.evaluate(index);
+
+                // The container is found by following a path such as
+                // `config["server"].ports`. Each step must be an element or map
+                // entry: the result of a transformer is a new value, so storing
+                // into it would be lost. The steps are pushed outermost first, so
+                // they run from the variable outwards.
+                let mut target = &**object;
+                loop {
+                    match target {
+                        Expr::Index { object, index } => {
+                            ev.work.push(Step::Index);
+                            ev.evaluate(index);
+                            target = object;
+                        },
+                        Expr::Member { object, name, .. } => {
+                            ev.work.push(Step::Field(*name));
+                            target = object;
+                        },
+                        _ => break ev.evaluate(target),
+                    }
+                }
+            },
+            Expr::Block(statements) => match statements.split_first() {
+                Some((first, rest)) => {
+                    ev.work.push(Step::Statements(rest));
+                    ev.evaluate(first);
+                },
+                None => ev.push(Value::Nil),
+            },
+            Expr::If { condition, then_branch, else_branch } => {
+                ev.work.push(Step::If(then_branch, else_branch.as_deref()));
+                ev.evaluate(condition);
+            },
+            Expr::For { iterable, step, .. } => {
+                ev.work.push(Step::ForStart(expr));
+                if let Some(step) = step {
+                    ev.evaluate(step);
+                }
+                ev.evaluate(iterable);
+            },
+            Expr::While { condition, body, label } => {
+                ev.work.push(Step::WhileCondition(Repeat { condition, body, label }));
+                ev.evaluate(condition);
+            },
+            Expr::DoWhile { body, condition, label } => {
+                let repeat = Repeat { condition, body, label };
+                self.enter_body(|base| Step::DoBody(repeat, base), body, Vec::new(), ev);
+            },
+            Expr::Loop { body, label } => self.enter_body(|base| Step::LoopBody(body, label, base), body, Vec::new(), ev),
+            Expr::Apply { object, transformer, arguments, safe } => {
+                ev.work.push(Step::Apply { transformer: *transformer, arguments, safe: *safe });
+                ev.evaluate(object);
+            },
+            Expr::Member { object, name, safe } => {
+                ev.work.push(Step::Member { name: *name, safe: *safe });
+                ev.evaluate(object);
+            },
+            Expr::Assert { callee, arguments, line, column } => {
+                let (line, column) = (*line, *column);
+                if callee == "assert_eq" {
+                    if !(2..=3).contains(&arguments.len()) {
+                        return Err("assert_eq() takes 2 or 3 arguments".to_string());
+                    }
+                    ev.work.push(Step::AssertEqual(Assertion { message: arguments.get(2), line, column }));
+                    ev.evaluate_all(arguments[..2].iter());
+                } else {
+                    if !(1..=2).contains(&arguments.len()) {
+                        return Err("assert() takes 1 or 2 arguments".to_string());
+                    }
+                    let assertion = Assertion { message: arguments.get(1), line, column };
+                    match &arguments[0] {
+                        Expr::Binary { left, operator, right } if matches!(
+                            operator.token_type,
+                            TokenType::EqualEqual | TokenType::BangEqual
+                                | TokenType::LessThan | TokenType::LessThanEqual
+                                | TokenType::GreaterThan | TokenType::GreaterThanEqual
+                        ) => {
+                            ev.work.push(Step::Compare(operator, assertion));
+                            ev.evaluate(right);
+                            ev.evaluate(left);
+                        },
+                        condition => {
+                            ev.work.push(Step::AssertCondition(assertion));
+                            ev.evaluate(condition);
+                        },
+                    }
+                }
+            },
+            Expr::Pragma { .. } => {
+                self.set_strict(true);
+                ev.push(Value::Nil);
+            },
+            Expr::Use { path } => {
+                self.import(path)?;
+                ev.push(Value::Nil);
+            },
+        }
+        Ok(())
+    }
+
+    // Unwinds the work left after a step failed, leaving the scopes the steps
+    // entered and placing the error at the innermost statement it passes, until
+    // a loop takes the `break` or `continue` it signals. Fails with the error
+    // when nothing takes it.
+    fn recover(&mut self, mut error: String, ev: &mut Evaluation) -> Result<(), String> {
+        while let Some(step) = ev.work.pop() {
+            let resumed = match step {
+                Step::Locate(position) => {
+                    self.locate(&error, position);
+                    continue;
+                },
+                Step::LeaveScope | Step::DoCondition(_) => {
+                    self.leave_scope();
+                    continue;
+                },
+                Step::For(mut state) => {
+                    self.leave_scope();
+                    match self.flow(Err(error), state.label) {
+                        Ok(Flow::Next(value)) => {
+                            ev.values.truncate(state.base);
+                            state.result = value;
+                            self.iterate(state, ev)
+                        },
+                        Ok(Flow::Break(value)) => {
+                            ev.values.truncate(state.base);
+                            ev.push(value);
+                            Ok(())
+                        },
+                        Err(e) => Err(e),
+                    }
+                },
+                Step::WhileBody(repeat, base) => {
+                    self.leave_scope();
+                    match self.flow(Err(error), repeat.label) {
+                        Ok(Flow::Next(_)) => {
+                            ev.values.truncate(base);
+                            ev.work.push(Step::WhileCondition(repeat));
+                            ev.evaluate(repeat.condition);
+                            Ok(())
+                        },
+                        Ok(Flow::Break(value)) => {
+                            ev.values.truncate(base);
+                            ev.push(value);
+                            Ok(())
+                        },
+                        Err(e) => Err(e),
+                    }
+                },
+                // A `continue` goes on to the condition, in the body's scope
+                Step::DoBody(repeat, base) => match self.flow(Err(error), repeat.label) {
+                    Ok(Flow::Next(_)) => {
+                        ev.values.truncate(base);
+                        ev.work.push(Step::DoCondition(repeat));
+                        ev.evaluate(repeat.condition);
+                        Ok(())
+                    },
+                    Ok(Flow::Break(value)) => {
+                        self.leave_scope();
+                        ev.values.truncate(base);
+                        ev.push(value);
+                        Ok(())
+                    },
+                    Err(e) => {
+                        self.leave_scope();
+                        Err(e)
+                    },
+                },
+                Step::LoopBody(body, label, base) => {
+                    self.leave_scope();
+                    match self.flow(Err(error), label) {
+                        Ok(Flow::Next(_)) => {
+                            ev.values.truncate(base);
+                            self.enter_body(|base| Step::LoopBody(body, label, base), body, Vec::new(), ev);
+                            Ok(())
+                        },
+                        Ok(Flow::Break(value)) => {
+                            ev.values.truncate(base);
+                            ev.push(value);
+                            Ok(())
+                        },
+                        Err(e) => Err(e),
+                    }
+                },
+                _ => continue,
+            };
+
+            match resumed {
+                Ok(()) => return Ok(()),
+                Err(e) => error = e,
+            }
+        }
+        Err(error)
+    }
+
+    // Runs a loop's body in a block scope of its own, with `frame` waiting for it
+    fn enter_body<'e>(&mut self, frame: impl FnOnce(usize) -> Step<'e>, body: &'e Expr, bindings: Vec<(Symbol, Value)>, ev: &mut Evaluation<'e>) {
+        self.enter_scope(bindings);
+        ev.work.push(frame(ev.values.len()));
+        ev.evaluate(body);
+    }
+
+    fn start_for<'e>(&mut self, expr: &'e Expr, ev: &mut Evaluation<'e>) -> Result<(), String> {
+        let Expr::For { variable, second, step, body, label, .. } = expr else {
+            unreachable!("only a `for` loop starts one");
+        };
+
+        let step = match step {
+            Some(_) => match ev.pop() {
+                Value::Int(0) => return Err("Loop step can't be zero".to_string()),
+                Value::Int(n) => Some(n),
+                _ => return Err("Loop step must be an integer".to_string()),
+            },
+            None => None,
+        };
+
+        let iterable = ev.pop();
+        let elements = match iterable {
+            Value::Array(_) | Value::Set(_) | Value::Tuple(_) => Elements::Values(iterable.elements()?.unwrap_or_default().into_iter()),
+            // Iterating a map visits its keys in sorted order
+            Value::Map(entries) => Elements::Entries(entries.get().into_iter(), second.is_some()),
+            Value::Range { start, end } => Elements::Range { next: Some(start), end, step: step.unwrap_or(1) },
+            _ if step.is_some() => return Err(format!("Loop step can only be used with a range, not {}", iterable.type_name())),
+            Value::Bytes(bytes) => Elements::Bytes(bytes.into_iter()),
+            Value::Receiver(channel) => Elements::Messages(channel),
+            Value::String(s) => Elements::Chars(s, 0),
+            _ => return Err(format!("Cannot iterate over non-iterable value: {:?}", iterable)),
+        };
+
+        let state = ForLoop { variable: *variable, second: *second, body, label, elements, result: Value::Nil, base: 0 };
+        self.iterate(Box::new(state), ev)
+    }
+
+    // Runs a `for` loop's body for its next element, or ends the loop with the
+    // value of the last run
+    fn iterate<'e>(&mut self, mut state: Box<ForLoop<'e>>, ev: &mut Evaluation<'e>) -> Result<(), String> {
+        let Some(element) = state.elements.next()? else {
+            ev.push(std::mem::take(&mut state.result));
+            return Ok(());
+        };
+
+        // A loop's value is its last iteration's; dropping the previous one first
+        // leaves strings it shared free to be appended to in place
+        drop(std::mem::take(&mut state.result));
+        let bindings = Self::loop_bindings(state.variable, state.second, element)?;
+        let body = state.body;
+        self.enter_body(|base| {
+            state.base = base;
+            Step::For(state)
+        }, body, bindings, ev);
+        Ok(())
+    }
+
+    // `s = s + x` (or `s = s + x + y`) with a string or array `s` appends to it
+    // in place when nothing else shares it, so building a string or array in a
+    // loop takes linear time rather than copying it on every step. None for
+    // other assignments.
+    fn appending<'e>(&self, name: Symbol, value: &'e Expr) -> Option<Box<Append<'e>>> {
+        // The operands added to the variable, last one first
+        let mut operands = Vec::new();
+        let mut expr = value;
+        while let Expr::Binary { left, operator, right } = expr {
+            if operator.token_type != TokenType::Plus {
+                return None;
+            }
+            operands.push(&**right);
+            expr = left;
+        }
+        if operands.is_empty() || !matches!(expr, Expr::Variable(variable) | Expr::Local { name: variable, .. } if *variable == name) {
+            return None;
+        }
+        let current = match self.environment.get(name) {
+            Some(current @ (Value::String(_) | Value::Array(_))) => current,
+            _ => return None,
+        };
+
+        Some(Box::new(Append { name, current, operands, additions: Vec::new(), sum: None }))
+    }
+
+    // Adds the value of the operand just evaluated to an appending assignment
+    fn append<'e>(&mut self, mut append: Box<Append<'e>>, ev: &mut Evaluation<'e>) -> Result<(), String> {
+        let addition = ev.pop();
+        match append.sum.take() {
+            Some(sum) => append.sum = Some(self.operate(sum, TokenType::Plus, addition)?),
+            None => {
+                // Maps may overload `+`, and arrays only concatenate with arrays, so
+                // from any other operand on the sum is worked out as usual
+                let appendable = match append.current {
+                    Value::Array(_) => matches!(addition, Value::Array(_)),
+                    _ => !matches!(addition, Value::Map(_)),
+                };
+                if appendable {
+                    append.additions.push(addition);
+                } else {
+                    let sum = Self::concatenate(&append.current, &append.additions);
+                    append.sum = Some(self.operate(sum, TokenType::Plus, addition)?);
+                }
+            },
+        }
+        self.next_addition(append, ev)
+    }
+
+    // Evaluates the next operand of an appending assignment, or stores the
+    // result once they all have been
+    fn next_addition<'e>(&mut self, mut append: Box<Append<'e>>, ev: &mut Evaluation<'e>) -> Result<(), String> {
+        if let Some(operand) = append.operands.pop() {
+            ev.work.push(Step::Append(append));
+            ev.evaluate(operand);
+            return Ok(());
+        }
+
+        let Append { name, current, additions, sum, .. } = *append;
+        let value = match sum {
+            Some(sum) => sum,
+            None => self.append_in_place(name, current, additions),
+        };
+        self.store(name, value.clone())?;
+        ev.push(value);
+        Ok(())
+    }
+
+    // Ends an assertion. A failure names where the assertion is written and the
+    // values it compared; an optional last argument, evaluated only then, adds
+    // a message.
+    fn check<'e>(&mut self, outcome: Value, detail: String, assertion: Assertion<'e>, ev: &mut Evaluation<'e>) -> Result<(), String> {
+        if self.condition(outcome)? {
+            ev.push(Value::Nil);
+            return Ok(());
+        }
+
+        let error = format!("Assertion failed at line {}, column {}: {}", assertion.line, assertion.column, detail);
+        match assertion.message {
+            Some(message) => {
+                ev.work.push(Step::AssertMessage(error));
+                ev.evaluate(message);
+                Ok(())
+            },
+            None => Err(error),
+        }
+    }
+}
@@ -0,0 +1,510 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use crate::ast::Expr;
+use crate::lexer::Lexer;
+use crate::parser::Parser;
+use crate::resolver::BUILTIN_FUNCTIONS;
+use crate::symbol::Symbol;
+use crate::token::TokenType;
+
+// Builtins and operators that JavaScript lacks, included at the top of every
+// compiled program
+const RUNTIME: &str = include_str!("js_runtime.js");
+
+// Names M allows that JavaScript reserves; variables with these names get a `$` prefix
+const RESERVED: &[&str] = &[
+    "arguments", "await", "break", "case", "catch", "class", "const", "continue",
+    "debugger", "default", "delete", "do", "else", "enum", "eval", "export", "extends",
+    "false", "finally", "for", "function", "if", "implements", "import", "in",
+    "instanceof", "interface", "let", "new", "null", "package", "private", "protected",
+    "public", "return", "static", "super", "switch", "this", "throw", "true", "try",
+    "typeof", "var", "void", "while", "with", "yield", "undefined", "NaN", "Infinity",
+];
+
+/// Compiles a program to a standalone JavaScript file: the runtime followed by
+/// the program. Modules it imports with `use` are compiled into the same file.
+///
+/// Variables are scoped lexically in the output: a function sees the variables
+/// around its definition rather than those of its caller.
+pub fn compile(program: &Expr, base_path: &Path) -> Result<String, String> {
+    let mut compiler = Compiler {
+        output: String::new(),
+        indent: 0,
+        scopes: Vec::new(),
+        base_path: base_path.to_path_buf(),
+        included: HashSet::new(),
+        function_depth: 0,
+        top_level_await: false,
+    };
+
+    compiler.block(statements_of(program), false)?;
+
+    let mut output = format!("{}\n", RUNTIME);
+    if compiler.top_level_await {
+        // Top-level `await` needs an async function to run in
+        output.push_str("(async () => {\n");
+        for line in compiler.output.lines() {
+            output.push_str(if line.is_empty() { "" } else { "  " });
+            output.push_str(line);
+            output.push('\n');
+        }
+        output.push_str("})();\n");
+    } else {
+        output.push_str(&compiler.output);
+    }
+    Ok(output)
+}
+
+fn statements_of(expr: &Expr) -> &[Expr] {
+    match expr {
+        Expr::Block(statements) => statements,
+        statement => std::slice::from_ref(statement),
+    }
+}
+
+fn ident(name: &str) -> String {
+    if RESERVED.contains(&name) { format!("${}", name) } else { name.to_string() }
+}
+
+fn string(s: &str) -> String {
+    serde_json::to_string(s).expect("strings always serialize")
+}
+
+struct Compiler {
+    output: String,
+    indent: usize,
+    // Names declared with `let` in each enclosing JavaScript block
+    scopes: Vec<HashSet<String>>,
+    base_path: PathBuf,
+    // Modules already compiled into the output
+    included: HashSet<PathBuf>,
+    function_depth: usize,
+    top_level_await: bool,
+}
+
+impl Compiler {
+    fn line(&mut self, text: &str) {
+        for _ in 0..self.indent {
+            self.output.push_str("  ");
+        }
+        self.output.push_str(text);
+        self.output.push('\n');
+    }
+
+    fn is_declared(&self, name: &str) -> bool {
+        self.scopes.iter().any(|scope| scope.contains(name))
+    }
+
+    // Builtins come from the runtime unless the program defines the name itself
+    fn is_builtin(&self, name: &str) -> bool {
+        BUILTIN_FUNCTIONS.contains(&name) && !self.is_declared(name)
+    }
+
+    // Compiles statements as a JavaScript block: the variables it assigns first
+    // are declared at its top. A function body returns its last expression.
+    fn block(&mut self, statements: &[Expr], function_body: bool) -> Result<(), String> {
+        self.scopes.push(HashSet::new());
+        self.declare(statements)?;
+        let result = self.statements(statements, function_body);
+        self.scopes.pop();
+        result
+    }
+
+    // Declares the variables first assigned by these statements, or by modules
+    // they import, in the innermost scope
+    fn declare(&mut self, statements: &[Expr]) -> Result<(), String> {
+        let mut names = Vec::new();
+        let mut visited = HashSet::new();
+        self.collect_declarations(statements, &mut names, &mut visited)?;
+
+        if !names.is_empty() {
+            let names: Vec<String> = names.iter().map(|name| ident(name)).collect();
+            self.line(&format!("let {};", names.join(", ")));
+        }
+        Ok(())
+    }
+
+    fn collect_declarations(&mut self, statements: &[Expr], names: &mut Vec<String>, visited: &mut HashSet<PathBuf>) -> Result<(), String> {
+        for statement in statements {
+            match statement {
+                // `let` always makes a new variable in its block
+                Expr::Let { name, .. } => {
+                    let scope = self.scopes.last_mut().expect("a block is being compiled");
+                    if scope.insert(name.to_string()) {
+                        names.push(name.to_string());
+                    }
+                },
+                Expr::Assign { name, .. }
+                | Expr::Function { name: Some(name), .. }
+                | Expr::Transformer { name, .. } if !self.is_declared(name) => {
+                    self.scopes.last_mut().expect("a block is being compiled").insert(name.to_string());
+                    names.push(name.to_string());
+                },
+                Expr::TupleAssign { names: assigned, .. } => {
+                    for name in assigned {
+                        if !self.is_declared(name) {
+                            self.scopes.last_mut().expect("a block is being compiled").insert(name.to_string());
+                            names.push(name.to_string());
+                        }
+                    }
+                },
+                Expr::Use { path } => {
+                    let (full_path, program) = self.load_module(path)?;
+                    if !self.included.contains(&full_path) && visited.insert(full_path) {
+                        self.collect_declarations(statements_of(&program), names, visited)?;
+                    }
+                },
+                _ => {},
+            }
+        }
+        Ok(())
+    }
+
+    fn load_module(&self, path: &str) -> Result<(PathBuf, Expr), String> {
+        let full_path = self.base_path.join(path);
+        let full_path = fs::canonicalize(&full_path).unwrap_or(full_path);
+        let source = fs::read_to_string(&full_path)
+            .map_err(|e| format!("Failed to read file '{}': {}", full_path.display(), e))?;
+        let tokens = Lexer::new(&source).tokenize()?;
+        let program = Parser::new(tokens).parse()?;
+        Ok((full_path, program))
+    }
+
+    fn statements(&mut self, statements: &[Expr], function_body: bool) -> Result<(), String> {
+        for (i, statement) in statements.iter().enumerate() {
+            if function_body && i + 1 == statements.len() && !is_statement(statement) {
+                let value = self.expression(statement)?;
+                self.line(&format!("return {};", value));
+            } else {
+                self.statement(statement)?;
+            }
+        }
+        Ok(())
+    }
+
+    // A nested block of statements, in braces after `head`
+    fn nested(&mut self, head: &str, body: &Expr) -> Result<(), String> {
+        self.line(&format!("{} {{", head));
+        self.indent += 1;
+        self.block(statements_of(body), false)?;
+        self.indent -= 1;
+        Ok(())
+    }
+
+    fn statement(&mut self, statement: &Expr) -> Result<(), String> {
+        match statement {
+            Expr::Let { name, value, .. } => {
+                let value = match value {
+                    Some(value) => self.expression(value)?,
+                    None => "null".to_string(),
+                };
+                self.line(&format!("{} = {};", ident(name), value));
+            },
+            Expr::Assign { name, value, .. } => {
+                let value = self.expression(value)?;
+                self.line(&format!("{} = {};", ident(name), value));
+            },
+            Expr::TupleAssign { names, value, .. } => {
+                let value = self.expression(value)?;
+                let targets: Vec<String> = names.iter().map(|name| ident(name)).collect();
+                let names: Vec<String> = names.iter().map(|name| string(name)).collect();
+                self.line(&format!("[{}] = $m.unpack({}, [{}]);", targets.join(", "), value, names.join(", ")));
+            },
+            Expr::Function { name: Some(name), params, body, is_async, .. } => {
+                let keyword = if *is_async { "async function" } else { "function" };
+                self.function(&format!("{} = {}", ident(name), keyword), params, body, None)?;
+                self.line("};");
+            },
+            Expr::Transformer { name, receiver, params, body, .. } => {
+                // The applied value is the first parameter
+                let head = format!("{} = $m.transformers[{}] = $m.transformer(function", ident(name), string(name));
+                self.function(&head, params, body, Some(receiver.as_deref().unwrap_or("applied")))?;
+                self.line("});");
+            },
+            Expr::Return { value, .. } => match value {
+                Some(value) => {
+                    let value = self.expression(value)?;
+                    self.line(&format!("return {};", value));
+                },
+                None => self.line("return null;"),
+            },
+            Expr::Block(statements) => {
+                self.line("{");
+                self.indent += 1;
+                self.block(statements, false)?;
+                self.indent -= 1;
+                self.line("}");
+            },
+            Expr::If { .. } => {
+                self.if_statement(statement, "")?;
+                self.line("}");
+            },
+            Expr::While { condition, body, label } => {
+                let condition = self.expression(condition)?;
+                self.nested(&format!("{}while ($m.truthy({}))", label_prefix(label), condition), body)?;
+                self.line("}");
+            },
+            Expr::For { variable, second, iterable, step, body, label } => {
+                let iterable = self.expression(iterable)?;
+                let head = match (second, step) {
+                    (Some(second), _) => format!("let [{}, {}] of $m.pairs({})", ident(variable), ident(second), iterable),
+                    (None, Some(step)) => format!("let {} of $m.iter({}, {})", ident(variable), iterable, self.expression(step)?),
+                    (None, None) => format!("let {} of $m.iter({})", ident(variable), iterable),
+                };
+
+                self.scopes.push(std::iter::once(variable).chain(second).map(Symbol::to_string).collect());
+                let result = self.nested(&format!("{}for ({})", label_prefix(label), head), body);
+                self.scopes.pop();
+                result?;
+                self.line("}");
+            },
+            Expr::DoWhile { body, condition, label } => {
+                // The condition sees the body's variables, so they are declared outside the loop
+                self.scopes.push(HashSet::new());
+                let result = self.do_while(body, condition, label);
+                self.scopes.pop();
+                result?;
+            },
+            Expr::Loop { body, label } => {
+                self.nested(&format!("{}while (true)", label_prefix(label)), body)?;
+                self.line("}");
+            },
+            Expr::Break { label, value } => {
+                if value.is_some() {
+                    return Err("A loop's value can't be used in JavaScript output".to_string());
+                }
+                self.line(&format!("break{};", label_suffix(label)));
+            },
+            Expr::Continue { label } => self.line(&format!("continue{};", label_suffix(label))),
+            Expr::Use { path } => {
+                let (full_path, program) = self.load_module(path)?;
+                if self.included.insert(full_path) {
+                    self.line(&format!("// use {}", string(path)));
+                    self.statements(statements_of(&program), false)?;
+                }
+            },
+            // Pragmas only change how the interpreter checks programs
+            Expr::Pragma { .. } => {},
+            expr => {
+                let value = self.expression(expr)?;
+                self.line(&format!("{};", value));
+            },
+        }
+        Ok(())
+    }
+
+    fn function(&mut self, head: &str, params: &[Symbol], body: &[Expr], applied: Option<&str>) -> Result<(), String> {
+        let names: Vec<String> = applied.into_iter().map(str::to_string).chain(params.iter().map(Symbol::to_string)).collect();
+        let list: Vec<String> = names.iter().map(|name| ident(name)).collect();
+        self.line(&format!("{} ({}) {{", head, list.join(", ")));
+
+        self.scopes.push(names.into_iter().collect());
+        self.indent += 1;
+        self.function_depth += 1;
+        let result = self.block(body, true);
+        self.function_depth -= 1;
+        self.indent -= 1;
+        self.scopes.pop();
+        result
+    }
+
+    // Writes an `if` chain up to its closing brace
+    fn if_statement(&mut self, statement: &Expr, prefix: &str) -> Result<(), String> {
+        let Expr::If { condition, then_branch, else_branch } = statement else {
+            unreachable!("only called with an if");
+        };
+
+        let condition = self.expression(condition)?;
+        self.nested(&format!("{}if ($m.truthy({}))", prefix, condition), then_branch)?;
+        match else_branch.as_deref() {
+            Some(branch @ Expr::If { .. }) => self.if_statement(branch, "} else ")?,
+            Some(branch) => self.nested("} else", branch)?,
+            None => {},
+        }
+        Ok(())
+    }
+
+    fn do_while(&mut self, body: &Expr, condition: &Expr, label: &Option<String>) -> Result<(), String> {
+        let body = statements_of(body);
+        self.declare(body)?;
+        self.line(&format!("{}do {{", label_prefix(label)));
+        self.indent += 1;
+        self.statements(body, false)?;
+        self.indent -= 1;
+        let condition = self.expression(condition)?;
+        self.line(&format!("}} while ($m.truthy({}));", condition));
+        Ok(())
+    }
+
+    fn expression(&mut self, expr: &Expr) -> Result<String, String> {
+        Ok(match expr {
+            Expr::Int(n) => n.to_string(),
+            // JavaScript numbers are floats, so this is the nearest one
+            Expr::BigInt(digits) => digits.clone(),
+            Expr::Number(n) if n.is_nan() => "NaN".to_string(),
+            Expr::Number(n) if n.is_infinite() => if *n > 0.0 { "Infinity" } else { "-Infinity" }.to_string(),
+            Expr::Number(n) => format!("{:?}", n),
+            Expr::String(s) => string(s),
+            Expr::Boolean(b) => b.to_string(),
+            Expr::Nil => "null".to_string(),
+            Expr::Array(elements) => format!("[{}]", self.list(elements)?),
+            Expr::Tuple(elements) => format!("$m.tuple({})", self.list(elements)?),
+            Expr::Map(entries) => {
+                if entries.is_empty() {
+                    return Ok("{}".to_string());
+                }
+                let mut compiled = Vec::new();
+                for (key, value) in entries {
+                    // A plain `__proto__` key would set the object's prototype
+                    let key = if key == "__proto__" { format!("[{}]", string(key)) } else { string(key) };
+                    compiled.push(format!("{}: {}", key, self.expression(value)?));
+                }
+                format!("{{ {} }}", compiled.join(", "))
+            },
+            Expr::Variable(name) | Expr::Local { name, .. } if self.is_builtin(name) => format!("$m.{}", name),
+            Expr::Variable(name) | Expr::Local { name, .. } => ident(name),
+            Expr::Binary { left, operator, right } => {
+                let left = self.expression(left)?;
+                let right = self.expression(right)?;
+                match operator.token_type {
+                    TokenType::Plus => format!("$m.add({}, {})", left, right),
+                    TokenType::Divide => format!("$m.div({}, {})", left, right),
+                    TokenType::IntDivide => format!("$m.idiv({}, {})", left, right),
+                    TokenType::Modulo => format!("$m.mod({}, {})", left, right),
+                    TokenType::EqualEqual => format!("$m.eq({}, {})", left, right),
+                    TokenType::BangEqual => format!("!$m.eq({}, {})", left, right),
+                    TokenType::And => format!("({} && {})", left, right),
+                    TokenType::Or => format!("({} || {})", left, right),
+                    TokenType::QuestionQuestion => format!("({} ?? {})", left, right),
+                    _ => format!("({} {} {})", left, operator.literal, right),
+                }
+            },
+            Expr::Unary { operator, right } => {
+                let right = self.expression(right)?;
+                match operator.token_type {
+                    TokenType::Not => format!("!{}", right),
+                    _ => format!("(-{})", right),
+                }
+            },
+            Expr::Let { name, value, .. } => {
+                let value = match value {
+                    Some(value) => self.expression(value)?,
+                    None => "null".to_string(),
+                };
+                format!("({} = {})", ident(name), value)
+            },
+            Expr::Assign { name, value, .. } => format!("({} = {})", ident(name), self.expression(value)?),
+            Expr::Function { name: None, params, body, is_async, .. } => {
+                // Compiled on its own and then written where the value goes
+                let output = std::mem::take(&mut self.output);
+                let result = self.function(if *is_async { "async function" } else { "function" }, params, body, None);
+                self.line("}");
+                let function = std::mem::replace(&mut self.output, output);
+                result?;
+                format!("({})", function.trim())
+            },
+            Expr::Call { callee, arguments } => {
+                let arguments = self.list(arguments)?;
+                if self.is_builtin(callee) {
+                    format!("$m.{}({})", callee, arguments)
+                } else {
+                    format!("{}({})", ident(callee), arguments)
+                }
+            },
+            Expr::Await(task) => {
+                if self.function_depth == 0 {
+                    self.top_level_await = true;
+                }
+                format!("(await {})", self.expression(task)?)
+            },
+            Expr::If { condition, then_branch, else_branch } => {
+                let condition = self.expression(condition)?;
+                let then_branch = self.branch(then_branch)?;
+                let else_branch = match else_branch {
+                    Some(branch) => self.branch(branch)?,
+                    None => "null".to_string(),
+                };
+                format!("($m.truthy({}) ? {} : {})", condition, then_branch, else_branch)
+            },
+            Expr::Index { object, index } => format!("$m.index({}, {})", self.expression(object)?, self.expression(index)?),
+            Expr::IndexAssign { object, index, value } => format!(
+                "$m.setIndex({}, {}, {})",
+                self.expression(object)?,
+                self.expression(index)?,
+                self.expression(value)?,
+            ),
+            Expr::Apply { object, transformer, arguments, safe } => {
+                let object = self.expression(object)?;
+                let arguments = self.list(arguments)?;
+                let safe = if *safe { ", true" } else { "" };
+                format!("$m.apply({}, {}, [{}]{})", object, string(transformer), arguments, safe)
+            },
+            Expr::Member { object, name, safe } => {
+                let object = self.expression(object)?;
+                let safe = if *safe { ", true" } else { "" };
+                format!("$m.member({}, {}{})", object, string(name), safe)
+            },
+            Expr::Assert { callee, arguments, line, column } => {
+                let (runtime, count) = if callee == "assert_eq" { ("assertEq", 2) } else { ("assert", 1) };
+                if arguments.len() != count && arguments.len() != count + 1 {
+                    return Err(format!("{}() takes {} or {} arguments", callee, count, count + 1));
+                }
+                let mut compiled: Vec<String> = arguments.iter().map(|argument| self.expression(argument)).collect::<Result<_, _>>()?;
+                if compiled.len() == count {
+                    compiled.push("undefined".to_string());
+                }
+                format!("$m.{}({}, {}, {})", runtime, compiled.join(", "), line, column)
+            },
+            _ => return Err(format!("{} can't be used as a value in JavaScript output", describe(expr))),
+        })
+    }
+
+    // A branch of an `if` used as a value, which must be a single expression
+    fn branch(&mut self, branch: &Expr) -> Result<String, String> {
+        match branch {
+            Expr::Block(statements) if statements.len() == 1 && !is_statement(&statements[0]) => self.expression(&statements[0]),
+            Expr::Block(_) => Err("An if used as a value must have single-expression branches in JavaScript output".to_string()),
+            branch => self.expression(branch),
+        }
+    }
+
+    fn list(&mut self, exprs: &[Expr]) -> Result<String, String> {
+        let compiled: Vec<String> = exprs.iter().map(|expr| self.expression(expr)).collect::<Result<_, _>>()?;
+        Ok(compiled.join(", "))
+    }
+}
+
+// Whether an expression only makes sense as a statement in JavaScript, so it
+// can't be a function's returned value
+fn is_statement(expr: &Expr) -> bool {
+    matches!(
+        expr,
+        Expr::Let { .. } | Expr::Assign { .. } | Expr::TupleAssign { .. } | Expr::Function { name: Some(_), .. } | Expr::Transformer { .. }
+            | Expr::Return { .. } | Expr::Block(_) | Expr::If { .. } | Expr::For { .. }
+            | Expr::While { .. } | Expr::DoWhile { .. } | Expr::Loop { .. } | Expr::Break { .. }
+            | Expr::Continue { .. } | Expr::Use { .. } | Expr::Pragma { .. }
+    )
+}
+
+fn describe(expr: &Expr) -> &'static str {
+    match expr {
+        Expr::Function { .. } => "A function definition",
+        Expr::Transformer { .. } => "A transformer definition",
+        Expr::Return { .. } => "return",
+        Expr::Block(_) => "A block",
+        Expr::For { .. } | Expr::While { .. } | Expr::DoWhile { .. } | Expr::Loop { .. } => "A loop",
+        Expr::Break { .. } => "break",
+        Expr::Continue { .. } => "continue",
+        Expr::Use { .. } => "use",
+        _ => "This expression",
+    }
+}
+
+fn label_prefix(label: &Option<String>) -> String {
+    label.as_ref().map(|label| format!("{}: ", ident(label))).unwrap_or_default()
+}
+
+fn label_suffix(label: &Option<String>) -> String {
+    label.as_ref().map(|label| format!(" {}", ident(label))).unwrap_or_default()
+}
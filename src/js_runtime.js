@@ -0,0 +1,555 @@
+// Runtime for M programs compiled to JavaScript by `m_lang compile --target js`.
+// Builtins and M's operators that differ from JavaScript's live on `$m`.
+const $m = (() => {
+  class Range {
+    constructor(start, end) {
+      this.start = start;
+      this.end = end;
+    }
+  }
+
+  const fail = (message) => { throw new Error(message); };
+  const unsupported = (name) => () => fail(`${name}() is not supported in JavaScript`);
+
+  // `ok(value)` or `err(error)`
+  class Outcome {
+    constructor(ok, value) {
+      this.ok = ok;
+      this.value = value;
+    }
+  }
+
+  const isMap = (v) => v !== null && typeof v === "object" && !Array.isArray(v) && !(v instanceof Range) && !(v instanceof Task) && !(v instanceof Set) && !(v instanceof Outcome);
+
+  // Tuples are frozen arrays marked as tuples
+  function tuple(...items) {
+    Object.defineProperty(items, "$tuple", { value: true });
+    return Object.freeze(items);
+  }
+
+  const isTuple = (v) => Array.isArray(v) && v.$tuple === true;
+
+  // The elements `(a, b) = v` assigns
+  function unpack(v, names) {
+    if (!Array.isArray(v) || v.length !== names.length) fail(`Cannot unpack ${str(v)} into (${names.join(", ")})`);
+    return v;
+  }
+
+  // A copy of an array or map and everything in it
+  function deepCopy(v) {
+    if (isTuple(v)) return tuple(...v.map(deepCopy));
+    if (Array.isArray(v)) return v.map(deepCopy);
+    if (v instanceof Set) return new Set(v);
+    if (v instanceof Outcome) return new Outcome(v.ok, deepCopy(v.value));
+    if (isMap(v)) return Object.fromEntries(Object.entries(v).map(([k, x]) => [k, deepCopy(x)]));
+    return v;
+  }
+
+  function typeName(v) {
+    if (v === null || v === undefined) return "nil";
+    if (typeof v === "number") return "number";
+    if (typeof v === "string") return "string";
+    if (typeof v === "boolean") return "boolean";
+    if (typeof v === "function") return v.$transformer ? "transformer" : "function";
+    if (isTuple(v)) return "tuple";
+    if (Array.isArray(v)) return "array";
+    if (v instanceof Range) return "range";
+    if (v instanceof Task) return "task";
+    if (v instanceof Set) return "set";
+    if (v instanceof Outcome) return "result";
+    return "map";
+  }
+
+  // Text as print() shows it
+  function str(v) {
+    if (v === null || v === undefined) return "nil";
+    if (isTuple(v)) return "(" + v.map(str).join(", ") + (v.length === 1 ? ",)" : ")");
+    if (Array.isArray(v)) return "[" + v.map(str).join(", ") + "]";
+    if (v instanceof Range) return `range(${v.start}, ${v.end})`;
+    if (v instanceof Task) return "<task>";
+    if (v instanceof Set) return "set([" + setElements(v).map(str).join(", ") + "])";
+    if (v instanceof Outcome) return `${v.ok ? "ok" : "err"}(${str(v.value)})`;
+    if (typeof v === "function") return v.$transformer ? "<transformer>" : "<function>";
+    if (isMap(v)) return "{" + Object.keys(v).sort().map((k) => `${k}: ${str(v[k])}`).join(", ") + "}";
+    return String(v);
+  }
+
+  // Nil, zero, empty containers and the strings "", "false" and "0" are false
+  function truthy(v) {
+    if (v === null || v === undefined) return false;
+    if (typeof v === "boolean") return v;
+    if (typeof v === "number") return v !== 0;
+    if (typeof v === "string") return !(v === "" || v === "false" || v === "0");
+    if (Array.isArray(v)) return v.length > 0;
+    if (v instanceof Range) return v.start < v.end;
+    if (v instanceof Set) return v.size > 0;
+    if (v instanceof Outcome) return v.ok;
+    if (isMap(v)) return Object.keys(v).length > 0;
+    return true;
+  }
+
+  function elements(v) {
+    if (Array.isArray(v)) return v.slice();
+    if (v instanceof Range) return toArray(v);
+    if (v instanceof Set) return setElements(v);
+    if (typeof v === "string") return Array.from(v);
+    return null;
+  }
+
+  // A set's elements in M's order: nil, booleans, numbers, then strings
+  function setElements(s) {
+    const rank = (x) => (x === null ? 0 : typeof x === "boolean" ? 1 : typeof x === "number" ? 2 : 3);
+    return Array.from(s).sort((a, b) => rank(a) - rank(b) || (a < b ? -1 : a > b ? 1 : 0));
+  }
+
+  function makeSet(values) {
+    if (values instanceof Set) return new Set(values);
+    const items = elements(values);
+    if (items === null) fail(`Cannot make a set from ${typeName(values)}`);
+    const s = new Set();
+    for (const v of items) setAdd(s, v);
+    return s;
+  }
+
+  function setAdd(s, v) {
+    if (v === undefined) v = null;
+    if (v !== null && !["boolean", "number", "string"].includes(typeof v)) {
+      fail(`Cannot add ${typeName(v)} to a set; elements must be nil, booleans, numbers or strings`);
+    }
+    if (Number.isNaN(v)) fail("NaN can't be added to a set");
+    s.add(v);
+    return s;
+  }
+
+  function toArray(range) {
+    const result = [];
+    for (let i = range.start; i < range.end; i++) result.push(i);
+    return result;
+  }
+
+  function eq(a, b) {
+    if (a === undefined) a = null;
+    if (b === undefined) b = null;
+    if (a instanceof Range || b instanceof Range) {
+      const l = elements(a), r = elements(b);
+      return l !== null && r !== null && typeof a !== "string" && typeof b !== "string" && eq(l, r);
+    }
+    if (Array.isArray(a) && Array.isArray(b)) {
+      return isTuple(a) === isTuple(b) && a.length === b.length && a.every((x, i) => eq(x, b[i]));
+    }
+    if (a instanceof Set && b instanceof Set) return a.size === b.size && [...a].every((x) => b.has(x));
+    if (a instanceof Outcome && b instanceof Outcome) return a.ok === b.ok && eq(a.value, b.value);
+    if (isMap(a) && isMap(b)) {
+      const keys = Object.keys(a);
+      return keys.length === Object.keys(b).length && keys.every((k) => Object.hasOwn(b, k) && eq(a[k], b[k]));
+    }
+    if (typeof a === "function" || typeof b === "function") return false;
+    return a === b;
+  }
+
+  function add(a, b) {
+    if (typeof a === "number" && typeof b === "number") return a + b;
+    if (typeof a === "string" || typeof b === "string") return str(a) + str(b);
+    if (Array.isArray(a) && Array.isArray(b) && !isTuple(a) && !isTuple(b)) return a.concat(b);
+    return fail("Invalid operands for operator: Plus");
+  }
+
+  function div(a, b) {
+    if (b === 0) fail("Division by zero");
+    return a / b;
+  }
+
+  // `~/` rounds down
+  function idiv(a, b) {
+    if (b === 0) fail("Division by zero");
+    return Math.floor(a / b);
+  }
+
+  // The remainder of floored division, so it has the sign of the divisor
+  function mod(a, b) {
+    if (b === 0) fail("Modulo by zero");
+    const r = a % b;
+    return r !== 0 && (r < 0) !== (b < 0) ? r + b : r;
+  }
+
+  function index(object, i) {
+    if (isMap(object)) {
+      if (typeof i !== "string") fail("Map keys must be strings");
+      return Object.hasOwn(object, i) ? object[i] : null;
+    }
+    // Strings are indexed by character, not UTF-16 code unit
+    const items = Array.isArray(object) ? object : typeof object === "string" ? Array.from(object) : object instanceof Range ? null : fail("Cannot index non-array type");
+    const n = Math.trunc(i);
+    if (object instanceof Range) {
+      if (n >= 0 && object.start + n < object.end) return object.start + n;
+    } else if (n >= 0 && n < items.length) {
+      return items[n];
+    }
+    return fail(`Index out of bounds: ${i}`);
+  }
+
+  function setIndex(object, i, value) {
+    if (isMap(object)) {
+      if (typeof i !== "string") fail("Map keys must be strings");
+      object[i] = value;
+    } else if (Array.isArray(object) && !isTuple(object)) {
+      const n = Math.trunc(i);
+      if (!(n >= 0 && n < object.length)) fail(`Index out of bounds: ${i}`);
+      object[n] = value;
+    } else {
+      fail(`Cannot assign to an index of ${typeName(object)}`);
+    }
+    return value;
+  }
+
+  // The values `for x in v` visits; a step only applies to ranges
+  function* iter(v, step) {
+    if (step !== undefined) {
+      if (!(v instanceof Range)) fail("A loop step can only be used with a range");
+      if (step === 0) fail("Loop step can't be zero");
+      if (step > 0) for (let i = v.start; i < v.end; i += step) yield i;
+      else for (let i = v.start; i > v.end; i += step) yield i;
+      return;
+    }
+    if (isMap(v)) {
+      yield* Object.keys(v).sort();
+      return;
+    }
+    const items = elements(v);
+    if (items === null) fail(`Cannot iterate over ${typeName(v)}`);
+    yield* items;
+  }
+
+  // The pairs `for a, b in v` visits: a map's keys and values, or two-element arrays
+  function* pairs(v) {
+    if (isMap(v)) {
+      for (const k of Object.keys(v).sort()) yield [k, v[k]];
+      return;
+    }
+    for (const element of iter(v)) {
+      if (!Array.isArray(element) || element.length !== 2) fail(`Cannot unpack ${str(element)} into two variables`);
+      yield element;
+    }
+  }
+
+  class Task {
+    constructor(value) {
+      this.value = value;
+    }
+  }
+
+  // Output goes to stdout in Node and to the console elsewhere, a line at a time
+  let pending = "";
+  function write(text) {
+    if (typeof process !== "undefined" && process.stdout) {
+      process.stdout.write(text);
+      return;
+    }
+    pending += text;
+    let newline;
+    while ((newline = pending.indexOf("\n")) >= 0) {
+      console.log(pending.slice(0, newline));
+      pending = pending.slice(newline + 1);
+    }
+  }
+
+  function format(template, ...values) {
+    let next = 0;
+    return template.replace(/\{\{|\}\}|\{([^}]*)\}/g, (match, placeholder) => {
+      if (match === "{{") return "{";
+      if (match === "}}") return "}";
+      if (placeholder.includes(":")) fail("format() specs are not supported in JavaScript");
+      const i = placeholder === "" ? next++ : Number(placeholder);
+      if (!(i < values.length)) fail("Not enough arguments for format string");
+      return str(values[i]);
+    });
+  }
+
+  const transformers = {};
+
+  const builtinTransformers = {
+    to_string: (v) => str(v),
+    to_number: (v) => {
+      if (typeof v === "number") return v;
+      if (typeof v === "boolean") return v ? 1 : 0;
+      const n = typeof v === "string" ? Number(v) : NaN;
+      return Number.isNaN(n) || v.trim() === "" ? (v === "true" ? 1 : 0) : n;
+    },
+    to_bool: (v) => truthy(v),
+    to_array: (v) => (isTuple(v) ? v.slice() : Array.isArray(v) ? v : v instanceof Range ? toArray(v) : v instanceof Set ? setElements(v) : [v]),
+    enumerate: (v) => {
+      const items = elements(v);
+      if (items === null) fail(`Cannot enumerate ${typeName(v)}`);
+      return items.map((x, i) => [i, x]);
+    },
+    // Array.prototype.sort is stable, so equal elements keep their order
+    sort_by: (v, f) => {
+      const items = elements(v);
+      if (items === null) fail(`Cannot sort ${typeName(v)}`);
+      return items.sort((a, b) => {
+        const order = f(a, b);
+        if (typeof order !== "number") fail(`Comparator passed to sort_by() must return a number, got ${typeName(order)}`);
+        return order;
+      });
+    },
+    sort_key: (v, f) => {
+      const items = elements(v);
+      if (items === null) fail(`Cannot sort ${typeName(v)}`);
+      return items.map((x) => [f(x), x]).sort(([a], [b]) => (a < b ? -1 : b < a ? 1 : 0)).map(([, x]) => x);
+    },
+    group_by: (v, f) => {
+      const groups = {};
+      for (const x of elements(v) ?? fail(`Cannot apply group_by() to ${typeName(v)}`)) {
+        const key = f(x);
+        if (typeof key !== "string") fail(`group_by() keys must be strings, got ${typeName(key)}; convert them with to_string()`);
+        (groups[key] ??= []).push(x);
+      }
+      return groups;
+    },
+    unique: (v) => {
+      const result = [];
+      for (const x of elements(v) ?? fail(`Cannot apply unique() to ${typeName(v)}`)) {
+        if (!result.some((y) => eq(x, y))) result.push(x);
+      }
+      return result;
+    },
+    flatten: (v) => (elements(v) ?? fail(`Cannot apply flatten() to ${typeName(v)}`)).flatMap((x) => (Array.isArray(x) ? [...x] : [x])),
+    chunk: (v, n) => {
+      const items = elements(v) ?? fail(`Cannot apply chunk() to ${typeName(v)}`);
+      if (!Number.isInteger(n) || n <= 0) fail("Size passed to chunk() must be a positive integer");
+      const chunks = [];
+      for (let i = 0; i < items.length; i += n) chunks.push(items.slice(i, i + n));
+      return chunks;
+    },
+    chars: (v) => Array.from(v),
+    len: (v) => {
+      if (typeof v === "string") return Array.from(v).length;
+      if (Array.isArray(v)) return v.length;
+      if (v instanceof Set) return v.size;
+      if (v instanceof Range) return Math.max(v.end - v.start, 0);
+      if (isMap(v)) return Object.keys(v).length;
+      return fail(`Cannot apply len() to ${typeName(v)}`);
+    },
+    trim: (v) => v.trim(),
+    upper: (v) => v.toUpperCase(),
+    lower: (v) => v.toLowerCase(),
+    split: (v, separator) => (separator === "" ? Array.from(v) : v.split(separator)),
+    join: (v, separator) => {
+      if (!Array.isArray(v) && !(v instanceof Range) && !(v instanceof Set)) fail(`Cannot apply join() to ${typeName(v)}`);
+      return elements(v).map(str).join(separator);
+    },
+    // A final line ending doesn't start another line
+    lines: (v) => {
+      const lines = v === "" ? [] : v.split(/\r?\n/);
+      if (lines.at(-1) === "" && /\n$/.test(v)) lines.pop();
+      return lines;
+    },
+    is_number: (v) => typeof v === "number",
+    is_integer: (v) => Number.isInteger(v),
+    is_string: (v) => typeof v === "string",
+    is_boolean: (v) => typeof v === "boolean",
+    is_array: (v) => Array.isArray(v) && !isTuple(v),
+    is_tuple: (v) => isTuple(v),
+    is_map: (v) => isMap(v),
+    is_set: (v) => v instanceof Set,
+    is_function: (v) => typeof v === "function" && !v.$transformer,
+    is_transformer: (v) => typeof v === "function" && !!v.$transformer,
+    is_nil: (v) => v === null || v === undefined,
+    is_nan: (v) => Number.isNaN(v),
+    is_infinite: (v) => typeof v === "number" && !Number.isNaN(v) && !Number.isFinite(v),
+    is_finite: (v) => Number.isFinite(v),
+    to_json: (v) => JSON.stringify(v, json),
+    to_json_pretty: (v) => JSON.stringify(v, json, 2),
+    from_json: (v) => parseJson(v),
+    then: (first, next) => transformer((v) => next(first(v))),
+    apply: (v, t, ...args) => t(v, ...args),
+  };
+
+  // Only sets have these; other values use transformers of the same name
+  const setTransformers = {
+    add: (s, v) => setAdd(s, v),
+    contains: (s, v) => s.has(v === undefined ? null : v),
+    union: (s, other) => new Set([...s, ...makeSet(other)]),
+    intersect: (s, other) => {
+      const o = makeSet(other);
+      return new Set([...s].filter((x) => o.has(x)));
+    },
+    difference: (s, other) => {
+      const o = makeSet(other);
+      return new Set([...s].filter((x) => !o.has(x)));
+    },
+  };
+
+  // Only collections have these; other values use transformers of the same name
+  const aggregateTransformers = {
+    sum: (v) => numbers(v, "sum").reduce((a, b) => a + b, 0),
+    avg: (v) => {
+      const items = numbers(v, "avg");
+      if (items.length === 0) fail(`Cannot take avg() of an empty ${typeName(v)}`);
+      return items.reduce((a, b) => a + b, 0) / items.length;
+    },
+    min: (v) => extreme(v, "min", (a, b) => a < b),
+    max: (v) => extreme(v, "max", (a, b) => a > b),
+  };
+
+  function numbers(v, name) {
+    const items = elements(v);
+    const index = items.findIndex((x) => typeof x !== "number");
+    if (index !== -1) fail(`${name}() needs numbers, got ${typeName(items[index])} at index ${index}`);
+    return items;
+  }
+
+  // The first of equal extremes wins
+  function extreme(v, name, better) {
+    const items = numbers(v, name);
+    if (items.length === 0) fail(`Cannot take ${name}() of an empty ${typeName(v)}`);
+    return items.reduce((best, x) => (better(x, best) ? x : best));
+  }
+
+  // Only results have these; other values use transformers of the same name
+  const resultTransformers = {
+    is_ok: (r) => r.ok,
+    is_err: (r) => !r.ok,
+    unwrap: (r) => (r.ok ? r.value : fail(`unwrap() of err(${str(r.value)})`)),
+    unwrap_or: (r, fallback) => (r.ok ? r.value : fallback),
+    unwrap_err: (r) => (r.ok ? fail(`unwrap_err() of ok(${str(r.value)})`) : r.value),
+  };
+
+  // Sets are written as arrays of their elements, and results as {"ok": value}
+  // or {"err": error}
+  function json(key, v) {
+    if (v instanceof Outcome) return { [v.ok ? "ok" : "err"]: v.value };
+    return v instanceof Set ? setElements(v) : v;
+  }
+
+  // ok(value), or err(message) for text that isn't valid JSON
+  function parseJson(text) {
+    if (typeof text !== "string") fail("Argument to from_json() must be a string");
+    try {
+      return new Outcome(true, JSON.parse(text));
+    } catch (e) {
+      return new Outcome(false, `Invalid JSON: ${e.message}`);
+    }
+  }
+
+  // Marks a function as a transformer, which takes the applied value first
+  function transformer(f) {
+    f.$transformer = true;
+    return f;
+  }
+
+  // `?.` skips the application when the object is nil
+  function apply(object, name, args, safe) {
+    if (safe && (object === null || object === undefined)) return null;
+    const collection = Array.isArray(object) || object instanceof Range || object instanceof Set;
+    const transformer =
+      (object instanceof Set ? setTransformers[name] : undefined) ??
+      (collection ? aggregateTransformers[name] : undefined) ??
+      (object instanceof Outcome ? resultTransformers[name] : undefined) ??
+      builtinTransformers[name] ??
+      transformers[name];
+    if (transformer === undefined) fail(`Undefined transformer '${name}'`);
+    return transformer(object, ...args);
+  }
+
+  // `object.name`: a map's entry, or else the transformer applied without arguments
+  function member(object, name, safe) {
+    if (safe && (object === null || object === undefined)) return null;
+    if (isMap(object) && Object.hasOwn(object, name)) return object[name];
+    return apply(object, name, []);
+  }
+
+  function assert(condition, message, line, column) {
+    if (!truthy(condition)) {
+      const suffix = message === undefined ? "" : ` (${str(message)})`;
+      fail(`Assertion failed at line ${line}, column ${column}: condition was ${str(condition)}${suffix}`);
+    }
+    return null;
+  }
+
+  function assertEq(actual, expected, message, line, column) {
+    if (!eq(actual, expected)) {
+      const show = (v) => (typeof v === "string" ? JSON.stringify(v) : str(v));
+      const suffix = message === undefined ? "" : ` (${str(message)})`;
+      fail(`Assertion failed at line ${line}, column ${column}: expected ${show(expected)}, got ${show(actual)}${suffix}`);
+    }
+    return null;
+  }
+
+  return {
+    Range, tuple, unpack, truthy, eq, add, div, idiv, mod, index, setIndex, iter, pairs, transformer, apply, member, assert, assertEq, transformers,
+    print: (...args) => { write(args.map(str).join(" ") + "\n"); return null; },
+    write: (...args) => { write(args.map(str).join(" ")); return null; },
+    print_raw: (...args) => { write(args.map(str).join(" ")); return null; },
+    format,
+    typeof: (v) => typeName(v),
+    ord: (s) => {
+      if (typeof s !== "string") fail(`ord() takes a single character, got ${typeName(s)}`);
+      const chars = Array.from(s);
+      if (chars.length !== 1) fail(`ord() takes a single character, got a string of ${chars.length} characters`);
+      return s.codePointAt(0);
+    },
+    chr: (n) => {
+      if (!Number.isInteger(n) || n < 0 || n > 0x10ffff || (n >= 0xd800 && n <= 0xdfff)) fail(`${str(n)} is not a Unicode code point`);
+      return String.fromCodePoint(n);
+    },
+    range: (start, end) => new Range(start, end),
+    push: (array, value) => { array.push(value); return null; },
+    set: (values) => (values === undefined ? new Set() : makeSet(values)),
+    zip: (a, b) => {
+      const l = elements(a), r = elements(b);
+      if (l === null || r === null) fail(`Cannot zip ${typeName(l === null ? a : b)}`);
+      return l.slice(0, Math.min(l.length, r.length)).map((x, i) => [x, r[i]]);
+    },
+    array_of: (size, value) => {
+      if (!Number.isInteger(size)) fail("First argument to array_of() must be an integer");
+      if (size < 0) fail("array_of() size cannot be negative");
+      return Array.from({ length: size }, () => deepCopy(value));
+    },
+    from_json: (text) => parseJson(text),
+    ok: (value) => new Outcome(true, value),
+    err: (error) => new Outcome(false, error),
+    try: (f, ...args) => {
+      try {
+        return new Outcome(true, f(...args));
+      } catch (e) {
+        return new Outcome(false, e.message);
+      }
+    },
+    try_parse_number: (v) => {
+      if (typeof v === "number") return v;
+      return typeof v === "string" && /^[+-]?(\d+\.?\d*|\.\d+)([eE][+-]?\d+)?$/.test(v) ? Number(v) : null;
+    },
+    // Tasks run to completion when they are spawned
+    spawn: (f, ...args) => new Task(f(...args)),
+    join: (task) => task.value,
+    input: unsupported("input"),
+    sleep: unsupported("sleep"),
+    read_bytes: unsupported("read_bytes"),
+    write_bytes: unsupported("write_bytes"),
+    sha256: unsupported("sha256"),
+    md5: unsupported("md5"),
+    crc32: unsupported("crc32"),
+    list_dir: unsupported("list_dir"),
+    mkdir: unsupported("mkdir"),
+    remove_file: unsupported("remove_file"),
+    path_join: (...parts) => parts.join("/"),
+    basename: (path) => path.split("/").pop() || null,
+    extension: unsupported("extension"),
+    channel: unsupported("channel"),
+    send: unsupported("send"),
+    receive: unsupported("receive"),
+    close: unsupported("close"),
+    on: unsupported("on"),
+    decimal: unsupported("decimal"),
+    eval: unsupported("eval"),
+    globals: unsupported("globals"),
+    defined: unsupported("defined"),
+    fn_params: unsupported("fn_params"),
+    random: () => Math.random(),
+    random_int: (start, end) => start + Math.floor(Math.random() * (end - start)),
+    now: () => Date.now(),
+    // The arguments after the script's name under Node, none in a browser
+    args: () => (typeof process === "undefined" ? [] : process.argv.slice(2)),
+  };
+})();
@@ -0,0 +1,83 @@
+// JSON conversion used by the to_json/to_json_pretty/from_json transformers and the
+// from_json() builtin. Integers and floats stay distinct both ways (`2` and
+// `2.0`), so converting a value to JSON and back gives an equal value.
+use std::collections::BTreeMap;
+
+use crate::stack;
+use crate::value::Value;
+
+pub fn to_json(value: &Value) -> Result<String, String> {
+    Ok(to_json_value(value)?.to_string())
+}
+
+// Indented two spaces per level, one array element or object entry per line
+pub fn to_json_pretty(value: &Value) -> Result<String, String> {
+    serde_json::to_string_pretty(&to_json_value(value)?).map_err(|e| e.to_string())
+}
+
+pub fn from_json(text: &str) -> Result<Value, String> {
+    let json: serde_json::Value = serde_json::from_str(text).map_err(|e| format!("Invalid JSON: {}", e))?;
+    Ok(from_json_value(json))
+}
+
+fn to_json_value(value: &Value) -> Result<serde_json::Value, String> {
+    stack::guard(|| Ok(match value {
+        Value::Nil => serde_json::Value::Null,
+        Value::Boolean(b) => serde_json::Value::Bool(*b),
+        Value::Int(n) => serde_json::Value::from(*n),
+        // A float would lose digits without saying so
+        // Written as a string, so no digits are lost in a float
+        Value::Decimal(d) => serde_json::Value::String(d.to_string()),
+        Value::BigInt(n) => return Err(format!("Cannot convert {} to JSON: it is too large for a JSON integer; use to_string()", n)),
+        Value::Number(n) => match serde_json::Number::from_f64(*n) {
+            Some(n) => serde_json::Value::Number(n),
+            None => return Err(format!("Cannot convert {} to JSON", n)),
+        },
+        Value::String(s) => serde_json::Value::String(s.to_string()),
+        Value::Array(elements) => {
+            serde_json::Value::Array(elements.borrow().iter().map(to_json_value).collect::<Result<_, _>>()?)
+        },
+        Value::Map(entries) => {
+            let mut object = serde_json::Map::new();
+            for (key, value) in entries.borrow().iter() {
+                object.insert(key.clone(), to_json_value(value)?);
+            }
+            serde_json::Value::Object(object)
+        },
+        // Sets and tuples become arrays of their elements, in order
+        Value::Tuple(elements) => serde_json::Value::Array(elements.iter().map(to_json_value).collect::<Result<_, _>>()?),
+        Value::Set(set) => serde_json::Value::Array(set.borrow().iter().map(to_json_value).collect::<Result<_, _>>()?),
+        Value::Range { start, end } => {
+            serde_json::Value::Array(Value::range_elements(*start, *end)?.iter().map(to_json_value).collect::<Result<_, _>>()?)
+        },
+        Value::Bytes(bytes) => serde_json::Value::Array(bytes.iter().map(|&b| serde_json::Value::from(b)).collect()),
+        // `ok(1)` is {"ok": 1} and `err("no")` is {"err": "no"}
+        Value::Result(result) => {
+            let (key, value) = match &**result {
+                Ok(value) => ("ok", value),
+                Err(error) => ("err", error),
+            };
+            serde_json::Value::Object([(key.to_string(), to_json_value(value)?)].into_iter().collect())
+        },
+        // Functions, transformers, tasks, channels and host objects have no JSON form
+        Value::Function { .. } | Value::NativeFunction(_) | Value::Builtin(_) | Value::Transformer { .. } | Value::Composed(_)
+            | Value::Task(_) | Value::Sender(_) | Value::Receiver(_) | Value::Native(_) => serde_json::Value::Null,
+    }))
+}
+
+fn from_json_value(json: serde_json::Value) -> Value {
+    match json {
+        serde_json::Value::Null => Value::Nil,
+        serde_json::Value::Bool(b) => Value::Boolean(b),
+        // Numbers written without a fraction or exponent are integers when they fit
+        serde_json::Value::Number(n) => match n.as_i64() {
+            Some(n) => Value::Int(n),
+            None => Value::Number(n.as_f64().unwrap_or(f64::NAN)),
+        },
+        serde_json::Value::String(s) => Value::String(s.into()),
+        serde_json::Value::Array(elements) => Value::array(elements.into_iter().map(from_json_value).collect()),
+        serde_json::Value::Object(object) => {
+            Value::map(object.into_iter().map(|(key, value)| (key, from_json_value(value))).collect::<BTreeMap<_, _>>())
+        },
+    }
+}
@@ -1,315 +1,346 @@
-use std::io;
+use std::fmt;
+use std::iter::Peekable;
+use std::str::CharIndices;
 
-use crate::token::{Token, TokenType};
+use crate::token::{Span, Token, TokenType};
 
-pub struct Lexer {
-    pub line: Result<Vec<String>, io::Error>,
+// Turns source text into tokens, one at a time. Every token records the byte
+// range it came from. Characters that can't start a token and strings missing
+// their closing quote produce an error item; lexing continues after an error,
+// so a caller can report several at once. The last item is always an EOF token.
+pub struct Lexer<'a> {
+    source: &'a str,
+    chars: Peekable<CharIndices<'a>>,
+    finished: bool,
+    // Lines are counted up to `counted`; tokens are produced in order, so
+    // positions only ever move forward
+    counted: usize,
+    line: usize,
+    line_start: usize,
 }
 
-impl Lexer {
-    pub fn new(content: &str) -> Self {
+#[derive(Debug, Clone, PartialEq)]
+pub struct LexError {
+    pub message: String,
+    pub span: Span,
+    // 1-based position of the start of the span
+    pub line: usize,
+    pub column: usize,
+}
+
+impl fmt::Display for LexError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at line {}, column {}", self.message, self.line, self.column)
+    }
+}
+
+impl<'a> Lexer<'a> {
+    pub fn new(source: &'a str) -> Self {
         Lexer {
-            line: Ok(vec![content.to_string()]),
+            source,
+            chars: source.char_indices().peekable(),
+            finished: false,
+            counted: 0,
+            line: 1,
+            line_start: 0,
         }
     }
-    
-    pub fn tokenize(&self) -> Result<Vec<Token>, String> {
-        Ok(self.lex())
+
+    /// Collects every token, stopping at the first error.
+    pub fn tokenize(self) -> Result<Vec<Token>, String> {
+        self.collect::<Result<_, _>>().map_err(|e: LexError| e.to_string())
+    }
+
+    // Byte offset of the next unread character
+    fn offset(&mut self) -> usize {
+        self.chars.peek().map_or(self.source.len(), |&(i, _)| i)
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.chars.peek().map(|&(_, c)| c)
+    }
+
+    // The character after the next one
+    fn peek_second(&self) -> Option<char> {
+        let mut lookahead = self.chars.clone();
+        lookahead.next();
+        lookahead.peek().map(|&(_, c)| c)
+    }
+
+    fn advance_if(&mut self, expected: char) -> bool {
+        self.chars.next_if(|&(_, c)| c == expected).is_some()
     }
 
-    pub fn lex(&self) -> Vec<Token> {
-        let mut tokens = Vec::new();
-        
-        match &self.line {
-            Ok(lines) => {
-                for line in lines {
-                    let mut chars = line.chars().peekable();
-                    let mut _position = 0;
-                    
-                    while let Some(c) = chars.next() {
-                        match c {
-                            ' ' | '\t' | '\r' | '\n' => {
-                                // Skip whitespace
-                                _position += 1;
-                            },
-                            // Digits
-                            '0'..='9' => {
-                                let mut number = c.to_string();
-                                
-                                // Consume all consecutive digits
-                                while let Some(&next_c) = chars.peek() {
-                                    if next_c.is_digit(10) || next_c == '.' {
-                                        number.push(chars.next().unwrap());
-                                    } else {
-                                        break;
-                                    }
-                                }
-                                
-                                tokens.push(Token {
-                                    token_type: TokenType::Number,
-                                    literal: number.clone(),
-                                });
-                                
-                                _position += number.len();
-                            },
-                            // String literals
-                            '"' => {
-                                let mut string = String::new();
-                                
-                                // Consume all characters until the closing quote
-                                while let Some(&next_c) = chars.peek() {
-                                    chars.next();
-                                    if next_c == '"' {
-                                        break;
-                                    }
-                                    string.push(next_c);
-                                }
-                                
-                                tokens.push(Token {
-                                    token_type: TokenType::String,
-                                    literal: string.clone(),
-                                });
-                                
-                                _position += string.len() + 2; // +2 for the quotes
-                            },
-                            // Identifiers and keywords
-                            'a'..='z' | 'A'..='Z' | '_' => {
-                                let mut identifier = c.to_string();
-                                
-                                // Consume all consecutive alphanumeric characters
-                                while let Some(&next_c) = chars.peek() {
-                                    if next_c.is_alphanumeric() || next_c == '_' {
-                                        identifier.push(chars.next().unwrap());
-                                    } else {
-                                        break;
-                                    }
-                                }
-                                
-                                // Check if it's a keyword
-                                let token_type = match identifier.as_str() {
-                                    "fn" => TokenType::Fn,
-                                    "return" => TokenType::Return,
-                                    "true" => TokenType::True,
-                                    "false" => TokenType::False,
-                                    "if" => TokenType::If,
-                                    "else" => TokenType::Else,
-                                    "for" => TokenType::For,
-                                    "in" => TokenType::In,
-                                    "while" => TokenType::While,
-                                    "transformer" => TokenType::Transformer,
-                                    "and" => TokenType::And,
-                                    "or" => TokenType::Or,
-                                    "not" => TokenType::Not,
-                                    "use" => TokenType::Use,
-                                    _ => TokenType::Identifier,
-                                };
-                                
-                                tokens.push(Token {
-                                    token_type,
-                                    literal: identifier.clone(),
-                                });
-                                
-                                _position += identifier.len();
-                            },
-                            // Operators and delimiters
-                            '+' => {
-                                tokens.push(Token {
-                                    token_type: TokenType::Plus,
-                                    literal: c.to_string(),
-                                });
-                                _position += 1;
-                            },
-                            '-' => {
-                                tokens.push(Token {
-                                    token_type: TokenType::Minus,
-                                    literal: c.to_string(),
-                                });
-                                _position += 1;
-                            },
-                            '*' => {
-                                tokens.push(Token {
-                                    token_type: TokenType::Multiply,
-                                    literal: c.to_string(),
-                                });
-                                _position += 1;
-                            },
-                            '/' => {
-                                // Check if it's a comment
-                                if chars.peek() == Some(&'/') {
-                                    // Consume the second '/'
-                                    chars.next();
-                                    
-                                    // Consume the rest of the line
-                                    while let Some(c) = chars.next() {
-                                        if c == '\n' {
-                                            break;
-                                        }
-                                    }
-                                    
-                                    _position += 1;
-                                } else {
-                                    tokens.push(Token {
-                                        token_type: TokenType::Divide,
-                                        literal: c.to_string(),
-                                    });
-                                    
-                                    _position += 1;
-                                }
-                            },
-                            '%' => {
-                                tokens.push(Token {
-                                    token_type: TokenType::Modulo,
-                                    literal: c.to_string(),
-                                });
-                                _position += 1;
-                            },
-                            '<' => {
-                                // Check if it's <= or just <
-                                if chars.peek() == Some(&'=') {
-                                    chars.next(); // Consume the '='
-                                    tokens.push(Token {
-                                        token_type: TokenType::LessThanEqual,
-                                        literal: "<=".to_string(),
-                                    });
-                                    _position += 2;
-                                } else {
-                                    tokens.push(Token {
-                                        token_type: TokenType::LessThan,
-                                        literal: c.to_string(),
-                                    });
-                                    _position += 1;
-                                }
-                            },
-                            '>' => {
-                                // Check if it's >= or just >
-                                if chars.peek() == Some(&'=') {
-                                    chars.next(); // Consume the '='
-                                    tokens.push(Token {
-                                        token_type: TokenType::GreaterThanEqual,
-                                        literal: ">=".to_string(),
-                                    });
-                                    _position += 2;
-                                } else {
-                                    tokens.push(Token {
-                                        token_type: TokenType::GreaterThan,
-                                        literal: c.to_string(),
-                                    });
-                                    _position += 1;
-                                }
-                            },
-                            '=' => {
-                                // Check if it's == or just =
-                                if chars.peek() == Some(&'=') {
-                                    chars.next(); // Consume the second '='
-                                    tokens.push(Token {
-                                        token_type: TokenType::EqualEqual,
-                                        literal: "==".to_string(),
-                                    });
-                                    _position += 2;
-                                } else {
-                                    tokens.push(Token {
-                                        token_type: TokenType::Equal,
-                                        literal: c.to_string(),
-                                    });
-                                    _position += 1;
-                                }
-                            },
-                            '!' => {
-                                // Check if it's != or just !
-                                if chars.peek() == Some(&'=') {
-                                    chars.next(); // Consume the '='
-                                    tokens.push(Token {
-                                        token_type: TokenType::BangEqual,
-                                        literal: "!=".to_string(),
-                                    });
-                                    _position += 2;
-                                } else {
-                                    // Just skip the character for now
-                                    _position += 1;
-                                }
-                            },
-                            '(' => {
-                                tokens.push(Token {
-                                    token_type: TokenType::LeftParen,
-                                    literal: c.to_string(),
-                                });
-                                _position += 1;
-                            },
-                            ')' => {
-                                tokens.push(Token {
-                                    token_type: TokenType::RightParen,
-                                    literal: c.to_string(),
-                                });
-                                _position += 1;
-                            },
-                            '[' => {
-                                tokens.push(Token {
-                                    token_type: TokenType::LeftBracket,
-                                    literal: c.to_string(),
-                                });
-                                _position += 1;
-                            },
-                            ']' => {
-                                tokens.push(Token {
-                                    token_type: TokenType::RightBracket,
-                                    literal: c.to_string(),
-                                });
-                                _position += 1;
-                            },
-                            '{' => {
-                                tokens.push(Token {
-                                    token_type: TokenType::LeftBrace,
-                                    literal: c.to_string(),
-                                });
-                                _position += 1;
-                            },
-                            '}' => {
-                                tokens.push(Token {
-                                    token_type: TokenType::RightBrace,
-                                    literal: c.to_string(),
-                                });
-                                _position += 1;
-                            },
-                            ',' => {
-                                tokens.push(Token {
-                                    token_type: TokenType::Comma,
-                                    literal: c.to_string(),
-                                });
-                                _position += 1;
-                            },
-                            ';' => {
-                                tokens.push(Token {
-                                    token_type: TokenType::Semicolon,
-                                    literal: c.to_string(),
-                                });
-                                _position += 1;
-                            },
-                            '.' => {
-                                tokens.push(Token {
-                                    token_type: TokenType::Dot,
-                                    literal: ".".to_string(),
-                                });
-                                _position += 1;
-                            },
-                            _ => {
-                                // Ignore unrecognized characters
-                                _position += 1;
-                            }
-                        }
+    fn advance_while(&mut self, predicate: impl Fn(char) -> bool) {
+        while self.chars.next_if(|&(_, c)| predicate(c)).is_some() {}
+    }
+
+    // The 1-based line and column of a byte offset
+    fn position(&mut self, offset: usize) -> (usize, usize) {
+        for (i, c) in self.source[self.counted..offset].char_indices() {
+            if c == '\n' {
+                self.line += 1;
+                self.line_start = self.counted + i + 1;
+            }
+        }
+        self.counted = offset;
+
+        (self.line, self.source[self.line_start..offset].chars().count() + 1)
+    }
+
+    // A token whose literal is the source text from `start` to the current position
+    fn token(&mut self, token_type: TokenType, start: usize) -> Token {
+        let end = self.offset();
+        let (line, column) = self.position(start);
+        Token {
+            token_type,
+            literal: self.source[start..end].to_string(),
+            span: Span { start, end },
+            line,
+            column,
+        }
+    }
+
+    fn error(&mut self, message: String, start: usize) -> LexError {
+        let end = self.offset();
+        let (line, column) = self.position(start);
+
+        LexError {
+            message,
+            span: Span { start, end },
+            line,
+            column,
+        }
+    }
+
+    fn number(&mut self, first: char, start: usize) -> Token {
+        let mut is_float = false;
+
+        if first == '0' && matches!(self.peek(), Some('x' | 'X' | 'b' | 'B' | 'o' | 'O')) {
+            // Radix prefix; the digits themselves are validated by the parser
+            self.chars.next();
+            self.advance_while(|c| c.is_ascii_alphanumeric() || c == '_');
+        } else {
+            while let Some(c) = self.peek() {
+                let after = self.peek_second();
+
+                if c.is_ascii_digit() || c == '_' {
+                    self.chars.next();
+                } else if c == '.' && after.is_some_and(|a| a.is_ascii_digit()) {
+                    // Only a '.' followed by a digit belongs to the number,
+                    // so `5.square()` and `0..10` still lex as expected
+                    is_float = true;
+                    self.chars.next();
+                } else if (c == 'e' || c == 'E') && after.is_some_and(|a| a.is_ascii_digit() || a == '+' || a == '-') {
+                    // Scientific notation exponent with an optional sign
+                    is_float = true;
+                    self.chars.next();
+                    if after == Some('+') || after == Some('-') {
+                        self.chars.next();
                     }
+                } else if c.is_alphanumeric() {
+                    // Keep malformed suffixes (e.g. `12abc`) in the literal so
+                    // the parser can report them
+                    self.chars.next();
+                } else {
+                    break;
                 }
-            },
-            Err(_) => {
-                // Handle error
             }
         }
-        
-        // Add EOF token
-        tokens.push(Token {
-            token_type: TokenType::EOF,
-            literal: String::new(),
-        });
-        
-        tokens
+
+        let token_type = if is_float { TokenType::Number } else { TokenType::Integer };
+        self.token(token_type, start)
     }
-}
\ No newline at end of file
+
+    fn string(&mut self, start: usize) -> Result<Token, LexError> {
+        if self.peek() == Some('"') && self.peek_second() == Some('"') {
+            self.chars.next();
+            self.chars.next();
+            return self.triple_quoted_string(start);
+        }
+
+        let mut string = String::new();
+
+        // Consume all characters until the closing quote
+        loop {
+            match self.chars.next() {
+                Some((_, '"')) => break,
+                Some((_, c)) => string.push(c),
+                None => return Err(self.error("Unterminated string".to_string(), start)),
+            }
+        }
+
+        let mut token = self.token(TokenType::String, start);
+        token.literal = string;
+        Ok(token)
+    }
+
+    // `"""` strings can hold `"` and `""`. A newline straight after the opening
+    // quotes isn't part of the string, and quotes just before the closing three
+    // are, so `""""quoted""""` is `"quoted"`.
+    fn triple_quoted_string(&mut self, start: usize) -> Result<Token, LexError> {
+        let mut string = String::new();
+        self.advance_if('\n');
+
+        loop {
+            match self.chars.next() {
+                Some((_, '"')) => {
+                    let mut quotes = 1;
+                    while self.advance_if('"') {
+                        quotes += 1;
+                    }
+                    if quotes >= 3 {
+                        string.extend(std::iter::repeat_n('"', quotes - 3));
+                        break;
+                    }
+                    string.extend(std::iter::repeat_n('"', quotes));
+                },
+                Some((_, c)) => string.push(c),
+                None => return Err(self.error("Unterminated string".to_string(), start)),
+            }
+        }
+
+        let mut token = self.token(TokenType::String, start);
+        token.literal = string;
+        Ok(token)
+    }
+
+    // `` `name` `` is an identifier even when the name is a keyword
+    fn raw_identifier(&mut self, start: usize) -> Result<Token, LexError> {
+        let name_start = self.offset();
+        self.advance_while(|c| c.is_alphanumeric() || c == '_');
+        let name = self.source[name_start..self.offset()].to_string();
+
+        if !self.advance_if('`') {
+            self.advance_while(|c| c != '`' && c != '\n');
+            self.advance_if('`');
+            return Err(self.error("Raw identifiers must be a name between backticks".to_string(), start));
+        }
+        if name.is_empty() || name.starts_with(|c: char| c.is_ascii_digit()) {
+            return Err(self.error(format!("Invalid raw identifier `{}`", name), start));
+        }
+
+        let mut token = self.token(TokenType::Identifier, start);
+        token.literal = name;
+        Ok(token)
+    }
+
+    fn identifier(&mut self, start: usize) -> Token {
+        self.advance_while(|c| c.is_alphanumeric() || c == '_');
+
+        // Check if it's a keyword
+        let token_type = match &self.source[start..self.offset()] {
+            "fn" => TokenType::Fn,
+            "async" => TokenType::Async,
+            "await" => TokenType::Await,
+            "let" => TokenType::Let,
+            "return" => TokenType::Return,
+            "true" => TokenType::True,
+            "false" => TokenType::False,
+            "nil" => TokenType::Nil,
+            "if" => TokenType::If,
+            "else" => TokenType::Else,
+            "for" => TokenType::For,
+            "in" => TokenType::In,
+            "while" => TokenType::While,
+            "do" => TokenType::Do,
+            "loop" => TokenType::Loop,
+            "break" => TokenType::Break,
+            "continue" => TokenType::Continue,
+            "transformer" => TokenType::Transformer,
+            "and" => TokenType::And,
+            "or" => TokenType::Or,
+            "not" => TokenType::Not,
+            "use" => TokenType::Use,
+            "pub" => TokenType::Pub,
+            _ => TokenType::Identifier,
+        };
+
+        self.token(token_type, start)
+    }
+}
+
+impl Iterator for Lexer<'_> {
+    type Item = Result<Token, LexError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let Some((start, c)) = self.chars.next() else {
+                if self.finished {
+                    return None;
+                }
+                self.finished = true;
+                return Some(Ok(self.token(TokenType::EOF, self.source.len())));
+            };
+
+            let token_type = match c {
+                ' ' | '\t' | '\r' | '\n' => continue,
+                '/' if self.advance_if('/') => {
+                    // `///` (but not `////`) starts a doc comment
+                    let is_doc = self.peek() == Some('/') && self.peek_second() != Some('/');
+
+                    // A comment runs to the end of the line
+                    self.advance_while(|c| c != '\n');
+                    if !is_doc {
+                        continue;
+                    }
+
+                    let mut token = self.token(TokenType::DocComment, start);
+                    let text = &token.literal[3..];
+                    token.literal = text.strip_prefix(' ').unwrap_or(text).trim_end().to_string();
+                    return Some(Ok(token));
+                },
+                // `#` comments also cover a `#!/usr/bin/env m_lang` first line
+                '#' => {
+                    self.advance_while(|c| c != '\n');
+                    continue;
+                },
+                '0'..='9' => return Some(Ok(self.number(c, start))),
+                '"' => return Some(self.string(start)),
+                // `r"..."` is a raw string; M has no escapes, so for now it reads
+                // the same as a plain one
+                'r' if self.advance_if('"') => return Some(self.string(start)),
+                'a'..='z' | 'A'..='Z' | '_' => return Some(Ok(self.identifier(start))),
+                '`' => return Some(self.raw_identifier(start)),
+                '+' => TokenType::Plus,
+                '-' => TokenType::Minus,
+                '*' => TokenType::Multiply,
+                '/' => TokenType::Divide,
+                // `~/` is integer division (`//` already starts a comment)
+                '~' if self.advance_if('/') => TokenType::IntDivide,
+                '%' => TokenType::Modulo,
+                '<' if self.advance_if('=') => TokenType::LessThanEqual,
+                '<' => TokenType::LessThan,
+                '>' if self.advance_if('=') => TokenType::GreaterThanEqual,
+                '>' => TokenType::GreaterThan,
+                '=' if self.advance_if('=') => TokenType::EqualEqual,
+                '=' => TokenType::Equal,
+                '!' if self.advance_if('=') => TokenType::BangEqual,
+                // `!x` is the same as `not x`
+                '!' => TokenType::Not,
+                // `|>` is the pipeline operator
+                '|' if self.advance_if('>') => TokenType::Pipe,
+                // `??` is nil-coalescing and `?.` is nil-safe application
+                '?' if self.advance_if('?') => TokenType::QuestionQuestion,
+                '?' if self.advance_if('.') => TokenType::QuestionDot,
+                '(' => TokenType::LeftParen,
+                ')' => TokenType::RightParen,
+                '[' => TokenType::LeftBracket,
+                ']' => TokenType::RightBracket,
+                '{' => TokenType::LeftBrace,
+                '}' => TokenType::RightBrace,
+                ',' => TokenType::Comma,
+                ';' => TokenType::Semicolon,
+                ':' => TokenType::Colon,
+                // `.=` applies a transformer and stores the result
+                '.' if self.advance_if('=') => TokenType::DotEqual,
+                // `a..b` is the range from a up to (not including) b
+                '.' if self.advance_if('.') => TokenType::DotDot,
+                '.' => TokenType::Dot,
+                _ => return Some(Err(self.error(format!("Unexpected character '{}'", c), start))),
+            };
+
+            return Some(Ok(self.token(token_type, start)));
+        }
+    }
+}
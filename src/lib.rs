@@ -0,0 +1,34 @@
+// The M interpreter as a library, shared by the m_lang binary and the benchmarks
+pub mod token;
+pub mod lexer;
+pub mod parser;
+pub mod ast;
+pub mod symbol;
+pub mod value;
+pub mod interpreter;
+pub mod typecheck;
+pub mod optimizer;
+pub mod resolver;
+pub mod lint;
+pub mod cache;
+pub mod host;
+pub mod module;
+pub mod js;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+mod environment;
+mod format;
+mod encoding;
+mod json;
+mod snapshot;
+mod hashing;
+mod channel;
+mod heap;
+mod stack;
+mod random;
+mod printer;
+mod bigint;
+mod decimal;
+mod set;
@@ -0,0 +1,345 @@
+use std::collections::HashSet;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use crate::ast::Expr;
+use crate::lexer::Lexer;
+use crate::module;
+use crate::parser::Parser;
+use crate::stack;
+use crate::symbol::Symbol;
+
+// The kinds of warning the linter can report, each enabled by name with `-W`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Warning {
+    UnusedVariable,
+    UnusedImport,
+    Shadowing,
+    UnreachableCode,
+    ImplicitApplied,
+}
+
+impl Warning {
+    pub const ALL: &'static [Warning] = &[Warning::UnusedVariable, Warning::UnusedImport, Warning::Shadowing, Warning::UnreachableCode, Warning::ImplicitApplied];
+
+    // What `lint` checks when no warnings are named. Uses of `applied` are
+    // only reported on request, while programs move to named receivers.
+    pub const DEFAULT: &'static [Warning] = &[Warning::UnusedVariable, Warning::UnusedImport, Warning::Shadowing, Warning::UnreachableCode];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Warning::UnusedVariable => "unused-variable",
+            Warning::UnusedImport => "unused-import",
+            Warning::Shadowing => "shadowing",
+            Warning::UnreachableCode => "unreachable-code",
+            Warning::ImplicitApplied => "implicit-applied",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Warning> {
+        Warning::ALL.iter().copied().find(|warning| warning.name() == name)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub warning: Warning,
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} [-W {}]", self.message, self.warning.name())
+    }
+}
+
+// Reports code that runs but is probably not what was meant. Unlike the
+// resolver, nothing reported here would fail at runtime, so every warning has
+// to be asked for.
+pub struct Linter {
+    base_path: PathBuf,
+    module_paths: Vec<PathBuf>,
+    enabled: Vec<Warning>,
+    diagnostics: Vec<Diagnostic>,
+    // Names declared by each enclosing scope, innermost last
+    scopes: Vec<HashSet<String>>,
+    // Every name the program reads, calls or applies
+    used: HashSet<String>,
+    imports: Vec<String>,
+    // The function bodies being walked, innermost last
+    functions: Vec<Locals>,
+}
+
+// The variables a function body assigns, where each is first assigned, and the
+// names read anywhere in the body (including functions nested in it)
+#[derive(Default)]
+struct Locals {
+    assigned: Vec<(String, usize, usize)>,
+    read: HashSet<String>,
+}
+
+impl Linter {
+    pub fn check(program: &Expr, base_path: &Path, enabled: &[Warning]) -> Vec<Diagnostic> {
+        Self::check_with_paths(program, base_path, &[], enabled)
+    }
+
+    /// Like check(), for a program whose imports are also looked for in the
+    /// module directories of its project.
+    pub fn check_with_paths(program: &Expr, base_path: &Path, module_paths: &[PathBuf], enabled: &[Warning]) -> Vec<Diagnostic> {
+        let mut linter = Linter {
+            base_path: base_path.to_path_buf(),
+            module_paths: module_paths.to_vec(),
+            enabled: enabled.to_vec(),
+            diagnostics: Vec::new(),
+            scopes: vec![HashSet::new()],
+            used: HashSet::new(),
+            imports: Vec::new(),
+            functions: Vec::new(),
+        };
+
+        linter.walk(program);
+        linter.check_imports();
+        linter.diagnostics
+    }
+
+    fn walk(&mut self, expr: &Expr) {
+        stack::guard(|| match expr {
+            Expr::Variable(name) | Expr::Local { name, .. } => self.use_name(name),
+            Expr::Call { callee, arguments } => {
+                self.use_name(callee);
+                self.walk_all(arguments);
+            },
+            Expr::Let { name, value, line, column, .. } => {
+                if let Some(value) = value {
+                    self.walk(value);
+                }
+                self.declare(name, "Variable");
+                self.assign_local(name, *line, *column);
+            },
+            Expr::Assign { name, value, line, column } => {
+                self.walk(value);
+                // Assigning to a name that doesn't exist yet defines it here
+                if !self.is_declared(name) {
+                    self.declare(name, "Variable");
+                    self.assign_local(name, *line, *column);
+                }
+            },
+            Expr::TupleAssign { names, value, line, column } => {
+                self.walk(value);
+                for name in names {
+                    if !self.is_declared(name) {
+                        self.declare(name, "Variable");
+                        self.assign_local(name, *line, *column);
+                    }
+                }
+            },
+            Expr::Function { name, params, body, .. } => {
+                if let Some(name) = name {
+                    self.declare(name, "Function");
+                }
+                self.function(|linter| {
+                    for param in params.iter() {
+                        linter.declare(param, "Parameter");
+                    }
+                    linter.walk_block(body);
+                });
+            },
+            Expr::Transformer { name, receiver, params, body, .. } => {
+                self.declare(name, "Transformer");
+                self.function(|linter| {
+                    linter.scopes.last_mut().unwrap().insert(receiver.as_deref().unwrap_or("applied").to_string());
+                    for param in params.iter() {
+                        linter.declare(param, "Parameter");
+                    }
+                    linter.walk_block(body);
+
+                    if receiver.is_none() && linter.functions.last().is_some_and(|locals| locals.read.contains("applied")) {
+                        linter.report(
+                            Warning::ImplicitApplied,
+                            format!("Transformer '{}' uses 'applied'; name the value instead, as in 'transformer x.{}()'", name, name),
+                        );
+                    }
+                });
+            },
+            Expr::For { variable, second, iterable, step, body, .. } => {
+                self.walk(iterable);
+                if let Some(step) = step {
+                    self.walk(step);
+                }
+                self.scoped(|linter| {
+                    linter.declare(variable, "Loop variable");
+                    if let Some(second) = second {
+                        linter.declare(second, "Loop variable");
+                    }
+                    linter.walk(body);
+                });
+            },
+            Expr::Use { path } => {
+                self.imports.push(path.clone());
+            },
+            Expr::Block(statements) => self.walk_block(statements),
+            Expr::Array(elements) | Expr::Tuple(elements) => self.walk_all(elements),
+            Expr::Map(entries) => {
+                for (_, value) in entries {
+                    self.walk(value);
+                }
+            },
+            Expr::Binary { left, right, .. } | Expr::Index { object: left, index: right } => {
+                self.walk(left);
+                self.walk(right);
+            },
+            Expr::IndexAssign { object, index, value } => {
+                self.walk(object);
+                self.walk(index);
+                self.walk(value);
+            },
+            Expr::Unary { right, .. } | Expr::Await(right) => self.walk(right),
+            Expr::Return { value, .. } | Expr::Break { value, .. } => {
+                if let Some(value) = value {
+                    self.walk(value);
+                }
+            },
+            Expr::If { condition, then_branch, else_branch } => {
+                self.walk(condition);
+                self.scoped(|linter| linter.walk(then_branch));
+                if let Some(else_branch) = else_branch {
+                    self.scoped(|linter| linter.walk(else_branch));
+                }
+            },
+            Expr::While { condition, body, .. } => {
+                self.walk(condition);
+                self.scoped(|linter| linter.walk(body));
+            },
+            Expr::Loop { body, .. } => self.scoped(|linter| linter.walk(body)),
+            Expr::DoWhile { body, condition, .. } => {
+                // The condition can see the body's variables
+                self.scoped(|linter| {
+                    linter.walk(body);
+                    linter.walk(condition);
+                });
+            },
+            Expr::Apply { object, transformer, arguments, .. } => {
+                self.use_name(transformer);
+                self.walk(object);
+                self.walk_all(arguments);
+            },
+            Expr::Member { object, name, .. } => {
+                self.use_name(name);
+                self.walk(object);
+            },
+            Expr::Assert { arguments, .. } => self.walk_all(arguments),
+            Expr::Int(_) | Expr::BigInt(_) | Expr::Number(_) | Expr::String(_) | Expr::Boolean(_) | Expr::Nil | Expr::Continue { .. } | Expr::Pragma { .. } => {},
+        })
+    }
+
+    fn walk_all(&mut self, expressions: &[Expr]) {
+        for expr in expressions {
+            self.walk(expr);
+        }
+    }
+
+    // Statements after a `return` in the same block can never run
+    fn walk_block(&mut self, statements: &[Expr]) {
+        let returned = statements.iter().take(statements.len().saturating_sub(1)).find_map(|statement| match statement {
+            Expr::Return { line, column, .. } => Some((*line, *column)),
+            _ => None,
+        });
+        if let Some((line, column)) = returned {
+            self.report(Warning::UnreachableCode, format!("Unreachable code after the return at line {}, column {}", line, column));
+        }
+
+        self.walk_all(statements);
+    }
+
+    // Walks a function or transformer body, then reports the variables it
+    // assigns but never reads. Functions also see their caller's variables, but
+    // reading those is rare enough that only the body itself is searched.
+    fn function(&mut self, walk: impl FnOnce(&mut Self)) {
+        self.functions.push(Locals::default());
+        self.scoped(walk);
+        let locals = self.functions.pop().unwrap();
+
+        for (name, line, column) in locals.assigned {
+            if !locals.read.contains(&name) && !name.starts_with('_') {
+                self.report(
+                    Warning::UnusedVariable,
+                    format!("Variable '{}' is assigned but never read at line {}, column {}", name, line, column),
+                );
+            }
+        }
+    }
+
+    fn assign_local(&mut self, name: &str, line: usize, column: usize) {
+        if let Some(locals) = self.functions.last_mut() {
+            if !locals.assigned.iter().any(|(assigned, ..)| assigned == name) {
+                locals.assigned.push((name.to_string(), line, column));
+            }
+        }
+    }
+
+    fn scoped(&mut self, walk: impl FnOnce(&mut Self)) {
+        self.scopes.push(HashSet::new());
+        walk(self);
+        self.scopes.pop();
+    }
+
+    fn is_declared(&self, name: &str) -> bool {
+        self.scopes.iter().any(|scope| scope.contains(name))
+    }
+
+    // Declares a name in the innermost scope, reporting it if an enclosing
+    // scope already has one by that name
+    fn declare(&mut self, name: &str, kind: &str) {
+        let enclosing = &self.scopes[..self.scopes.len() - 1];
+        if enclosing.iter().any(|scope| scope.contains(name)) {
+            self.report(Warning::Shadowing, format!("{} '{}' shadows an outer definition", kind, name));
+        }
+        self.scopes.last_mut().unwrap().insert(name.to_string());
+    }
+
+    fn use_name(&mut self, name: &str) {
+        self.used.insert(name.to_string());
+        for locals in &mut self.functions {
+            locals.read.insert(name.to_string());
+        }
+    }
+
+    // An import is unused when nothing it makes available is ever referred to
+    fn check_imports(&mut self) {
+        for path in std::mem::take(&mut self.imports) {
+            let mut visited = HashSet::new();
+            let names = self.imported_names(&module::locate(&path, &self.base_path, &self.module_paths), &mut visited);
+            // Files that can't be read or parsed are reported when the program runs
+            if names.is_some_and(|names| !names.iter().any(|name| self.used.contains(name.as_str()))) {
+                self.report(Warning::UnusedImport, format!("Nothing from '{}' is used", path));
+            }
+        }
+    }
+
+    // The names a file exports: those it marks `pub`, or else everything it
+    // defines at its top level along with what it imports
+    fn imported_names(&self, file_path: &Path, visited: &mut HashSet<PathBuf>) -> Option<Vec<Symbol>> {
+        if !visited.insert(file_path.to_path_buf()) {
+            return Some(Vec::new());
+        }
+
+        let content = fs::read_to_string(file_path).ok()?;
+        let tokens = Lexer::new(&content).tokenize().ok()?;
+        let ast = Parser::new(tokens).parse().ok()?;
+        if let Some(public) = module::public_names(&ast) {
+            return Some(public);
+        }
+
+        let mut names = module::top_level_names(&ast);
+        for import in module::top_level_imports(&ast) {
+            names.extend(self.imported_names(&module::locate(import, &self.base_path, &self.module_paths), visited).unwrap_or_default());
+        }
+        Some(names)
+    }
+
+    fn report(&mut self, warning: Warning, message: String) {
+        if self.enabled.contains(&warning) {
+            self.diagnostics.push(Diagnostic { warning, message });
+        }
+    }
+}
@@ -1,80 +1,456 @@
-use std::fs::File;
-use std::io::{self, BufRead, BufReader};
+use std::fs;
 use std::path::{Path, PathBuf};
 use std::env;
+use std::sync::Arc;
 
-mod token;
-mod lexer;
-mod parser;
-mod ast;
-mod value;
-mod environment;
-mod interpreter;
-
-use lexer::Lexer;
-use parser::Parser;
-use interpreter::Interpreter;
-
-fn read_file(file_path: &Path, line_index: i32) -> Result<Vec<String>, io::Error> {
-    let file = File::open(file_path)?;
-    let reader = BufReader::new(file);
-    
-    let lines: Vec<String> = reader.lines().collect::<Result<_, _>>()?;
-    
-    if line_index >= 0 && line_index < lines.len() as i32 {
-        // Return only the specified line
-        Ok(vec![lines[line_index as usize].clone()])
-    } else {
-        // Return all lines
-        Ok(lines)
-    }
-}
+mod bench;
+mod bundle;
+mod diagnostic;
+mod doc;
+mod project;
+mod repl;
+mod style;
+
+use m_lang::cache;
+use m_lang::lexer::Lexer;
+use m_lang::parser::Parser;
+use m_lang::interpreter::Interpreter;
+use m_lang::typecheck::TypeChecker;
+use m_lang::optimizer::Optimizer;
+use m_lang::resolver::Resolver;
+use m_lang::lint::{Linter, Warning};
+use m_lang::js;
+
+use diagnostic::{Diagnostic, Kind};
+
+// Only reserved up front; memory is used as the stack actually grows
+const MAIN_STACK_SIZE: usize = 256 * 1024 * 1024;
 
 fn main() {
     // Get the current directory to use as the base path
     let current_dir = std::env::current_dir().unwrap_or_else(|_| std::path::PathBuf::from("."));
+
+    // A bundled binary only runs the program embedded in it
+    if let Some(bundle) = bundle::embedded() {
+        run_bundle(bundle, current_dir);
+        return;
+    }
     
-    // Get the file path from command-line arguments or use the default
-    let file_path = env::args().nth(1)
-        .map(PathBuf::from)
-        .unwrap_or_else(|| PathBuf::from("main.m"));
-    
-    println!("Running file: {}", file_path.display());
-    
-    // Create a single interpreter instance to maintain state across all processing
-    let mut interpreter = Interpreter::with_base_path(&current_dir);
+    // Get the file paths and options from command-line arguments
+    let mut file_paths = Vec::new();
+    let mut require_let = false;
+    let mut strict_conditions = false;
+    let mut strict = false;
+    let mut strict_numbers = false;
+    let mut typecheck = false;
+    let mut optimize = false;
+    let mut use_cache = false;
+    let mut shared_state = false;
+    let mut script_args = Vec::new();
+    let mut seed = None;
+    let mut warnings = Vec::new();
+
+    // `bench` as the first argument times the program instead of running it once,
+    // `doc` documents it, `lint` checks it for warnings, `compile` translates it
+    // to another language and `bundle` packages it as an executable instead of
+    // running it. `run` runs the project's program when not given a file, `new`
+    // creates a project, `install` fetches its dependencies and `repl` runs code
+    // as it is typed.
+    let mut args = env::args().skip(1).peekable();
+    let command = args.next_if(|arg| matches!(
+        arg.as_str(),
+        "bench" | "doc" | "lint" | "compile" | "bundle" | "run" | "new" | "install" | "repl"
+    ));
+    let bench = command.as_deref() == Some("bench");
+    let doc = command.as_deref() == Some("doc");
+    let lint = command.as_deref() == Some("lint");
+    let compile = command.as_deref() == Some("compile");
+    let bundle = command.as_deref() == Some("bundle");
+    let repl = command.as_deref() == Some("repl");
+    let mut iterations = 10;
+    let mut html = false;
+    let mut output_path = None;
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--require-let" => require_let = true,
+            "--strict-conditions" => strict_conditions = true,
+            "--strict" => strict = true,
+            "--strict-numbers" => strict_numbers = true,
+            "--typecheck" => typecheck = true,
+            "-O" => optimize = true,
+            "--cache" => use_cache = true,
+            "--json-diagnostics" => diagnostic::set_json(true),
+            "--shared-state" => shared_state = true,
+            // Everything after `--` is for the program, read with args()
+            "--" => script_args.extend(args.by_ref()),
+            "--seed" => {
+                seed = match args.next().and_then(|n| n.parse().ok()) {
+                    Some(n) => Some(n),
+                    None => {
+                        style::report("Error: --seed expects a non-negative integer");
+                        std::process::exit(2);
+                    },
+                };
+            },
+            "--html" if doc => html = true,
+            // JavaScript is the only target so far
+            "--target" if compile => {
+                let target = args.next().unwrap_or_default();
+                if target != "js" {
+                    style::report("Error: --target expects js");
+                    std::process::exit(2);
+                }
+            },
+            "-o" if compile || bundle => match args.next() {
+                Some(path) => output_path = Some(PathBuf::from(path)),
+                None => {
+                    style::report("Error: -o expects a file name");
+                    std::process::exit(2);
+                },
+            },
+            "-W" => {
+                let name = args.next().unwrap_or_default();
+                match Warning::from_name(&name) {
+                    Some(warning) => warnings.push(warning),
+                    None if name == "all" => warnings.extend_from_slice(Warning::ALL),
+                    None => {
+                        let names: Vec<_> = Warning::ALL.iter().map(|warning| warning.name()).collect();
+                        style::report(&format!("Error: -W expects one of: all, {}", names.join(", ")));
+                        std::process::exit(2);
+                    },
+                }
+            },
+            "--iterations" if bench => {
+                iterations = match args.next().and_then(|n| n.parse().ok()) {
+                    Some(n) if n > 0 => n,
+                    _ => {
+                        style::report("Error: --iterations expects a positive number");
+                        std::process::exit(2);
+                    },
+                };
+            },
+            _ if arg.starts_with('-') => {
+                style::report(&format!("Error: Unknown option '{}'", arg));
+                std::process::exit(2);
+            },
+            _ => file_paths.push(PathBuf::from(arg)),
+        }
+    }
+
+    // Only running takes several files
+    if file_paths.len() > 1 && !matches!(command.as_deref(), None | Some("run")) {
+        style::report(&format!("Error: {} expects a single file", command.unwrap_or_default()));
+        std::process::exit(2);
+    }
+
+    if command.as_deref() == Some("new") {
+        let created = match file_paths.first() {
+            Some(dir) => project::create(dir).map(|()| println!("Created project {}", dir.display())),
+            None => Err("Error: new expects a project name".to_string()),
+        };
+        if let Err(e) = created {
+            style::report(&e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if command.as_deref() == Some("install") {
+        let installed = project::Project::find(&current_dir).and_then(|project| match project {
+            Some(project) => project.install(),
+            None => Err(format!("Error: no {} found in {} or its parents", project::MANIFEST, current_dir.display())),
+        });
+        match installed {
+            Ok(installed) => {
+                for line in installed {
+                    println!("Installed {}", line);
+                }
+            },
+            Err(e) => {
+                style::report(&e);
+                std::process::exit(1);
+            },
+        }
+        return;
+    }
+
+    // `run` without a file runs the program named by the project's manifest,
+    // with imports relative to the project directory, and `bundle` packages it
+    let project = match (command.as_deref(), file_paths.is_empty()) {
+        (Some("run" | "bundle"), true) => project::Project::find(&current_dir).unwrap_or_else(|e| {
+            style::report(&e);
+            std::process::exit(1);
+        }),
+        _ => None,
+    };
+    let (file_paths, base_path, module_paths) = match project {
+        Some(project) => (vec![project.main()], project.dir.clone(), project.module_paths()),
+        // Fall back to the default file
+        None if file_paths.is_empty() => (vec![PathBuf::from("main.m")], current_dir.clone(), Vec::new()),
+        None => (file_paths, current_dir.clone(), Vec::new()),
+    };
+    let file_path = file_paths[0].clone();
+
+    if doc {
+        document(&file_path, html);
+        return;
+    }
+
+    if compile {
+        compile_file(&file_path, &current_dir, output_path.as_deref());
+        return;
+    }
+
+    if bundle {
+        // `tool.m` becomes `tool` unless another name is given
+        let output_path = output_path.unwrap_or_else(|| PathBuf::from(file_path.file_stem().unwrap_or_default()));
+        if let Err(e) = bundle::create(&file_path, &base_path, &module_paths, &output_path) {
+            style::report(&e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if lint {
+        // The default warnings are checked unless particular ones are asked for
+        if warnings.is_empty() {
+            warnings.extend_from_slice(Warning::DEFAULT);
+        }
+        let base_path = current_dir.clone();
+        if !lint_file(&file_path, &base_path, &warnings) {
+            std::process::exit(1);
+        }
+        return;
+    }
     
-    // Process the specified file
-    process_file(&file_path, -1, &mut interpreter);
+    // Passes over the program grow their stack as needed (see stack.rs), but
+    // writing a deeply nested syntax tree to the cache can't, so the program
+    // runs on a thread with plenty of room for that
+    let program = std::thread::Builder::new().stack_size(MAIN_STACK_SIZE).spawn(move || {
+        let new_interpreter = || {
+            let mut interpreter = Interpreter::with_base_path(&base_path);
+            interpreter.set_module_paths(module_paths.clone());
+            interpreter.set_require_let(require_let);
+            interpreter.set_strict_conditions(strict_conditions);
+            interpreter.set_strict(strict);
+            interpreter.set_strict_numbers(strict || strict_numbers);
+            interpreter.set_optimize(optimize);
+            interpreter.set_cache(use_cache);
+            interpreter.set_args(script_args.clone());
+            if let Some(seed) = seed {
+                interpreter.set_seed(seed);
+            }
+            interpreter
+        };
+
+        if repl {
+            repl::run(new_interpreter());
+            return true;
+        }
+
+        if bench {
+            println!("Running file: {}", file_path.display());
+            bench::run(&file_path, iterations, || {
+                process_file(&file_path, &mut new_interpreter(), typecheck, optimize, use_cache, &warnings);
+            });
+            return true;
+        }
+
+        // Each file runs in an interpreter of its own, unless --shared-state
+        // lets later files use what earlier ones defined. A failing file
+        // doesn't stop the ones after it.
+        let mut shared = shared_state.then(new_interpreter);
+        let mut failed = Vec::new();
+        for file_path in &file_paths {
+            println!("Running file: {}", file_path.display());
+            let succeeded = match &mut shared {
+                Some(interpreter) => process_file(file_path, interpreter, typecheck, optimize, use_cache, &warnings),
+                None => process_file(file_path, &mut new_interpreter(), typecheck, optimize, use_cache, &warnings),
+            };
+            if !succeeded {
+                failed.push(file_path.display().to_string());
+            }
+        }
+
+        // Each error was reported after its file's name; with JSON diagnostics
+        // each one names its file instead
+        if file_paths.len() > 1 && !failed.is_empty() && !diagnostic::is_json() {
+            style::report(&format!("Error: {} of {} files failed: {}", failed.len(), file_paths.len(), failed.join(", ")));
+        }
+        failed.is_empty()
+    });
+
+    match program.map(|handle| handle.join()) {
+        Ok(Ok(true)) => {},
+        Ok(Ok(false)) => std::process::exit(1),
+        // The panic message has already been printed
+        Ok(Err(_)) => std::process::exit(101),
+        Err(e) => {
+            style::report(&format!("Error: Failed to start the interpreter: {}", e));
+            std::process::exit(1);
+        },
+    }
 }
 
-fn process_file(file_path: &Path, line_index: i32, interpreter: &mut Interpreter) {
-    let file_content = read_file(file_path, line_index);
-    
-    match file_content {
-        Ok(lines) => {
-            // Process the entire file as a single string
-            let file_str = lines.join("\n");
-            
-            // Create a lexer with the entire file content
-            let lexer = Lexer { line: Ok(vec![file_str]) };
-            let tokens = lexer.lex();
-            
-            // Parse the tokens
-            let mut parser = Parser::new(tokens);
-            match parser.parse() {
+// Runs the program embedded in a bundled binary, with its imports served from the bundle
+fn run_bundle(bundle: bundle::Bundle, current_dir: PathBuf) {
+    let program = std::thread::Builder::new().stack_size(MAIN_STACK_SIZE).spawn(move || {
+        let parsed = Lexer::new(&bundle.main)
+            .tokenize()
+            .and_then(|tokens| Parser::new(tokens).parse())
+            .map_err(|e| format!("Error in {}: {}", bundle.name, e));
+
+        let mut interpreter = Interpreter::with_base_path(&current_dir);
+        interpreter.set_module_paths(bundle.module_paths.iter().map(|path| current_dir.join(path)).collect());
+        interpreter.set_host(Arc::new(bundle::EmbeddedHost::new(bundle, &current_dir)));
+        // A bundled program takes its arguments directly, without `--`
+        interpreter.set_args(env::args().skip(1).collect());
+        parsed.and_then(|expr| interpreter.evaluate(&expr).map_err(|e| format!("Error: {}", e)))
+    });
+
+    match program.map(|handle| handle.join()) {
+        Ok(Ok(Ok(_))) => {},
+        Ok(Ok(Err(e))) => {
+            style::report(&e);
+            std::process::exit(1);
+        },
+        Ok(Err(_)) => std::process::exit(101),
+        Err(e) => {
+            style::report(&format!("Error: Failed to start the interpreter: {}", e));
+            std::process::exit(1);
+        },
+    }
+}
+
+fn document(file_path: &Path, html: bool) {
+    match diagnostic::parse_file(file_path) {
+        Ok(expr) => print!("{}", doc::generate(file_path, &expr, html)),
+        Err(diagnostic) => {
+            diagnostic.report();
+            std::process::exit(1);
+        },
+    }
+}
+
+// Writes the program as JavaScript to the output file, or to stdout without one
+fn compile_file(file_path: &Path, base_path: &Path, output_path: Option<&Path>) {
+    let compiled = diagnostic::parse_file(file_path).and_then(|expr| {
+        js::compile(&expr, base_path).map_err(|e| Diagnostic::new(Kind::Compile, Some(file_path), e))
+    });
+    let output = match compiled {
+        Ok(output) => output,
+        Err(diagnostic) => {
+            diagnostic.report();
+            std::process::exit(1);
+        },
+    };
+
+    let written = match output_path {
+        Some(path) => fs::write(path, output).map_err(|e| format!("Error writing file: {}", e)),
+        None => {
+            print!("{}", output);
+            Ok(())
+        },
+    };
+
+    if let Err(e) = written {
+        style::report(&e);
+        std::process::exit(1);
+    }
+}
+
+// Reports the file's warnings without running it; true when there are none
+fn lint_file(file_path: &Path, base_path: &Path, warnings: &[Warning]) -> bool {
+    match diagnostic::parse_file(file_path) {
+        Ok(expr) => {
+            let mut diagnostics: Vec<String> = Resolver::check(&expr, base_path);
+            diagnostics.extend(Linter::check(&expr, base_path, warnings).iter().map(|diagnostic| diagnostic.to_string()));
+            for diagnostic in &diagnostics {
+                Diagnostic::new(Kind::Warning, Some(file_path), diagnostic).report();
+            }
+            diagnostics.is_empty()
+        },
+        Err(diagnostic) => {
+            diagnostic.report();
+            false
+        },
+    }
+}
+
+// Runs a file, reporting what goes wrong; true when it ran without an error
+fn process_file(file_path: &Path, interpreter: &mut Interpreter, typecheck: bool, optimize: bool, use_cache: bool, warnings: &[Warning]) -> bool {
+    // The file is lexed exactly as stored, so token spans are offsets into it
+    match diagnostic::read(file_path) {
+        Ok(file_str) => {
+            // The main file's cache holds the AST as written; it is optimized after checking
+            let cached = if use_cache { cache::load(file_path, &file_str, false) } else { None };
+            let parsed = match cached {
+                Some(expr) => Ok(expr),
+                None => {
+                    // Tokenize the entire file content, then parse the tokens
+                    let parsed = diagnostic::parse(&file_str, Some(file_path));
+                    if let (true, Ok(expr)) = (use_cache, &parsed) {
+                        cache::store(file_path, &file_str, false, expr);
+                    }
+                    parsed
+                },
+            };
+
+            match parsed {
                 Ok(expr) => {
+                    // The type checker is advisory: report problems, then run anyway
+                    if typecheck {
+                        for diagnostic in TypeChecker::check(&expr) {
+                            Diagnostic::new(Kind::Type, Some(file_path), diagnostic).report();
+                        }
+                    }
+
+                    // References to names that are never defined would fail at runtime,
+                    // so the program isn't run, unless it calls eval(), which may define
+                    // them. Files run before this one with --shared-state may have too.
+                    let base_path = interpreter.base_path().unwrap_or(Path::new("."));
+                    let defined = interpreter.globals().map(|(name, _)| name);
+                    let resolution = Resolver::check_with_names(&expr, base_path, interpreter.module_paths(), defined);
+                    let kind = if resolution.evaluates { Kind::Warning } else { Kind::Resolve };
+                    for diagnostic in &resolution.undefined {
+                        Diagnostic::new(kind, Some(file_path), diagnostic).report();
+                    }
+
+                    // Lints only run when asked for with -W
+                    if !warnings.is_empty() {
+                        for diagnostic in Linter::check_with_paths(&expr, base_path, interpreter.module_paths(), warnings) {
+                            Diagnostic::new(Kind::Warning, Some(file_path), diagnostic.to_string()).report();
+                        }
+                    }
+
+                    if !resolution.evaluates && !resolution.undefined.is_empty() {
+                        return false;
+                    }
+
+                    // Optimize after type checking, so warnings refer to the code as written
+                    let expr = if optimize { Optimizer::optimize(expr) } else { expr };
+
                     // Evaluate the expression using the interpreter
+                    interpreter.set_main_file(file_path);
                     match interpreter.evaluate(&expr) {
-                        Ok(_) => (), // Don't print the result
-                        Err(e) => eprintln!("Error: {}", e),
+                        Ok(_) => true, // Don't print the result
+                        Err(e) => {
+                            let location = interpreter.take_error_location(&e);
+                            Diagnostic::runtime(Some(file_path), e, location).report();
+                            false
+                        },
                     }
                 },
-                Err(e) => eprintln!("Error: {}", e),
+                Err(diagnostic) => {
+                    diagnostic.report();
+                    false
+                },
             }
         },
-        Err(e) => {
-            eprintln!("Error reading file: {}", e);
-        }
+        Err(diagnostic) => {
+            diagnostic.report();
+            false
+        },
     }
 }
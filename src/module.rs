@@ -0,0 +1,169 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use crate::ast::Expr;
+use crate::symbol::Symbol;
+use crate::value::Value;
+
+// Every module loaded by `use`, keyed on its canonical path so that different
+// spellings of the same file (`lib.m`, `./lib.m`) share one entry. It is shared
+// by all interpreters of a program, including those of imported files and
+// spawned tasks, so each module is parsed and run only once.
+#[derive(Debug, Default)]
+pub struct ModuleRegistry {
+    modules: HashMap<PathBuf, Module>,
+}
+
+// The private definitions of modules that export with `pub`, by the address of
+// each function and transformer body the module defines. Calls of those see
+// the definitions as if they were global.
+#[derive(Debug, Default)]
+pub struct ModuleScopes {
+    scopes: HashMap<usize, ModuleScope>,
+}
+
+#[derive(Debug)]
+struct ModuleScope {
+    module: PathBuf,
+    // Held so that no other body can be allocated at the same address while
+    // the entry exists
+    _body: Arc<[Expr]>,
+    definitions: Arc<[(Symbol, Value)]>,
+}
+
+impl ModuleScopes {
+    pub fn get(&self, body: &[Expr]) -> Option<&[(Symbol, Value)]> {
+        self.scopes.get(&(body.as_ptr() as usize)).map(|scope| &*scope.definitions)
+    }
+
+    // Replaces the scopes of the module's previous version, if it had any, with
+    // those of the version just loaded
+    pub fn replace(&mut self, module: &Path, bodies: Vec<Arc<[Expr]>>, definitions: Arc<[(Symbol, Value)]>) {
+        self.scopes.retain(|_, scope| scope.module != module);
+        for body in bodies {
+            self.scopes.entry(body.as_ptr() as usize).or_insert_with(|| ModuleScope {
+                module: module.to_path_buf(),
+                _body: body,
+                definitions: definitions.clone(),
+            });
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Module {
+    // None while the module is still running its top level
+    pub exports: Option<Vec<(Symbol, Value)>>,
+    // While its top level waits for a module another task is loading, that module
+    pub waiting_for: Option<PathBuf>,
+}
+
+impl ModuleRegistry {
+    pub fn get(&self, path: &Path) -> Option<&Module> {
+        self.modules.get(path)
+    }
+
+    // Records a module whose top level is about to run
+    pub fn start(&mut self, path: PathBuf) {
+        self.modules.insert(path, Module { exports: None, waiting_for: None });
+    }
+
+    // Notes that the module's top level is waiting for another task to load a
+    // module, or has stopped waiting
+    pub fn wait_for(&mut self, path: &Path, waiting_for: Option<PathBuf>) {
+        if let Some(module) = self.modules.get_mut(path) {
+            module.waiting_for = waiting_for;
+        }
+    }
+
+    pub fn finish(&mut self, path: &Path, exports: Vec<(Symbol, Value)>) {
+        if let Some(module) = self.modules.get_mut(path) {
+            module.exports = Some(exports);
+        }
+    }
+
+    // Forgets a module that failed to load, so a later `use` tries again
+    pub fn remove(&mut self, path: &Path) -> Option<Module> {
+        self.modules.remove(path)
+    }
+
+    pub fn insert(&mut self, path: PathBuf, module: Module) {
+        self.modules.insert(path, module);
+    }
+}
+
+// The file `use path` refers to: relative to the base path, or else to the first
+// module directory (see Interpreter::set_module_paths) that has it. Paths that
+// exist nowhere resolve against the base path, so errors name that file.
+pub fn locate(path: &str, base_path: &Path, module_paths: &[PathBuf]) -> PathBuf {
+    std::iter::once(base_path)
+        .chain(module_paths.iter().map(PathBuf::as_path))
+        .map(|dir| dir.join(path))
+        .find(|file_path| file_path.exists())
+        .unwrap_or_else(|| base_path.join(path))
+}
+
+// Names a module defines at its top level, which is what `use` makes available
+// to the importer. Names brought in by the module's own imports are added by
+// the caller from the registry.
+pub fn top_level_names(ast: &Expr) -> Vec<Symbol> {
+    let statements = match ast {
+        Expr::Block(statements) => statements.as_slice(),
+        statement => std::slice::from_ref(statement),
+    };
+
+    let mut names = Vec::new();
+    for statement in statements {
+        match statement {
+            Expr::Let { name, .. }
+            | Expr::Assign { name, .. }
+            | Expr::Function { name: Some(name), .. }
+            | Expr::Transformer { name, .. } if !names.contains(name) => names.push(*name),
+            Expr::TupleAssign { names: assigned, .. } => {
+                for name in assigned {
+                    if !names.contains(name) {
+                        names.push(*name);
+                    }
+                }
+            },
+            _ => {},
+        }
+    }
+    names
+}
+
+// The names a module marks `pub`, or None when it marks none and so exports
+// everything it defines along with what it imports
+pub fn public_names(ast: &Expr) -> Option<Vec<Symbol>> {
+    let statements = match ast {
+        Expr::Block(statements) => statements.as_slice(),
+        statement => std::slice::from_ref(statement),
+    };
+
+    let names: Vec<Symbol> = statements
+        .iter()
+        .filter_map(|statement| match statement {
+            Expr::Let { name, public: true, .. }
+            | Expr::Function { name: Some(name), public: true, .. }
+            | Expr::Transformer { name, public: true, .. } => Some(*name),
+            _ => None,
+        })
+        .collect();
+    (!names.is_empty()).then_some(names)
+}
+
+// Paths of the modules a module imports at its top level
+pub fn top_level_imports(ast: &Expr) -> Vec<&str> {
+    let statements = match ast {
+        Expr::Block(statements) => statements.as_slice(),
+        statement => std::slice::from_ref(statement),
+    };
+
+    statements
+        .iter()
+        .filter_map(|statement| match statement {
+            Expr::Use { path } => Some(path.as_str()),
+            _ => None,
+        })
+        .collect()
+}
@@ -0,0 +1,148 @@
+use std::sync::Arc;
+use crate::ast::Expr;
+use crate::interpreter::Interpreter;
+use crate::stack;
+use crate::value::Value;
+
+// An AST optimization pass, enabled with -O. It folds operators whose operands
+// are literals (including string concatenation), collapses `if` and `while`
+// with constant conditions, and drops statements that follow a `return` in a
+// function body.
+//
+// Folding evaluates the operator with a scratch interpreter, so folded results
+// are exactly what the program would have computed. Operations that fail (such
+// as integer overflow) are left in place to report their error at runtime, and
+// so are results that aren't finite, which are an error with --strict-numbers.
+pub struct Optimizer {
+    scratch: Interpreter,
+}
+
+impl Optimizer {
+    pub fn optimize(mut program: Expr) -> Expr {
+        let mut scratch = Interpreter::new();
+        scratch.set_strict_numbers(true);
+        Optimizer { scratch }.fold(&mut program);
+        program
+    }
+
+    fn fold(&mut self, expr: &mut Expr) {
+        stack::guard(|| match expr {
+            Expr::Binary { left, right, .. } => {
+                self.fold(left);
+                self.fold(right);
+                if Self::is_literal(left) && Self::is_literal(right) {
+                    self.evaluate_constant(expr);
+                }
+            },
+            Expr::Unary { right, .. } => {
+                self.fold(right);
+                if Self::is_literal(right) {
+                    self.evaluate_constant(expr);
+                }
+            },
+            Expr::If { condition, then_branch, else_branch } => {
+                self.fold(condition);
+                self.fold(then_branch);
+                if let Some(branch) = else_branch {
+                    self.fold(branch);
+                }
+
+                // The taken branch keeps its `if` so it still gets its own block scope
+                let constant = match **condition {
+                    Expr::Boolean(constant) => Some(constant),
+                    _ => None,
+                };
+                match (constant, else_branch.take()) {
+                    (Some(true), _) => {},
+                    (Some(false), Some(branch)) => {
+                        **condition = Expr::Boolean(true);
+                        *then_branch = branch;
+                    },
+                    (Some(false), None) => *expr = Expr::Nil,
+                    (None, branch) => *else_branch = branch,
+                }
+            },
+            Expr::While { condition, body, .. } => {
+                self.fold(condition);
+                if matches!(**condition, Expr::Boolean(false)) {
+                    *expr = Expr::Nil;
+                } else {
+                    self.fold(body);
+                }
+            },
+            // The body of a `do` always runs once, so even a false condition keeps the loop
+            Expr::DoWhile { body, condition, .. } => {
+                self.fold(body);
+                self.fold(condition);
+            },
+            Expr::Function { body, .. } | Expr::Transformer { body, .. } => self.fold_body(body),
+            Expr::Array(elements)
+            | Expr::Tuple(elements)
+            | Expr::Block(elements)
+            | Expr::Call { arguments: elements, .. }
+            | Expr::Assert { arguments: elements, .. } => elements.iter_mut().for_each(|element| self.fold(element)),
+            Expr::Map(entries) => entries.iter_mut().for_each(|(_, value)| self.fold(value)),
+            Expr::Let { value, .. } | Expr::Return { value, .. } | Expr::Break { value, .. } => {
+                if let Some(value) = value {
+                    self.fold(value);
+                }
+            },
+            Expr::Assign { value: inner, .. }
+            | Expr::TupleAssign { value: inner, .. }
+            | Expr::Await(inner)
+            | Expr::Loop { body: inner, .. }
+            | Expr::Member { object: inner, .. } => self.fold(inner),
+            Expr::For { iterable, step, body, .. } => {
+                self.fold(iterable);
+                if let Some(step) = step {
+                    self.fold(step);
+                }
+                self.fold(body);
+            },
+            Expr::Index { object, index } => {
+                self.fold(object);
+                self.fold(index);
+            },
+            Expr::IndexAssign { object, index, value } => {
+                self.fold(object);
+                self.fold(index);
+                self.fold(value);
+            },
+            Expr::Apply { object, arguments, .. } => {
+                self.fold(object);
+                arguments.iter_mut().for_each(|argument| self.fold(argument));
+            },
+            _ => {},
+        })
+    }
+
+    // Function bodies stop at a top-level `return`, so anything after it is dead.
+    // A body no function value shares yet is folded without copying it.
+    fn fold_body(&mut self, body: &mut Arc<[Expr]>) {
+        let end = body.iter().position(|expr| matches!(expr, Expr::Return { .. })).map_or(body.len(), |i| i + 1);
+        let mut folded: Vec<Expr> = match Arc::get_mut(body) {
+            Some(body) => body[..end].iter_mut().map(std::mem::take).collect(),
+            None => body[..end].to_vec(),
+        };
+        folded.iter_mut().for_each(|expr| self.fold(expr));
+        *body = folded.into();
+    }
+
+    fn is_literal(expr: &Expr) -> bool {
+        matches!(expr, Expr::Int(_) | Expr::BigInt(_) | Expr::Number(_) | Expr::String(_) | Expr::Boolean(_) | Expr::Nil)
+    }
+
+    // Evaluates an operator over literal operands, keeping the original expression
+    // if it fails or produces something that isn't a literal
+    fn evaluate_constant(&mut self, expr: &mut Expr) {
+        *expr = match self.scratch.evaluate(expr) {
+            Ok(Value::Int(n)) => Expr::Int(n),
+            Ok(Value::BigInt(n)) => Expr::BigInt(n.to_string()),
+            Ok(Value::Number(n)) => Expr::Number(n),
+            Ok(Value::String(s)) => Expr::String(s.to_string()),
+            Ok(Value::Boolean(b)) => Expr::Boolean(b),
+            Ok(Value::Nil) => Expr::Nil,
+            _ => return,
+        };
+    }
+}
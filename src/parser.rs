@@ -1,19 +1,65 @@
-use crate::token::{Token, TokenType};
+use std::mem;
+use crate::token::{Span, Token, TokenType};
 use crate::ast::Expr;
+use crate::bigint;
+use crate::resolver;
+use crate::symbol::Symbol;
+use crate::stack;
 
 pub struct Parser {
     tokens: Vec<Token>,
     current: usize,
+    // Labels of the loops being parsed, innermost last
+    labels: Vec<String>,
+    // The position an error is about, when that isn't the next token
+    failed_at: Option<(usize, usize)>,
 }
 
 impl Parser {
     pub fn new(tokens: Vec<Token>) -> Self {
+        // Doc comments only document a function or transformer right after them;
+        // anywhere else they are ordinary comments
+        let mut documents = false;
+        let mut keep = vec![true; tokens.len()];
+        for (i, token) in tokens.iter().enumerate().rev() {
+            match token.token_type {
+                TokenType::DocComment => keep[i] = documents,
+                TokenType::Fn | TokenType::Async | TokenType::Transformer => documents = true,
+                // `pub fn` is documented like `fn`
+                TokenType::Pub => {},
+                _ => documents = false,
+            }
+        }
+        let mut tokens: Vec<Token> = tokens.into_iter().zip(keep).filter_map(|(token, keep)| keep.then_some(token)).collect();
+
+        // Parsing stops at EOF, so the tokens must end with one, even when
+        // they didn't come from the lexer
+        if tokens.last().is_none_or(|token| token.token_type != TokenType::EOF) {
+            let end = tokens.last().map_or(0, |token| token.span.end);
+            let (line, column) = tokens.last().map_or((1, 1), |token| (token.line, token.column));
+            tokens.push(Token {
+                token_type: TokenType::EOF,
+                literal: String::new(),
+                span: Span { start: end, end },
+                line,
+                column,
+            });
+        }
+
         Parser {
             tokens,
             current: 0,
+            labels: Vec::new(),
+            failed_at: None,
         }
     }
 
+    /// The line and column of the token parsing has reached. After parse()
+    /// fails, this is the token the error is about.
+    pub fn position(&self) -> (usize, usize) {
+        self.failed_at.unwrap_or((self.peek().line, self.peek().column))
+    }
+
     pub fn parse(&mut self) -> Result<Expr, String> {
         let mut expressions = Vec::new();
         
@@ -34,46 +80,90 @@ impl Parser {
     }
 
     fn statement(&mut self) -> Result<Expr, String> {
-        // Check for function definition
-        if self.match_tokens(&[TokenType::Fn]) {
-            return self.function_definition();
-        }
+        // Nested blocks recurse through here
+        stack::guard(|| {
+            let doc = self.doc_comment();
+
+            // `pub` marks a definition as exported to files that import this one
+            let public = self.match_tokens(&[TokenType::Pub]);
+
+            // `use = 3` would otherwise fail somewhere inside a `use` statement
+            let keyword = self.peek();
+            if keyword.token_type.is_keyword()
+                && self.tokens.get(self.current + 1).is_some_and(|token| token.token_type == TokenType::Equal)
+            {
+                return Err(format!("Unexpected keyword '{}'; write `{}` to use it as a name", keyword.literal, keyword.literal));
+            }
 
-        // Check for transformer definition
-        if self.match_tokens(&[TokenType::Transformer]) {
-            return self.transformer_definition();
-        }
+            let assigns = self.check(TokenType::Identifier)
+                && self.tokens.get(self.current + 1).is_some_and(|token| token.token_type == TokenType::Equal);
+            if public && !assigns && !matches!(
+                self.peek().token_type,
+                TokenType::Fn | TokenType::Async | TokenType::Transformer | TokenType::Let
+            ) {
+                return Err("Expected 'fn', 'transformer', 'let' or an assignment after 'pub'".to_string());
+            }
 
-        // Check for use statement
-        if self.match_tokens(&[TokenType::Use]) {
-            return self.use_statement();
-        }
+            // Check for function definition; one without a name is an expression
+            if !self.at_function_expression() {
+                if self.match_tokens(&[TokenType::Fn]) {
+                    let name = self.identifier("Expected function name")?;
+                    return self.function_definition(Some(name), false, doc, public);
+                }
 
-        // Check for return statement
-        if self.match_tokens(&[TokenType::Return]) {
-            return self.return_statement();
-        }
+                if self.match_tokens(&[TokenType::Async]) {
+                    self.consume(TokenType::Fn, "Expected 'fn' after 'async'")?;
+                    let name = self.identifier("Expected function name")?;
+                    return self.function_definition(Some(name), true, doc, public);
+                }
+            }
 
-        // Check for if statement
-        if self.match_tokens(&[TokenType::If]) {
-            return self.if_statement();
-        }
+            // Check for transformer definition
+            if self.match_tokens(&[TokenType::Transformer]) {
+                return self.transformer_definition(doc, public);
+            }
 
-        // Check for for loop
-        if self.match_tokens(&[TokenType::For]) {
-            return self.for_loop();
-        }
+            // Check for variable declaration; `pub x = 1` declares x too
+            if self.match_tokens(&[TokenType::Let]) || public {
+                return self.let_declaration(public);
+            }
+
+            // Check for use statement
+            if self.match_tokens(&[TokenType::Use]) {
+                return self.use_statement();
+            }
+
+            // Check for return statement
+            if self.match_tokens(&[TokenType::Return]) {
+                return self.return_statement();
+            }
+
+            // Check for if statement
+            if self.match_tokens(&[TokenType::If]) {
+                return self.if_statement();
+            }
+
+            // Check for for loop
+            if self.match_tokens(&[TokenType::For]) {
+                return self.for_loop();
+            }
         
-        // Check for while loop
-        if self.match_tokens(&[TokenType::While]) {
-            return self.while_loop();
-        }
+            // Check for while loop
+            if self.match_tokens(&[TokenType::While]) {
+                return self.while_loop();
+            }
+
+            // Check for do-while loop
+            if self.match_tokens(&[TokenType::Do]) {
+                return self.do_while_loop();
+            }
 
-        self.expression()
+            self.expression()
+        })
     }
 
     fn expression(&mut self) -> Result<Expr, String> {
-        self.logical_or()
+        stack::guard(|| self.logical_or())
     }
 
     fn logical_or(&mut self) -> Result<Expr, String> {
@@ -109,16 +199,116 @@ impl Parser {
     }
 
     fn assignment(&mut self) -> Result<Expr, String> {
-        let expr = self.equality()?;
+        let (line, column) = (self.peek().line, self.peek().column);
+        let mut expr = self.pipeline()?;
 
         if self.match_tokens(&[TokenType::Equal]) {
-            let value = Box::new(self.assignment()?);
-
-            if let Expr::Variable(name) = expr {
-                return Ok(Expr::Assign { name, value });
+            let value = Box::new(stack::guard(|| self.assignment())?);
+
+            match &mut expr {
+                Expr::Variable(name) => return Ok(Expr::Assign { name: *name, value, line, column }),
+                Expr::Tuple(targets) => {
+                    let mut names = Vec::new();
+                    for target in targets.iter() {
+                        match *target {
+                            Expr::Variable(name) if !names.contains(&name) => names.push(name),
+                            Expr::Variable(name) => {
+                                return Err(self.failed_at((line, column), format!("'{}' appears twice in the assignment target", name)));
+                            },
+                            _ => return Err(self.failed_at((line, column), "Only variables can be assigned from a tuple")),
+                        }
+                    }
+                    return Ok(Expr::TupleAssign { names, value, line, column });
+                },
+                Expr::Index { object, index } => {
+                    return Ok(Expr::IndexAssign { object: mem::take(object), index: mem::take(index), value });
+                },
+                // `obj.field = x` stores into the map entry "field"
+                Expr::Member { object, name, safe: false } => {
+                    let index = Box::new(Expr::String(name.to_string()));
+                    return Ok(Expr::IndexAssign { object: mem::take(object), index, value });
+                },
+                _ => {},
             }
 
-            return Err("Invalid assignment target".to_string());
+            return Err(self.failed_at((line, column), "Invalid assignment target"));
+        }
+
+        // `x .= t(args)` applies t to x and stores the result back into x
+        if self.match_tokens(&[TokenType::DotEqual]) {
+            let name = match expr {
+                Expr::Variable(name) => name,
+                _ => return Err(self.failed_at((line, column), "Invalid target for '.='")),
+            };
+
+            let start = (self.peek().line, self.peek().column);
+            let applications = self.call()?;
+            let value = Box::new(Self::apply_to(applications, Expr::Variable(name)).map_err(|e| self.failed_at(start, e))?);
+
+            return Ok(Expr::Assign { name, value, line, column });
+        }
+
+        Ok(expr)
+    }
+
+    // Rewrites `t(args).u()` so that its first transformer is applied to `object`
+    fn apply_to(mut expr: Expr, object: Expr) -> Result<Expr, String> {
+        match &mut expr {
+            Expr::Call { callee, arguments } => Ok(Expr::Apply {
+                object: Box::new(object),
+                transformer: *callee,
+                arguments: mem::take(arguments),
+                safe: false,
+            }),
+            Expr::Variable(transformer) => Ok(Expr::Apply {
+                object: Box::new(object),
+                transformer: *transformer,
+                arguments: Vec::new(),
+                safe: false,
+            }),
+            Expr::Apply { object: inner, transformer, arguments, safe } => Ok(Expr::Apply {
+                object: Box::new(Self::apply_to(mem::take(&mut **inner), object)?),
+                transformer: *transformer,
+                arguments: mem::take(arguments),
+                safe: *safe,
+            }),
+            Expr::Member { object: inner, name, safe } => Ok(Expr::Apply {
+                object: Box::new(Self::apply_to(mem::take(&mut **inner), object)?),
+                transformer: *name,
+                arguments: Vec::new(),
+                safe: *safe,
+            }),
+            _ => Err("Expected transformer application after '.='".to_string()),
+        }
+    }
+
+    // `value |> f |> g(2)` desugars to `g(f(value), 2)`: the piped value becomes
+    // the first argument of each call
+    fn pipeline(&mut self) -> Result<Expr, String> {
+        let mut expr = self.nil_coalescing()?;
+
+        while self.match_tokens(&[TokenType::Pipe]) {
+            let start = (self.peek().line, self.peek().column);
+            expr = match &mut self.call()? {
+                Expr::Variable(callee) => Expr::Call { callee: *callee, arguments: vec![expr] },
+                Expr::Call { callee, arguments } => {
+                    arguments.insert(0, expr);
+                    Expr::Call { callee: *callee, arguments: mem::take(arguments) }
+                },
+                _ => return Err(self.failed_at(start, "Expected function name or call after '|>'")),
+            };
+        }
+
+        Ok(expr)
+    }
+
+    fn nil_coalescing(&mut self) -> Result<Expr, String> {
+        let mut expr = self.equality()?;
+
+        while self.match_tokens(&[TokenType::QuestionQuestion]) {
+            let operator = self.previous().clone();
+            let right = self.equality()?;
+            expr = Expr::Binary { left: Box::new(expr), operator, right: Box::new(right) };
         }
 
         Ok(expr)
@@ -137,7 +327,7 @@ impl Parser {
     }
 
     fn comparison(&mut self) -> Result<Expr, String> {
-        let mut expr = self.term()?;
+        let mut expr = self.range()?;
 
         while self.match_tokens(&[
             TokenType::LessThan,
@@ -146,13 +336,25 @@ impl Parser {
             TokenType::GreaterThanEqual,
         ]) {
             let operator = self.previous().clone();
-            let right = self.term()?;
+            let right = self.range()?;
             expr = Expr::Binary { left: Box::new(expr), operator, right: Box::new(right) };
         }
 
         Ok(expr)
     }
 
+    // `start..end` is shorthand for `range(start, end)`
+    fn range(&mut self) -> Result<Expr, String> {
+        let start = self.term()?;
+
+        if self.match_tokens(&[TokenType::DotDot]) {
+            let end = self.term()?;
+            return Ok(Expr::Call { callee: Symbol::new("range"), arguments: vec![start, end] });
+        }
+
+        Ok(start)
+    }
+
     fn term(&mut self) -> Result<Expr, String> {
         let mut expr = self.factor()?;
 
@@ -168,9 +370,9 @@ impl Parser {
     fn factor(&mut self) -> Result<Expr, String> {
         let mut expr = self.unary()?;
 
-        while self.match_tokens(&[TokenType::Multiply, TokenType::Divide, TokenType::Modulo]) {
+        while self.match_tokens(&[TokenType::Multiply, TokenType::Divide, TokenType::IntDivide, TokenType::Modulo]) {
             let operator = self.previous().clone();
-            let right = self.unary()?;
+            let right = stack::guard(|| self.unary())?;
             expr = Expr::Binary { left: Box::new(expr), operator, right: Box::new(right) };
         }
 
@@ -178,9 +380,14 @@ impl Parser {
     }
 
     fn unary(&mut self) -> Result<Expr, String> {
+        if self.match_tokens(&[TokenType::Await]) {
+            let task = stack::guard(|| self.unary())?;
+            return Ok(Expr::Await(Box::new(task)));
+        }
+
         if self.match_tokens(&[TokenType::Minus, TokenType::Not]) {
             let operator = self.previous().clone();
-            let right = self.unary()?;
+            let right = stack::guard(|| self.unary())?;
             return Ok(Expr::Unary {
                 operator,
                 right: Box::new(right),
@@ -191,11 +398,20 @@ impl Parser {
     }
 
     fn call(&mut self) -> Result<Expr, String> {
+        let (line, column) = (self.peek().line, self.peek().column);
         let mut expr = self.primary()?;
 
         loop {
-            if self.match_tokens(&[TokenType::LeftParen]) {
-                expr = self.finish_call(expr)?;
+            // A `(` starting a line begins a new expression, such as `(a, b) = pair`,
+            // rather than calling the one before it
+            if self.check(TokenType::LeftParen) && self.peek().line == self.previous().line {
+                self.advance();
+                expr = match self.finish_call(expr)? {
+                    Expr::Call { callee, ref mut arguments } if callee == "assert" || callee == "assert_eq" => {
+                        Expr::Assert { callee: callee.to_string(), arguments: mem::take(arguments), line, column }
+                    },
+                    call => call,
+                };
             } else if self.match_tokens(&[TokenType::LeftBracket]) {
                 let index = self.expression()?;
                 self.consume(TokenType::RightBracket, "Expected ']' after index")?;
@@ -203,24 +419,31 @@ impl Parser {
                     object: Box::new(expr),
                     index: Box::new(index),
                 };
-            } else if self.match_tokens(&[TokenType::Dot]) {
+            } else if self.match_tokens(&[TokenType::Dot, TokenType::QuestionDot]) {
                 // Handle dot notation for applying transformers
-                if self.match_tokens(&[TokenType::Identifier]) {
-                    let transformer_name = self.previous().literal.clone();
-                    
-                    // Parse arguments
-                    self.consume(TokenType::LeftParen, "Expected '(' after transformer name")?;
+                let safe = self.previous().token_type == TokenType::QuestionDot;
+
+                let dot = self.previous().literal.clone();
+                let name = self.identifier(&format!("Expected identifier after '{}'", dot))?;
+
+                // `.name(args)` applies a transformer; a bare `.name` is member access
+                expr = if self.match_tokens(&[TokenType::LeftParen]) {
                     let arguments = self.arguments()?;
                     self.consume(TokenType::RightParen, "Expected ')' after arguments")?;
-                    
-                    expr = Expr::Apply {
+
+                    Expr::Apply {
                         object: Box::new(expr),
-                        transformer: transformer_name,
+                        transformer: name,
                         arguments,
-                    };
+                        safe,
+                    }
                 } else {
-                    return Err("Expected identifier after '.'".to_string());
-                }
+                    Expr::Member {
+                        object: Box::new(expr),
+                        name,
+                        safe,
+                    }
+                };
             } else {
                 break;
             }
@@ -230,12 +453,14 @@ impl Parser {
     }
 
     fn finish_call(&mut self, callee: Expr) -> Result<Expr, String> {
+        // Only a name can be called, so an error is about the `(`
+        let paren = (self.previous().line, self.previous().column);
         let arguments = self.arguments()?;
         self.consume(TokenType::RightParen, "Expected ')' after arguments")?;
 
         match callee {
             Expr::Variable(name) => Ok(Expr::Call { callee: name, arguments }),
-            _ => Err("Expected function name".to_string()),
+            _ => Err(self.failed_at(paren, "Expected function name")),
         }
     }
 
@@ -256,9 +481,16 @@ impl Parser {
     }
 
     fn primary(&mut self) -> Result<Expr, String> {
+        if self.match_tokens(&[TokenType::Integer]) {
+            let literal = self.previous();
+            let position = (literal.line, literal.column);
+            return parse_integer_literal(&literal.literal).map_err(|e| self.failed_at(position, e));
+        }
+
         if self.match_tokens(&[TokenType::Number]) {
-            let value = self.previous().literal.parse::<f64>().unwrap();
-            return Ok(Expr::Number(value));
+            let literal = self.previous();
+            let position = (literal.line, literal.column);
+            return parse_float_literal(&literal.literal).map(Expr::Number).map_err(|e| self.failed_at(position, e));
         }
 
         if self.match_tokens(&[TokenType::String]) {
@@ -274,24 +506,75 @@ impl Parser {
             return Ok(Expr::Boolean(false));
         }
 
+        if self.match_tokens(&[TokenType::Nil]) {
+            return Ok(Expr::Nil);
+        }
+
+        // `fn(a, b) { a - b }` is a function value without a name
+        if self.at_function_expression() {
+            let is_async = self.match_tokens(&[TokenType::Async]);
+            self.advance();
+            return self.function_definition(None, is_async, None, false);
+        }
+
+        // `name: for ...` labels a loop
+        if self.check(TokenType::Identifier) && self.check_next(TokenType::Colon) {
+            return self.labeled_loop();
+        }
+
         if self.match_tokens(&[TokenType::Identifier]) {
-            return Ok(Expr::Variable(self.previous().literal.clone()));
+            return Ok(Expr::Variable(Symbol::new(&self.previous().literal)));
         }
 
         if self.match_tokens(&[TokenType::LeftBracket]) {
             return self.array();
         }
 
+        if self.match_tokens(&[TokenType::LeftBrace]) {
+            return self.map();
+        }
+
         if self.match_tokens(&[TokenType::LeftParen]) {
             let expr = self.expression()?;
-            self.consume(TokenType::RightParen, "Expected ')' after expression")?;
-            return Ok(expr);
+            if !self.match_tokens(&[TokenType::Comma]) {
+                self.consume(TokenType::RightParen, "Expected ')' after expression")?;
+                return Ok(expr);
+            }
+
+            // A comma makes a tuple; `(x,)` is a tuple of one
+            let mut elements = vec![expr];
+            while !self.check(TokenType::RightParen) {
+                elements.push(self.expression()?);
+                if !self.match_tokens(&[TokenType::Comma]) {
+                    break;
+                }
+            }
+            self.consume(TokenType::RightParen, "Expected ')' after tuple elements")?;
+            return Ok(Expr::Tuple(elements));
         }
 
         if self.match_tokens(&[TokenType::Return]) {
             return self.return_statement();
         }
 
+        if self.match_tokens(&[TokenType::Loop]) {
+            return self.loop_expression();
+        }
+
+        if self.match_tokens(&[TokenType::Break]) {
+            return self.break_statement();
+        }
+
+        if self.match_tokens(&[TokenType::Continue]) {
+            let label = self.loop_label();
+            return Ok(Expr::Continue { label });
+        }
+
+        if self.peek().token_type.is_keyword() {
+            let keyword = &self.peek().literal;
+            return Err(format!("Unexpected keyword '{}'; write `{}` to use it as a name", keyword, keyword));
+        }
+
         Err("Expected expression".to_string())
     }
 
@@ -313,6 +596,32 @@ impl Parser {
         Ok(Expr::Array(elements))
     }
 
+    fn map(&mut self) -> Result<Expr, String> {
+        let mut entries = Vec::new();
+
+        if !self.check(TokenType::RightBrace) {
+            loop {
+                // Keys are either bare identifiers or string literals
+                let key = if self.match_tokens(&[TokenType::Identifier, TokenType::String]) {
+                    self.previous().literal.clone()
+                } else {
+                    return Err("Expected map key".to_string());
+                };
+
+                self.consume(TokenType::Colon, "Expected ':' after map key")?;
+                entries.push((key, self.expression()?));
+
+                if !self.match_tokens(&[TokenType::Comma]) {
+                    break;
+                }
+            }
+        }
+
+        self.consume(TokenType::RightBrace, "Expected '}' after map entries")?;
+
+        Ok(Expr::Map(entries))
+    }
+
     fn if_statement(&mut self) -> Result<Expr, String> {
         // Parse condition
         let condition = Box::new(self.expression()?);
@@ -370,16 +679,25 @@ impl Parser {
 
     fn for_loop(&mut self) -> Result<Expr, String> {
         // Parse variable
-        let variable = if self.match_tokens(&[TokenType::Identifier]) {
-            self.previous().literal.clone()
+        let variable = self.identifier("Expected variable name")?;
+        let second = if self.match_tokens(&[TokenType::Comma]) {
+            Some(self.identifier("Expected variable name after ','")?)
         } else {
-            return Err("Expected variable name".to_string());
+            None
         };
 
         // Parse iterable
         self.consume(TokenType::In, "Expected 'in' after variable")?;
         let iterable = Box::new(self.expression()?);
 
+        // `step` is only special here, so it can still be used as a name
+        let step = if self.check(TokenType::Identifier) && self.peek().literal == "step" {
+            self.advance();
+            Some(Box::new(self.expression()?))
+        } else {
+            None
+        };
+
         // Parse body
         self.consume(TokenType::LeftBrace, "Expected '{' after iterable")?;
 
@@ -394,7 +712,7 @@ impl Parser {
 
         self.consume(TokenType::RightBrace, "Expected '}' after for loop body")?;
 
-        Ok(Expr::For { variable, iterable, body: Box::new(Expr::Block(body)) })
+        Ok(Expr::For { variable, second, iterable, step, body: Box::new(Expr::Block(body)), label: None })
     }
 
     fn while_loop(&mut self) -> Result<Expr, String> {
@@ -417,7 +735,34 @@ impl Parser {
         
         Ok(Expr::While { 
             condition, 
-            body: Box::new(Expr::Block(body)) 
+            body: Box::new(Expr::Block(body)),
+            label: None,
+        })
+    }
+
+    fn do_while_loop(&mut self) -> Result<Expr, String> {
+        // Parse body
+        self.consume(TokenType::LeftBrace, "Expected '{' after 'do'")?;
+
+        let mut body = Vec::new();
+
+        while !self.check(TokenType::RightBrace) && !self.is_at_end() {
+            body.push(self.statement()?);
+
+            // Allow optional semicolons
+            self.match_tokens(&[TokenType::Semicolon]);
+        }
+
+        self.consume(TokenType::RightBrace, "Expected '}' after do loop body")?;
+
+        // Parse condition
+        self.consume(TokenType::While, "Expected 'while' after do loop body")?;
+        let condition = Box::new(self.expression()?);
+
+        Ok(Expr::DoWhile {
+            body: Box::new(Expr::Block(body)),
+            condition,
+            label: None,
         })
     }
 
@@ -440,6 +785,17 @@ impl Parser {
         self.peek().token_type == token_type
     }
 
+    fn check_next(&self, token_type: TokenType) -> bool {
+        self.tokens.get(self.current + 1).is_some_and(|token| token.token_type == token_type)
+    }
+
+    // `fn(` or `async fn(`, which start a function expression
+    fn at_function_expression(&self) -> bool {
+        let start = if self.check(TokenType::Async) { self.current + 1 } else { self.current };
+        let token_type = |i: usize| self.tokens.get(i).map(|token| token.token_type);
+        token_type(start) == Some(TokenType::Fn) && token_type(start + 1) == Some(TokenType::LeftParen)
+    }
+
     fn advance(&mut self) -> &Token {
         if !self.is_at_end() {
             self.current += 1;
@@ -456,8 +812,29 @@ impl Parser {
         &self.tokens[self.current]
     }
 
+    // The first token when nothing has been consumed yet
     fn previous(&self) -> &Token {
-        &self.tokens[self.current - 1]
+        &self.tokens[self.current.saturating_sub(1)]
+    }
+
+    // Consumes a name, explaining the problem when a keyword is used as one
+    fn identifier(&mut self, message: &str) -> Result<Symbol, String> {
+        if self.match_tokens(&[TokenType::Identifier]) {
+            return Ok(Symbol::new(&self.previous().literal));
+        }
+
+        let found = self.peek();
+        if found.token_type.is_keyword() {
+            return Err(format!("{}, found keyword '{}' (write `{}` to use it as a name)", message, found.literal, found.literal));
+        }
+        Err(message.to_string())
+    }
+
+    // Records that the error being returned is about the token at `position`,
+    // which parsing has already gone past
+    fn failed_at(&mut self, position: (usize, usize), message: impl Into<String>) -> String {
+        self.failed_at = Some(position);
+        message.into()
     }
 
     fn consume(&mut self, token_type: TokenType, message: &str) -> Result<&Token, String> {
@@ -468,40 +845,44 @@ impl Parser {
         }
     }
 
-    fn function_definition(&mut self) -> Result<Expr, String> {
-        // Parse function name
-        let name = if self.match_tokens(&[TokenType::Identifier]) {
-            self.previous().literal.clone()
-        } else {
-            return Err("Expected function name".to_string());
-        };
+    // The lines of the doc comment before a declaration, if there is one
+    fn doc_comment(&mut self) -> Option<String> {
+        let mut lines = Vec::new();
+        while self.match_tokens(&[TokenType::DocComment]) {
+            lines.push(self.previous().literal.clone());
+        }
+        (!lines.is_empty()).then(|| lines.join("\n"))
+    }
 
+    // Parses the parameters and body of a function, after its name if it has one
+    fn function_definition(&mut self, name: Option<Symbol>, is_async: bool, doc: Option<String>, public: bool) -> Result<Expr, String> {
         // Parse parameter list
         self.consume(TokenType::LeftParen, "Expected '(' after function name")?;
 
         let mut params = Vec::new();
+        let mut param_types = Vec::new();
 
         if !self.check(TokenType::RightParen) {
             // Parse first parameter
-            if self.match_tokens(&[TokenType::Identifier]) {
-                params.push(self.previous().literal.clone());
-            }
+            params.push(self.identifier("Expected parameter name")?);
+            param_types.push(self.type_annotation()?);
 
             // Parse remaining parameters
             while self.match_tokens(&[TokenType::Comma]) {
-                if self.match_tokens(&[TokenType::Identifier]) {
-                    params.push(self.previous().literal.clone());
-                } else {
-                    return Err("Expected parameter name".to_string());
-                }
+                params.push(self.identifier("Expected parameter name")?);
+                param_types.push(self.type_annotation()?);
             }
         }
 
         self.consume(TokenType::RightParen, "Expected ')' after parameters")?;
 
-        // Parse function body
+        // Parse optional return type
+        let return_type = self.type_annotation()?;
+
+        // Parse function body; loops outside it can't be targeted from inside
         self.consume(TokenType::LeftBrace, "Expected '{' before function body")?;
 
+        let labels = std::mem::take(&mut self.labels);
         let mut body = Vec::new();
 
         while !self.check(TokenType::RightBrace) && !self.is_at_end() {
@@ -512,17 +893,33 @@ impl Parser {
         }
 
         self.consume(TokenType::RightBrace, "Expected '}' after function body")?;
+        self.labels = labels;
 
-        Ok(Expr::Function { name, params, body })
+        let locals = resolver::bind_locals(&params, &mut body);
+        Ok(Expr::Function { name, params: params.into(), locals, param_types, return_type, body: body.into(), is_async, doc, public })
     }
 
-    fn transformer_definition(&mut self) -> Result<Expr, String> {
-        // Parse transformer name
-        let name = if self.match_tokens(&[TokenType::Identifier]) {
-            self.previous().literal.clone()
+    // Parses an optional `: type` annotation
+    fn type_annotation(&mut self) -> Result<Option<String>, String> {
+        if !self.match_tokens(&[TokenType::Colon]) {
+            return Ok(None);
+        }
+
+        if self.match_tokens(&[TokenType::Identifier, TokenType::Nil]) {
+            Ok(Some(self.previous().literal.clone()))
         } else {
-            return Err("Expected transformer name".to_string());
-        };
+            Err("Expected type name after ':'".to_string())
+        }
+    }
+
+    fn transformer_definition(&mut self, doc: Option<String>, public: bool) -> Result<Expr, String> {
+        // Parse transformer name, after the receiver's name if one is given
+        let mut name = self.identifier("Expected transformer name")?;
+        let mut receiver = None;
+        if self.match_tokens(&[TokenType::Dot]) {
+            receiver = Some(name);
+            name = self.identifier("Expected transformer name after '.'")?;
+        }
 
         // Parse parameters
         self.consume(TokenType::LeftParen, "Expected '(' after transformer name")?;
@@ -531,11 +928,7 @@ impl Parser {
         
         if !self.check(TokenType::RightParen) {
             loop {
-                if self.match_tokens(&[TokenType::Identifier]) {
-                    params.push(self.previous().literal.clone());
-                } else {
-                    return Err("Expected parameter name".to_string());
-                }
+                params.push(self.identifier("Expected parameter name")?);
                 
                 if !self.match_tokens(&[TokenType::Comma]) {
                     break;
@@ -548,6 +941,7 @@ impl Parser {
         // Parse body
         self.consume(TokenType::LeftBrace, "Expected '{' before transformer body")?;
         
+        let labels = std::mem::take(&mut self.labels);
         let mut body = Vec::new();
         
         while !self.check(TokenType::RightBrace) && !self.is_at_end() {
@@ -558,11 +952,30 @@ impl Parser {
         }
         
         self.consume(TokenType::RightBrace, "Expected '}' after transformer body")?;
-        
-        Ok(Expr::Transformer { name, params, body })
+        self.labels = labels;
+
+        // The applied value's slot comes before the parameters'
+        let bound: Vec<Symbol> = std::iter::once(receiver.unwrap_or_else(|| Symbol::new("applied"))).chain(params.iter().copied()).collect();
+        let locals = resolver::bind_locals(&bound, &mut body);
+        Ok(Expr::Transformer { name, receiver, params: params.into(), locals, body: body.into(), doc, public })
+    }
+
+    fn let_declaration(&mut self, public: bool) -> Result<Expr, String> {
+        let (line, column) = (self.previous().line, self.previous().column);
+        let name = self.identifier("Expected variable name after 'let'")?;
+
+        // The initializer is optional; `let x` declares x as nil
+        let value = if self.match_tokens(&[TokenType::Equal]) {
+            Some(Box::new(self.expression()?))
+        } else {
+            None
+        };
+
+        Ok(Expr::Let { name, value, line, column, public })
     }
 
     fn return_statement(&mut self) -> Result<Expr, String> {
+        let (line, column) = (self.previous().line, self.previous().column);
         let value = if self.check(TokenType::Semicolon) {
             None
         } else {
@@ -572,7 +985,80 @@ impl Parser {
         // Allow optional semicolon
         self.match_tokens(&[TokenType::Semicolon]);
 
-        Ok(Expr::Return { value })
+        Ok(Expr::Return { value, line, column })
+    }
+
+    fn break_statement(&mut self) -> Result<Expr, String> {
+        let label = self.loop_label();
+
+        // A value is optional: `break` alone ends its statement or block
+        let value = if self.check(TokenType::Semicolon) || self.check(TokenType::RightBrace) || self.is_at_end() {
+            None
+        } else {
+            Some(Box::new(self.expression()?))
+        };
+
+        Ok(Expr::Break { label, value })
+    }
+
+    fn loop_expression(&mut self) -> Result<Expr, String> {
+        // Parse body
+        self.consume(TokenType::LeftBrace, "Expected '{' after 'loop'")?;
+
+        let mut body = Vec::new();
+
+        while !self.check(TokenType::RightBrace) && !self.is_at_end() {
+            body.push(self.statement()?);
+
+            // Allow optional semicolons
+            self.match_tokens(&[TokenType::Semicolon]);
+        }
+
+        self.consume(TokenType::RightBrace, "Expected '}' after loop body")?;
+
+        Ok(Expr::Loop { body: Box::new(Expr::Block(body)), label: None })
+    }
+
+    fn labeled_loop(&mut self) -> Result<Expr, String> {
+        let label = self.advance();
+        let (name, position) = (label.literal.clone(), (label.line, label.column));
+        self.advance();
+
+        if self.labels.contains(&name) {
+            return Err(self.failed_at(position, format!("Label '{}' is already used by an enclosing loop", name)));
+        }
+
+        self.labels.push(name.clone());
+        let parsed = match self.advance().token_type {
+            TokenType::For => self.for_loop(),
+            TokenType::While => self.while_loop(),
+            TokenType::Do => self.do_while_loop(),
+            TokenType::Loop => self.loop_expression(),
+            _ => {
+                let found = (self.previous().line, self.previous().column);
+                Err(self.failed_at(found, format!("Expected a loop after label '{}:'", name)))
+            },
+        };
+        self.labels.pop();
+
+        let mut parsed = parsed?;
+        match &mut parsed {
+            Expr::For { label, .. } | Expr::While { label, .. } | Expr::DoWhile { label, .. } | Expr::Loop { label, .. } => {
+                *label = Some(name);
+            },
+            _ => return Err(format!("Expected a loop after label '{}:'", name)),
+        }
+        Ok(parsed)
+    }
+
+    // The label after `break` or `continue`. Only names of enclosing loops are
+    // labels, so `break total` still breaks with the value of `total`.
+    fn loop_label(&mut self) -> Option<String> {
+        if self.check(TokenType::Identifier) && self.labels.contains(&self.peek().literal) {
+            Some(self.advance().literal.clone())
+        } else {
+            None
+        }
     }
 
     fn use_statement(&mut self) -> Result<Expr, String> {
@@ -584,8 +1070,68 @@ impl Parser {
             self.match_tokens(&[TokenType::Semicolon]);
             
             Ok(Expr::Use { path })
+        } else if self.match_tokens(&[TokenType::Identifier]) {
+            // A name instead of a path is a pragma
+            let name = self.previous().literal.clone();
+            if name != "strict" {
+                let position = (self.previous().line, self.previous().column);
+                return Err(self.failed_at(position, format!("Unknown pragma '{}'", name)));
+            }
+            self.match_tokens(&[TokenType::Semicolon]);
+
+            Ok(Expr::Pragma { name })
         } else {
-            Err("Expected string path after 'use'".to_string())
+            Err("Expected string path or pragma after 'use'".to_string())
+        }
+    }
+}
+// Removes `_` digit separators, which are only allowed between two digits
+fn strip_separators(digits: &str, is_digit: impl Fn(char) -> bool) -> Option<String> {
+    let chars: Vec<char> = digits.chars().collect();
+    for (i, c) in chars.iter().enumerate() {
+        if *c == '_' {
+            let before = i.checked_sub(1).map(|j| chars[j]);
+            let after = chars.get(i + 1).copied();
+            if !before.is_some_and(&is_digit) || !after.is_some_and(&is_digit) {
+                return None;
+            }
         }
     }
-}
\ No newline at end of file
+    Some(digits.replace('_', ""))
+}
+
+fn parse_integer_literal(literal: &str) -> Result<Expr, String> {
+    let invalid = || format!("Invalid number literal '{}'", literal);
+
+    let (digits, radix) = match literal.get(..2) {
+        Some("0x" | "0X") => (&literal[2..], 16),
+        Some("0b" | "0B") => (&literal[2..], 2),
+        Some("0o" | "0O") => (&literal[2..], 8),
+        _ => (literal, 10),
+    };
+
+    let digits = strip_separators(digits, |c| c.is_digit(radix)).ok_or_else(invalid)?;
+    if digits.is_empty() || !digits.chars().all(|c| c.is_digit(radix)) {
+        return Err(invalid());
+    }
+
+    if let Ok(n) = i64::from_str_radix(&digits, radix) {
+        return Ok(Expr::Int(n));
+    }
+    // Larger literals are big integers, when the interpreter has them
+    match bigint::parse_radix(&digits, radix) {
+        Some(value) => Ok(Expr::BigInt(value.to_string())),
+        None => Err(format!("Integer literal '{}' is too large", literal)),
+    }
+}
+
+fn parse_float_literal(literal: &str) -> Result<f64, String> {
+    let invalid = || format!("Invalid number literal '{}'", literal);
+
+    let digits = strip_separators(literal, |c| c.is_ascii_digit()).ok_or_else(invalid)?;
+    if !digits.chars().all(|c| c.is_ascii_digit() || matches!(c, '.' | 'e' | 'E' | '+' | '-')) {
+        return Err(invalid());
+    }
+
+    digits.parse::<f64>().map_err(|_| invalid())
+}
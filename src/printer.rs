@@ -0,0 +1,351 @@
+use crate::ast::Expr;
+use crate::lexer::Lexer;
+use crate::stack;
+use crate::token::{Token, TokenType};
+
+// Turns a syntax tree back into M source for Expr::to_source(). The layout is
+// the README's (one statement per line, four-space indents); comments other
+// than doc comments, and the original spacing, are gone after parsing.
+const INDENT: &str = "    ";
+
+// How tightly each level of the grammar binds, loosest first, as parser.rs
+// nests them. An expression printed where a tighter level is expected gets
+// parentheses. Statements that can't be operands at all rank lowest.
+const STATEMENT: u8 = 0;
+const OR: u8 = 1;
+const AND: u8 = 2;
+// Assignment binds tighter than `and` and `or`: `x = a or b` is `(x = a) or b`
+const ASSIGNMENT: u8 = 3;
+const COALESCE: u8 = 5;
+const EQUALITY: u8 = 6;
+const COMPARISON: u8 = 7;
+const TERM: u8 = 9;
+const FACTOR: u8 = 10;
+const UNARY: u8 = 11;
+const POSTFIX: u8 = 12;
+const PRIMARY: u8 = 13;
+
+pub(crate) fn print(program: &Expr) -> String {
+    let mut printer = Printer { depth: 0 };
+    match program {
+        Expr::Block(statements) => printer.statements(statements),
+        statement => printer.statements(std::slice::from_ref(statement)),
+    }
+}
+
+struct Printer {
+    depth: usize,
+}
+
+impl Printer {
+    fn indent(&self) -> String {
+        INDENT.repeat(self.depth)
+    }
+
+    // One statement per line at the current depth
+    fn statements(&mut self, statements: &[Expr]) -> String {
+        let printed: Vec<String> = statements.iter().map(|statement| self.statement(statement)).collect();
+        let indent = self.indent();
+
+        let mut lines = Vec::new();
+        for (i, text) in printed.iter().enumerate() {
+            // Newlines don't end statements: one starting with `(`, `[` or `-`
+            // would continue the one before, a bare `break` would take the next
+            // statement as its value, and `return` always expects one
+            let semicolon = match printed.get(i + 1) {
+                Some(next) => next.starts_with(['(', '[', '-']) || ends_without_value(&statements[i]),
+                None => matches!(statements[i], Expr::Return { value: None, .. }),
+            };
+            // Definitions are set apart by blank lines
+            if i > 0 && (is_definition(&statements[i - 1]) || is_definition(&statements[i])) {
+                lines.push(String::new());
+            }
+            lines.push(format!("{}{}{}", indent, text, if semicolon { ";" } else { "" }));
+        }
+        lines.join("\n")
+    }
+
+    fn block(&mut self, statements: &[Expr]) -> String {
+        if statements.is_empty() {
+            return "{}".to_string();
+        }
+
+        self.depth += 1;
+        let body = self.statements(statements);
+        self.depth -= 1;
+        format!("{{\n{}\n{}}}", body, self.indent())
+    }
+
+    // An `if` branch or loop body, which the parser leaves unwrapped when it
+    // holds a single statement
+    fn body(&mut self, body: &Expr) -> String {
+        match body {
+            Expr::Block(statements) => self.block(statements),
+            statement => self.block(std::slice::from_ref(statement)),
+        }
+    }
+
+    fn statement(&mut self, expr: &Expr) -> String {
+        stack::guard(|| match expr {
+            Expr::Function { name: function, params, param_types, return_type, body, is_async, doc, public, .. } => {
+                let params: Vec<String> = params.iter().zip(param_types).map(|(param, param_type)| {
+                    name(param) + &annotation(param_type.as_deref())
+                }).collect();
+                format!(
+                    "{}{}{}fn{}({}){} {}",
+                    self.doc(doc.as_deref()),
+                    if *public { "pub " } else { "" },
+                    if *is_async { "async " } else { "" },
+                    function.map(|function| format!(" {}", name(&function))).unwrap_or_default(),
+                    params.join(", "),
+                    annotation(return_type.as_deref()),
+                    self.block(body),
+                )
+            },
+            Expr::Transformer { name: transformer, receiver, params, body, doc, public, .. } => {
+                let params: Vec<String> = params.iter().map(|param| name(param)).collect();
+                let receiver = receiver.as_ref().map(|receiver| format!("{}.", name(receiver))).unwrap_or_default();
+                format!(
+                    "{}{}transformer {}{}({}) {}",
+                    self.doc(doc.as_deref()),
+                    if *public { "pub " } else { "" },
+                    receiver,
+                    name(transformer),
+                    params.join(", "),
+                    self.block(body),
+                )
+            },
+            Expr::Let { name: variable, value, public, .. } => {
+                let value = value.as_ref().map(|value| format!(" = {}", self.expression(value, OR)));
+                format!("{}let {}{}", if *public { "pub " } else { "" }, name(variable), value.unwrap_or_default())
+            },
+            Expr::Use { path } => format!("use {}", quote(path)),
+            Expr::Pragma { name } => format!("use {}", name),
+            Expr::If { condition, then_branch, else_branch } => {
+                let mut text = format!("if {} {}", self.expression(condition, OR), self.body(then_branch));
+                if let Some(else_branch) = else_branch {
+                    text.push_str(" else ");
+                    text.push_str(&self.body(else_branch));
+                }
+                text
+            },
+            // `return` takes a whole statement, so `return if ...` works
+            Expr::Return { value, .. } => match value {
+                Some(value) => format!("return {}", self.statement(value)),
+                None => "return".to_string(),
+            },
+            Expr::For { .. } | Expr::While { .. } | Expr::DoWhile { .. } => self.looping(expr),
+            expr => self.expression(expr, STATEMENT),
+        })
+    }
+
+    fn doc(&self, doc: Option<&str>) -> String {
+        let indent = self.indent();
+        doc.map_or(String::new(), |doc| {
+            doc.lines().map(|line| {
+                let line = if line.is_empty() { "///".to_string() } else { format!("/// {}", line) };
+                format!("{}\n{}", line, indent)
+            }).collect()
+        })
+    }
+
+    // Loops, with their label if they have one
+    fn looping(&mut self, expr: &Expr) -> String {
+        let (label, text) = match expr {
+            Expr::For { variable, second, iterable, step, body, label } => {
+                let second = second.as_ref().map(|second| format!(", {}", name(second))).unwrap_or_default();
+                let step = step.as_ref().map(|step| format!(" step {}", self.expression(step, OR))).unwrap_or_default();
+                let iterable = self.expression(iterable, OR);
+                (label, format!("for {}{} in {}{} {}", name(variable), second, iterable, step, self.body(body)))
+            },
+            Expr::While { condition, body, label } => {
+                (label, format!("while {} {}", self.expression(condition, OR), self.body(body)))
+            },
+            Expr::DoWhile { body, condition, label } => {
+                (label, format!("do {} while {}", self.body(body), self.expression(condition, OR)))
+            },
+            Expr::Loop { body, label } => (label, format!("loop {}", self.body(body))),
+            expr => return self.statement(expr),
+        };
+
+        match label {
+            Some(label) => format!("{}: {}", name(label), text),
+            None => text,
+        }
+    }
+
+    // The expression, in parentheses if it binds more loosely than `min`
+    fn expression(&mut self, expr: &Expr, min: u8) -> String {
+        let (text, strength) = stack::guard(|| self.operand(expr));
+        if strength < min {
+            format!("({})", text)
+        } else {
+            text
+        }
+    }
+
+    fn list(&mut self, exprs: &[Expr]) -> String {
+        exprs.iter().map(|expr| self.expression(expr, OR)).collect::<Vec<_>>().join(", ")
+    }
+
+    // The text of an expression and how tightly it binds
+    fn operand(&mut self, expr: &Expr) -> (String, u8) {
+        match expr {
+            // `-5` is the unary minus applied to 5
+            Expr::Int(i64::MIN) => (format!("({} - 1)", i64::MIN + 1), PRIMARY),
+            Expr::Int(n) => (n.to_string(), if *n < 0 { UNARY } else { PRIMARY }),
+            Expr::BigInt(digits) => (digits.clone(), if digits.starts_with('-') { UNARY } else { PRIMARY }),
+            Expr::Number(n) => number(*n),
+            Expr::String(s) => (quote(s), PRIMARY),
+            Expr::Boolean(b) => (b.to_string(), PRIMARY),
+            Expr::Nil => ("nil".to_string(), PRIMARY),
+            Expr::Array(elements) => (format!("[{}]", self.list(elements)), PRIMARY),
+            Expr::Tuple(elements) if elements.len() == 1 => (format!("({},)", self.list(elements)), PRIMARY),
+            Expr::Tuple(elements) => (format!("({})", self.list(elements)), PRIMARY),
+            Expr::Map(entries) => {
+                let entries: Vec<String> = entries.iter().map(|(key, value)| {
+                    let key = if is_plain_name(key) { key.clone() } else { quote(key) };
+                    format!("{}: {}", key, self.expression(value, OR))
+                }).collect();
+                (format!("{{{}}}", entries.join(", ")), PRIMARY)
+            },
+            Expr::Variable(variable) | Expr::Local { name: variable, .. } => (name(variable), PRIMARY),
+            Expr::Binary { left, operator, right } => {
+                let strength = binary_strength(operator);
+                let left = self.expression(left, strength);
+                let right = self.expression(right, strength + 1);
+                (format!("{} {} {}", left, operator_text(operator), right), strength)
+            },
+            Expr::Unary { operator, right } => {
+                let operator = match operator.token_type {
+                    TokenType::Minus => "-",
+                    _ if operator.literal == "!" => "!",
+                    _ => "not ",
+                };
+                (format!("{}{}", operator, self.expression(right, UNARY)), UNARY)
+            },
+            Expr::Await(task) => (format!("await {}", self.expression(task, UNARY)), UNARY),
+            Expr::Assign { name: variable, value, .. } => {
+                (format!("{} = {}", name(variable), self.expression(value, ASSIGNMENT)), ASSIGNMENT)
+            },
+            Expr::TupleAssign { names, value, .. } => {
+                let names: Vec<String> = names.iter().map(|variable| name(variable)).collect();
+                (format!("({}) = {}", names.join(", "), self.expression(value, ASSIGNMENT)), ASSIGNMENT)
+            },
+            Expr::IndexAssign { object, index, value } => {
+                let object = self.expression(object, POSTFIX);
+                let index = self.expression(index, OR);
+                (format!("{}[{}] = {}", object, index, self.expression(value, ASSIGNMENT)), ASSIGNMENT)
+            },
+            Expr::Call { callee, arguments } => {
+                (format!("{}({})", name(callee), self.list(arguments)), POSTFIX)
+            },
+            Expr::Assert { callee, arguments, .. } => {
+                (format!("{}({})", name(callee), self.list(arguments)), POSTFIX)
+            },
+            Expr::Index { object, index } => {
+                let object = self.expression(object, POSTFIX);
+                (format!("{}[{}]", object, self.expression(index, OR)), POSTFIX)
+            },
+            Expr::Apply { object, transformer, arguments, safe } => {
+                let object = self.expression(object, POSTFIX);
+                let dot = if *safe { "?." } else { "." };
+                (format!("{}{}{}({})", object, dot, name(transformer), self.list(arguments)), POSTFIX)
+            },
+            Expr::Member { object, name: member, safe } => {
+                let object = self.expression(object, POSTFIX);
+                (format!("{}{}{}", object, if *safe { "?." } else { "." }, name(member)), POSTFIX)
+            },
+            // A value after `break` runs to the end of the expression
+            Expr::Break { label, value } => {
+                let label = label.as_ref().map(|label| format!(" {}", name(label))).unwrap_or_default();
+                match value {
+                    Some(value) => (format!("break{} {}", label, self.expression(value, OR)), STATEMENT),
+                    None => (format!("break{}", label), PRIMARY),
+                }
+            },
+            Expr::Continue { label } => {
+                let label = label.as_ref().map(|label| format!(" {}", name(label))).unwrap_or_default();
+                (format!("continue{}", label), PRIMARY)
+            },
+            Expr::Loop { .. } => (self.looping(expr), PRIMARY),
+            Expr::Function { name: None, .. } => (self.statement(expr), PRIMARY),
+            // Labeled loops are expressions; others are only statements
+            Expr::For { label: Some(_), .. } | Expr::While { label: Some(_), .. } | Expr::DoWhile { label: Some(_), .. } => {
+                (self.looping(expr), PRIMARY)
+            },
+            Expr::Block(statements) => (self.block(statements), STATEMENT),
+            statement => (self.statement(statement), STATEMENT),
+        }
+    }
+}
+
+fn is_definition(statement: &Expr) -> bool {
+    matches!(statement, Expr::Function { name: Some(_), .. } | Expr::Transformer { .. })
+}
+
+// `break` or `continue` without a value, or `return` without one
+fn ends_without_value(statement: &Expr) -> bool {
+    matches!(statement, Expr::Break { value: None, .. } | Expr::Continue { .. } | Expr::Return { value: None, .. })
+}
+
+fn binary_strength(operator: &Token) -> u8 {
+    match operator.token_type {
+        TokenType::Or => OR,
+        TokenType::And => AND,
+        TokenType::QuestionQuestion => COALESCE,
+        TokenType::EqualEqual | TokenType::BangEqual => EQUALITY,
+        TokenType::LessThan | TokenType::LessThanEqual | TokenType::GreaterThan | TokenType::GreaterThanEqual => COMPARISON,
+        TokenType::Multiply | TokenType::Divide | TokenType::IntDivide | TokenType::Modulo => FACTOR,
+        _ => TERM,
+    }
+}
+
+fn operator_text(operator: &Token) -> &str {
+    operator.token_type.operator_text().unwrap_or(&operator.literal)
+}
+
+// Floats keep a `.` or exponent so they read back as floats; infinity is
+// written as a literal too large to represent, and NaN as a sum that gives it
+fn number(n: f64) -> (String, u8) {
+    let text = if n.is_nan() {
+        return ("(1e999 - 1e999)".to_string(), PRIMARY);
+    } else if n.is_infinite() {
+        if n > 0.0 { "1e999".to_string() } else { "-1e999".to_string() }
+    } else {
+        format!("{:?}", n)
+    };
+    let strength = if text.starts_with('-') { UNARY } else { PRIMARY };
+    (text, strength)
+}
+
+// Strings are written as they are, since M has no escapes. One holding `"` is
+// triple-quoted, which only a string holding `"""` can't be.
+fn quote(s: &str) -> String {
+    if !s.contains('"') {
+        return format!("\"{}\"", s);
+    }
+    // The lexer drops a newline straight after the opening quotes
+    let newline = if s.starts_with('\n') { "\n" } else { "" };
+    format!("\"\"\"{}{}\"\"\"", newline, s)
+}
+
+fn annotation(type_name: Option<&str>) -> String {
+    type_name.map(|type_name| format!(": {}", type_name)).unwrap_or_default()
+}
+
+// A name, in backticks when it would otherwise read as a keyword
+fn name(name: &str) -> String {
+    if is_plain_name(name) {
+        name.to_string()
+    } else {
+        format!("`{}`", name)
+    }
+}
+
+fn is_plain_name(name: &str) -> bool {
+    match Lexer::new(name).tokenize() {
+        Ok(tokens) => tokens.len() == 2 && tokens[0].token_type == TokenType::Identifier && tokens[0].literal == name,
+        Err(_) => false,
+    }
+}
@@ -0,0 +1,198 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use serde::Deserialize;
+
+// A project is a directory with an `m.toml` manifest naming the program to run
+// and the directories its imports are found in:
+//
+//     [package]
+//     name = "myproject"
+//     main = "src/main.m"
+//
+//     [modules]
+//     paths = ["src"]
+//
+//     [dependencies]
+//     strings = { git = "https://example.com/strings.git", branch = "v1" }
+//     helpers = { path = "../helpers" }
+//
+// `m_lang install` copies each dependency into m_modules/<name>, which `use`
+// searches after the module paths.
+pub const MANIFEST: &str = "m.toml";
+const VENDOR_DIR: &str = "m_modules";
+
+#[derive(Deserialize)]
+struct Manifest {
+    #[serde(default)]
+    package: Package,
+    #[serde(default)]
+    modules: Modules,
+    #[serde(default)]
+    dependencies: BTreeMap<String, Dependency>,
+}
+
+#[derive(Deserialize)]
+struct Package {
+    #[serde(default = "default_main")]
+    main: PathBuf,
+}
+
+impl Default for Package {
+    fn default() -> Self {
+        Package { main: default_main() }
+    }
+}
+
+fn default_main() -> PathBuf {
+    PathBuf::from("src/main.m")
+}
+
+#[derive(Deserialize, Default)]
+struct Modules {
+    #[serde(default)]
+    paths: Vec<PathBuf>,
+}
+
+// Where a dependency comes from: a git repository (at a branch or tag) or a
+// local directory, relative to the project
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Dependency {
+    git: Option<String>,
+    branch: Option<String>,
+    path: Option<PathBuf>,
+}
+
+pub struct Project {
+    pub dir: PathBuf,
+    manifest: Manifest,
+}
+
+impl Project {
+    // The project containing `dir`: the nearest directory upwards with a manifest
+    pub fn find(dir: &Path) -> Result<Option<Project>, String> {
+        let Some(dir) = dir.ancestors().find(|dir| dir.join(MANIFEST).is_file()) else {
+            return Ok(None);
+        };
+
+        let path = dir.join(MANIFEST);
+        let text = fs::read_to_string(&path).map_err(|e| format!("Error reading {}: {}", path.display(), e))?;
+        let manifest = toml::from_str(&text).map_err(|e| format!("Invalid {}: {}", path.display(), e))?;
+        Ok(Some(Project { dir: dir.to_path_buf(), manifest }))
+    }
+
+    pub fn main(&self) -> PathBuf {
+        self.dir.join(&self.manifest.package.main)
+    }
+
+    // Directories searched by `use`: those in the manifest, relative to the
+    // project directory, then the installed dependencies
+    pub fn module_paths(&self) -> Vec<PathBuf> {
+        let paths = self.manifest.modules.paths.iter().map(|path| self.dir.join(path));
+        paths.chain(std::iter::once(self.dir.join(VENDOR_DIR))).collect()
+    }
+
+    // Copies every dependency into the vendor directory, replacing any earlier
+    // copy, and returns a line describing each one
+    pub fn install(&self) -> Result<Vec<String>, String> {
+        let vendor_dir = self.dir.join(VENDOR_DIR);
+        let mut installed = Vec::new();
+        for (name, dependency) in &self.manifest.dependencies {
+            // Names become directory names that `use "name/file.m"` refers to
+            if name.is_empty() || name.starts_with('.') || name.contains(['/', '\\']) {
+                return Err(format!("Error: '{}' isn't a valid dependency name", name));
+            }
+
+            // Fetched into a temporary directory first, so a failure keeps the old copy
+            let target = vendor_dir.join(name);
+            let staging = vendor_dir.join(format!(".{}.tmp", name));
+            let _ = fs::remove_dir_all(&staging);
+            let source = match (&dependency.git, &dependency.path, &dependency.branch) {
+                (Some(url), None, branch) => {
+                    clone(url, branch.as_deref(), &staging)?;
+                    match branch {
+                        Some(branch) => format!("{} ({})", url, branch),
+                        None => url.clone(),
+                    }
+                },
+                (None, Some(path), None) => {
+                    copy_dir(&self.dir.join(path), &staging)
+                        .map_err(|e| format!("Error copying {}: {}", path.display(), e))?;
+                    path.display().to_string()
+                },
+                _ => return Err(format!("Error: dependency '{}' needs either a git URL (with an optional branch) or a path", name)),
+            };
+
+            let _ = fs::remove_dir_all(&target);
+            fs::rename(&staging, &target).map_err(|e| format!("Error installing {}: {}", name, e))?;
+            installed.push(format!("{} from {}", name, source));
+        }
+        Ok(installed)
+    }
+}
+
+// A shallow clone without the repository's history, leaving only the files
+fn clone(url: &str, branch: Option<&str>, target: &Path) -> Result<(), String> {
+    let mut git = Command::new("git");
+    git.args(["clone", "--quiet", "--depth", "1"]);
+    if let Some(branch) = branch {
+        git.args(["--branch", branch]);
+    }
+
+    let status = git.arg("--").arg(url).arg(target).status().map_err(|e| format!("Error running git: {}", e))?;
+    if !status.success() {
+        let _ = fs::remove_dir_all(target);
+        return Err(format!("Error: git couldn't clone {}", url));
+    }
+    fs::remove_dir_all(target.join(".git")).map_err(|e| format!("Error installing {}: {}", url, e))
+}
+
+fn copy_dir(from: &Path, to: &Path) -> std::io::Result<()> {
+    fs::create_dir_all(to)?;
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        if name == ".git" || name == VENDOR_DIR {
+            continue;
+        }
+        if entry.file_type()?.is_dir() {
+            copy_dir(&entry.path(), &to.join(name))?;
+        } else {
+            fs::copy(entry.path(), to.join(name))?;
+        }
+    }
+    Ok(())
+}
+
+// `m_lang new name`: a directory with a manifest and a program that says hello
+pub fn create(dir: &Path) -> Result<(), String> {
+    let name = dir.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+    if name.is_empty() {
+        return Err("Error: new expects a project name".to_string());
+    }
+    if dir.exists() {
+        return Err(format!("Error: {} already exists", dir.display()));
+    }
+
+    let manifest = format!(
+        "[package]\nname = {}\nversion = \"0.1.0\"\nmain = \"src/main.m\"\n\n\
+         [modules]\n# Directories `use` also searches, relative to this file\npaths = [\"src\"]\n",
+        toml::Value::String(name.clone()),
+    );
+    let files = [
+        (PathBuf::from(MANIFEST), manifest),
+        (PathBuf::from("src/main.m"), format!("print(\"Hello from {}!\")\n", name)),
+        // AST caches written by --cache, and installed dependencies
+        (PathBuf::from(".gitignore"), format!("*.mc\n/{}/\n", VENDOR_DIR)),
+    ];
+
+    for (path, contents) in files {
+        let path = dir.join(path);
+        fs::create_dir_all(path.parent().unwrap_or(dir))
+            .and_then(|()| fs::write(&path, contents))
+            .map_err(|e| format!("Error writing {}: {}", path.display(), e))?;
+    }
+    Ok(())
+}
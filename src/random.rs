@@ -0,0 +1,72 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// The source of random() and now(). Normally the generator is seeded from the
+// clock and now() reads the host's clock. Seeded (`--seed`), a run can be replayed
+// exactly: the same seed gives the same numbers, and the clock starts at zero
+// and only moves when the program sleeps.
+pub(crate) struct Random {
+    state: u64,
+    // Milliseconds slept so far, when the clock is simulated
+    elapsed: Option<f64>,
+}
+
+impl Random {
+    pub(crate) fn new() -> Self {
+        // Without a clock, the hash keys std picks for each map still vary
+        let seed = system_clock().map_or_else(|| RandomState::new().build_hasher().finish(), |time| (time * 1e6) as u64);
+        Random { state: seed, elapsed: None }
+    }
+
+    // Stirs in a reading from the host's clock, unless the run is seeded
+    pub(crate) fn mix(&mut self, time: f64) {
+        if self.elapsed.is_none() {
+            self.state ^= time.to_bits();
+        }
+    }
+
+    pub(crate) fn seeded(seed: u64) -> Self {
+        Random { state: seed, elapsed: Some(0.0) }
+    }
+
+    // SplitMix64: small, fast and good enough for scripts, though not for secrets
+    fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    // A float from 0 up to (not including) 1, from the top 53 bits
+    pub(crate) fn next_float(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    // An integer from start up to (not including) end, as `range` counts
+    pub(crate) fn next_int(&mut self, start: i64, end: i64) -> i64 {
+        let span = end.wrapping_sub(start) as u64;
+        start.wrapping_add((self.next_u64() % span) as i64)
+    }
+
+    // Milliseconds since the Unix epoch by `clock`, or since the start when simulated
+    pub(crate) fn now(&self, clock: Option<f64>) -> Option<f64> {
+        self.elapsed.or(clock)
+    }
+
+    pub(crate) fn slept(&mut self, milliseconds: f64) {
+        if let Some(elapsed) = &mut self.elapsed {
+            *elapsed += milliseconds;
+        }
+    }
+}
+
+// Milliseconds since the Unix epoch. wasm32-unknown-unknown has no system
+// clock: reading it there panics, so it is never read.
+pub(crate) fn system_clock() -> Option<f64> {
+    if cfg!(all(target_arch = "wasm32", target_os = "unknown")) {
+        return None;
+    }
+    SystemTime::now().duration_since(UNIX_EPOCH).ok().map(|time| time.as_secs_f64() * 1000.0)
+}
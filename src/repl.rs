@@ -0,0 +1,385 @@
+use std::path::Path;
+
+use m_lang::ast::Expr;
+use m_lang::interpreter::Interpreter;
+use m_lang::lexer::Lexer;
+use m_lang::token::TokenType;
+use m_lang::value::Value;
+
+use crate::diagnostic::{self, Diagnostic};
+use crate::style;
+
+// `m_lang repl`: runs M code as it is typed, all in one interpreter, so what
+// one input defines can be used by the next. Input with an unclosed bracket or
+// string continues on the next line. The value of an input is printed and kept
+// in `_`. Lines starting with `:` are commands.
+
+const HELP: &str = "\
+:help        show this help
+:vars        list the variables, functions and transformers defined so far
+:load file   run a file, keeping what it defines
+:quit        leave the REPL (as does Ctrl-D)";
+
+const PROMPT: &str = "> ";
+
+#[cfg(feature = "repl")]
+const COMMANDS: &[&str] = &[":help", ":vars", ":load", ":quit"];
+
+// Values printed on one line up to this width are split over several
+const WIDTH: usize = 80;
+// Only this many elements of a collection are printed
+const MAX_ELEMENTS: usize = 100;
+
+pub fn run(mut interpreter: Interpreter) {
+    let mut input = match Input::new() {
+        Ok(input) => input,
+        Err(e) => {
+            style::report(&format!("Error: {}", e));
+            std::process::exit(1);
+        },
+    };
+
+    while let Some(line) = input.read(&interpreter) {
+        let line = line.trim();
+        if let Some(command) = line.strip_prefix(':') {
+            if !run_command(&mut interpreter, command) {
+                break;
+            }
+        } else if !line.is_empty() {
+            match evaluate(&mut interpreter, line, None) {
+                Ok(Some(value)) => {
+                    println!("{}", pretty(&value, 0));
+                    interpreter.set_variable("_", value);
+                },
+                Ok(None) => {},
+                Err(diagnostic) => diagnostic.report(),
+            }
+        }
+    }
+    input.save();
+}
+
+// False when the command ends the session
+fn run_command(interpreter: &mut Interpreter, command: &str) -> bool {
+    let (name, argument) = command.split_once(char::is_whitespace).unwrap_or((command, ""));
+    match (name, argument.trim()) {
+        ("help", _) => println!("{}", HELP),
+        ("vars", _) => {
+            for (name, value) in interpreter.globals() {
+                println!("{} = {}", name, pretty(value, 0));
+            }
+        },
+        ("load", "") => style::report("Error: :load expects a file name"),
+        ("load", path) => {
+            let path = Path::new(path);
+            if let Err(diagnostic) = diagnostic::read(path).and_then(|source| evaluate(interpreter, &source, Some(path))) {
+                diagnostic.report();
+            }
+        },
+        ("quit", _) => return false,
+        _ => style::report(&format!("Error: Unknown command ':{}' (:help lists the commands)", name)),
+    }
+    true
+}
+
+// The value worth showing: not nil, and not the value of an assignment, a
+// definition or a loop, which are run for their effect
+fn evaluate(interpreter: &mut Interpreter, source: &str, file: Option<&Path>) -> Result<Option<Value>, Diagnostic> {
+    let program = diagnostic::parse(source, file)?;
+    let value = interpreter.evaluate(&program).map_err(|e| {
+        let location = interpreter.take_error_location(&e);
+        Diagnostic::runtime(file, e, location)
+    })?;
+
+    let last = match &program {
+        Expr::Block(statements) => statements.last(),
+        statement => Some(statement),
+    };
+    Ok(match (last, value) {
+        (_, Value::Nil) => None,
+        (
+            Some(
+                Expr::Let { .. }
+                | Expr::Assign { .. }
+                | Expr::TupleAssign { .. }
+                | Expr::IndexAssign { .. }
+                | Expr::Function { .. }
+                | Expr::Transformer { .. }
+                | Expr::Use { .. }
+                | Expr::Pragma { .. }
+                | Expr::For { .. }
+                | Expr::While { .. }
+                | Expr::DoWhile { .. },
+            ),
+            _,
+        ) => None,
+        (_, value) => Some(value),
+    })
+}
+
+// Shows a value as it would be written, strings quoted. A collection too wide
+// for one line gets a line per element, indented; long ones end with a count of
+// the elements left out.
+fn pretty(value: &Value, depth: usize) -> String {
+    let flat = flat(value);
+    let Some((open, close, elements, total)) = elements(value) else {
+        return flat;
+    };
+    if depth * 4 + flat.len() <= WIDTH {
+        return flat;
+    }
+
+    let indent = "    ".repeat(depth + 1);
+    let mut lines: Vec<String> = elements
+        .iter()
+        .map(|(key, element)| format!("{}{}{}", indent, key_prefix(key), pretty(element, depth + 1)))
+        .collect();
+    if total > elements.len() {
+        lines.push(format!("{}... {} more", indent, total - elements.len()));
+    }
+    format!("{}\n{}\n{}{}", open, lines.join(",\n"), "    ".repeat(depth), close)
+}
+
+fn flat(value: &Value) -> String {
+    let Some((open, close, elements, total)) = elements(value) else {
+        return match value {
+            Value::String(s) => quote(s),
+            value => value.to_string(),
+        };
+    };
+
+    let mut parts: Vec<String> = elements.iter().map(|(key, element)| key_prefix(key) + &flat(element)).collect();
+    if total > elements.len() {
+        parts.push(format!("... {} more", total - elements.len()));
+    }
+    format!("{}{}{}", open, parts.join(", "), close)
+}
+
+// The brackets around a collection, its first elements (with their keys, for
+// a map) and how many elements it has
+type Elements = (&'static str, &'static str, Vec<(Option<String>, Value)>, usize);
+
+fn elements(value: &Value) -> Option<Elements> {
+    fn first(values: impl Iterator<Item = Value>) -> Vec<(Option<String>, Value)> {
+        values.take(MAX_ELEMENTS).map(|value| (None, value)).collect()
+    }
+
+    Some(match value {
+        Value::Array(elements) => {
+            let elements = elements.borrow();
+            ("[", "]", first(elements.iter().cloned()), elements.len())
+        },
+        Value::Map(entries) => {
+            let entries = entries.borrow();
+            let first = entries.iter().take(MAX_ELEMENTS).map(|(key, value)| (Some(key.clone()), value.clone())).collect();
+            ("{", "}", first, entries.len())
+        },
+        Value::Set(set) => {
+            let set = set.borrow();
+            ("set([", "])", first(set.iter().cloned()), set.len())
+        },
+        // `(1,)` as it is written, rather than `(1)`
+        Value::Tuple(elements) if elements.len() == 1 => ("(", ",)", first(elements.iter().cloned()), 1),
+        Value::Tuple(elements) => ("(", ")", first(elements.iter().cloned()), elements.len()),
+        Value::Result(result) => match &**result {
+            Ok(value) => ("ok(", ")", first(std::iter::once(value.clone())), 1),
+            Err(error) => ("err(", ")", first(std::iter::once(error.clone())), 1),
+        },
+        _ => return None,
+    })
+}
+
+fn key_prefix(key: &Option<String>) -> String {
+    key.as_deref().map(|key| quote(key) + ": ").unwrap_or_default()
+}
+
+// M strings have no escapes, so text with a quote or a line break is shown in
+// triple quotes
+fn quote(s: &str) -> String {
+    if s.contains('"') || s.contains('\n') {
+        format!("\"\"\"{}\"\"\"", s)
+    } else {
+        format!("\"{}\"", s)
+    }
+}
+
+// Whether the input stops inside brackets or a string, so more lines are needed
+fn is_incomplete(source: &str) -> bool {
+    if source.trim_start().starts_with(':') {
+        return false;
+    }
+
+    let mut depth = 0;
+    for token in Lexer::new(source) {
+        match token {
+            Ok(token) => match token.token_type {
+                TokenType::LeftParen | TokenType::LeftBracket | TokenType::LeftBrace => depth += 1,
+                TokenType::RightParen | TokenType::RightBracket | TokenType::RightBrace => depth -= 1,
+                _ => {},
+            },
+            Err(e) if e.message == "Unterminated string" => return true,
+            Err(_) => {},
+        }
+    }
+    depth > 0
+}
+
+// The names that can be completed: what the program has defined, then the
+// builtins it hasn't shadowed
+#[cfg(feature = "repl")]
+fn names(interpreter: &Interpreter) -> Vec<String> {
+    let mut names: Vec<String> = interpreter.globals().map(|(name, _)| name.to_string()).collect();
+    names.extend(interpreter.builtins().map(str::to_string));
+    names.sort_unstable();
+    names.dedup();
+    names
+}
+
+// With the `repl` feature, input is read with a line editor: arrow keys move
+// through the line and the history (kept in ~/.m_history between sessions),
+// Tab completes names and file names after :load, and Enter on an incomplete
+// input starts a new line instead of running it
+#[cfg(feature = "repl")]
+struct Input {
+    editor: rustyline::Editor<Helper, rustyline::history::DefaultHistory>,
+    history: Option<std::path::PathBuf>,
+}
+
+#[cfg(feature = "repl")]
+impl Input {
+    fn new() -> Result<Self, String> {
+        let mut editor = rustyline::Editor::new().map_err(|e| format!("Failed to start the line editor: {}", e))?;
+        editor.set_helper(Some(Helper { names: Vec::new(), files: rustyline::completion::FilenameCompleter::new() }));
+
+        let history = std::env::var_os("HOME").map(|home| std::path::PathBuf::from(home).join(".m_history"));
+        if let Some(path) = &history {
+            // There's no history the first time
+            let _ = editor.load_history(path);
+        }
+        Ok(Input { editor, history })
+    }
+
+    // None at the end of input
+    fn read(&mut self, interpreter: &Interpreter) -> Option<String> {
+        if let Some(helper) = self.editor.helper_mut() {
+            helper.names = names(interpreter);
+        }
+
+        match self.editor.readline(PROMPT) {
+            Ok(line) => {
+                let _ = self.editor.add_history_entry(line.as_str());
+                Some(line)
+            },
+            // Ctrl-C abandons the line being typed
+            Err(rustyline::error::ReadlineError::Interrupted) => Some(String::new()),
+            Err(_) => None,
+        }
+    }
+
+    fn save(&mut self) {
+        if let Some(path) = &self.history {
+            if let Err(e) = self.editor.save_history(path) {
+                style::report(&format!("Error: Failed to save the history to {}: {}", path.display(), e));
+            }
+        }
+    }
+}
+
+#[cfg(feature = "repl")]
+struct Helper {
+    names: Vec<String>,
+    files: rustyline::completion::FilenameCompleter,
+}
+
+#[cfg(feature = "repl")]
+impl rustyline::completion::Completer for Helper {
+    type Candidate = rustyline::completion::Pair;
+
+    fn complete(&self, line: &str, pos: usize, context: &rustyline::Context<'_>) -> rustyline::Result<(usize, Vec<Self::Candidate>)> {
+        if line.trim_start().starts_with(":load ") {
+            return self.files.complete(line, pos, context);
+        }
+
+        // A command being typed, or else the identifier the cursor is at the end of
+        let typed = line[..pos].trim_start();
+        if typed.starts_with(':') && !typed.contains(char::is_whitespace) {
+            return Ok((pos - typed.len(), matching(COMMANDS.iter().copied(), typed)));
+        }
+        let start = line[..pos]
+            .char_indices()
+            .rev()
+            .take_while(|&(_, c)| c.is_alphanumeric() || c == '_')
+            .last()
+            .map_or(pos, |(i, _)| i);
+        Ok((start, matching(self.names.iter().map(String::as_str), &line[start..pos])))
+    }
+}
+
+#[cfg(feature = "repl")]
+fn matching<'a>(names: impl Iterator<Item = &'a str>, prefix: &str) -> Vec<rustyline::completion::Pair> {
+    if prefix.is_empty() {
+        return Vec::new();
+    }
+    names
+        .filter(|name| name.starts_with(prefix))
+        .map(|name| rustyline::completion::Pair { display: name.to_string(), replacement: name.to_string() })
+        .collect()
+}
+
+#[cfg(feature = "repl")]
+impl rustyline::validate::Validator for Helper {
+    fn validate(&self, context: &mut rustyline::validate::ValidationContext) -> rustyline::Result<rustyline::validate::ValidationResult> {
+        Ok(if is_incomplete(context.input()) {
+            rustyline::validate::ValidationResult::Incomplete
+        } else {
+            rustyline::validate::ValidationResult::Valid(None)
+        })
+    }
+}
+
+#[cfg(feature = "repl")]
+impl rustyline::hint::Hinter for Helper {
+    type Hint = String;
+}
+
+#[cfg(feature = "repl")]
+impl rustyline::highlight::Highlighter for Helper {
+    fn highlight_prompt<'b, 's: 'b, 'p: 'b>(&'s self, prompt: &'p str, _default: bool) -> std::borrow::Cow<'b, str> {
+        style::prompt(prompt).into()
+    }
+}
+
+#[cfg(feature = "repl")]
+impl rustyline::Helper for Helper {}
+
+// Without the `repl` feature, lines are read from stdin as they are
+#[cfg(not(feature = "repl"))]
+struct Input;
+
+#[cfg(not(feature = "repl"))]
+impl Input {
+    fn new() -> Result<Self, String> {
+        Ok(Input)
+    }
+
+    fn read(&mut self, _interpreter: &Interpreter) -> Option<String> {
+        use std::io::{BufRead, IsTerminal, Write};
+
+        let mut source = String::new();
+        loop {
+            // Like the line editor, only prompt someone typing
+            if std::io::stdin().is_terminal() {
+                print!("{}", style::prompt(if source.is_empty() { PROMPT } else { "... " }));
+                std::io::stdout().flush().ok()?;
+            }
+            match std::io::stdin().lock().read_line(&mut source) {
+                Ok(0) | Err(_) if source.is_empty() => return None,
+                Ok(0) | Err(_) => return Some(source),
+                Ok(_) if is_incomplete(&source) => {},
+                Ok(_) => return Some(source),
+            }
+        }
+    }
+
+    fn save(&mut self) {}
+}
@@ -0,0 +1,386 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use crate::ast::Expr;
+use crate::lexer::Lexer;
+use crate::module;
+use crate::parser::Parser;
+use crate::stack;
+use crate::symbol::Symbol;
+
+// Functions handled directly by the interpreter rather than defined in M
+pub(crate) const BUILTIN_FUNCTIONS: &[&str] = &[
+    "print", "write", "print_raw", "format", "input", "typeof", "range",
+    "read_bytes", "write_bytes", "sha256", "md5", "crc32",
+    "list_dir", "mkdir", "remove_file", "path_join", "basename", "extension",
+    "spawn", "join", "sleep", "channel", "send", "receive", "close", "push", "zip",
+    "array_of", "set", "ord", "chr", "eval", "globals", "defined", "fn_params",
+    "from_json", "on", "random", "random_int", "now", "decimal", "try_parse_number",
+    "ok", "err", "try", "args",
+];
+
+// Resolves every variable and function reference against the names the program
+// (and the files it imports) defines, reporting references that can never
+// succeed before the program runs.
+//
+// Functions see the variables of their caller, so where most names come from
+// depends on the call site, and a reference is only reported when no
+// definition of the name exists anywhere. The names a function binds itself
+// are the exception: bind_locals() below binds references to those to slots.
+pub struct Resolver {
+    base_path: PathBuf,
+    module_paths: Vec<PathBuf>,
+    defined: HashSet<String>,
+    // Each referenced name with whether it was called, in order of first use
+    references: Vec<(String, bool)>,
+    visited: HashSet<PathBuf>,
+    evaluates: bool,
+}
+
+/// What check_with_names() finds.
+pub struct Resolution {
+    /// A message for each name that is never defined, such as "Undefined
+    /// variable 'x'".
+    pub undefined: Vec<String>,
+    /// Whether the program or a file it imports calls eval(), which can define
+    /// names the resolver can't see.
+    pub evaluates: bool,
+}
+
+impl Resolver {
+    pub fn check(program: &Expr, base_path: &Path) -> Vec<String> {
+        Self::check_with_paths(program, base_path, &[])
+    }
+
+    /// Like check(), for a program whose imports are also looked for in the
+    /// module directories of its project.
+    pub fn check_with_paths(program: &Expr, base_path: &Path, module_paths: &[PathBuf]) -> Vec<String> {
+        Self::check_with_names(program, base_path, module_paths, []).undefined
+    }
+
+    /// Like check_with_paths(), for a program run in an interpreter that
+    /// already defines some names, such as one that ran other files first.
+    pub fn check_with_names<'a>(program: &Expr, base_path: &Path, module_paths: &[PathBuf], names: impl IntoIterator<Item = &'a str>) -> Resolution {
+        let mut defined: HashSet<String> = BUILTIN_FUNCTIONS.iter().map(|name| name.to_string()).collect();
+        defined.extend(names.into_iter().map(str::to_string));
+        let mut resolver = Resolver {
+            base_path: base_path.to_path_buf(),
+            module_paths: module_paths.to_vec(),
+            defined,
+            references: Vec::new(),
+            visited: HashSet::new(),
+            evaluates: false,
+        };
+
+        resolver.walk(program);
+
+        let mut reported = HashSet::new();
+        let mut diagnostics = Vec::new();
+        for (name, called) in &resolver.references {
+            if !resolver.defined.contains(name) && reported.insert(name.clone()) {
+                let kind = if *called { "function" } else { "variable" };
+                diagnostics.push(format!("Undefined {} '{}'", kind, name));
+            }
+        }
+        Resolution { undefined: diagnostics, evaluates: resolver.evaluates }
+    }
+
+    fn walk(&mut self, expr: &Expr) {
+        stack::guard(|| match expr {
+            Expr::Variable(name) | Expr::Local { name, .. } => self.reference(name, false),
+            Expr::Call { callee, arguments } => {
+                self.reference(callee, true);
+                self.walk_all(arguments);
+            },
+            Expr::Let { name, value, .. } => {
+                self.define(name);
+                if let Some(value) = value {
+                    self.walk(value);
+                }
+            },
+            Expr::Assign { name, value, .. } => {
+                self.define(name);
+                self.walk(value);
+            },
+            Expr::TupleAssign { names, value, .. } => {
+                for name in names {
+                    self.define(name);
+                }
+                self.walk(value);
+            },
+            Expr::Function { name, params, body, .. } => {
+                if let Some(name) = name {
+                    self.define(name);
+                }
+                for param in params.iter() {
+                    self.define(param);
+                }
+                self.walk_all(body);
+            },
+            Expr::Transformer { name, receiver, params, body, .. } => {
+                self.define(name);
+                self.define(receiver.as_deref().unwrap_or("applied"));
+                for param in params.iter() {
+                    self.define(param);
+                }
+                self.walk_all(body);
+            },
+            Expr::For { variable, second, iterable, step, body, .. } => {
+                self.define(variable);
+                if let Some(second) = second {
+                    self.define(second);
+                }
+                self.walk(iterable);
+                if let Some(step) = step {
+                    self.walk(step);
+                }
+                self.walk(body);
+            },
+            Expr::Use { path } => self.walk_import(path),
+            Expr::Array(elements) | Expr::Tuple(elements) | Expr::Block(elements) => self.walk_all(elements),
+            Expr::Map(entries) => {
+                for (_, value) in entries {
+                    self.walk(value);
+                }
+            },
+            Expr::Binary { left, right, .. } | Expr::Index { object: left, index: right } => {
+                self.walk(left);
+                self.walk(right);
+            },
+            Expr::IndexAssign { object, index, value } => {
+                self.walk(object);
+                self.walk(index);
+                self.walk(value);
+            },
+            Expr::Unary { right, .. } | Expr::Await(right) => self.walk(right),
+            Expr::Return { value, .. } => {
+                if let Some(value) = value {
+                    self.walk(value);
+                }
+            },
+            Expr::If { condition, then_branch, else_branch } => {
+                self.walk(condition);
+                self.walk(then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.walk(else_branch);
+                }
+            },
+            Expr::While { condition, body, .. } => {
+                self.walk(condition);
+                self.walk(body);
+            },
+            Expr::Loop { body, .. } => self.walk(body),
+            Expr::Break { value, .. } => {
+                if let Some(value) = value {
+                    self.walk(value);
+                }
+            },
+            Expr::DoWhile { body, condition, .. } => {
+                self.walk(body);
+                self.walk(condition);
+            },
+            Expr::Apply { object, arguments, .. } => {
+                self.walk(object);
+                self.walk_all(arguments);
+            },
+            Expr::Member { object, .. } => self.walk(object),
+            Expr::Assert { arguments, .. } => self.walk_all(arguments),
+            Expr::Int(_) | Expr::BigInt(_) | Expr::Number(_) | Expr::String(_) | Expr::Boolean(_) | Expr::Nil | Expr::Continue { .. } | Expr::Pragma { .. } => {},
+        })
+    }
+
+    fn walk_all(&mut self, expressions: &[Expr]) {
+        for expr in expressions {
+            self.walk(expr);
+        }
+    }
+
+    // Imported files contribute their definitions. Files that can't be read or
+    // parsed are skipped here; running the program reports those errors.
+    fn walk_import(&mut self, path: &str) {
+        let file_path = module::locate(path, &self.base_path, &self.module_paths);
+        if !self.visited.insert(file_path.clone()) {
+            return;
+        }
+
+        let Ok(content) = fs::read_to_string(&file_path) else { return };
+        let Ok(tokens) = Lexer::new(&content).tokenize() else { return };
+        if let Ok(ast) = Parser::new(tokens).parse() {
+            self.walk(&ast);
+        }
+    }
+
+    fn define(&mut self, name: &str) {
+        self.defined.insert(name.to_string());
+    }
+
+    fn reference(&mut self, name: &str, called: bool) {
+        self.evaluates |= name == "eval";
+        self.references.push((name.to_string(), called));
+    }
+}
+
+/// Binds the references a function or transformer body makes to the names its
+/// call binds, to slots in the call's scope: first `bound` (the receiver and
+/// parameters), then the names the body declares with `let` at its top level,
+/// which are returned. Running the body then indexes the scope for them rather
+/// than looking them up by name in each scope on the way out. Only references
+/// that run once their slot is set are bound; the others are left by name.
+pub(crate) fn bind_locals(bound: &[Symbol], body: &mut [Expr]) -> Arc<[Symbol]> {
+    let mut slots = bound.to_vec();
+    let mut locals = Vec::new();
+    for expr in body.iter() {
+        if let Expr::Let { name, .. } = expr {
+            if !slots.contains(name) {
+                slots.push(*name);
+                locals.push(*name);
+            }
+        }
+    }
+
+    let mut binder = SlotBinder { slots: &slots, set: bound.len(), blocks: Vec::new() };
+    for expr in body {
+        binder.bind(expr);
+    }
+    locals.into()
+}
+
+// Walks a body in the order it runs, keeping the names declared so far in each
+// block scope opened inside it. A name a block declares hides the call's slot
+// for the rest of that block, so references to it there stay by name. Nested
+// functions and transformers are bound when they are parsed, to their own slots.
+struct SlotBinder<'a> {
+    slots: &'a [Symbol],
+    // The slots before this one are set where the walk has got to: those
+    // bound by the call, then each local once its `let` has run
+    set: usize,
+    blocks: Vec<Block>,
+}
+
+#[derive(Default)]
+struct Block {
+    declared: Vec<Symbol>,
+    // eval() or `use` ran in the block, and may have defined any name in it
+    opaque: bool,
+}
+
+impl SlotBinder<'_> {
+    fn bind(&mut self, expr: &mut Expr) {
+        stack::guard(|| match expr {
+            Expr::Variable(name) => {
+                let name = *name;
+                let hidden = self.blocks.iter().any(|block| block.opaque || block.declared.contains(&name));
+                if let (false, Some(slot)) = (hidden, self.slots[..self.set].iter().position(|slot| *slot == name)) {
+                    *expr = Expr::Local { name, depth: self.blocks.len(), slot };
+                }
+                // eval() passed on as a value may be called from here
+                if name == "eval" {
+                    self.define_at_runtime();
+                }
+            },
+            Expr::Let { name, value, .. } => {
+                if let Some(value) = value {
+                    self.bind(value);
+                }
+                self.declare(*name);
+            },
+            Expr::Function { name: Some(name), .. } | Expr::Transformer { name, .. } => self.declare(*name),
+            Expr::Assign { value, .. } | Expr::TupleAssign { value, .. } => self.bind(value),
+            Expr::Call { callee, arguments } => {
+                self.bind_all(arguments);
+                if *callee == "eval" {
+                    self.define_at_runtime();
+                }
+            },
+            Expr::Assert { arguments, .. } => self.bind_all(arguments),
+            Expr::For { variable, second, iterable, step, body, .. } => {
+                self.bind(iterable);
+                if let Some(step) = step {
+                    self.bind(step);
+                }
+                let declared = std::iter::once(*variable).chain(*second).collect();
+                self.block(Block { declared, opaque: false }, |binder| binder.bind(body));
+            },
+            Expr::If { condition, then_branch, else_branch } => {
+                self.bind(condition);
+                self.block(Block::default(), |binder| binder.bind(then_branch));
+                if let Some(else_branch) = else_branch {
+                    self.block(Block::default(), |binder| binder.bind(else_branch));
+                }
+            },
+            Expr::While { condition, body, .. } => {
+                self.bind(condition);
+                self.block(Block::default(), |binder| binder.bind(body));
+            },
+            Expr::Loop { body, .. } => self.block(Block::default(), |binder| binder.bind(body)),
+            // The condition runs in the body's scope
+            Expr::DoWhile { body, condition, .. } => self.block(Block::default(), |binder| {
+                binder.bind(body);
+                binder.bind(condition);
+            }),
+            Expr::Array(elements) | Expr::Tuple(elements) | Expr::Block(elements) => self.bind_all(elements),
+            Expr::Map(entries) => {
+                for (_, value) in entries {
+                    self.bind(value);
+                }
+            },
+            Expr::Binary { left, right, .. } | Expr::Index { object: left, index: right } => {
+                self.bind(left);
+                self.bind(right);
+            },
+            Expr::IndexAssign { object, index, value } => {
+                self.bind(object);
+                self.bind(index);
+                self.bind(value);
+            },
+            Expr::Unary { right, .. } | Expr::Await(right) => self.bind(right),
+            Expr::Return { value, .. } | Expr::Break { value, .. } => {
+                if let Some(value) = value {
+                    self.bind(value);
+                }
+            },
+            Expr::Apply { object, arguments, .. } => {
+                self.bind(object);
+                self.bind_all(arguments);
+            },
+            Expr::Member { object, .. } => self.bind(object),
+            // What `use` defines isn't known until it runs
+            Expr::Use { .. } => self.define_at_runtime(),
+            Expr::Function { name: None, .. } | Expr::Local { .. } | Expr::Int(_) | Expr::BigInt(_) | Expr::Number(_) | Expr::String(_) | Expr::Boolean(_) | Expr::Nil | Expr::Continue { .. } | Expr::Pragma { .. } => {},
+        })
+    }
+
+    fn bind_all(&mut self, expressions: &mut [Expr]) {
+        for expr in expressions {
+            self.bind(expr);
+        }
+    }
+
+    // At the top of the body a declaration defines the name's slot, so only
+    // declarations in blocks hide it
+    fn declare(&mut self, name: Symbol) {
+        match self.blocks.last_mut() {
+            Some(block) => block.declared.push(name),
+            None => {
+                if self.slots.get(self.set) == Some(&name) {
+                    self.set += 1;
+                }
+            },
+        }
+    }
+
+    // Names defined at the top of the body go to their slots whatever defines
+    // them, but in a block they hide the call's slots for the rest of it
+    fn define_at_runtime(&mut self) {
+        if let Some(block) = self.blocks.last_mut() {
+            block.opaque = true;
+        }
+    }
+
+    fn block(&mut self, declared: Block, bind: impl FnOnce(&mut Self)) {
+        self.blocks.push(declared);
+        bind(self);
+        self.blocks.pop();
+    }
+}
@@ -0,0 +1,178 @@
+// Sets made by set(): collections of distinct values with fast membership
+// tests. A set keeps its elements in order (numbers by value, then strings), so
+// it prints and iterates the same way every time.
+//
+// Elements can be nil, booleans, numbers or strings. Numbers that are equal
+// with `==` are the same element, so a set holding 1 also contains 1.0; it keeps
+// whichever was added first.
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use rust_decimal::prelude::ToPrimitive;
+use crate::bigint::BigInt;
+use crate::decimal;
+use crate::value::{Decimal, Text, Value};
+
+/// The elements of a set value (see `Value::Set`).
+#[derive(Debug, Clone, Default)]
+pub struct Set(BTreeMap<Key, Value>);
+
+// An element's identity within a set
+#[derive(Debug, Clone)]
+enum Key {
+    Nil,
+    Boolean(bool),
+    // Whole numbers of any type that fit an i64
+    Int(i64),
+    // Only made with the bigint feature, like the values it stands for
+    #[cfg_attr(not(feature = "bigint"), allow(dead_code))]
+    BigInt(BigInt),
+    // Never NaN, and never a whole number that would fit an Int
+    Float(f64),
+    Decimal(Decimal),
+    String(Text),
+}
+
+impl Key {
+    fn of(value: &Value) -> Result<Key, String> {
+        Ok(match value {
+            Value::Nil => Key::Nil,
+            Value::Boolean(b) => Key::Boolean(*b),
+            Value::Int(n) => Key::Int(*n),
+            #[cfg(feature = "bigint")]
+            Value::BigInt(n) => Key::BigInt(n.clone()),
+            #[cfg(not(feature = "bigint"))]
+            Value::BigInt(n) => match *n {},
+            Value::Number(n) if n.is_nan() => return Err("NaN can't be added to a set".to_string()),
+            // i64::MAX as f64 is 2^63, just past the largest i64
+            Value::Number(n) if n.fract() == 0.0 && *n >= i64::MIN as f64 && *n < i64::MAX as f64 => Key::Int(*n as i64),
+            Value::Number(n) => Key::Float(*n),
+            Value::Decimal(d) => match d.fract().is_zero().then(|| d.to_i64()).flatten() {
+                Some(n) => Key::Int(n),
+                None => Key::Decimal(d.normalize()),
+            },
+            Value::String(s) => Key::String(s.clone()),
+            _ => return Err(format!("Cannot add {} to a set; elements must be nil, booleans, numbers or strings", value.type_name())),
+        })
+    }
+
+    // Nil sorts first, then booleans, numbers and strings
+    fn class(&self) -> u8 {
+        match self {
+            Key::Nil => 0,
+            Key::Boolean(_) => 1,
+            Key::Int(_) | Key::BigInt(_) | Key::Float(_) | Key::Decimal(_) => 2,
+            Key::String(_) => 3,
+        }
+    }
+
+    // Numbers of different types are ordered by their nearest float first
+    fn number(&self) -> (f64, u8) {
+        match self {
+            Key::Int(n) => (*n as f64, 0),
+            Key::BigInt(n) => (n.to_f64(), 1),
+            Key::Float(n) => (*n, 2),
+            Key::Decimal(d) => (decimal::to_f64(*d), 3),
+            _ => (0.0, 0),
+        }
+    }
+}
+
+impl Ord for Key {
+    fn cmp(&self, other: &Key) -> Ordering {
+        self.class().cmp(&other.class()).then_with(|| match (self, other) {
+            (Key::Boolean(l), Key::Boolean(r)) => l.cmp(r),
+            (Key::String(l), Key::String(r)) => l.cmp(r),
+            (Key::Nil, Key::Nil) => Ordering::Equal,
+            _ => {
+                let ((l, l_type), (r, r_type)) = (self.number(), other.number());
+                l.total_cmp(&r).then(l_type.cmp(&r_type)).then_with(|| match (self, other) {
+                    (Key::Int(l), Key::Int(r)) => l.cmp(r),
+                    (Key::BigInt(l), Key::BigInt(r)) => l.cmp(r),
+                    (Key::Float(l), Key::Float(r)) => l.total_cmp(r),
+                    (Key::Decimal(l), Key::Decimal(r)) => l.cmp(r),
+                    _ => Ordering::Equal,
+                })
+            },
+        })
+    }
+}
+
+impl PartialOrd for Key {
+    fn partial_cmp(&self, other: &Key) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Key {
+    fn eq(&self, other: &Key) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Key {}
+
+// Sets are equal when they have the same elements
+impl PartialEq for Set {
+    fn eq(&self, other: &Set) -> bool {
+        self.0.keys().eq(other.0.keys())
+    }
+}
+
+impl Set {
+    /// Makes a set of the given values, leaving out repeats.
+    pub fn from_values(values: impl IntoIterator<Item = Value>) -> Result<Set, String> {
+        let mut set = Set::default();
+        for value in values {
+            set.insert(value)?;
+        }
+        Ok(set)
+    }
+
+    /// Adds a value, returning false if the set already had it. Fails for values
+    /// that can't be elements.
+    pub fn insert(&mut self, value: Value) -> Result<bool, String> {
+        let key = Key::of(&value)?;
+        if self.0.contains_key(&key) {
+            return Ok(false);
+        }
+        self.0.insert(key, value);
+        Ok(true)
+    }
+
+    /// Returns true if the set has an element equal to the value.
+    pub fn contains(&self, value: &Value) -> bool {
+        Key::of(value).is_ok_and(|key| self.0.contains_key(&key))
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// The elements, in order.
+    pub fn iter(&self) -> impl Iterator<Item = &Value> {
+        self.0.values()
+    }
+
+    /// The elements in either set.
+    pub fn union(&self, other: &Set) -> Set {
+        let mut union = self.clone();
+        for (key, value) in &other.0 {
+            union.0.entry(key.clone()).or_insert_with(|| value.clone());
+        }
+        union
+    }
+
+    /// The elements in both sets.
+    pub fn intersection(&self, other: &Set) -> Set {
+        Set(self.0.iter().filter(|(key, _)| other.0.contains_key(key)).map(|(key, value)| (key.clone(), value.clone())).collect())
+    }
+
+    /// The elements of this set that aren't in the other one.
+    pub fn difference(&self, other: &Set) -> Set {
+        Set(self.0.iter().filter(|(key, _)| !other.0.contains_key(key)).map(|(key, value)| (key.clone(), value.clone())).collect())
+    }
+}
@@ -0,0 +1,245 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use serde::{Deserialize, Serialize};
+use crate::ast::Expr;
+use crate::bigint;
+use crate::environment::{Environment, Layout};
+use crate::heap::Handle;
+use crate::stack;
+use crate::symbol::Symbol;
+use crate::value::{Decimal, Outcome, Set, Value};
+
+// A saved set of variables, written by Interpreter::save_state() as JSON.
+// Arrays, maps and sets are stored once each in `containers` and referred to by
+// index, so variables that shared a container still share it after loading.
+#[derive(Serialize, Deserialize)]
+struct Snapshot {
+    version: String,
+    containers: Vec<Container>,
+    bindings: Vec<(Symbol, Saved)>,
+}
+
+#[derive(Serialize, Deserialize)]
+enum Container {
+    Array(Vec<Saved>),
+    Map(BTreeMap<String, Saved>),
+    Set(Vec<Saved>),
+}
+
+#[derive(Serialize, Deserialize)]
+enum Saved {
+    Int(i64),
+    // The decimal digits
+    BigInt(String),
+    Decimal(String),
+    // The bit pattern, so nan and infinities survive JSON
+    Number(u64),
+    String(String),
+    Boolean(bool),
+    Array(usize),
+    Map(usize),
+    Set(usize),
+    Tuple(Vec<Saved>),
+    Ok(Box<Saved>),
+    Err(Box<Saved>),
+    Bytes(Vec<u8>),
+    Range { start: i64, end: i64 },
+    Function {
+        params: Arc<[Symbol]>,
+        locals: Arc<[Symbol]>,
+        body: Arc<[Expr]>,
+        is_async: bool,
+        // What it sees of the scopes it captured, as one scope
+        #[serde(default)]
+        captured: Vec<(Symbol, Saved)>,
+    },
+    Transformer { receiver: Symbol, params: Arc<[Symbol]>, locals: Arc<[Symbol]>, body: Arc<[Expr]> },
+    Composed(Vec<Saved>),
+    Nil,
+}
+
+pub fn save(bindings: &[(Symbol, Value)]) -> Result<String, String> {
+    let mut saver = Saver { containers: Vec::new(), indexes: HashMap::new() };
+    let mut saved = Vec::new();
+    for (name, value) in bindings {
+        let value = saver.save(value).map_err(|e| format!("Cannot save '{}': {}", name, e))?;
+        saved.push((*name, value));
+    }
+
+    let snapshot = Snapshot {
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        containers: saver.containers,
+        bindings: saved,
+    };
+    serde_json::to_string(&snapshot).map_err(|e| e.to_string())
+}
+
+pub fn load(state: &str) -> Result<Vec<(Symbol, Value)>, String> {
+    let snapshot: Snapshot = serde_json::from_str(state).map_err(|e| format!("Invalid saved state: {}", e))?;
+    if snapshot.version != env!("CARGO_PKG_VERSION") {
+        return Err(format!("State was saved by version {} of the interpreter", snapshot.version));
+    }
+
+    // Every container exists before any is filled, so they can refer to each other
+    let handles: Vec<Value> = snapshot.containers.iter().map(|container| match container {
+        Container::Array(_) => Value::array(Vec::new()),
+        Container::Map(_) => Value::map(BTreeMap::new()),
+        Container::Set(_) => Value::set(Set::default()),
+    }).collect();
+
+    let loader = Loader { handles: &handles };
+    for (container, handle) in snapshot.containers.iter().zip(&handles) {
+        match (container, handle) {
+            (Container::Array(elements), Value::Array(array)) => {
+                *array.borrow_mut() = elements.iter().map(|element| loader.load(element)).collect::<Result<_, _>>()?;
+            },
+            (Container::Map(entries), Value::Map(map)) => {
+                let mut loaded = BTreeMap::new();
+                for (key, value) in entries {
+                    loaded.insert(key.clone(), loader.load(value)?);
+                }
+                *map.borrow_mut() = loaded;
+            },
+            (Container::Set(elements), Value::Set(set)) => {
+                let elements = elements.iter().map(|element| loader.load(element)).collect::<Result<Vec<_>, _>>()?;
+                *set.borrow_mut() = Set::from_values(elements).map_err(|e| format!("Invalid saved state: {}", e))?;
+            },
+            _ => unreachable!("handles are created to match their containers"),
+        }
+    }
+
+    snapshot.bindings.iter().map(|(name, value)| Ok((*name, loader.load(value)?))).collect()
+}
+
+struct Saver {
+    containers: Vec<Container>,
+    // Index of each container already saved, by heap address
+    indexes: HashMap<usize, usize>,
+}
+
+impl Saver {
+    fn save(&mut self, value: &Value) -> Result<Saved, String> {
+        stack::guard(|| Ok(match value {
+            Value::Int(n) => Saved::Int(*n),
+            Value::BigInt(n) => Saved::BigInt(n.to_string()),
+            Value::Decimal(d) => Saved::Decimal(d.to_string()),
+            Value::Number(n) => Saved::Number(n.to_bits()),
+            Value::String(s) => Saved::String(s.to_string()),
+            Value::Boolean(b) => Saved::Boolean(*b),
+            Value::Array(elements) => Saved::Array(self.container(elements, |saver| {
+                let elements = elements.get();
+                Ok(Container::Array(elements.iter().map(|element| saver.save(element)).collect::<Result<_, _>>()?))
+            })?),
+            Value::Map(entries) => Saved::Map(self.container(entries, |saver| {
+                let mut saved = BTreeMap::new();
+                for (key, value) in entries.get() {
+                    saved.insert(key, saver.save(&value)?);
+                }
+                Ok(Container::Map(saved))
+            })?),
+            Value::Set(set) => Saved::Set(self.container(set, |saver| {
+                let elements = set.get();
+                Ok(Container::Set(elements.iter().map(|element| saver.save(element)).collect::<Result<_, _>>()?))
+            })?),
+            Value::Tuple(elements) => Saved::Tuple(elements.iter().map(|element| self.save(element)).collect::<Result<_, _>>()?),
+            Value::Result(result) => match &**result {
+                Ok(value) => Saved::Ok(Box::new(self.save(value)?)),
+                Err(error) => Saved::Err(Box::new(self.save(error)?)),
+            },
+            Value::Bytes(bytes) => Saved::Bytes(bytes.clone()),
+            Value::Range { start, end } => Saved::Range { start: *start, end: *end },
+            Value::Function { params, layout, body, is_async, captured } => Saved::Function {
+                params: params.clone(),
+                locals: layout.names()[params.len()..].into(),
+                body: body.clone(),
+                is_async: *is_async,
+                captured: match captured {
+                    Some(captured) => captured.visible().into_iter().map(|(name, value)| Ok((name, self.save(value)?))).collect::<Result<_, String>>()?,
+                    None => Vec::new(),
+                },
+            },
+            Value::Transformer { receiver, params, layout, body } => Saved::Transformer { receiver: *receiver, params: params.clone(), locals: layout.names()[1 + params.len()..].into(), body: body.clone() },
+            Value::Composed(parts) => Saved::Composed(parts.iter().map(|part| self.save(part)).collect::<Result<_, _>>()?),
+            Value::Nil => Saved::Nil,
+            // Host objects and functions belong to this process
+            Value::Task(_) | Value::Sender(_) | Value::Receiver(_) | Value::Native(_) => {
+                return Err(format!("a {} can't be saved", value.type_name()));
+            },
+            Value::NativeFunction(function) => {
+                return Err(format!("the native function '{}' can't be saved", function.name));
+            },
+            Value::Builtin(builtin) => {
+                return Err(format!("the builtin '{}' can't be saved", builtin.name));
+            },
+        }))
+    }
+
+    // Saves a container the first time it is reached and returns its index
+    fn container<T>(&mut self, handle: &Handle<T>, save: impl FnOnce(&mut Self) -> Result<Container, String>) -> Result<usize, String> {
+        if let Some(&index) = self.indexes.get(&handle.id()) {
+            return Ok(index);
+        }
+
+        // Reserve the slot first, so the container's own contents see it
+        let index = self.containers.len();
+        self.indexes.insert(handle.id(), index);
+        self.containers.push(Container::Array(Vec::new()));
+        self.containers[index] = save(self)?;
+        Ok(index)
+    }
+}
+
+struct Loader<'a> {
+    handles: &'a [Value],
+}
+
+impl Loader<'_> {
+    fn load(&self, saved: &Saved) -> Result<Value, String> {
+        stack::guard(|| Ok(match saved {
+            Saved::Int(n) => Value::Int(*n),
+            Saved::BigInt(digits) => match bigint::parse(digits) {
+                Some(n) => n,
+                None => return Err("Invalid saved state: integers this large need the 'bigint' feature".to_string()),
+            },
+            Saved::Decimal(digits) => match Decimal::from_str_exact(digits) {
+                Ok(d) => Value::Decimal(d),
+                Err(_) => return Err("Invalid saved state: bad decimal".to_string()),
+            },
+            Saved::Number(bits) => Value::Number(f64::from_bits(*bits)),
+            Saved::String(s) => Value::String(s.as_str().into()),
+            Saved::Boolean(b) => Value::Boolean(*b),
+            Saved::Array(index) | Saved::Map(index) | Saved::Set(index) => match self.handles.get(*index) {
+                Some(handle) => handle.clone(),
+                None => return Err("Invalid saved state: missing container".to_string()),
+            },
+            Saved::Tuple(elements) => Value::Tuple(elements.iter().map(|element| self.load(element)).collect::<Result<_, _>>()?),
+            Saved::Ok(value) => Value::Result(Outcome::new(Ok(self.load(value)?))),
+            Saved::Err(error) => Value::Result(Outcome::new(Err(self.load(error)?))),
+            Saved::Bytes(bytes) => Value::Bytes(bytes.clone()),
+            Saved::Range { start, end } => Value::Range { start: *start, end: *end },
+            Saved::Function { params, locals, body, is_async, captured } => Value::Function {
+                params: params.clone(),
+                layout: Layout::new(params.iter().chain(locals.iter()).copied(), None),
+                body: body.clone(),
+                is_async: *is_async,
+                captured: if captured.is_empty() {
+                    None
+                } else {
+                    let mut scope = Environment::new();
+                    for (name, value) in captured {
+                        scope.define(*name, self.load(value)?);
+                    }
+                    Some(Arc::new(scope))
+                },
+            },
+            Saved::Transformer { receiver, params, locals, body } => Value::Transformer {
+                receiver: *receiver,
+                params: params.clone(),
+                layout: Layout::new(std::iter::once(*receiver).chain(params.iter().copied()).chain(locals.iter().copied()), None),
+                body: body.clone(),
+            },
+            Saved::Composed(parts) => Value::Composed(parts.iter().map(|part| self.load(part)).collect::<Result<_, _>>()?),
+            Saved::Nil => Value::Nil,
+        }))
+    }
+}
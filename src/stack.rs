@@ -0,0 +1,18 @@
+// The parser and the other passes over programs and values are recursive, so
+// deeply nested code or data would otherwise overflow the thread's stack and
+// abort the process. Each recursive step runs through guard(), which moves onto
+// a fresh stack segment on the heap when the current one runs low.
+//
+// The evaluator itself works from an explicit list of steps (see
+// interpreter/evaluation.rs), and syntax trees and values are freed with loops,
+// so neither needs this. It still guards each nested evaluation a call, eval()
+// or an import starts; how deep those go is bounded by the call depth limit.
+
+// Space that must be left on the stack before taking another recursive step
+const RED_ZONE: usize = 256 * 1024;
+// Size of each additional stack segment
+const SEGMENT_SIZE: usize = 4 * 1024 * 1024;
+
+pub fn guard<R>(f: impl FnOnce() -> R) -> R {
+    stacker::maybe_grow(RED_ZONE, SEGMENT_SIZE, f)
+}
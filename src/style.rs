@@ -0,0 +1,39 @@
+use std::io::IsTerminal;
+
+// Colors for the terminal. Output is only styled when it goes to a terminal
+// that can show colors, and never when NO_COLOR is set (https://no-color.org).
+
+const RED: &str = "1;31";
+const YELLOW: &str = "1;33";
+const CYAN: &str = "1;36";
+
+fn enabled(terminal: bool) -> bool {
+    let set = |name| std::env::var_os(name).is_some_and(|value| !value.is_empty());
+    terminal && !set("NO_COLOR") && std::env::var_os("TERM").is_none_or(|term| term != "dumb")
+}
+
+fn paint(text: &str, color: &str, terminal: bool) -> String {
+    if enabled(terminal) {
+        format!("\x1b[{}m{}\x1b[0m", color, text)
+    } else {
+        text.to_string()
+    }
+}
+
+// Prints a message to stderr with its label ("Error:", "Warning: " and the like,
+// up to the first colon) in red, or yellow for a warning
+pub fn report(message: &str) {
+    let terminal = std::io::stderr().is_terminal();
+    match message.split_once(':') {
+        Some((label, rest)) => {
+            let color = if label.to_lowercase().contains("warning") { YELLOW } else { RED };
+            eprintln!("{}{}", paint(&format!("{}:", label), color, terminal), rest);
+        },
+        None => eprintln!("{}", message),
+    }
+}
+
+// The REPL's prompt
+pub fn prompt(prompt: &str) -> String {
+    paint(prompt, CYAN, std::io::stdout().is_terminal())
+}
@@ -0,0 +1,131 @@
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::ops::Deref;
+use std::sync::{Mutex, OnceLock, PoisonError};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+// An identifier: the name of a variable, function, transformer or parameter.
+// Names are interned, so the text of each distinct name is stored once for the
+// life of the process and a symbol is a pointer to it. Symbols compare and hash
+// by that pointer instead of by their text, which makes them cheap keys for
+// scopes, and copying a scope (as every call does) copies no strings.
+//
+// Interned names are never freed. Programs only have as many as they spell
+// out, though code built at runtime and passed to eval() can add more.
+#[derive(Clone, Copy)]
+pub struct Symbol(&'static str);
+
+fn interned() -> &'static Mutex<HashSet<&'static str>> {
+    static NAMES: OnceLock<Mutex<HashSet<&'static str>>> = OnceLock::new();
+    NAMES.get_or_init(Mutex::default)
+}
+
+impl Symbol {
+    pub fn new(name: &str) -> Symbol {
+        let mut names = interned().lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(&name) = names.get(name) {
+            return Symbol(name);
+        }
+        let name: &'static str = Box::leak(name.into());
+        names.insert(name);
+        Symbol(name)
+    }
+
+    /// The symbol for a name if one was ever made. A name without one can't be
+    /// bound in any scope, so lookups by text can skip interning it.
+    pub fn existing(name: &str) -> Option<Symbol> {
+        let names = interned().lock().unwrap_or_else(PoisonError::into_inner);
+        names.get(name).map(|&name| Symbol(name))
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        self.0
+    }
+}
+
+impl PartialEq for Symbol {
+    fn eq(&self, other: &Symbol) -> bool {
+        std::ptr::eq(self.0, other.0)
+    }
+}
+
+impl Eq for Symbol {}
+
+impl Hash for Symbol {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        (self.0.as_ptr() as usize).hash(state);
+    }
+}
+
+// Symbols sort by name, so listings of variables come out in alphabetical order
+impl Ord for Symbol {
+    fn cmp(&self, other: &Symbol) -> Ordering {
+        self.0.cmp(other.0)
+    }
+}
+
+impl PartialOrd for Symbol {
+    fn partial_cmp(&self, other: &Symbol) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq<str> for Symbol {
+    fn eq(&self, other: &str) -> bool {
+        self.0 == other
+    }
+}
+
+impl PartialEq<&str> for Symbol {
+    fn eq(&self, other: &&str) -> bool {
+        self.0 == *other
+    }
+}
+
+impl Deref for Symbol {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.0
+    }
+}
+
+impl From<&str> for Symbol {
+    fn from(name: &str) -> Symbol {
+        Symbol::new(name)
+    }
+}
+
+impl From<&String> for Symbol {
+    fn from(name: &String) -> Symbol {
+        Symbol::new(name)
+    }
+}
+
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.0)
+    }
+}
+
+impl fmt::Debug for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(self.0, f)
+    }
+}
+
+// Written as the name, so cached programs and saved state hold no pointers
+impl Serialize for Symbol {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for Symbol {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Symbol, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        Ok(Symbol::new(&name))
+    }
+}
@@ -1,19 +1,26 @@
-#[derive(Debug, Clone, PartialEq, Copy)]
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Copy, Serialize, Deserialize)]
+#[allow(clippy::upper_case_acronyms)]
 pub enum TokenType {
     // Literals
+    Integer,
     Number,
     String,
     Identifier,
     True,
     False,
+    Nil,
     
     // Operators
     Plus,
     Minus,
     Multiply,
     Divide,
+    IntDivide,
     Modulo,
     Equal,
+    DotEqual,
     
     // Comparison operators
     LessThan,
@@ -22,6 +29,8 @@ pub enum TokenType {
     GreaterThanEqual,
     EqualEqual,
     BangEqual,
+    QuestionQuestion,
+    Pipe,
     
     // Logical operators
     And,
@@ -37,27 +46,90 @@ pub enum TokenType {
     RightBrace,
     Comma,
     Semicolon,
+    Colon,
     Dot,
+    DotDot,
+    QuestionDot,
     
     // Keywords
     Fn,
+    Async,
+    Await,
+    Let,
     Return,
     If,
     Else,
     For,
     In,
     While,
+    Do,
+    Loop,
+    Break,
+    Continue,
     Transformer,
     Use,
+    Pub,
     
+    // `/// text` before a function or transformer; the literal is the text
+    DocComment,
+
     // End of file
     EOF,
 }
 
-#[derive(Debug, Clone)]
+// A byte range in the source text
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Token {
     pub token_type: TokenType,
     pub literal: String,
+    pub span: Span,
+    // 1-based position of the start of the span
+    pub line: usize,
+    pub column: usize,
+}
+
+impl TokenType {
+    /// Returns true for words reserved by the language, which can only be used
+    /// as names when written as raw identifiers (`` `use` ``).
+    pub fn is_keyword(self) -> bool {
+        matches!(
+            self,
+            TokenType::True | TokenType::False | TokenType::Nil
+                | TokenType::And | TokenType::Or | TokenType::Not
+                | TokenType::Fn | TokenType::Async | TokenType::Await | TokenType::Let | TokenType::Return
+                | TokenType::If | TokenType::Else | TokenType::For | TokenType::In | TokenType::While | TokenType::Do
+                | TokenType::Loop | TokenType::Break | TokenType::Continue
+                | TokenType::Transformer | TokenType::Use | TokenType::Pub
+        )
+    }
+
+    /// How a binary operator is written, e.g. "~/" for IntDivide.
+    pub fn operator_text(self) -> Option<&'static str> {
+        Some(match self {
+            TokenType::Or => "or",
+            TokenType::And => "and",
+            TokenType::QuestionQuestion => "??",
+            TokenType::EqualEqual => "==",
+            TokenType::BangEqual => "!=",
+            TokenType::LessThan => "<",
+            TokenType::LessThanEqual => "<=",
+            TokenType::GreaterThan => ">",
+            TokenType::GreaterThanEqual => ">=",
+            TokenType::Plus => "+",
+            TokenType::Minus => "-",
+            TokenType::Multiply => "*",
+            TokenType::Divide => "/",
+            TokenType::IntDivide => "~/",
+            TokenType::Modulo => "%",
+            _ => return None,
+        })
+    }
 }
 
 impl Token {
@@ -0,0 +1,456 @@
+use std::collections::HashMap;
+use crate::ast::Expr;
+use crate::symbol::Symbol;
+use crate::token::TokenType;
+use crate::stack;
+
+// Type names accepted in annotations. `any` (like a missing annotation) matches every value.
+const KNOWN_TYPES: &[&str] = &[
+    "number", "decimal", "string", "boolean", "array", "bytes", "map", "set", "tuple", "range", "result", "function", "transformer", "task", "sender", "receiver", "nil", "any",
+];
+
+struct Signature {
+    param_types: Vec<Option<String>>,
+    return_type: Option<String>,
+    is_async: bool,
+}
+
+// An advisory type checker. It walks the AST before execution, tracks the types
+// it can infer statically (literals, annotated parameters, results of annotated
+// functions) and reports obvious mismatches. Anything it can't infer is skipped,
+// so execution semantics stay fully dynamic.
+pub struct TypeChecker {
+    signatures: HashMap<String, Signature>,
+    // Known variable types; None records a variable whose type can't be inferred
+    scopes: Vec<HashMap<String, Option<String>>>,
+    // Name and declared return type of each function being checked
+    functions: Vec<(String, Option<String>)>,
+    diagnostics: Vec<String>,
+}
+
+impl TypeChecker {
+    pub fn check(program: &Expr) -> Vec<String> {
+        let mut checker = TypeChecker {
+            signatures: HashMap::new(),
+            scopes: vec![HashMap::new()],
+            functions: Vec::new(),
+            diagnostics: Vec::new(),
+        };
+
+        // Collect signatures first so calls can be checked before the definition
+        checker.collect_signatures(program);
+        checker.infer(program);
+
+        checker.diagnostics
+    }
+
+    fn collect_signatures(&mut self, expr: &Expr) {
+        stack::guard(|| match expr {
+            Expr::Function { name, params, param_types, return_type, body, is_async, .. } => {
+                for (param, param_type) in params.iter().zip(param_types) {
+                    if let Some(param_type) = param_type {
+                        self.check_type_name(param_type, &format!("parameter '{}' of '{}'", param, function_name(name)));
+                    }
+                }
+                if let Some(return_type) = return_type {
+                    self.check_type_name(return_type, &format!("return type of '{}'", function_name(name)));
+                }
+
+                // Function expressions are only called through variables, which have no signature
+                if let Some(name) = name {
+                    self.signatures.insert(name.to_string(), Signature {
+                        param_types: param_types.clone(),
+                        return_type: return_type.clone(),
+                        is_async: *is_async,
+                    });
+                }
+
+                for expr in body.iter() {
+                    self.collect_signatures(expr);
+                }
+            },
+            Expr::Block(expressions) => {
+                for expr in expressions {
+                    self.collect_signatures(expr);
+                }
+            },
+            Expr::Transformer { body, .. } => {
+                for expr in body.iter() {
+                    self.collect_signatures(expr);
+                }
+            },
+            Expr::If { then_branch, else_branch, .. } => {
+                self.collect_signatures(then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.collect_signatures(else_branch);
+                }
+            },
+            Expr::For { body, .. } | Expr::While { body, .. } | Expr::DoWhile { body, .. } | Expr::Loop { body, .. } => {
+                self.collect_signatures(body)
+            },
+            _ => {},
+        })
+    }
+
+    fn check_type_name(&mut self, type_name: &str, context: &str) {
+        if !KNOWN_TYPES.contains(&type_name) {
+            self.diagnostics.push(format!("Unknown type '{}' in {}", type_name, context));
+        }
+    }
+
+    // Returns the statically known type of an expression, reporting mismatches along the way
+    fn infer(&mut self, expr: &Expr) -> Option<String> {
+        stack::guard(|| match expr {
+            Expr::Int(_) | Expr::BigInt(_) | Expr::Number(_) => Some("number".to_string()),
+            Expr::String(_) => Some("string".to_string()),
+            Expr::Boolean(_) => Some("boolean".to_string()),
+            Expr::Nil => Some("nil".to_string()),
+            Expr::Array(elements) => {
+                for element in elements {
+                    self.infer(element);
+                }
+                Some("array".to_string())
+            },
+            Expr::Tuple(elements) => {
+                for element in elements {
+                    self.infer(element);
+                }
+                Some("tuple".to_string())
+            },
+            Expr::Map(entries) => {
+                for (_, value) in entries {
+                    self.infer(value);
+                }
+                Some("map".to_string())
+            },
+            Expr::Variable(name) | Expr::Local { name, .. } => self.lookup(name),
+            Expr::Binary { left, operator, right } => {
+                let left_type = self.infer(left);
+                let right_type = self.infer(right);
+                self.infer_binary(operator.token_type, &operator.literal, left_type, right_type)
+            },
+            Expr::Unary { operator, right } => {
+                let right_type = self.infer(right);
+                match operator.token_type {
+                    TokenType::Minus => {
+                        self.expect_number(&operator.literal, &right_type);
+                        Some("number".to_string())
+                    },
+                    TokenType::Not => Some("boolean".to_string()),
+                    _ => None,
+                }
+            },
+            Expr::Let { name, value, .. } => {
+                let value_type = match value {
+                    Some(value) => self.infer(value),
+                    None => Some("nil".to_string()),
+                };
+                self.declare(name, value_type.clone());
+                value_type
+            },
+            Expr::Assign { name, value, .. } => {
+                let value_type = self.infer(value);
+                self.assign(name, value_type.clone());
+                value_type
+            },
+            // The elements' types aren't tracked
+            Expr::TupleAssign { names, value, .. } => {
+                let value_type = self.infer(value);
+                for name in names {
+                    self.assign(name, None);
+                }
+                value_type
+            },
+            Expr::Call { callee, arguments } => self.infer_call(callee, arguments),
+            Expr::Await(task) => {
+                // The result of awaiting a task isn't known statically
+                self.infer(task).filter(|t| t != "task")
+            },
+            Expr::Function { name, params, param_types, return_type, body, .. } => {
+                if let Some(name) = name {
+                    self.declare(name, Some("function".to_string()));
+                }
+
+                self.scopes.push(HashMap::new());
+                for (param, param_type) in params.iter().zip(param_types) {
+                    let param_type = param_type.clone().filter(|t| t != "any");
+                    self.declare(param, param_type);
+                }
+
+                self.functions.push((function_name(name), return_type.clone()));
+                for expr in body.iter() {
+                    self.infer(expr);
+                }
+                self.functions.pop();
+                self.scopes.pop();
+
+                Some("function".to_string())
+            },
+            Expr::Return { value, .. } => {
+                let value_type = match value {
+                    Some(value) => self.infer(value),
+                    None => Some("nil".to_string()),
+                };
+
+                if let Some((name, Some(expected))) = self.functions.last() {
+                    if let Some(actual) = &value_type {
+                        if !Self::compatible(expected, actual) {
+                            let message = format!("'{}' is declared to return {}, but returns {}", name, expected, actual);
+                            self.diagnostics.push(message);
+                        }
+                    }
+                }
+
+                value_type
+            },
+            Expr::Block(expressions) => {
+                let mut result = None;
+                for expr in expressions {
+                    result = self.infer(expr);
+                }
+                result
+            },
+            Expr::If { condition, then_branch, else_branch } => {
+                self.expect_condition(condition);
+                self.infer_scoped(then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.infer_scoped(else_branch);
+                }
+                None
+            },
+            Expr::For { variable, second, iterable, step, body, .. } => {
+                self.infer(iterable);
+                if let Some(step) = step {
+                    self.infer(step);
+                }
+                self.scopes.push(HashMap::new());
+                self.declare(variable, None);
+                if let Some(second) = second {
+                    self.declare(second, None);
+                }
+                self.infer(body);
+                self.scopes.pop();
+                None
+            },
+            Expr::While { condition, body, .. } => {
+                self.expect_condition(condition);
+                self.infer_scoped(body);
+                None
+            },
+            Expr::Loop { body, .. } => {
+                self.infer_scoped(body);
+                None
+            },
+            Expr::Break { value, .. } => {
+                if let Some(value) = value {
+                    self.infer(value);
+                }
+                None
+            },
+            Expr::Continue { .. } => None,
+            Expr::DoWhile { body, condition, .. } => {
+                self.scopes.push(HashMap::new());
+                self.infer(body);
+                self.expect_condition(condition);
+                self.scopes.pop();
+                None
+            },
+            Expr::Index { object, index } => {
+                self.infer(object);
+                self.infer(index);
+                None
+            },
+            Expr::Member { object, .. } => {
+                self.infer(object);
+                None
+            },
+            Expr::IndexAssign { object, index, value } => {
+                self.infer(object);
+                self.infer(index);
+                self.infer(value)
+            },
+            Expr::Transformer { name, receiver, params, body, .. } => {
+                self.declare(name, Some("transformer".to_string()));
+
+                self.scopes.push(HashMap::new());
+                self.declare(receiver.as_deref().unwrap_or("applied"), None);
+                for param in params.iter() {
+                    self.declare(param, None);
+                }
+
+                // Transformers can't be annotated, so their returns aren't checked
+                self.functions.push((name.to_string(), None));
+                for expr in body.iter() {
+                    self.infer(expr);
+                }
+                self.functions.pop();
+                self.scopes.pop();
+
+                Some("transformer".to_string())
+            },
+            Expr::Apply { object, transformer, arguments, .. } => {
+                self.infer(object);
+                for argument in arguments {
+                    self.infer(argument);
+                }
+
+                // Result types of the built-in transformers
+                match transformer.as_str() {
+                    "to_string" | "to_json" | "to_json_pretty" | "to_fixed" | "to_precision" | "to_base64" | "to_hex"
+                    | "trim" | "upper" | "lower" | "join" => Some("string".to_string()),
+                    "to_number" | "parse_number" | "round" | "len" => Some("number".to_string()),
+                    "to_bool" | "parse_bool" => Some("boolean".to_string()),
+                    "to_array" | "parallel_map" | "sort_by" | "sort_key" | "unique" | "flatten" | "chunk" | "split" | "lines" | "chars" | "enumerate" => Some("array".to_string()),
+                    "group_by" => Some("map".to_string()),
+                    "to_bytes" | "bytes" | "from_base64" | "from_hex" => Some("bytes".to_string()),
+                    name if name.starts_with("is_") => Some("boolean".to_string()),
+                    _ => None,
+                }
+            },
+            Expr::Use { .. } | Expr::Pragma { .. } => None,
+            Expr::Assert { arguments, .. } => {
+                for argument in arguments {
+                    self.infer(argument);
+                }
+                Some("nil".to_string())
+            },
+        })
+    }
+
+    fn infer_scoped(&mut self, body: &Expr) {
+        self.scopes.push(HashMap::new());
+        self.infer(body);
+        self.scopes.pop();
+    }
+
+    fn infer_binary(&mut self, operator: TokenType, literal: &str, left: Option<String>, right: Option<String>) -> Option<String> {
+        match operator {
+            TokenType::Plus => match (left.as_deref(), right.as_deref()) {
+                (Some("string"), _) | (_, Some("string")) => Some("string".to_string()),
+                (Some("number"), Some("number")) => Some("number".to_string()),
+                (Some("decimal"), Some("number" | "decimal")) | (Some("number"), Some("decimal")) => Some("decimal".to_string()),
+                (Some("array"), Some("array")) => Some("array".to_string()),
+                (Some(l), Some(r)) => {
+                    self.diagnostics.push(format!("Operator '{}' can't be applied to {} and {}", literal, l, r));
+                    None
+                },
+                _ => None,
+            },
+            TokenType::Minus | TokenType::Multiply | TokenType::Divide | TokenType::IntDivide | TokenType::Modulo => {
+                self.expect_number(literal, &left);
+                self.expect_number(literal, &right);
+                // Integers keep the other operand a decimal
+                if left.as_deref() == Some("decimal") || right.as_deref() == Some("decimal") {
+                    Some("decimal".to_string())
+                } else {
+                    Some("number".to_string())
+                }
+            },
+            TokenType::LessThan
+            | TokenType::LessThanEqual
+            | TokenType::GreaterThan
+            | TokenType::GreaterThanEqual
+            | TokenType::EqualEqual
+            | TokenType::BangEqual
+            | TokenType::And
+            | TokenType::Or => Some("boolean".to_string()),
+            TokenType::QuestionQuestion => match left.as_deref() {
+                Some("nil") => right,
+                Some(_) => left,
+                None => None,
+            },
+            _ => None,
+        }
+    }
+
+    fn infer_call(&mut self, callee: &str, arguments: &[Expr]) -> Option<String> {
+        let argument_types: Vec<Option<String>> = arguments.iter().map(|argument| self.infer(argument)).collect();
+
+        // A function defined in the program shadows the builtin of the same name
+        match callee {
+            _ if self.signatures.contains_key(callee) => {},
+            "typeof" | "input" | "format" | "sha256" | "md5" | "crc32" | "path_join" | "chr" => return Some("string".to_string()),
+            "random" | "random_int" | "now" | "ord" => return Some("number".to_string()),
+            "range" => return Some("range".to_string()),
+            "set" => return Some("set".to_string()),
+            "globals" => return Some("map".to_string()),
+            "defined" => return Some("boolean".to_string()),
+            "ok" | "err" | "try" | "read_bytes" | "write_bytes" | "list_dir" | "mkdir" | "remove_file" | "from_json" => return Some("result".to_string()),
+            "decimal" => return Some("decimal".to_string()),
+            "channel" | "zip" | "array_of" | "fn_params" | "args" => return Some("array".to_string()),
+            "spawn" => return Some("task".to_string()),
+            "print" | "write" | "print_raw" | "sleep" | "send" | "close" | "push" | "on" => return Some("nil".to_string()),
+            _ => {},
+        }
+
+        let signature = self.signatures.get(callee)?;
+
+        let mut diagnostics = Vec::new();
+        if argument_types.len() > signature.param_types.len() {
+            diagnostics.push(format!(
+                "'{}' takes {} arguments, but {} were given",
+                callee,
+                signature.param_types.len(),
+                argument_types.len()
+            ));
+        }
+
+        for (i, (expected, actual)) in signature.param_types.iter().zip(&argument_types).enumerate() {
+            if let (Some(expected), Some(actual)) = (expected, actual) {
+                if !Self::compatible(expected, actual) {
+                    diagnostics.push(format!("Argument {} of '{}' expects {}, got {}", i + 1, callee, expected, actual));
+                }
+            }
+        }
+
+        // Calling an async function gives a task; the annotation describes its result
+        let return_type = if signature.is_async {
+            Some("task".to_string())
+        } else {
+            signature.return_type.clone().filter(|t| t != "any")
+        };
+        self.diagnostics.extend(diagnostics);
+        return_type
+    }
+
+    fn expect_number(&mut self, operator: &str, actual: &Option<String>) {
+        if let Some(actual) = actual {
+            if actual != "number" && actual != "decimal" {
+                self.diagnostics.push(format!("Operator '{}' expects numbers, got {}", operator, actual));
+            }
+        }
+    }
+
+    fn expect_condition(&mut self, condition: &Expr) {
+        if let Some(actual) = self.infer(condition) {
+            if actual != "boolean" {
+                self.diagnostics.push(format!("Condition must be a boolean, got {}", actual));
+            }
+        }
+    }
+
+    fn compatible(expected: &str, actual: &str) -> bool {
+        expected == "any" || expected == actual
+    }
+
+    fn lookup(&self, name: &str) -> Option<String> {
+        self.scopes.iter().rev().find_map(|scope| scope.get(name)).cloned().flatten()
+    }
+
+    // Unknown types are recorded too, so they shadow what outer scopes know
+    fn declare(&mut self, name: &str, value_type: Option<String>) {
+        let scope = self.scopes.last_mut().expect("type checker without a scope");
+        scope.insert(name.to_string(), value_type);
+    }
+
+    fn assign(&mut self, name: &str, value_type: Option<String>) {
+        // Update the innermost scope that knows the name, otherwise define it here
+        let index = self.scopes.iter().rposition(|scope| scope.contains_key(name)).unwrap_or(self.scopes.len() - 1);
+        self.scopes[index].insert(name.to_string(), value_type);
+    }
+}
+
+// How messages refer to a function; one without a name is a function expression
+fn function_name(name: &Option<Symbol>) -> String {
+    name.map_or_else(|| "fn(...)".to_string(), |name| name.to_string())
+}
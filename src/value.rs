@@ -1,32 +1,292 @@
+use std::any::Any;
+use std::collections::BTreeMap;
 use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use crate::channel::Channel;
+pub use crate::bigint::BigInt;
+pub use crate::set::Set;
+pub use rust_decimal::Decimal;
 use crate::ast::Expr;
+use crate::environment::{Environment, Layout};
+use crate::symbol::Symbol;
+use crate::heap::{self, Handle};
+use crate::interpreter::Interpreter;
+use crate::stack;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub enum Value {
+    Int(i64),
+    // An integer beyond the range of Int, made by arithmetic that overflows it.
+    // Never holds a value that would fit an Int.
+    BigInt(BigInt),
     Number(f64),
-    String(String),
+    // An exact base-10 number made by decimal()
+    Decimal(Decimal),
+    // Cloning a string value shares its text instead of copying it
+    String(Text),
     Boolean(bool),
-    Array(Vec<Value>),
+    // Arrays and maps live on the heap: copies of the value share the container
+    Array(Handle<Vec<Value>>),
+    // Raw binary data, e.g. from read_bytes()
+    Bytes(Vec<u8>),
+    Map(Handle<BTreeMap<String, Value>>),
+    // Distinct values made by set(), shared like arrays and maps
+    Set(Handle<Set>),
+    // A fixed group of values written `(a, b)`, which can't be changed
+    Tuple(Tuple),
+    Range {
+        start: i64,
+        end: i64,
+    },
+    // Bodies are shared with the AST, so cloning a function value is cheap
     Function {
-        params: Vec<String>,
-        body: Vec<Expr>,
+        params: Arc<[Symbol]>,
+        // The slots of a call's scope: the parameters, then the body's locals
+        layout: Arc<Layout>,
+        body: Arc<[Expr]>,
+        is_async: bool,
+        // Copies of the scopes it was defined in, if that was inside a call,
+        // for names its caller doesn't have (see Environment::capture)
+        captured: Option<Arc<Environment>>,
     },
     Transformer {
-        params: Vec<String>,
-        body: Vec<Expr>,
+        // The name the applied value is bound to in the body
+        receiver: Symbol,
+        params: Arc<[Symbol]>,
+        // The receiver's slot, then those of the parameters and the body's locals
+        layout: Arc<Layout>,
+        body: Arc<[Expr]>,
     },
+    // `ok(value)` or `err(error)`, made by those builtins and by try()
+    Result(Outcome),
+    // Transformers composed with `then`, applied in order
+    Composed(Vec<Value>),
+    // A function running on another thread, started by spawn()
+    Task(Arc<Mutex<TaskState>>),
+    // The two endpoints returned by channel()
+    Sender(Arc<Channel>),
+    Receiver(Arc<Channel>),
+    // An object belonging to the host program, such as a database connection,
+    // which M code can store and pass around but not look inside
+    Native(NativeObject),
+    // A Rust function added with Interpreter::register_function()
+    NativeFunction(NativeFunction),
+    // A function built into the interpreter, such as print()
+    Builtin(Builtin),
+    #[default]
     Nil,
 }
 
+/// The text of a string value. Copies of a string share it, and the interpreter
+/// appends to a string no other value shares in place, so `s = s + x` in a loop
+/// doesn't copy the whole string each time.
+#[derive(Debug, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Text(Arc<String>);
+
+impl Text {
+    // The string itself, if nothing else shares it
+    pub(crate) fn get_mut(&mut self) -> Option<&mut String> {
+        Arc::get_mut(&mut self.0)
+    }
+
+    pub(crate) fn ptr_eq(&self, other: &Text) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl std::ops::Deref for Text {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<String> for Text {
+    fn from(text: String) -> Self {
+        Text(Arc::new(text))
+    }
+}
+
+impl From<&str> for Text {
+    fn from(text: &str) -> Self {
+        Text(Arc::new(text.to_string()))
+    }
+}
+
+impl fmt::Display for Text {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// The elements of a tuple value, shared by its copies.
+#[derive(Clone, PartialEq)]
+pub struct Tuple(Arc<[Value]>);
+
+impl std::ops::Deref for Tuple {
+    type Target = [Value];
+
+    fn deref(&self) -> &[Value] {
+        &self.0
+    }
+}
+
+impl From<Vec<Value>> for Tuple {
+    fn from(elements: Vec<Value>) -> Self {
+        Tuple(elements.into())
+    }
+}
+
+impl FromIterator<Value> for Tuple {
+    fn from_iter<I: IntoIterator<Item = Value>>(elements: I) -> Self {
+        Tuple(elements.into_iter().collect())
+    }
+}
+
+impl fmt::Debug for Tuple {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+// Tuples and results nest like arrays do, so the last copy of one hands what
+// it holds to the heap's queue to be freed (see Handle's Drop) rather than
+// freeing it recursively
+impl Drop for Tuple {
+    fn drop(&mut self) {
+        if let Some(elements) = Arc::get_mut(&mut self.0).filter(|elements| !elements.is_empty()) {
+            let elements: Vec<Value> = elements.iter_mut().map(std::mem::take).collect();
+            heap::release(Box::new(elements));
+        }
+    }
+}
+
+/// The value or error held by `ok(value)` or `err(error)`, shared by its copies.
+#[derive(Clone, PartialEq)]
+pub struct Outcome(Arc<Result<Value, Value>>);
+
+impl Outcome {
+    pub fn new(result: Result<Value, Value>) -> Self {
+        Outcome(Arc::new(result))
+    }
+
+    pub fn into_inner(mut self) -> Result<Value, Value> {
+        match Arc::get_mut(&mut self.0) {
+            Some(result) => std::mem::replace(result, Ok(Value::Nil)),
+            None => (*self.0).clone(),
+        }
+    }
+}
+
+impl std::ops::Deref for Outcome {
+    type Target = Result<Value, Value>;
+
+    fn deref(&self) -> &Result<Value, Value> {
+        &self.0
+    }
+}
+
+impl fmt::Debug for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl Drop for Outcome {
+    fn drop(&mut self) {
+        if let Some(result) = Arc::get_mut(&mut self.0) {
+            let (Ok(value) | Err(value)) = std::mem::replace(result, Ok(Value::Nil));
+            if !matches!(value, Value::Nil) {
+                heap::release(Box::new(value));
+            }
+        }
+    }
+}
+
+/// A host object wrapped by Value::native().
+#[derive(Clone)]
+pub struct NativeObject {
+    type_name: &'static str,
+    object: Arc<dyn Any + Send + Sync>,
+}
+
+impl fmt::Debug for NativeObject {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "NativeObject({})", self.type_name)
+    }
+}
+
+/// The signature of functions registered with Interpreter::register_function().
+pub type NativeFn = dyn Fn(Vec<Value>) -> Result<Value, String> + Send + Sync;
+
+#[derive(Clone)]
+pub struct NativeFunction {
+    pub name: Arc<str>,
+    pub function: Arc<NativeFn>,
+}
+
+impl fmt::Debug for NativeFunction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "NativeFunction({})", self.name)
+    }
+}
+
+// The signature of the interpreter's builtins, which can use the interpreter
+pub(crate) type BuiltinFn = fn(&mut Interpreter, Vec<Value>) -> Result<Value, String>;
+
+/// A function built into the interpreter, such as print() or range(). Every
+/// interpreter starts with them defined; see Interpreter::remove_builtin().
+#[derive(Clone, Copy)]
+pub struct Builtin {
+    pub name: &'static str,
+    pub(crate) function: BuiltinFn,
+}
+
+impl fmt::Debug for Builtin {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Builtin({})", self.name)
+    }
+}
+
+#[derive(Debug)]
+pub enum TaskState {
+    // Started by spawn() on a thread of its own
+    Running(JoinHandle<Result<Value, String>>),
+    // A call of an async function, waiting for the interpreter that made it
+    // to run it
+    Queued(Box<AsyncCall>),
+    // An async call the interpreter is part way through, further up its stack
+    Started,
+    // join() caches the result, so a task can be joined more than once
+    Finished(Result<Value, String>),
+}
+
+// What an async call runs with: the variables it was called with, like any
+// other call, and its arguments
+#[derive(Debug)]
+pub struct AsyncCall {
+    pub environment: Environment,
+    pub params: Arc<[Symbol]>,
+    pub layout: Arc<Layout>,
+    pub captured: Option<Arc<Environment>>,
+    pub body: Arc<[Expr]>,
+    pub arguments: Vec<Value>,
+}
+
 impl fmt::Display for Value {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match self {
+        stack::guard(|| match self {
+            Value::Int(n) => write!(f, "{}", n),
+            Value::BigInt(n) => write!(f, "{}", n),
             Value::Number(n) => write!(f, "{}", n),
+            Value::Decimal(d) => write!(f, "{}", d),
             Value::String(s) => write!(f, "{}", s),
             Value::Boolean(b) => write!(f, "{}", b),
             Value::Array(elements) => {
                 write!(f, "[")?;
-                for (i, element) in elements.iter().enumerate() {
+                for (i, element) in elements.borrow().iter().enumerate() {
                     if i > 0 {
                         write!(f, ", ")?;
                     }
@@ -34,9 +294,291 @@ impl fmt::Display for Value {
                 }
                 write!(f, "]")
             },
+            Value::Bytes(bytes) => {
+                write!(f, "<bytes")?;
+                for byte in bytes {
+                    write!(f, " {:02x}", byte)?;
+                }
+                write!(f, ">")
+            },
+            Value::Map(entries) => {
+                write!(f, "{{")?;
+                for (i, (key, value)) in entries.borrow().iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}: {}", key, value)?;
+                }
+                write!(f, "}}")
+            },
+            Value::Set(set) => {
+                write!(f, "set([")?;
+                for (i, element) in set.borrow().iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", element)?;
+                }
+                write!(f, "])")
+            },
+            Value::Tuple(elements) => {
+                write!(f, "(")?;
+                for (i, element) in elements.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", element)?;
+                }
+                // `(1,)` as it is written, rather than `(1)`
+                if elements.len() == 1 {
+                    write!(f, ",")?;
+                }
+                write!(f, ")")
+            },
+            Value::Range { start, end } => write!(f, "range({}, {})", start, end),
+            Value::Result(result) => match &**result {
+                Ok(value) => write!(f, "ok({})", value),
+                Err(error) => write!(f, "err({})", error),
+            },
             Value::Function { .. } => write!(f, "<function>"),
-            Value::Transformer { .. } => write!(f, "<transformer>"),
+            Value::Transformer { .. } | Value::Composed(_) => write!(f, "<transformer>"),
+            Value::Task(_) => write!(f, "<task>"),
+            Value::Sender(_) => write!(f, "<sender>"),
+            Value::Receiver(_) => write!(f, "<receiver>"),
+            Value::Native(native) => write!(f, "<{}>", native.type_name),
+            Value::NativeFunction(_) | Value::Builtin(_) => write!(f, "<function>"),
             Value::Nil => write!(f, "nil"),
+        })
+    }
+}
+
+// Structural equality used by the == and != operators.
+//
+// Values of different types are never equal, and nil is only equal to nil.
+// Arrays, maps, sets, tuples and results compare element by element (recursively), though
+// a tuple never equals an array, and a range is equal to any range or array that
+// produces the same elements. Functions and transformers have no identity, so
+// they never compare equal; tasks, channel endpoints and host objects are only
+// equal to themselves.
+impl PartialEq for Value {
+    fn eq(&self, other: &Value) -> bool {
+        stack::guard(|| match (self, other) {
+            (Value::Int(l), Value::Int(r)) => l == r,
+            (Value::Number(l), Value::Number(r)) => l == r,
+            // Integers and floats compare by numeric value
+            (Value::Int(l), Value::Number(r)) | (Value::Number(r), Value::Int(l)) => *l as f64 == *r,
+            (Value::BigInt(l), Value::BigInt(r)) => l == r,
+            (Value::BigInt(l), Value::Number(r)) | (Value::Number(r), Value::BigInt(l)) => l.to_f64() == *r,
+            // Decimals compare by value (1.0 == 1.00) and with integers, but never equal a float
+            (Value::Decimal(l), Value::Decimal(r)) => l == r,
+            (Value::Decimal(l), Value::Int(r)) | (Value::Int(r), Value::Decimal(l)) => *l == Decimal::from(*r),
+            (Value::String(l), Value::String(r)) => l == r,
+            (Value::Boolean(l), Value::Boolean(r)) => l == r,
+            (Value::Nil, Value::Nil) => true,
+            (Value::Task(l), Value::Task(r)) => Arc::ptr_eq(l, r),
+            (Value::Sender(l), Value::Sender(r)) | (Value::Receiver(l), Value::Receiver(r)) => Arc::ptr_eq(l, r),
+            (Value::Native(l), Value::Native(r)) => Arc::ptr_eq(&l.object, &r.object),
+            (Value::Array(l), Value::Array(r)) => l.ptr_eq(r) || *l.borrow() == *r.borrow(),
+            (Value::Bytes(l), Value::Bytes(r)) => l == r,
+            (Value::Map(l), Value::Map(r)) => l.ptr_eq(r) || *l.borrow() == *r.borrow(),
+            (Value::Set(l), Value::Set(r)) => l.ptr_eq(r) || *l.borrow() == *r.borrow(),
+            (Value::Tuple(l), Value::Tuple(r)) => l == r,
+            (Value::Result(l), Value::Result(r)) => l == r,
+            (Value::Range { start: ls, end: le }, Value::Range { start: rs, end: re }) => {
+                // All empty ranges produce the same (empty) sequence
+                (ls >= le && rs >= re) || (ls == rs && le == re)
+            },
+            (Value::Range { start, end }, Value::Array(elements))
+            | (Value::Array(elements), Value::Range { start, end }) => {
+                let elements = elements.borrow();
+                elements.len() as i128 == (*end as i128 - *start as i128).max(0)
+                    && elements.iter().zip(*start..*end).all(|(element, i)| *element == Value::Int(i))
+            },
+            _ => false,
+        })
+    }
+}
+
+impl Value {
+    /// Materializes a lazy range into the equivalent array.
+    pub fn range_to_array(start: i64, end: i64) -> Result<Value, String> {
+        Value::range_elements(start, end).map(Value::array)
+    }
+
+    /// Returns the integers in a range, or an error when there are too many to
+    /// hold in memory.
+    pub fn range_elements(start: i64, end: i64) -> Result<Vec<Value>, String> {
+        let len = usize::try_from((end as i128 - start as i128).max(0)).unwrap_or(usize::MAX);
+        let mut elements = Vec::new();
+        elements.try_reserve_exact(len).map_err(|_| format!("range({}, {}) is too large to hold in memory", start, end))?;
+        elements.extend((start..end).map(Value::Int));
+        Ok(elements)
+    }
+
+    /// Returns the elements `for` visits in an array, set, tuple, range, string
+    /// or bytes value, and None for other values.
+    pub fn elements(&self) -> Result<Option<Vec<Value>>, String> {
+        Ok(match self {
+            Value::Array(elements) => Some(elements.get()),
+            Value::Set(set) => Some(set.borrow().iter().cloned().collect()),
+            Value::Tuple(elements) => Some(elements.to_vec()),
+            Value::Range { start, end } => Some(Value::range_elements(*start, *end)?),
+            Value::String(s) => Some(s.chars().map(|c| Value::String(c.to_string().into())).collect()),
+            Value::Bytes(bytes) => Some(bytes.iter().map(|&b| Value::Int(b as i64)).collect()),
+            _ => None,
+        })
+    }
+
+    /// Wraps a host object so M code can hold it. `typeof()` reports it as
+    /// `type_name`, and only the host can get the object back, with as_native().
+    pub fn native<T: Any + Send + Sync>(type_name: &'static str, object: T) -> Value {
+        Value::Native(NativeObject { type_name, object: Arc::new(object) })
+    }
+
+    /// Returns the host object in a native value, if it is a `T`.
+    pub fn as_native<T: Any>(&self) -> Option<&T> {
+        match self {
+            Value::Native(native) => native.object.downcast_ref(),
+            _ => None,
+        }
+    }
+
+    /// Allocates a new array on the heap.
+    pub fn array(elements: Vec<Value>) -> Value {
+        Value::Array(Handle::new(elements))
+    }
+
+    /// Allocates a new map on the heap.
+    pub fn map(entries: BTreeMap<String, Value>) -> Value {
+        Value::Map(Handle::new(entries))
+    }
+
+    /// Allocates a new set on the heap.
+    pub fn set(set: Set) -> Value {
+        Value::Set(Handle::new(set))
+    }
+
+    /// Wraps the outcome of an operation that can fail as `ok(value)` or
+    /// `err(message)`.
+    pub fn result(result: Result<Value, String>) -> Value {
+        Value::Result(Outcome::new(result.map_err(|message| Value::String(message.into()))))
+    }
+
+    /// Returns true if this value is the given array or map, or contains it at any depth.
+    pub fn refers_to(&self, container: &Value) -> bool {
+        let same = match (self, container) {
+            (Value::Array(l), Value::Array(r)) => l.ptr_eq(r),
+            (Value::Map(l), Value::Map(r)) => l.ptr_eq(r),
+            _ => false,
+        };
+
+        same || stack::guard(|| match self {
+            Value::Array(elements) => elements.borrow().iter().any(|element| element.refers_to(container)),
+            Value::Map(entries) => entries.borrow().values().any(|value| value.refers_to(container)),
+            Value::Tuple(elements) => elements.iter().any(|element| element.refers_to(container)),
+            Value::Result(result) => match &**result {
+                Ok(value) | Err(value) => value.refers_to(container),
+            },
+            _ => false,
+        })
+    }
+
+    /// Copies a value along with every array, map and set it contains, so the copy
+    /// shares no containers with the original.
+    pub fn deep_copy(&self) -> Value {
+        stack::guard(|| match self {
+            Value::Array(elements) => Value::array(elements.borrow().iter().map(Value::deep_copy).collect()),
+            Value::Map(entries) => Value::map(
+                entries.borrow().iter().map(|(key, value)| (key.clone(), value.deep_copy())).collect(),
+            ),
+            Value::Set(set) => Value::set(set.get()),
+            Value::Tuple(elements) => Value::Tuple(elements.iter().map(Value::deep_copy).collect()),
+            Value::Function { params, layout, body, is_async, captured } => Value::Function {
+                params: params.clone(),
+                layout: layout.clone(),
+                body: body.clone(),
+                is_async: *is_async,
+                captured: captured.as_deref().map(|captured| Arc::new(captured.deep_copy())),
+            },
+            Value::Result(result) => Value::Result(Outcome::new(match &**result {
+                Ok(value) => Ok(value.deep_copy()),
+                Err(error) => Err(error.deep_copy()),
+            })),
+            value => value.clone(),
+        })
+    }
+
+    /// Returns the name reported by `typeof()`. Integers and floats are both
+    /// "number", while decimals are "decimal".
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Int(_) | Value::BigInt(_) | Value::Number(_) => "number",
+            Value::Decimal(_) => "decimal",
+            Value::String(_) => "string",
+            Value::Boolean(_) => "boolean",
+            Value::Array(_) => "array",
+            Value::Bytes(_) => "bytes",
+            Value::Map(_) => "map",
+            Value::Set(_) => "set",
+            Value::Tuple(_) => "tuple",
+            Value::Range { .. } => "range",
+            Value::Result(_) => "result",
+            Value::Function { .. } | Value::NativeFunction(_) | Value::Builtin(_) => "function",
+            Value::Transformer { .. } | Value::Composed(_) => "transformer",
+            Value::Task(_) => "task",
+            Value::Sender(_) => "sender",
+            Value::Receiver(_) => "receiver",
+            Value::Native(native) => native.type_name,
+            Value::Nil => "nil",
+        }
+    }
+
+    /// Returns the value's truthiness, as used by `to_bool()` and conditions. Nil,
+    /// zero, empty containers, errors and the strings "", "false" and "0" are false.
+    pub fn is_truthy(&self) -> bool {
+        match self {
+            Value::Int(n) => *n != 0,
+            // Never zero, which is an Int
+            Value::BigInt(_) => true,
+            Value::Number(n) => *n != 0.0,
+            Value::Decimal(d) => !d.is_zero(),
+            Value::String(s) => !(s.is_empty() || &**s == "false" || &**s == "0"),
+            Value::Boolean(b) => *b,
+            Value::Array(arr) => !arr.borrow().is_empty(),
+            Value::Bytes(bytes) => !bytes.is_empty(),
+            Value::Map(entries) => !entries.borrow().is_empty(),
+            Value::Set(set) => !set.borrow().is_empty(),
+            Value::Tuple(elements) => !elements.is_empty(),
+            Value::Range { start, end } => start < end,
+            Value::Result(result) => result.is_ok(),
+            Value::Function { .. } | Value::Task(_) | Value::Sender(_) | Value::Receiver(_) => true,
+            Value::Transformer { .. } | Value::Composed(_) => true,
+            Value::Native(_) | Value::NativeFunction(_) | Value::Builtin(_) => true,
+            Value::Nil => false,
+        }
+    }
+
+    /// Returns true for values that can be applied with dot notation or `apply()`.
+    pub fn is_transformer(&self) -> bool {
+        matches!(self, Value::Transformer { .. } | Value::Composed(_))
+    }
+
+    /// Returns the numeric value of an integer or float, promoting integers to floats.
+    pub fn as_number(&self) -> Option<f64> {
+        match self {
+            Value::Int(n) => Some(*n as f64),
+            Value::BigInt(n) => Some(n.to_f64()),
+            Value::Number(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    /// Returns the value as an array index. Floats are accepted when they hold a whole number.
+    pub fn as_index(&self) -> Option<usize> {
+        match self {
+            Value::Int(n) => usize::try_from(*n).ok(),
+            Value::Number(n) if n.fract() == 0.0 && *n >= 0.0 => Some(*n as usize),
+            _ => None,
         }
     }
 }
@@ -0,0 +1,68 @@
+// The browser API, compiled with the `wasm` feature. JavaScript calls run()
+// with a program's source; what the program prints is returned, or passed to
+// the function given to setPrintHook() as it is printed. Files and input()
+// aren't available, and neither are spawn() and sleep(), which need threads.
+use std::cell::RefCell;
+use std::sync::Arc;
+use wasm_bindgen::prelude::*;
+use crate::host::Host;
+use crate::interpreter::Interpreter;
+use crate::lexer::Lexer;
+use crate::parser::Parser;
+
+thread_local! {
+    static PRINT_HOOK: RefCell<Option<js_sys::Function>> = const { RefCell::new(None) };
+    // Output waiting to be returned by run() when there is no print hook
+    static OUTPUT: RefCell<String> = const { RefCell::new(String::new()) };
+}
+
+struct BrowserHost;
+
+impl Host for BrowserHost {
+    fn write(&self, text: &str) -> Result<(), String> {
+        PRINT_HOOK.with(|hook| match &*hook.borrow() {
+            Some(hook) => hook
+                .call1(&JsValue::NULL, &JsValue::from_str(text))
+                .map(|_| ())
+                .map_err(|e| format!("Print hook failed: {:?}", e)),
+            None => {
+                OUTPUT.with(|output| output.borrow_mut().push_str(text));
+                Ok(())
+            },
+        })
+    }
+
+    fn read_line(&self) -> Result<String, String> {
+        Err("input() is not available in the browser".to_string())
+    }
+
+    fn now(&self) -> Option<f64> {
+        Some(js_sys::Date::now())
+    }
+}
+
+/// Calls `hook` with each piece of output as the program prints it, instead of
+/// collecting the output for run() to return. Pass nothing to remove the hook.
+#[wasm_bindgen(js_name = setPrintHook)]
+pub fn set_print_hook(hook: Option<js_sys::Function>) {
+    PRINT_HOOK.with(|current| *current.borrow_mut() = hook);
+}
+
+/// Runs a program with a fresh interpreter and returns what it printed,
+/// followed by `Error: ...` if it failed.
+#[wasm_bindgen]
+pub fn run(source: &str) -> String {
+    let mut interpreter = Interpreter::new();
+    interpreter.set_host(Arc::new(BrowserHost));
+
+    let result = Lexer::new(source)
+        .tokenize()
+        .and_then(|tokens| Parser::new(tokens).parse())
+        .and_then(|program| interpreter.evaluate(&program));
+
+    let mut output = OUTPUT.with(|output| std::mem::take(&mut *output.borrow_mut()));
+    if let Err(e) = result {
+        output += &format!("Error: {}\n", e);
+    }
+    output
+}
@@ -2,7 +2,7 @@
 
 // Array functions
 fn create_array(size, default_value) {
-    arr = []
+    let arr = []
     for i in range(0, size) {
         arr = arr + [default_value]
     }
@@ -15,7 +15,7 @@ fn array_get(arr, index) {
 
 fn array_set(arr, index, value) {
     // Create a new array with the value at the specified index
-    result = []
+    let result = []
     for i in range(0, arr.length()) {
         if i == index {
             result = result + [value]
@@ -28,7 +28,7 @@ fn array_set(arr, index, value) {
 
 // Array transformers
 transformer length() {
-    count = 0
+    let count = 0
     for item in applied {
         count = count + 1
     }
@@ -36,7 +36,7 @@ transformer length() {
 }
 
 transformer sum() {
-    total = 0
+    let total = 0
     for item in applied {
         total = total + item
     }
@@ -44,8 +44,8 @@ transformer sum() {
 }
 
 transformer average() {
-    total = 0
-    count = 0
+    let total = 0
+    let count = 0
     
     for item in applied {
         total = total + item
@@ -60,7 +60,7 @@ transformer average() {
 }
 
 transformer map(func) {
-    result = []
+    let result = []
     for item in applied {
         result = result + [func(item)]
     }
@@ -68,7 +68,7 @@ transformer map(func) {
 }
 
 transformer filter(predicate) {
-    result = []
+    let result = []
     for item in applied {
         if predicate(item) {
             result = result + [item]
@@ -78,7 +78,7 @@ transformer filter(predicate) {
 }
 
 transformer reverse() {
-    result = []
+    let result = []
     for item in applied {
         result = [item] + result
     }
@@ -88,9 +88,9 @@ transformer reverse() {
 transformer sort() {
     // This is a simplified implementation that creates a new sorted array
     // We'll use a simple selection sort algorithm
-    arr = applied
-    n = arr.length()
-    result = []
+    let arr = applied
+    let n = arr.length()
+    let result = []
     
     // Create a copy of the array
     for item in arr {
@@ -99,8 +99,8 @@ transformer sort() {
     
     // Simple selection sort
     for i in range(0, n) {
-        min_idx = i
-        min_val = result[i]
+        let min_idx = i
+        let min_val = result[i]
         
         // Find the minimum element
         for j in range(i + 1, n) {
@@ -113,7 +113,7 @@ transformer sort() {
         // Swap the found minimum element with the element at index i
         if min_idx != i {
             // We can't directly modify the array, so we'll create a new one
-            temp = result[i]
+            let temp = result[i]
             result = array_set(result, i, result[min_idx])
             result = array_set(result, min_idx, temp)
         }
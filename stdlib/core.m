@@ -7,22 +7,8 @@ use "stdlib/array.m"
 
 // Additional core functions and transformers
 
-// Type checking functions to be implemented in interpreter
-// fn is_number(value) {
-//     return value == value + 0
-// }
-// 
-// fn is_string(value) {
-//     return value == value + ""
-// }
-//
-// fn is_array(value) {
-//     return value == value
-// }
-// fn is_function(value) {
-//     return value == value
-// }
-//
+// Type checking is built into the interpreter: typeof(value) returns the
+// type name, and transformers such as value.is_number() test for a type
 
 // Print array function
 
@@ -27,7 +27,7 @@ fn pow(base, exponent) {
         return 1
     }
     
-    result = 1
+    let result = 1
     for i in range(0, exponent) {
         result = result * base
     }
@@ -40,7 +40,7 @@ fn factorial(n) {
         return 1
     }
     
-    result = 1
+    let result = 1
     for i in range(2, n + 1) {
         result = result * i
     }
@@ -77,8 +77,8 @@ transformer sqrt() {
         return 0
     }
     
-    x = applied
-    y = 1
+    let x = applied
+    let y = 1
     
     // Just a few iterations for approximation
     for i in range(0, 10) {
@@ -6,7 +6,7 @@ fn concat(a, b) {
 }
 
 fn repeat(str, times) {
-    result = ""
+    let result = ""
     for i in range(0, times) {
         result = result + str
     }
@@ -15,7 +15,7 @@ fn repeat(str, times) {
 
 // String transformers
 transformer length() {
-    count = 0
+    let count = 0
     for c in applied {
         count = count + 1
     }
@@ -36,7 +36,7 @@ transformer lowercase() {
 
 transformer reverse() {
     // Reverse a string
-    result = ""
+    let result = ""
     for c in applied {
         result = c + result
     }
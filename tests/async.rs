@@ -0,0 +1,104 @@
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use m_lang::interpreter::Interpreter;
+use m_lang::lexer::Lexer;
+use m_lang::parser::Parser;
+use m_lang::value::Value;
+
+fn evaluate(interpreter: &mut Interpreter, source: &str) -> Result<(), String> {
+    let tokens = Lexer::new(source).tokenize()?;
+    let program = Parser::new(tokens).parse()?;
+    interpreter.evaluate(&program).map(|_| ())
+}
+
+type Log = Arc<Mutex<Vec<(String, thread::ThreadId)>>>;
+
+// An interpreter with log(), which records what it's given and the thread it
+// was called on
+fn logging() -> (Interpreter, Log) {
+    let mut interpreter = Interpreter::new();
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let log = seen.clone();
+    interpreter.register_function("log", move |arguments| {
+        log.lock().unwrap().push((arguments.iter().map(Value::to_string).collect::<Vec<_>>().join(" "), thread::current().id()));
+        Ok(Value::Nil)
+    });
+    (interpreter, seen)
+}
+
+#[test]
+fn async_calls_run_on_the_calling_thread_when_awaited() {
+    let (mut interpreter, seen) = logging();
+    evaluate(&mut interpreter, "
+        async fn square(n) {
+            log(\"square\", n)
+            n * n
+        }
+        a = square(3)
+        b = square(4)
+        log(\"called\")
+        assert_eq(await b, 16)
+        assert_eq(await a + await a, 18)
+        assert_eq(join(a), 9)
+        assert_eq(await 5, 5)
+    ").unwrap();
+
+    let seen = seen.lock().unwrap();
+    let messages: Vec<_> = seen.iter().map(|(message, _)| message.as_str()).collect();
+    assert_eq!(messages, ["called", "square 4", "square 3"]);
+    assert!(seen.iter().all(|(_, id)| *id == thread::current().id()));
+}
+
+#[test]
+fn sleeping_runs_queued_calls_so_timers_overlap() {
+    let (mut interpreter, seen) = logging();
+    let start = Instant::now();
+    evaluate(&mut interpreter, "
+        async fn slow_square(n) {
+            sleep(100)
+            log(\"done\", n)
+            n * n
+        }
+        a = slow_square(3)
+        b = slow_square(4)
+        c = slow_square(5)
+        assert_eq(await a + await b + await c, 50)
+    ").unwrap();
+
+    assert!(start.elapsed() < Duration::from_millis(250), "{:?}", start.elapsed());
+    let seen = seen.lock().unwrap();
+    let messages: Vec<_> = seen.iter().map(|(message, _)| message.as_str()).collect();
+    // Each call ran inside the sleep of the one before it
+    assert_eq!(messages, ["done 5", "done 4", "done 3"]);
+    assert!(seen.iter().all(|(_, id)| *id == thread::current().id()));
+}
+
+#[test]
+fn async_errors_are_reported_when_awaited() {
+    let (mut interpreter, _) = logging();
+    evaluate(&mut interpreter, "
+        async fn broken() { 1 / nil }
+        task = broken()
+        sleep(1)
+    ").unwrap();
+    let error = evaluate(&mut interpreter, "await task").unwrap_err();
+    assert!(error.starts_with("Task failed"), "{}", error);
+    // and again, from the cached result
+    assert!(evaluate(&mut interpreter, "await task").unwrap_err().starts_with("Task failed"));
+
+    // A task paused in sleep() can't finish until the call it's running does
+    let error = evaluate(&mut interpreter, "
+        tasks = []
+        async fn first() {
+            sleep(20)
+            1
+        }
+        async fn second() { await tasks[0] }
+        push(tasks, first())
+        waiting = second()
+        assert_eq(await tasks[0], 1)
+        await waiting
+    ").unwrap_err();
+    assert!(error.contains("Cannot await a task that is waiting for this one to finish"), "{}", error);
+}
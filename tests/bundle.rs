@@ -0,0 +1,49 @@
+use std::fs;
+use std::process::Command;
+
+#[test]
+fn bundled_programs_run_without_their_sources() {
+    let dir = std::env::temp_dir().join(format!("m_lang_bundle_{}", std::process::id()));
+    fs::create_dir_all(dir.join("lib")).unwrap();
+    fs::write(dir.join("tool.m"), "use \"lib/square.m\"\nprint(square(7), args())\n").unwrap();
+    fs::write(dir.join("lib/square.m"), "fn square(x) { x * x }\n").unwrap();
+
+    let status = Command::new(env!("CARGO_BIN_EXE_m_lang"))
+        .args(["bundle", "tool.m", "-o", "tool"])
+        .current_dir(&dir)
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    // The bundle runs from elsewhere, where the sources don't exist, and its
+    // arguments all go to the program
+    let output = Command::new(dir.join("tool")).args(["x", "--y"]).current_dir(std::env::temp_dir()).output().unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "49 [x, --y]\n");
+}
+
+#[test]
+fn bundling_a_project_finds_imports_in_its_module_paths() {
+    let dir = std::env::temp_dir().join(format!("m_lang_bundle_project_{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(dir.join("src")).unwrap();
+    fs::create_dir_all(dir.join("m_modules/shapes")).unwrap();
+    fs::write(dir.join("m.toml"), "[package]\nmain = \"src/main.m\"\n\n[modules]\npaths = [\"src\"]\n").unwrap();
+    fs::write(dir.join("src/main.m"), "use \"greeting.m\"\nuse \"shapes/area.m\"\nprint(greeting(), area(3, 4))\n").unwrap();
+    fs::write(dir.join("src/greeting.m"), "fn greeting() { \"Hi\" }\n").unwrap();
+    // Installed dependencies import through the module paths too
+    fs::write(dir.join("m_modules/shapes/area.m"), "use \"shapes/multiply.m\"\nfn area(w, h) { multiply(w, h) }\n").unwrap();
+    fs::write(dir.join("m_modules/shapes/multiply.m"), "fn multiply(a, b) { a * b }\n").unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_m_lang"))
+        .args(["bundle", "-o", "tool"])
+        .current_dir(&dir)
+        .output()
+        .unwrap();
+    assert_eq!(String::from_utf8_lossy(&output.stderr), "");
+
+    let output = Command::new(dir.join("tool")).current_dir(std::env::temp_dir()).output().unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(String::from_utf8_lossy(&output.stderr), "");
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "Hi 12\n");
+}
@@ -0,0 +1,77 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use m_lang::cache;
+use m_lang::lexer::Lexer;
+use m_lang::parser::Parser;
+
+fn parse(source: &str) -> m_lang::ast::Expr {
+    Parser::new(Lexer::new(source).tokenize().unwrap()).parse().unwrap()
+}
+
+// Runs main.m in the directory with --cache and returns what it printed
+fn run_cached(dir: &Path) -> String {
+    let output = Command::new(env!("CARGO_BIN_EXE_m_lang")).arg("--cache").arg("main.m").current_dir(dir).output().unwrap();
+    let printed = String::from_utf8_lossy(&output.stdout) + String::from_utf8_lossy(&output.stderr);
+    printed.lines().filter(|line| !line.starts_with("Running file:")).collect::<Vec<_>>().join("\n")
+}
+
+fn project(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("m_lang_cache_{}_{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("main.m"), "use \"lib.m\"\nprint(value())\n").unwrap();
+    dir
+}
+
+#[test]
+fn caches_are_written_and_used_while_the_source_is_unchanged() {
+    let dir = project("used");
+    let lib = "fn value() { \"parsed\" }\n";
+    fs::write(dir.join("lib.m"), lib).unwrap();
+
+    assert_eq!(run_cached(&dir), "parsed");
+    assert!(dir.join("main.mc").exists() && dir.join("lib.mc").exists());
+    assert_eq!(cache::load(&dir.join("lib.m"), lib, false).map(|ast| ast.to_string()), Some(parse(lib).to_string()));
+
+    // A cache recorded for this exact source is run instead of parsing it again
+    cache::store(&dir.join("lib.m"), lib, false, &parse("fn value() { \"cached\" }"));
+    assert_eq!(run_cached(&dir), "cached");
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn caches_are_ignored_when_the_source_changes() {
+    let dir = project("invalidated");
+    fs::write(dir.join("lib.m"), "fn value() { 1 }\n").unwrap();
+    assert_eq!(run_cached(&dir), "1");
+
+    fs::write(dir.join("lib.m"), "fn value() { 2 }\n").unwrap();
+    assert_eq!(run_cached(&dir), "2");
+
+    // The stale cache was replaced with one for the new source
+    let lib = dir.join("lib.m");
+    assert!(cache::load(&lib, "fn value() { 1 }\n", false).is_none());
+    assert!(cache::load(&lib, "fn value() { 2 }\n", false).is_some());
+    // and a cache written without -O isn't used with it
+    assert!(cache::load(&lib, "fn value() { 2 }\n", true).is_none());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn corrupt_caches_fall_back_to_parsing() {
+    let dir = project("corrupt");
+    let lib = "fn value() { \"fine\" }\n";
+    fs::write(dir.join("lib.m"), lib).unwrap();
+    fs::write(dir.join("lib.mc"), "{\"version\": truncated").unwrap();
+    fs::write(dir.join("main.mc"), [0xff, 0xfe, 0x00]).unwrap();
+
+    assert_eq!(run_cached(&dir), "fine");
+    // Both were rewritten
+    assert!(cache::load(&dir.join("lib.m"), lib, false).is_some());
+    assert!(cache::load(&dir.join("main.m"), &fs::read_to_string(dir.join("main.m")).unwrap(), false).is_some());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,54 @@
+mod common;
+
+use common::run;
+
+#[test]
+fn builtin_transformers_chain_on_literals() {
+    let source = "
+print(\"  hi  \".trim().upper().split(\"\"))
+print(\"A-B-C\".lower().split(\"-\").slice(1).to_json())
+";
+    assert_eq!(run("builtin_chain", source), "[H, I]\n[\"b\",\"c\"]");
+}
+
+#[test]
+fn chains_mix_builtin_and_user_transformers() {
+    let source = "
+transformer exclaim(times) {
+    result = applied
+    for i in range(0, times) {
+        result = result + \"!\"
+    }
+    result
+}
+transformer first() {
+    applied[0]
+}
+words = \" hello world \".trim().split(\" \")
+print(words.first().upper().exclaim(2).split(\"\").slice(4).to_json())
+print(3.to_string().exclaim(1).lower().to_array().first().trim())
+";
+    assert_eq!(run("mixed_chain", source), "[\"O\",\"!\",\"!\"]\n3!");
+}
+
+#[test]
+fn chained_results_do_not_change_the_original() {
+    let source = "
+transformer double() {
+    applied * 2
+}
+x = 5
+y = x.double().double().to_string().split(\"\")
+x .= double().double()
+print(x, y)
+";
+    assert_eq!(run("chain_pure", source), "20 [2, 0]");
+}
+
+#[test]
+fn errors_name_the_failing_link() {
+    let source = "
+print(\" a \".trim().to_number().upper())
+";
+    assert_eq!(run("chain_error", source), "Error: Cannot apply upper() to number");
+}
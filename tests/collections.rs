@@ -0,0 +1,75 @@
+use m_lang::interpreter::Interpreter;
+use m_lang::lexer::Lexer;
+use m_lang::parser::Parser;
+
+fn evaluate(interpreter: &mut Interpreter, source: &str) -> Result<(), String> {
+    let tokens = Lexer::new(source).tokenize()?;
+    let program = Parser::new(tokens).parse()?;
+    interpreter.evaluate(&program).map(|_| ())
+}
+
+#[test]
+fn group_by_keeps_elements_in_order() {
+    evaluate(&mut Interpreter::new(), "
+        fn parity(n) {
+            if n % 2 == 0 { return \"even\" }
+            \"odd\"
+        }
+        transformer initial() { return applied.slice(0, 1) }
+
+        assert_eq([1, 2, 3, 4, 5].group_by(parity), {\"even\": [2, 4], \"odd\": [1, 3, 5]})
+        assert_eq([\"ant\", \"bee\", \"asp\"].group_by(initial), {\"a\": [\"ant\", \"asp\"], \"b\": [\"bee\"]})
+        assert_eq([].group_by(parity), {})
+    ").unwrap();
+
+    let error = evaluate(&mut Interpreter::new(), "
+        fn itself(x) { return x }
+        [1].group_by(itself)
+    ").unwrap_err();
+    assert!(error.contains("group_by() keys must be strings, got number"), "{}", error);
+}
+
+#[test]
+fn unique_flatten_and_chunk_make_new_arrays() {
+    evaluate(&mut Interpreter::new(), "
+        values = [3, 1, 3, 1.0, [1], [1], \"a\", nil, \"a\", nil]
+        assert_eq(values.unique(), [3, 1, [1], \"a\", nil])
+        assert_eq(values[2], 3)
+
+        nested = [[1, 2], [3], 4, (5, 6), [[7]]]
+        assert_eq(nested.flatten(), [1, 2, 3, 4, 5, 6, [7]])
+        assert_eq(nested[0], [1, 2])
+        assert_eq(range(0, 7).chunk(3), [[0, 1, 2], [3, 4, 5], [6]])
+        assert_eq(\"abc\".chunk(2), [[\"a\", \"b\"], [\"c\"]])
+        assert_eq([].chunk(2), [])
+    ").unwrap();
+
+    let error = evaluate(&mut Interpreter::new(), "[1, 2].chunk(0)").unwrap_err();
+    assert!(error.contains("must be a positive integer"), "{}", error);
+}
+
+#[test]
+fn aggregates_need_numbers() {
+    evaluate(&mut Interpreter::new(), "
+        scores = [72, 95, 88, 95.0]
+        assert_eq(scores.sum(), 350.0)
+        assert_eq([72, 95, 88].avg(), 85)
+        assert_eq(scores.max(), 95)
+        assert(scores.max().is_integer())
+        assert_eq(set([3, 1, 2]).min(), 1)
+        assert_eq(range(1, 5).sum(), 10)
+        assert_eq([].sum(), 0)
+        assert_eq([decimal(\"0.1\"), decimal(\"0.2\")].sum(), decimal(\"0.3\"))
+
+        transformer max(limit) {
+            if applied > limit { return limit }
+            applied
+        }
+        assert_eq(12.max(10), 10)
+    ").unwrap();
+
+    let error = evaluate(&mut Interpreter::new(), "[1, \"2\"].sum()").unwrap_err();
+    assert!(error.contains("sum() needs numbers, got string at index 1"), "{}", error);
+    let error = evaluate(&mut Interpreter::new(), "[].avg()").unwrap_err();
+    assert!(error.contains("Cannot take avg() of an empty array"), "{}", error);
+}
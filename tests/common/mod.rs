@@ -0,0 +1,18 @@
+use std::fs;
+use std::process::Command;
+
+// Runs a program with the interpreter binary and returns what it printed
+// (errors included), without the "Running file" banner
+pub fn run(name: &str, source: &str) -> String {
+    let path = std::env::temp_dir().join(format!("m_lang_{}_{}.m", name, std::process::id()));
+    fs::write(&path, source).unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_m_lang")).arg(&path).output().unwrap();
+    fs::remove_file(&path).unwrap();
+
+    let printed = String::from_utf8_lossy(&output.stdout) + String::from_utf8_lossy(&output.stderr);
+    printed
+        .lines()
+        .filter(|line| !line.starts_with("Running file:"))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
@@ -0,0 +1,76 @@
+use std::fs;
+use std::process::Command;
+use m_lang::interpreter::Interpreter;
+use m_lang::lexer::Lexer;
+use m_lang::parser::Parser;
+use m_lang::value::Value;
+
+fn evaluate(interpreter: &mut Interpreter, source: &str) -> Result<(), String> {
+    let tokens = Lexer::new(source).tokenize()?;
+    let program = Parser::new(tokens).parse()?;
+    interpreter.evaluate(&program).map(|_| ())
+}
+
+#[test]
+fn let_declares_and_redeclares() {
+    let mut interpreter = Interpreter::new();
+    evaluate(&mut interpreter, "
+        let count = 1
+        let empty
+        assert_eq(empty, nil)
+
+        // Declaring a name again replaces it, and can use the old value
+        let count = count + 1
+        assert_eq(count, 2)
+
+        // Without --require-let, plain assignment still defines names
+        total = count * 10
+    ").unwrap();
+    assert_eq!(interpreter.get_variable("count"), Some(Value::Int(2)));
+    assert_eq!(interpreter.get_variable("total"), Some(Value::Int(20)));
+}
+
+#[test]
+fn require_let_rejects_assignments_to_undeclared_names() {
+    let mut interpreter = Interpreter::new();
+    interpreter.set_require_let(true);
+    evaluate(&mut interpreter, "
+        let total = 0
+        for x in [1, 2, 3] {
+            total = total + x
+        }
+        fn add(a, b) {
+            let sum = a + b
+            sum = sum * 1
+            sum
+        }
+        assert_eq(add(total, 1), 7)
+    ").unwrap();
+
+    // A typo no longer creates a new variable
+    let error = evaluate(&mut interpreter, "totl = total + 1").unwrap_err();
+    assert_eq!(error, "Assignment to undeclared variable 'totl' (declare it with 'let')");
+    assert!(interpreter.get_variable("totl").is_none());
+
+    // Parameters and loop variables count as declared
+    evaluate(&mut interpreter, "fn f(n) { n = n + 1 }\nfor i in range(0, 2) { i = i * 2 }\nassert_eq(f(1), 2)").unwrap();
+}
+
+#[test]
+fn the_require_let_option_makes_undeclared_assignments_errors() {
+    let path = std::env::temp_dir().join(format!("m_lang_require_let_{}.m", std::process::id()));
+    fs::write(&path, "let declared = 1\nprint(declared)\nundeclared = 2\nprint(undeclared)\n").unwrap();
+    let strict = Command::new(env!("CARGO_BIN_EXE_m_lang")).arg("--require-let").arg(&path).output().unwrap();
+    let lenient = Command::new(env!("CARGO_BIN_EXE_m_lang")).arg(&path).output().unwrap();
+    fs::remove_file(&path).unwrap();
+
+    assert_eq!(strict.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&strict.stdout).ends_with("\n1\n"));
+    assert_eq!(
+        String::from_utf8_lossy(&strict.stderr),
+        "Error: Assignment to undeclared variable 'undeclared' (declare it with 'let')\n",
+    );
+
+    assert!(lenient.status.success());
+    assert!(String::from_utf8_lossy(&lenient.stdout).ends_with("\n1\n2\n"));
+}
@@ -0,0 +1,68 @@
+mod common;
+
+use std::thread;
+use m_lang::interpreter::Interpreter;
+use m_lang::lexer::Lexer;
+use m_lang::parser::Parser;
+use common::run;
+
+#[test]
+fn deeply_nested_expressions_evaluate() {
+    let source = format!("print({}1{})", "(".repeat(20_000), ")".repeat(20_000));
+    assert_eq!(run("parens", &source), "1");
+
+    let source = format!("print({}1)", "1 + ".repeat(20_000));
+    assert_eq!(run("sum", &source), "20001");
+}
+
+#[test]
+fn deeply_nested_arrays_can_be_built_and_freed() {
+    let source = "
+a = []
+for i in range(0, 100000) {
+    a = [a]
+}
+print(typeof(a), a == a)
+";
+    assert_eq!(run("arrays", source), "array true");
+}
+
+#[test]
+fn runaway_recursion_is_an_error() {
+    let source = "
+fn forever(n) {
+    return forever(n + 1)
+}
+forever(0)
+";
+    assert_eq!(run("recursion", source), "Error: Maximum call depth of 1000 exceeded");
+}
+
+// Through the library rather than the CLI, which runs programs on a thread
+// with a large stack: hosts call it from threads with the default one
+#[test]
+fn deep_programs_run_and_are_freed_on_a_default_sized_thread() {
+    // A line per level, since the lexer counts columns from the start of the line
+    let depth = 100_000;
+    let sources = [
+        format!("x = {}1{}", "[\n".repeat(depth), "]".repeat(depth)),
+        format!("x = {}1{}", "(\n".repeat(depth), ",)".repeat(depth)),
+        format!("x = {}1{}", "ok(\n".repeat(depth), ")".repeat(depth)),
+        format!("x = {}1", "1 +\n".repeat(depth)),
+        format!("x = 0\n{}x = 1{}", "if true {\n".repeat(depth), "\n}".repeat(depth)),
+        // Calls nest evaluations, as deep as the call depth limit allows
+        "fn down(n) { if n == 0 { return 1 } [down(n - 1)] }\nx = down(999)".to_string(),
+    ];
+
+    thread::spawn(move || {
+        for source in sources {
+            let tokens = Lexer::new(&source).tokenize().unwrap();
+            let program = Parser::new(tokens).parse().unwrap();
+            let mut interpreter = Interpreter::new();
+            let value = interpreter.evaluate(&program).unwrap();
+            assert_eq!(Some(value), interpreter.get_variable("x"));
+            drop(interpreter);
+            drop(program);
+        }
+    }).join().unwrap();
+}
@@ -0,0 +1,92 @@
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+use serde_json::{json, Value};
+
+// Runs a program with --json-diagnostics from its directory, which imports
+// are found in, and returns each line it printed on stderr
+fn run(path: &Path) -> Vec<Value> {
+    let output = Command::new(env!("CARGO_BIN_EXE_m_lang"))
+        .arg("--json-diagnostics")
+        .arg(path)
+        .current_dir(path.parent().unwrap())
+        .output()
+        .unwrap();
+    String::from_utf8_lossy(&output.stderr).lines().map(|line| serde_json::from_str(line).unwrap()).collect()
+}
+
+// The diagnostics of a program in a file of its own, without the file they name
+fn diagnostics(name: &str, source: &str) -> Vec<Value> {
+    let path = std::env::temp_dir().join(format!("m_lang_diagnostics_{}_{}.m", name, std::process::id()));
+    fs::write(&path, source).unwrap();
+    let diagnostics = run(&path);
+    fs::remove_file(&path).unwrap();
+
+    diagnostics
+        .into_iter()
+        .map(|mut diagnostic| {
+            // The file is a temporary one
+            assert_eq!(diagnostic["file"], json!(path.display().to_string()));
+            diagnostic.as_object_mut().unwrap().remove("file");
+            diagnostic
+        })
+        .collect()
+}
+
+#[test]
+fn errors_are_printed_as_json() {
+    assert_eq!(diagnostics("lex", "x = 1\ny = @"), [json!({
+        "line": 2, "column": 5, "kind": "lex", "message": "Unexpected character '@' at line 2, column 5",
+    })]);
+    assert_eq!(diagnostics("parse", "x = (1\n\nprint(x)"), [json!({
+        "line": 3, "column": 1, "kind": "parse", "message": "Expected ')' after expression",
+    })]);
+    // The column of the token the error is about, not of the one after it
+    assert_eq!(diagnostics("target", "x = [1, 2]\nprint(1 = 2)"), [json!({
+        "line": 2, "column": 7, "kind": "parse", "message": "Invalid assignment target",
+    })]);
+    assert_eq!(diagnostics("callee", "x = [1, 2]\ny = first(x)(2)"), [json!({
+        "line": 2, "column": 13, "kind": "parse", "message": "Expected function name",
+    })]);
+    assert_eq!(diagnostics("runtime", "x = 1\n  assert(x == 2)"), [json!({
+        "line": 2, "column": 3, "kind": "runtime", "message": "Assertion failed at line 2, column 3: 1 == 2",
+    })]);
+    // Found before running, so the program doesn't run and fail on it again
+    assert_eq!(diagnostics("undefined", "print(y)"), [json!({
+        "line": null, "column": null, "kind": "resolve", "message": "Undefined variable 'y'",
+    })]);
+}
+
+#[test]
+fn errors_in_imported_files_name_the_file() {
+    let dir = std::env::temp_dir().join(format!("m_lang_diagnostics_imports_{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("half.m"), "fn half(n) {\n  let h = n / 2\n  h\n}\n").unwrap();
+    fs::write(dir.join("broken.m"), "a = 1\nb = \"x\" @\n").unwrap();
+    fs::write(dir.join("failing.m"), "a = 1\n  b = a / nil\n").unwrap();
+    let file = |name: &str| json!(fs::canonicalize(dir.join(name)).unwrap().display().to_string());
+
+    // In a function the file defines, called from the main file
+    fs::write(dir.join("main.m"), "use \"half.m\"\n\ny = half(nil)\n").unwrap();
+    assert_eq!(run(&dir.join("main.m")), [json!({
+        "file": file("half.m"), "line": 2, "column": 3, "kind": "runtime",
+        "message": "Invalid operands for operator: Divide",
+    })]);
+
+    // Reading the file
+    fs::write(dir.join("main.m"), "x = 1\nuse \"broken.m\"\n").unwrap();
+    let diagnostics = run(&dir.join("main.m"));
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0]["file"], file("broken.m"));
+    assert_eq!((&diagnostics[0]["line"], &diagnostics[0]["column"]), (&json!(2), &json!(9)));
+
+    // Running it
+    fs::write(dir.join("main.m"), "x = 1\nuse \"failing.m\"\n").unwrap();
+    let diagnostics = run(&dir.join("main.m"));
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0]["file"], file("failing.m"));
+    assert_eq!((&diagnostics[0]["line"], &diagnostics[0]["column"]), (&json!(2), &json!(3)));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,44 @@
+use m_lang::interpreter::Interpreter;
+use m_lang::lexer::Lexer;
+use m_lang::parser::Parser;
+
+fn evaluate(interpreter: &mut Interpreter, source: &str) -> Result<(), String> {
+    let tokens = Lexer::new(source).tokenize()?;
+    let program = Parser::new(tokens).parse()?;
+    interpreter.evaluate(&program).map(|_| ())
+}
+
+#[test]
+fn eval_runs_in_the_current_scope() {
+    evaluate(&mut Interpreter::new(), "
+        rate = 3
+        assert_eq(eval(\"rate * 14\"), 42)
+        eval(\"total = rate + 1\")
+        assert_eq(total, 4)
+        assert_eq(eval(\"\"), nil)
+
+        fn scoped() {
+            local = 1
+            eval(\"local = local + 1\")
+            local
+        }
+        assert_eq(scoped(), 2)
+    ").unwrap();
+}
+
+#[test]
+fn eval_code_cannot_leave_its_caller() {
+    let error = evaluate(&mut Interpreter::new(), "
+        fn f() {
+            eval(\"return 1\")
+            2
+        }
+        f()
+    ").unwrap_err();
+    assert!(error.contains("'return' outside of a function"), "{}", error);
+
+    let error = evaluate(&mut Interpreter::new(), "eval(\"1 +\")").unwrap_err();
+    assert!(error.starts_with("In eval(): "), "{}", error);
+    let error = evaluate(&mut Interpreter::new(), "eval(1)").unwrap_err();
+    assert!(error.contains("eval() takes a string of M code, got number"), "{}", error);
+}
@@ -0,0 +1,81 @@
+use std::sync::{Arc, Mutex};
+use m_lang::interpreter::Interpreter;
+use m_lang::lexer::Lexer;
+use m_lang::parser::Parser;
+use m_lang::value::Value;
+
+fn evaluate(interpreter: &mut Interpreter, source: &str) -> Result<(), String> {
+    let tokens = Lexer::new(source).tokenize()?;
+    let program = Parser::new(tokens).parse()?;
+    interpreter.evaluate(&program).map(|_| ())
+}
+
+#[test]
+fn emitted_events_call_their_handlers_in_order() {
+    let mut interpreter = Interpreter::new();
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let log = seen.clone();
+    interpreter.register_function("log", move |arguments| {
+        log.lock().unwrap().push(arguments.iter().map(Value::to_string).collect::<Vec<_>>().join(" "));
+        Ok(Value::Nil)
+    });
+
+    evaluate(&mut interpreter, "
+        fn on_tick(frame) {
+            log(\"tick\", frame)
+            frame * 2
+        }
+        fn also_on_tick() {
+            log(\"again\")
+        }
+        on(\"tick\", on_tick)
+        on(\"tick\", also_on_tick)
+    ").unwrap();
+
+    let results = interpreter.emit("tick", vec![Value::Int(1), Value::Int(99)]).unwrap();
+    assert_eq!(results, [Value::Int(2), Value::Nil]);
+    assert!(interpreter.emit("nothing", Vec::new()).unwrap().is_empty());
+
+    // The host can subscribe functions defined in M too
+    let handler = interpreter.get_variable("also_on_tick").unwrap();
+    interpreter.on("quit", handler).unwrap();
+    interpreter.emit("quit", Vec::new()).unwrap();
+    assert_eq!(*seen.lock().unwrap(), ["tick 1", "again", "again"]);
+
+    assert!(interpreter.on("quit", Value::Int(1)).is_err());
+    let error = evaluate(&mut interpreter, "on(\"tick\", 5)").unwrap_err();
+    assert!(error.contains("must be a function"), "{}", error);
+}
+
+#[test]
+fn handlers_keep_the_scope_they_were_defined_in() {
+    let mut interpreter = Interpreter::new();
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let log = seen.clone();
+    interpreter.register_function("print", move |arguments| {
+        log.lock().unwrap().push(arguments.iter().map(Value::to_string).collect::<Vec<_>>().join(" "));
+        Ok(Value::Nil)
+    });
+
+    evaluate(&mut interpreter, "
+        fn setup(greeting) {
+            fn handler(x) { print(greeting, x) }
+            on(\"evt\", handler)
+        }
+        setup(\"hello\")
+
+        fn countdown_from(start) {
+            let label = \"left\"
+            fn count(n) {
+                print(label, n)
+                if n > 0 { count(n - 1) }
+            }
+            on(\"count\", fn() { count(start) })
+        }
+        countdown_from(2)
+    ").unwrap();
+
+    interpreter.emit("evt", vec![Value::Int(1)]).unwrap();
+    interpreter.emit("count", Vec::new()).unwrap();
+    assert_eq!(*seen.lock().unwrap(), ["hello 1", "left 2", "left 1", "left 0"]);
+}
@@ -0,0 +1,110 @@
+use std::fs;
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+
+// Writes the programs to a directory of their own and runs the interpreter there
+fn run_files(name: &str, files: &[(&str, &str)], args: &[&str]) -> Output {
+    let dir = std::env::temp_dir().join(format!("m_lang_files_{}_{}", name, std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    for (file, source) in files {
+        fs::write(dir.join(file), source).unwrap();
+    }
+    let output = Command::new(env!("CARGO_BIN_EXE_m_lang")).args(args).current_dir(&dir).output().unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+    output
+}
+
+const FILES: &[(&str, &str)] = &[
+    ("a.m", "x = 1\nprint(\"a\")\n"),
+    ("b.m", "print(x)\n"),
+    ("c.m", "print(\"c\")\n"),
+];
+
+#[test]
+fn each_file_runs_on_its_own() {
+    let output = run_files("isolated", FILES, &["a.m", "b.m", "c.m"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "Running file: a.m\na\nRunning file: b.m\nRunning file: c.m\nc\n");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    // b.m doesn't run, as nothing defines x before it does
+    assert!(stderr.contains("Error: Undefined variable 'x'"), "{}", stderr);
+    assert!(stderr.ends_with("Error: 1 of 3 files failed: b.m\n"), "{}", stderr);
+}
+
+#[test]
+fn files_can_share_an_interpreter() {
+    let output = run_files("shared", FILES, &["--shared-state", "a.m", "b.m", "c.m"]);
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "Running file: a.m\na\nRunning file: b.m\n1\nRunning file: c.m\nc\n");
+    // What a.m defined isn't reported as undefined in b.m
+    assert_eq!(String::from_utf8_lossy(&output.stderr), "");
+}
+
+#[test]
+fn a_failing_program_exits_with_an_error() {
+    let output = run_files("failing", &[("main.m", "print(1 / nil)\n")], &[]);
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(String::from_utf8_lossy(&output.stderr), "Error: Invalid operands for operator: Divide\n");
+
+    let output = run_files("single", FILES, &["lint", "a.m", "b.m"]);
+    assert_eq!(output.status.code(), Some(2));
+    assert_eq!(String::from_utf8_lossy(&output.stderr), "Error: lint expects a single file\n");
+}
+
+#[test]
+fn arguments_after_the_separator_go_to_the_program() {
+    let program = &[("main.m", "print(typeof(args()), args().len())\nfor arg in args() { print(arg) }\n")];
+    let output = run_files("args", program, &["main.m", "--", "input.csv", "--fast", "-O", "--"]);
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "Running file: main.m\narray 4\ninput.csv\n--fast\n-O\n--\n");
+
+    let output = run_files("no_args", program, &[]);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "Running file: main.m\narray 0\n");
+}
+
+#[test]
+fn input_keeps_spaces_but_not_the_line_ending() {
+    let path = std::env::temp_dir().join(format!("m_lang_files_input_{}.m", std::process::id()));
+    fs::write(&path, "print(input(\"\").len())\nprint(input(\"\").len())\n").unwrap();
+    let mut child = Command::new(env!("CARGO_BIN_EXE_m_lang"))
+        .arg(&path)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(b"  two \t\r\nthree \n").unwrap();
+    let output = child.wait_with_output().unwrap();
+    fs::remove_file(&path).unwrap();
+    assert!(String::from_utf8_lossy(&output.stdout).ends_with("\n7\n6\n"), "{}", String::from_utf8_lossy(&output.stdout));
+}
+
+#[test]
+fn file_builtins_give_results() {
+    let program = "
+        assert_eq(mkdir(\"out/nested\"), ok(nil))
+        assert_eq(write_bytes(\"out/nested/data.bin\", \"hi\".to_bytes()), ok(nil))
+        assert_eq(read_bytes(\"out/nested/data.bin\").unwrap().to_string(), \"hi\")
+        assert_eq(list_dir(\"out/nested\"), ok([\"data.bin\"]))
+        assert_eq(remove_file(\"out/nested/data.bin\"), ok(nil))
+        assert_eq(list_dir(\"out/nested\"), ok([]))
+
+        // Failures are errs the program can handle
+        for failed in [read_bytes(\"missing.txt\"), list_dir(\"missing\"), remove_file(\"missing.txt\"), write_bytes(\"missing/x.bin\", \"\".to_bytes())] {
+            assert(failed.is_err())
+            print(failed.unwrap_err().split(\" '\")[0])
+        }
+        print(read_bytes(\"missing.txt\").unwrap_or(\"default\"))
+        read_bytes(42)
+    ";
+    let output = run_files("results", &[("main.m", program)], &[]);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "\
+Running file: main.m
+Failed to read file
+Failed to list directory
+Failed to remove file
+Failed to write file
+default
+");
+    // Misuse still stops the program
+    assert_eq!(String::from_utf8_lossy(&output.stderr), "Error: Argument to read_bytes() must be a string\n");
+}
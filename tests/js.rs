@@ -0,0 +1,84 @@
+use std::path::Path;
+use m_lang::js;
+use m_lang::lexer::Lexer;
+use m_lang::parser::Parser;
+
+fn compile(source: &str) -> Result<String, String> {
+    let tokens = Lexer::new(source).tokenize()?;
+    let program = Parser::new(tokens).parse()?;
+    js::compile(&program, Path::new("."))
+}
+
+// The compiled program, without the runtime in front of it
+fn program(source: &str) -> String {
+    let output = compile(source).unwrap();
+    let start = output.find("\nlet ").expect("the program declares its variables");
+    output[start + 1..].to_string()
+}
+
+#[test]
+fn variables_are_declared_once_per_block() {
+    assert_eq!(
+        program("total = 0\nfor i in range(0, 3) {\n    total = total + i\n    let last = i\n}\nprint(total)"),
+        "let total;\n\
+         total = 0;\n\
+         for (let i of $m.iter($m.range(0, 3))) {\n  \
+           let last;\n  \
+           total = $m.add(total, i);\n  \
+           last = i;\n\
+         }\n\
+         $m.print(total);\n",
+    );
+}
+
+#[test]
+fn functions_return_their_last_expression() {
+    assert_eq!(
+        program("fn square(x) { x * x }\ntransformer twice() { applied * 2 }\nnew = 3.twice()"),
+        "let square, twice, $new;\n\
+         square = function (x) {\n  \
+           return (x * x);\n\
+         };\n\
+         twice = $m.transformers[\"twice\"] = $m.transformer(function (applied) {\n  \
+           return (applied * 2);\n\
+         });\n\
+         $new = $m.apply(3, \"twice\", []);\n",
+    );
+}
+
+#[test]
+fn function_expressions_are_values() {
+    assert_eq!(
+        program("f = fn(a, b) { a - b }\nprint([2, 1].sort_by(fn(a, b) {\n    let d = a - b\n    d\n}))"),
+        "let f;\n\
+         f = (function (a, b) {\n  \
+           return (a - b);\n\
+         });\n\
+         $m.print($m.apply([2, 1], \"sort_by\", [(function (a, b) {\n  \
+           let d;\n  \
+           d = (a - b);\n  \
+           return d;\n\
+         })]));\n",
+    );
+}
+
+#[test]
+fn definitions_shadow_builtins() {
+    assert_eq!(
+        program("say = print\nfn range(n) { n }\nsay(range(3))"),
+        "let say, range;\n\
+         say = $m.print;\n\
+         range = function (n) {\n  \
+           return n;\n\
+         };\n\
+         say(range(3));\n",
+    );
+}
+
+#[test]
+fn loop_values_are_an_error() {
+    assert_eq!(
+        compile("x = loop { break 1 }"),
+        Err("A loop can't be used as a value in JavaScript output".to_string()),
+    );
+}
@@ -0,0 +1,89 @@
+use m_lang::interpreter::Interpreter;
+use m_lang::lexer::Lexer;
+use m_lang::parser::Parser;
+
+// A small xorshift generator, so every run checks the same values
+struct Generator(u64);
+
+impl Generator {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, n: u64) -> u64 {
+        self.next() % n
+    }
+
+    fn string(&mut self) -> String {
+        // Everything but '"', which M string literals can't contain
+        const CHARS: &[char] = &['a', 'Z', '0', ' ', '\\', '/', '\n', '\t', '\'', '{', 'é', '猫', '🐱', '\u{1}'];
+        let len = self.below(6);
+        let chars: String = (0..len).map(|_| CHARS[self.below(CHARS.len() as u64) as usize]).collect();
+        format!("\"{}\"", chars)
+    }
+
+    // M source for a value that JSON can represent
+    fn literal(&mut self, depth: u32) -> String {
+        let kinds = if depth == 0 { 5 } else { 7 };
+        match self.below(kinds) {
+            0 => "nil".to_string(),
+            1 => (self.below(2) == 0).to_string(),
+            2 => {
+                let n = (self.next() as i64) >> self.below(64);
+                if n == i64::MIN { "0".to_string() } else { n.to_string() }
+            },
+            3 => {
+                let n = f64::from_bits(self.next());
+                if n.is_finite() { format!("{:?}", n) } else { format!("{:?}", self.below(1000) as f64 / 8.0) }
+            },
+            4 => self.string(),
+            5 => {
+                let elements: Vec<String> = (0..self.below(4)).map(|_| self.literal(depth - 1)).collect();
+                format!("[{}]", elements.join(", "))
+            },
+            _ => {
+                let entries: Vec<String> = (0..self.below(4)).map(|_| format!("{}: {}", self.string(), self.literal(depth - 1))).collect();
+                format!("{{{}}}", entries.join(", "))
+            },
+        }
+    }
+}
+
+fn evaluate(source: &str) -> Result<(), String> {
+    let tokens = Lexer::new(source).tokenize()?;
+    let program = Parser::new(tokens).parse()?;
+    Interpreter::new().evaluate(&program).map(|_| ())
+}
+
+#[test]
+fn values_round_trip_through_json() {
+    let mut generator = Generator(0x2545_f491_4f6c_dd1d);
+
+    for _ in 0..300 {
+        let literal = generator.literal(3);
+        // Comparing the JSON text as well catches an integer turning into a float
+        let source = format!(
+            "x = {}\ny = from_json(x.to_json()).unwrap()\nassert(y == x)\nassert_eq(y.to_json(), x.to_json())",
+            literal,
+        );
+        if let Err(e) = evaluate(&source) {
+            panic!("{} did not round-trip: {}", literal, e);
+        }
+    }
+}
+
+#[test]
+fn invalid_json_gives_an_err() {
+    assert_eq!(
+        evaluate("
+            assert_eq(from_json(\"{1: 2}\"), err(\"Invalid JSON: key must be a string at line 1 column 2\"))
+            assert_eq(\"[1,\".from_json().is_err(), true)
+            assert_eq(\"[1, 2]\".from_json(), ok([1, 2]))
+        "),
+        Ok(()),
+    );
+    assert_eq!(evaluate("from_json(1)"), Err("Argument to from_json() must be a string".to_string()));
+}
@@ -0,0 +1,83 @@
+use m_lang::lexer::Lexer;
+use m_lang::parser::Parser;
+use m_lang::token::{Span, TokenType};
+
+#[test]
+fn tokens_carry_byte_spans() {
+    let tokens = Lexer::new("x = \"hé\" // note\n!y").tokenize().unwrap();
+    let spans: Vec<_> = tokens.iter().map(|token| (token.token_type, token.span)).collect();
+
+    assert_eq!(spans, vec![
+        (TokenType::Identifier, Span { start: 0, end: 1 }),
+        (TokenType::Equal, Span { start: 2, end: 3 }),
+        (TokenType::String, Span { start: 4, end: 9 }),
+        (TokenType::Not, Span { start: 18, end: 19 }),
+        (TokenType::Identifier, Span { start: 19, end: 20 }),
+        (TokenType::EOF, Span { start: 20, end: 20 }),
+    ]);
+    assert_eq!(tokens[2].literal, "hé");
+}
+
+#[test]
+fn unexpected_characters_are_errors() {
+    let errors: Vec<_> = Lexer::new("a @ b\n  $ c").filter_map(Result::err).collect();
+
+    assert_eq!(errors.len(), 2);
+    assert_eq!(errors[0].to_string(), "Unexpected character '@' at line 1, column 3");
+    assert_eq!(errors[1].span, Span { start: 8, end: 9 });
+    assert_eq!((errors[1].line, errors[1].column), (2, 3));
+}
+
+#[test]
+fn unterminated_strings_are_errors() {
+    let error = Lexer::new("print(\"hello)").tokenize().unwrap_err();
+    assert_eq!(error, "Unterminated string at line 1, column 7");
+}
+
+#[test]
+fn triple_quoted_strings_hold_quotes_and_newlines() {
+    let tokens = Lexer::new("\"\"\"\n<a href=\"x\">\n\"\"\" + \"\"\"\"\"quoted\"\"\"\"\" + \"\"").tokenize().unwrap();
+    let literals: Vec<_> = tokens.iter().filter(|token| token.token_type == TokenType::String).map(|token| token.literal.as_str()).collect();
+    assert_eq!(literals, ["<a href=\"x\">\n", "\"\"quoted\"\"", ""]);
+
+    let error = Lexer::new("x = \"\"\"open\"\"").tokenize().unwrap_err();
+    assert_eq!(error, "Unterminated string at line 1, column 5");
+}
+
+#[test]
+fn raw_strings_keep_backslashes() {
+    let tokens = Lexer::new("r\"C:\\new\" + r\"\"\"\\d+ \"x\"\"\"\" + r").tokenize().unwrap();
+    assert_eq!(tokens[0].token_type, TokenType::String);
+    assert_eq!(tokens[0].literal, "C:\\new");
+    assert_eq!(tokens[2].literal, "\\d+ \"x\"");
+    assert_eq!(tokens[4].token_type, TokenType::Identifier);
+}
+
+#[test]
+fn raw_identifiers_name_keywords() {
+    let tokens = Lexer::new("`use` = 1").tokenize().unwrap();
+    assert_eq!(tokens[0].token_type, TokenType::Identifier);
+    assert_eq!(tokens[0].literal, "use");
+    assert_eq!(tokens[0].span, Span { start: 0, end: 5 });
+
+    let error = Lexer::new("x = `use").tokenize().unwrap_err();
+    assert_eq!(error, "Raw identifiers must be a name between backticks at line 1, column 5");
+}
+
+#[test]
+fn assigning_to_a_keyword_suggests_backticks() {
+    for keyword in ["use", "fn", "loop", "if"] {
+        let tokens = Lexer::new(&format!("{} = 1", keyword)).tokenize().unwrap();
+        let error = Parser::new(tokens).parse().unwrap_err();
+        assert_eq!(error, format!("Unexpected keyword '{0}'; write `{0}` to use it as a name", keyword));
+    }
+}
+
+#[test]
+fn shebang_and_hash_comments_are_skipped() {
+    let tokens = Lexer::new("#!/usr/bin/env m_lang\nx # note\n# y").tokenize().unwrap();
+    let types: Vec<_> = tokens.iter().map(|token| token.token_type).collect();
+
+    assert_eq!(types, vec![TokenType::Identifier, TokenType::EOF]);
+    assert_eq!((tokens[0].line, tokens[0].column), (2, 1));
+}
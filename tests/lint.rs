@@ -0,0 +1,39 @@
+use std::path::Path;
+
+use m_lang::lexer::Lexer;
+use m_lang::lint::{Linter, Warning};
+use m_lang::parser::Parser;
+
+fn lint(source: &str, warnings: &[Warning]) -> Vec<String> {
+    let tokens = Lexer::new(source).tokenize().unwrap();
+    let program = Parser::new(tokens).parse().unwrap();
+    Linter::check(&program, Path::new("."), warnings).iter().map(|diagnostic| diagnostic.message.clone()).collect()
+}
+
+#[test]
+fn unused_variables_are_reported_where_assigned() {
+    let source = "fn f(a) {\n    let unused = 1\n    total = a\n    _skipped = 2\n    return total\n}";
+
+    assert_eq!(lint(source, &[Warning::UnusedVariable]), vec![
+        "Variable 'unused' is assigned but never read at line 2, column 5",
+    ]);
+}
+
+#[test]
+fn code_after_return_is_unreachable() {
+    let source = "fn f() {\n    if true {\n        return 1\n        print(2)\n    }\n    return 3\n}";
+
+    assert_eq!(lint(source, &[Warning::UnreachableCode]), vec![
+        "Unreachable code after the return at line 3, column 9",
+    ]);
+}
+
+#[test]
+fn transformers_can_be_asked_to_name_their_receiver() {
+    let source = "transformer square() { applied * applied }\ntransformer n.double() { n * 2 }\ntransformer zero() { 0 }";
+
+    assert_eq!(lint(source, &[Warning::ImplicitApplied]), vec![
+        "Transformer 'square' uses 'applied'; name the value instead, as in 'transformer x.square()'",
+    ]);
+    assert!(lint(source, Warning::DEFAULT).is_empty());
+}
@@ -0,0 +1,42 @@
+mod common;
+
+use common::run;
+use m_lang::lexer::Lexer;
+use m_lang::parser::Parser;
+
+// Inputs that used to panic instead of reporting an error
+
+#[test]
+fn parser_accepts_tokens_without_eof() {
+    assert!(Parser::new(Vec::new()).parse().is_ok());
+
+    let mut tokens = Lexer::new("print(1 +").tokenize().unwrap();
+    tokens.pop();
+    assert!(Parser::new(tokens).parse().is_err());
+}
+
+#[test]
+fn huge_ranges_and_format_specs_are_errors() {
+    assert_eq!(
+        run("huge_range", "range(0, 9223372036854775807).to_array()"),
+        "Error: range(0, 9223372036854775807) is too large to hold in memory",
+    );
+    assert_eq!(
+        run("huge_precision", "print(format(\"{:.999999999}\", 1.5))"),
+        "Error: Invalid precision in '{:.999999999}'",
+    );
+}
+
+#[test]
+fn range_arithmetic_near_the_integer_limits() {
+    let smallest = "x = -9223372036854775807 - 1\n";
+    assert_eq!(run("range_index", "print(range(5, 10)[9223372036854775807])"), "Error: Index out of bounds: 9223372036854775807");
+    assert_eq!(run("range_equality", &format!("{}print(range(x, 9223372036854775807) == [])", smallest)), "false");
+
+    let length = run("range_length", &format!("{}print(range(x, 9223372036854775807).len())", smallest));
+    if cfg!(feature = "bigint") {
+        assert_eq!(length, "18446744073709551615");
+    } else {
+        assert!(length.contains("too large for an integer"), "{}", length);
+    }
+}
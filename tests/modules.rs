@@ -0,0 +1,160 @@
+mod common;
+
+use std::fs;
+use std::process::Command;
+use common::run;
+
+#[test]
+fn use_only_exposes_pub_definitions() {
+    let module = std::env::temp_dir().join(format!("m_lang_pub_{}.m", std::process::id()));
+    fs::write(&module, "pub fn area(r) { square(r) * 3 }\npub unit = \"cm\"\nfn square(x) { x * x }\n").unwrap();
+
+    let source = format!("use \"{}\"\nprint(area(2), unit)\nprint(square(2))\n", module.display());
+    let output = run("pub_exports", &source);
+    fs::remove_file(&module).unwrap();
+    assert_eq!(output, "12 cm\nError: Undefined function 'square'");
+}
+
+#[test]
+fn pub_must_precede_a_definition() {
+    assert_eq!(
+        run("pub_misplaced", "pub print(1)"),
+        "Error: Expected 'fn', 'transformer', 'let' or an assignment after 'pub'",
+    );
+}
+
+// Writes module files into a fresh directory and returns the directory
+fn modules(name: &str, files: &[(&str, &str)]) -> std::path::PathBuf {
+    let dir = std::env::temp_dir().join(format!("m_lang_{}_{}", name, std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    for (file, source) in files {
+        if let Some(parent) = dir.join(file).parent() {
+            fs::create_dir_all(parent).unwrap();
+        }
+        fs::write(dir.join(file), source.replace("DIR", &dir.display().to_string())).unwrap();
+    }
+    dir
+}
+
+#[test]
+fn import_cycles_are_reported_with_their_chain() {
+    let dir = modules("cycle", &[
+        ("a.m", "use \"DIR/b.m\"\nfn a() { 1 }\n"),
+        ("b.m", "use \"DIR/a.m\"\nfn b() { 2 }\n"),
+    ]);
+    let output = run("import_cycle", &format!("use \"{}/a.m\"", dir.display()));
+    fs::remove_dir_all(&dir).unwrap();
+
+    let a = dir.join("a.m").canonicalize().unwrap_or(dir.join("a.m"));
+    let b = dir.join("b.m").canonicalize().unwrap_or(dir.join("b.m"));
+    assert!(
+        output.ends_with(&format!("Import cycle: {} -> {} -> {}", a.display(), b.display(), a.display())),
+        "{}",
+        output,
+    );
+}
+
+#[test]
+fn importing_the_main_file_is_a_cycle() {
+    let dir = modules("main_cycle", &[
+        ("c1.m", "print(\"c1 runs\")\nuse \"c2.m\"\n"),
+        ("c2.m", "use \"c1.m\"\n"),
+    ]);
+    let output = Command::new(env!("CARGO_BIN_EXE_m_lang")).arg("c1.m").current_dir(&dir).output().unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+
+    // The main file runs once, and the cycle is traced from it
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "Running file: c1.m\nc1 runs\n");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.trim_end().ends_with("Import cycle: c1.m -> c2.m -> c1.m"), "{}", stderr);
+    assert_eq!(stderr.matches("Error evaluating file").count(), 1, "{}", stderr);
+}
+
+#[test]
+fn diamond_imports_share_one_module() {
+    let dir = modules("diamond", &[
+        ("shared.m", "print(\"loading shared\")\nfn shared() { \"shared\" }\n"),
+        ("left.m", "use \"DIR/shared.m\"\nfn left() { shared() }\n"),
+        ("right.m", "use \"DIR/shared.m\"\nfn right() { shared() }\n"),
+    ]);
+    let source = format!("use \"{0}/left.m\"\nuse \"{0}/right.m\"\nprint(left(), right())", dir.display());
+    let output = run("import_diamond", &source);
+    fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(output, "loading shared\nshared shared");
+}
+
+#[test]
+fn modules_run_in_their_own_scope() {
+    let dir = modules("own_scope", &[
+        ("lib.m", "seen = defined(\"secret\")\neval(\"made_by_eval = 1\")\nif true {\n    nested = 2\n}\n"),
+    ]);
+    let source = format!("secret = 1\nuse \"{}/lib.m\"\nprint(seen, made_by_eval, defined(\"nested\"))", dir.display());
+    let output = run("own_scope", &source);
+    fs::remove_dir_all(&dir).unwrap();
+    // The resolver can't know what eval() defines, so warns about it
+    assert_eq!(output, "false 1 false\nWarning: Undefined variable 'made_by_eval'");
+}
+
+#[test]
+fn concurrent_imports_wait_for_the_module_to_load() {
+    let dir = modules("concurrent", &[
+        ("slow.m", "print(\"loading slow\")\nsleep(200)\nfn slow_value() { 42 }\n"),
+    ]);
+    let source = format!(
+        "fn worker() {{\n    use \"{}/slow.m\"\n    slow_value()\n}}\na = spawn(worker)\nb = spawn(worker)\nprint(join(a), join(b))",
+        dir.display(),
+    );
+    let output = run("concurrent_imports", &source);
+    fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(output, "loading slow\n42 42");
+}
+
+#[test]
+fn import_cycles_between_tasks_are_reported() {
+    let dir = modules("task_cycle", &[
+        ("a.m", "sleep(50)\nuse \"DIR/b.m\"\n"),
+        ("b.m", "sleep(100)\nuse \"DIR/a.m\"\n"),
+    ]);
+    let source = format!(
+        "fn wa() {{\n    use \"{0}/a.m\"\n}}\nfn wb() {{\n    use \"{0}/b.m\"\n}}\nx = spawn(wa)\ny = spawn(wb)\nprint(try(join, x))\nprint(try(join, y))",
+        dir.display(),
+    );
+    let output = run("task_cycle", &source);
+    fs::remove_dir_all(&dir).unwrap();
+
+    // Each task would otherwise wait forever for the module the other is loading
+    let lines: Vec<&str> = output.lines().collect();
+    assert_eq!(lines.len(), 2, "{}", output);
+    assert!(lines.iter().all(|line| line.starts_with("err(") && line.contains("Import cycle")), "{}", output);
+}
+
+#[test]
+fn differently_spelled_imports_load_a_module_once() {
+    let dir = modules("spellings", &[
+        ("shared.m", "print(\"loading shared\")\nfn shared() { \"shared\" }\n"),
+        ("left.m", "use \"./shared.m\"\nfn left() { shared() }\n"),
+        ("sub/right.m", "use \"sub/../shared.m\"\nfn right() { shared() }\n"),
+        ("main.m", "use \"shared.m\"\nuse \"left.m\"\nuse \"./sub/right.m\"\nuse \"DIR/shared.m\"\nprint(left(), right(), shared())\n"),
+    ]);
+    let output = Command::new(env!("CARGO_BIN_EXE_m_lang")).arg("main.m").current_dir(&dir).output().unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "Running file: main.m\nloading shared\nshared shared shared\n");
+    assert!(output.status.success());
+}
+
+#[test]
+fn cycles_through_differently_spelled_imports_are_reported() {
+    let dir = modules("spelled_cycle", &[
+        ("main.m", "use \"x.m\"\n"),
+        ("x.m", "use \"./y.m\"\n"),
+        ("y.m", "use \"sub/z.m\"\n"),
+        ("sub/z.m", "use \"sub/../x.m\"\n"),
+    ]);
+    let output = Command::new(env!("CARGO_BIN_EXE_m_lang")).arg("main.m").current_dir(&dir).output().unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.trim_end().ends_with("Import cycle: x.m -> y.m -> sub/z.m -> x.m"), "{}", stderr);
+}
@@ -0,0 +1,77 @@
+use std::sync::Mutex;
+use m_lang::interpreter::Interpreter;
+use m_lang::lexer::Lexer;
+use m_lang::parser::Parser;
+use m_lang::value::Value;
+
+fn evaluate(interpreter: &mut Interpreter, source: &str) -> Result<(), String> {
+    let tokens = Lexer::new(source).tokenize()?;
+    let program = Parser::new(tokens).parse()?;
+    interpreter.evaluate(&program).map(|_| ())
+}
+
+struct Connection {
+    queries: Mutex<Vec<String>>,
+}
+
+#[test]
+fn host_objects_pass_through_scripts() {
+    let mut interpreter = Interpreter::new();
+    interpreter.register_function("connect", |_| {
+        Ok(Value::native("connection", Connection { queries: Mutex::new(Vec::new()) }))
+    });
+    interpreter.register_function("query", |arguments| {
+        let connection = arguments.first().and_then(Value::as_native::<Connection>)
+            .ok_or("query() expects a connection")?;
+        let mut queries = connection.queries.lock().unwrap();
+        queries.push(arguments[1].to_string());
+        Ok(Value::Int(queries.len() as i64))
+    });
+
+    evaluate(&mut interpreter, "
+        db = connect()
+        assert_eq(typeof(db), \"connection\")
+        assert_eq(db, db)
+        assert(db != connect())
+        query(db, \"select 1\")
+        assert_eq(query(db, \"select 2\"), 2)
+        assert(query.is_function())
+    ").unwrap();
+
+    let db = interpreter.get_variable("db").unwrap();
+    let queries = db.as_native::<Connection>().unwrap().queries.lock().unwrap().clone();
+    assert_eq!(queries, ["select 1", "select 2"]);
+
+    let error = evaluate(&mut interpreter, "query(42, \"select 3\")").unwrap_err();
+    assert!(error.contains("query() expects a connection"), "{}", error);
+
+    // Host objects can't be saved, but registered functions are simply skipped
+    let error = interpreter.save_state().unwrap_err();
+    assert_eq!(error, "Cannot save 'db': a connection can't be saved");
+    evaluate(&mut interpreter, "db = nil").unwrap();
+    assert!(!interpreter.save_state().unwrap().contains("query"));
+}
+
+#[test]
+fn hosts_can_replace_and_remove_builtins() {
+    let printed = std::sync::Arc::new(Mutex::new(Vec::new()));
+    let mut interpreter = Interpreter::new();
+    let log = printed.clone();
+    interpreter.register_function("print", move |arguments| {
+        let line: Vec<String> = arguments.iter().map(Value::to_string).collect();
+        log.lock().unwrap().push(line.join(" "));
+        Ok(Value::Nil)
+    });
+    assert!(interpreter.remove_builtin("input"));
+    assert!(!interpreter.remove_builtin("input"));
+
+    evaluate(&mut interpreter, "
+        print(\"hello\", 42)
+        assert(!defined(\"input\"))
+        assert_eq(typeof(range(0, 2)), \"range\")
+    ").unwrap();
+    assert_eq!(*printed.lock().unwrap(), ["hello 42"]);
+
+    let error = evaluate(&mut interpreter, "input(\"? \")").unwrap_err();
+    assert!(error.contains("Undefined function 'input'"), "{}", error);
+}
@@ -0,0 +1,191 @@
+use m_lang::interpreter::Interpreter;
+use m_lang::lexer::Lexer;
+use m_lang::optimizer::Optimizer;
+use m_lang::parser::Parser;
+
+fn evaluate(interpreter: &mut Interpreter, source: &str) -> Result<(), String> {
+    let tokens = Lexer::new(source).tokenize()?;
+    let program = Parser::new(tokens).parse()?;
+    interpreter.evaluate(&program).map(|_| ())
+}
+
+#[test]
+fn special_values_can_be_tested_for() {
+    evaluate(&mut Interpreter::new(), "
+        big = 1e308 * 10
+        nan = big - big
+        assert(big.is_infinite() and (-big).is_infinite() and not big.is_nan())
+        assert(nan.is_nan() and not nan.is_infinite() and not nan.is_finite())
+        assert(nan != nan)
+        assert(1.5.is_finite() and 3.is_finite() and not 3.is_nan())
+        assert(not \"nan\".is_nan() and not nil.is_finite())
+        assert(\"inf\".to_number().is_infinite())
+    ").unwrap();
+}
+
+#[test]
+fn strict_numbers_reject_nan_and_infinities() {
+    let mut interpreter = Interpreter::new();
+    interpreter.set_strict_numbers(true);
+    evaluate(&mut interpreter, "assert_eq(1e300 * 10, 1e301)").unwrap();
+
+    let cases = [
+        ("1e308 * 10", "The result of * is infinite"),
+        ("1e999 - 1e999", "The result of - is NaN"),
+        ("\"inf\".to_number()", "\"inf\".to_number() is infinite"),
+        ("\"NaN\".parse_number()", "\"NaN\".parse_number() is NaN"),
+    ];
+    for (source, expected) in cases {
+        let error = evaluate(&mut interpreter, source).unwrap_err();
+        assert!(error.contains(expected), "{}: {}", source, error);
+    }
+
+    // `use strict` includes it
+    let mut interpreter = Interpreter::new();
+    assert!(evaluate(&mut interpreter, "x = 1e308 * 10").is_ok());
+    assert!(evaluate(&mut interpreter, "use strict\nx = 1e308 * 10").is_err());
+}
+
+#[test]
+fn optimizing_leaves_special_results_to_runtime() {
+    let program = Parser::new(Lexer::new("x = 1e308 * 10\ny = 2.0 * 3").tokenize().unwrap()).parse().unwrap();
+    assert_eq!(Optimizer::optimize(program).to_string(), "x = 1e308 * 10\ny = 6.0");
+}
+
+#[cfg(feature = "bigint")]
+#[test]
+fn integers_grow_past_64_bits() {
+    let mut interpreter = Interpreter::new();
+    evaluate(&mut interpreter, "
+        max = 9223372036854775807
+        assert_eq((max + 1).to_string(), \"9223372036854775808\")
+        assert_eq(max + 1 - 1, max)
+        assert((max + 1 - 1).is_integer() and typeof(max * max) == \"number\")
+        assert_eq((-max - 1) * -1, max + 1)
+        assert_eq(-(-max - 1), max + 1)
+
+        power = 1
+        for _ in 0..100 { power = power * 2 }
+        assert_eq(power.to_string(), \"1267650600228229401496703205376\")
+        assert_eq(power ~/ (power ~/ 4), 4)
+        assert_eq(power % 1000, 376)
+        assert_eq(-power % 1000, 624)
+        assert(power > max and -power < -max and power < power + 1)
+        assert_eq(power / 2.0, 6.338253001141147e29)
+        assert_eq(\"1267650600228229401496703205376\".to_number(), power)
+        big = power
+    ").unwrap();
+
+    let error = evaluate(&mut interpreter, "big ~/ 0").unwrap_err();
+    assert!(error.contains("Division by zero"), "{}", error);
+    let error = evaluate(&mut interpreter, "big.to_json()").unwrap_err();
+    assert!(error.contains("too large for a JSON integer"), "{}", error);
+
+    let state = interpreter.save_state().unwrap();
+    let mut restored = Interpreter::new();
+    restored.load_state(&state).unwrap();
+    evaluate(&mut restored, "assert(big.is_integer() and big == 2 * (big ~/ 2))").unwrap();
+    evaluate(&mut restored, "assert_eq(big.to_string(), \"1267650600228229401496703205376\")").unwrap();
+}
+
+#[cfg(feature = "bigint")]
+#[test]
+fn integer_literals_can_be_big() {
+    evaluate(&mut Interpreter::new(), "
+        big = 123456789012345678901234567890
+        assert_eq(big.to_string(), \"123456789012345678901234567890\")
+        assert(big.is_integer() and typeof(big) == \"number\")
+        assert_eq(big - 123456789012345678901234567889, 1)
+        assert_eq(big % 1000, 890)
+        assert_eq(-9223372036854775808, -9223372036854775807 - 1)
+        assert_eq(0x1_0000_0000_0000_0000, 18446744073709551615 + 1)
+        assert_eq(1_000_000_000_000_000_000_000, 1000000000000 * 1000000000000 ~/ 1000)
+    ").unwrap();
+
+    let program = Parser::new(Lexer::new("x = 123456789012345678901234567890 * 10\ny = -0xFFFF_FFFF_FFFF_FFFF").tokenize().unwrap()).parse().unwrap();
+    assert_eq!(program.to_string(), "x = 123456789012345678901234567890 * 10\ny = -18446744073709551615");
+    assert_eq!(Optimizer::optimize(program).to_string(), "x = 1234567890123456789012345678900\ny = -18446744073709551615");
+}
+
+#[test]
+fn division_is_floored_for_every_kind_of_number() {
+    evaluate(&mut Interpreter::new(), "
+        // The quotient rounds down and the remainder has the divisor's sign
+        assert_eq([7 ~/ 2, 7 ~/ -2, -7 ~/ 2, -7 ~/ -2], [3, -4, -4, 3])
+        assert_eq([7 % 2, 7 % -2, -7 % 2, -7 % -2], [1, -1, 1, -1])
+        assert_eq([7.0 ~/ -2, -7.5 ~/ 2, 7.0 % -2.0, -7.5 % 2], [-4, -4, -1, 0.5])
+        assert_eq([6 ~/ -2, -6 % 4, 6 % -3], [-3, 2, 0])
+        assert_eq([decimal(7) ~/ -2, decimal(7) % -2, decimal(\"-7.5\") % -2], [-4, -1, decimal(\"-1.5\")])
+
+        // a == (a ~/ b) * b + a % b
+        for pair in [[7, -2], [-7, 2], [-9223372036854775807 - 1, -1], [-9223372036854775807 - 1, 10]] {
+            a = pair[0]
+            b = pair[1]
+            assert_eq((a ~/ b) * b + a % b, a)
+        }
+
+        power = 1
+        for _ in 0..100 { power = power * 2 }
+        assert_eq((power ~/ -3).to_string(), \"-422550200076076467165567735126\")
+        assert_eq(power % -3, -2)
+        assert_eq((-power ~/ 3).to_string(), \"-422550200076076467165567735126\")
+        assert_eq(-power % -3, -1)
+        assert_eq((power + 1) % -power, -power + 1)
+    ").unwrap();
+}
+
+#[test]
+fn decimals_are_exact() {
+    let mut interpreter = Interpreter::new();
+    evaluate(&mut interpreter, "
+        price = decimal(\"10.05\")
+        total = price * 3 + decimal(\"0.10\")
+        assert_eq(total.to_string(), \"30.25\")
+        assert_eq(typeof(total), \"decimal\")
+        assert(decimal(\"0.1\") + decimal(\"0.2\") == decimal(\"0.3\"))
+        assert(decimal(\"1.50\") == decimal(\"1.5\") and decimal(3) == 3 and decimal(0.1) == decimal(\"0.1\"))
+        assert(price > 10 and price < decimal(\"10.06\"))
+        assert_eq(decimal(\"-7.5\") % 2, decimal(\"0.5\"))
+        assert_eq(decimal(7) ~/ 2, 3)
+        assert_eq((decimal(1) / 3).to_string(), \"0.3333333333333333333333333333\")
+
+        assert_eq(decimal(\"2.345\").round(2).to_string(), \"2.35\")
+        assert_eq(decimal(\"-2.5\").round(), decimal(-3))
+        assert_eq(decimal(\"1250\").round(-2), 1300)
+        assert_eq(decimal(\"10.055\").to_fixed(2), \"10.06\")
+        assert_eq(format(\"{:.1}|{:>7}\", price, price), \"10.1|  10.05\")
+        assert_eq(from_json([price].to_json()).unwrap()[0], \"10.05\")
+        assert_eq(price.to_number(), 10.05)
+        saved = total
+    ").unwrap();
+
+    let error = evaluate(&mut interpreter, "decimal(\"1.5\") + 0.5").unwrap_err();
+    assert!(error.contains("Cannot combine a decimal with a float"), "{}", error);
+    let error = evaluate(&mut interpreter, "decimal(\"ten\")").unwrap_err();
+    assert!(error.contains("Cannot convert \"ten\" to a decimal"), "{}", error);
+
+    let mut restored = Interpreter::new();
+    restored.load_state(&interpreter.save_state().unwrap()).unwrap();
+    evaluate(&mut restored, "assert_eq(saved, decimal(\"30.25\"))").unwrap();
+}
+
+#[test]
+fn parsing_can_report_failure() {
+    evaluate(&mut Interpreter::new(), "
+        assert_eq(try_parse_number(\"42\"), 42)
+        assert(try_parse_number(\"42\").is_integer())
+        assert_eq(try_parse_number(\"-2.5e3\"), -2500)
+        assert_eq(try_parse_number(\"abc\"), nil)
+        assert_eq(try_parse_number(\"\"), nil)
+        assert_eq(try_parse_number(7), 7)
+        assert_eq(try_parse_number(nil), nil)
+        assert_eq(\"abc\".to_number(), 0)
+    ").unwrap();
+
+    let mut interpreter = Interpreter::new();
+    interpreter.set_strict_numbers(true);
+    evaluate(&mut interpreter, "assert_eq(try_parse_number(\"inf\"), nil)").unwrap();
+
+    let error = evaluate(&mut Interpreter::new(), "use strict\n\"abc\".to_number()").unwrap_err();
+    assert!(error.contains("Cannot convert \"abc\" to a number"), "{}", error);
+}
@@ -0,0 +1,92 @@
+use m_lang::ast::Expr;
+use m_lang::interpreter::Interpreter;
+use m_lang::lexer::Lexer;
+use m_lang::optimizer::Optimizer;
+use m_lang::parser::Parser;
+
+fn parse(source: &str) -> Expr {
+    Parser::new(Lexer::new(source).tokenize().unwrap()).parse().unwrap()
+}
+
+// Runs a program as written and optimized, returning what each left in `result`
+fn run_both(source: &str) -> (Result<String, String>, Result<String, String>) {
+    let run = |program: &Expr| {
+        let mut interpreter = Interpreter::new();
+        interpreter.evaluate(program)?;
+        Ok(format!("{:?}", interpreter.get_variable("result")))
+    };
+    let program = parse(source);
+    (run(&program), run(&Optimizer::optimize(program)))
+}
+
+#[test]
+fn literal_operations_are_folded() {
+    let program = parse("x = 1 + 2 * 3\ny = \"a\" + \"b\"\nz = -(4)\nw = !true\nt = a + 2 * 3\nv = 10 / 0");
+
+    // Division by zero is left to fail at runtime
+    assert_eq!(Optimizer::optimize(program).to_string(), "x = 7\ny = \"ab\"\nz = -4\nw = false\nt = a + 6\nv = 10 / 0");
+}
+
+#[test]
+fn dead_code_is_removed() {
+    let program = parse("
+if false { print(1) }
+while false { print(2) }
+if true { let a = 1 } else { print(3) }
+if false { print(4) } else { print(5) }
+fn f() {
+    return 1
+    print(6)
+}
+do { print(7) } while false
+");
+
+    assert_eq!(Optimizer::optimize(program).to_string(), "\
+nil
+nil
+if true {
+    let a = 1
+}
+if true {
+    print(5)
+}
+
+fn f() {
+    return 1
+}
+
+do {
+    print(7)
+} while false");
+}
+
+#[test]
+fn optimized_programs_behave_the_same() {
+    let (plain, optimized) = run_both("
+        let x = 1
+        if 2 > 1 {
+            // The branch kept by folding still has its own scope
+            let x = \"inner \" + \"value\"
+            assert_eq(x, \"inner value\")
+        } else {
+            x = 100
+        }
+        fn f(n) {
+            if n > 0 and true { return n * (2 + 3) }
+            return -1
+            x = 5
+        }
+        let count = 0
+        while false { count = count + 1 }
+        do { count = count + 1 } while 1 > 2
+        result = [x, f(2), f(0), count, 7 ~/ 2, 1.5 * 2, \"a\" + \"b\"]
+        assert_eq(result, [1, 10, -1, 1, 3, 3.0, \"ab\"])
+    ");
+    assert!(plain.is_ok(), "{:?}", plain);
+    assert_eq!(optimized, plain);
+
+    // Errors left to runtime are reported the same way
+    let (plain, optimized) = run_both("result = 1\nresult = 10 / 0");
+    assert!(plain.is_err());
+    assert_eq!(optimized, plain);
+}
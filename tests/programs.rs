@@ -0,0 +1,122 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use m_lang::ast::Expr;
+use m_lang::host::Host;
+use m_lang::interpreter::Interpreter;
+use m_lang::lexer::Lexer;
+use m_lang::parser::Parser;
+
+// Every `.m` file in tests/programs is run and what it prints is compared with
+// the `.out` file next to it. An error ends the output with "Error: ...", as
+// the binary prints it. Files in subdirectories are only there to be imported.
+//
+// After an intended change in output, `UPDATE_GOLDEN=1 cargo test --test programs`
+// rewrites the `.out` files; check the diff before committing it.
+const PROGRAMS: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/programs");
+
+// Keeps what a program prints instead of writing it to stdout
+#[derive(Default)]
+struct Capture {
+    output: Mutex<String>,
+}
+
+impl Host for Capture {
+    fn write(&self, text: &str) -> Result<(), String> {
+        self.output.lock().unwrap().push_str(text);
+        Ok(())
+    }
+
+    fn read_line(&self) -> Result<String, String> {
+        Err("input() isn't available in golden tests".to_string())
+    }
+}
+
+fn parse(source: &str) -> Result<Expr, String> {
+    Lexer::new(source).tokenize().and_then(|tokens| Parser::new(tokens).parse())
+}
+
+fn run(source: &str) -> String {
+    let capture = Arc::new(Capture::default());
+    let mut interpreter = Interpreter::with_base_path(Path::new(PROGRAMS));
+    interpreter.set_host(capture.clone());
+
+    let result = parse(source).and_then(|program| interpreter.evaluate(&program));
+
+    let mut output = capture.output.lock().unwrap().clone();
+    if let Err(e) = result {
+        output.push_str(&format!("Error: {}\n", e));
+    }
+    output
+}
+
+fn programs() -> Vec<PathBuf> {
+    let mut paths: Vec<_> = fs::read_dir(PROGRAMS).unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|extension| extension == "m"))
+        .collect();
+    paths.sort();
+    assert!(!paths.is_empty(), "no programs in {}", PROGRAMS);
+    paths
+}
+
+#[test]
+fn programs_print_their_expected_output() {
+    let update = std::env::var_os("UPDATE_GOLDEN").is_some();
+    let mut failures = Vec::new();
+    for path in programs() {
+        let output = run(&fs::read_to_string(&path).unwrap());
+        let expected_path = path.with_extension("out");
+        if update {
+            fs::write(&expected_path, &output).unwrap();
+            continue;
+        }
+
+        let name = path.file_name().unwrap().to_string_lossy().into_owned();
+        match fs::read_to_string(&expected_path) {
+            Ok(expected) if expected == output => {},
+            Ok(expected) => failures.push(format!("{}:\n{}", name, diff(&expected, &output))),
+            Err(_) => failures.push(format!("{}: no {} (run with UPDATE_GOLDEN=1 to create it)", name, expected_path.display())),
+        }
+    }
+
+    assert!(failures.is_empty(), "\n{}", failures.join("\n\n"));
+}
+
+// Printing a program with to_source() and running that gives the same output
+#[test]
+fn printed_programs_behave_the_same() {
+    let mut failures = Vec::new();
+    for path in programs() {
+        let source = fs::read_to_string(&path).unwrap();
+        let printed = parse(&source).unwrap().to_source();
+        let (expected, output) = (run(&source), run(&printed));
+        if expected != output {
+            let name = path.file_name().unwrap().to_string_lossy().into_owned();
+            failures.push(format!("{} printed as:\n{}\n{}", name, printed, diff(&expected, &output)));
+        }
+    }
+
+    assert!(failures.is_empty(), "\n{}", failures.join("\n\n"));
+}
+
+// The first line that differs, with its number
+fn diff(expected: &str, actual: &str) -> String {
+    let mut expected_lines = expected.lines();
+    let mut actual_lines = actual.lines();
+    for number in 1.. {
+        match (expected_lines.next(), actual_lines.next()) {
+            (None, None) => break,
+            (expected, actual) if expected == actual => {},
+            (expected, actual) => {
+                return format!(
+                    "  line {}\n  expected: {}\n  actual:   {}",
+                    number,
+                    expected.unwrap_or("<end of output>"),
+                    actual.unwrap_or("<end of output>"),
+                );
+            },
+        }
+    }
+    "  output differs only in line endings".to_string()
+}
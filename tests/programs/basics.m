@@ -0,0 +1,17 @@
+// Variables, arithmetic, strings and nil
+name = "Misty"
+age = 12
+print("Hello, " + name + "!")
+print(name, "is", age)
+
+print(10 / 4, 10 ~/ 3, 7 % 3, 2 + 0.5)
+print(0xFF, 0b1010, 0o17, 1_000_000, 2.5e3)
+print(-7 % 3, 2 * (3 + 4))
+
+config = {port: 8080}
+print(config["host"] ?? "localhost")
+missing = nil
+print(missing?.upper(), missing == nil)
+
+print("abc" == "abc", [1, [2, 3]] == [1, [2, 3]], 1 == "1")
+print(not true, true and false, false or true)
@@ -0,0 +1,26 @@
+// Arrays and maps: indexing, mutation, sharing and iteration
+scores = [3, 1, 2]
+push(scores, 10)
+scores[0] = 5
+print(scores, scores[3])
+
+alias = scores
+push(alias, 0)
+print(scores)
+copy = scores.slice(0)
+push(copy, 99)
+print(scores, copy)
+
+cat = {name: "Misty", "age": 12}
+cat["color"] = "grey"
+for key, value in cat {
+    print(key, "=", value)
+}
+print(cat.name, cat["missing"])
+
+nested = {server: {port: 80}}
+nested["server"]["port"] = 8080
+print(nested)
+
+print(zip([1, 2, 3], ["a", "b"]))
+print(range(0, 4).to_array(), [1, 2] + [3])
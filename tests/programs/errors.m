@@ -0,0 +1,5 @@
+// A runtime error stops the program after what it printed so far
+print("before")
+values = [1, 2, 3]
+print(values[5])
+print("never printed")
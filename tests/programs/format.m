@@ -0,0 +1,6 @@
+// format() placeholders and specs
+print(format("{} + {} = {}", 1, 2, 3))
+print(format("{1} before {0}", "a", "b"))
+print(format("[{:>6}] [{:<6}] [{:^6}]", "ab", "cd", "ef"))
+print(format("{:.2} {:+} {:05}", 3.14159, 7, 42))
+print(format("{{literal}}"))
@@ -0,0 +1,37 @@
+// Functions, recursion, defaults for missing arguments and returns
+fn factorial(n) {
+    if n <= 1 {
+        return 1
+    }
+    return n * factorial(n - 1)
+}
+
+fn greet(name, greeting) {
+    greeting = greeting ?? "Hello"
+    greeting + ", " + name
+}
+
+fn apply_twice(f, x) {
+    f(f(x))
+}
+
+fn double(x) {
+    x * 2
+}
+
+print(factorial(10))
+print(greet("Misty"))
+print(greet("Misty", "Hi"))
+print(apply_twice(double, 5))
+print(typeof(double), double.is_function())
+
+// Builtins are functions like any other: they can be passed around, and a
+// definition of the same name shadows them
+say = print
+say("aliased", typeof(say))
+print(apply_twice(typeof, 1))
+
+fn range(n) {
+    "my range of " + n.to_string()
+}
+print(range(3))
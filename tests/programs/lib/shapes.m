@@ -0,0 +1,14 @@
+// Imported by modules.m
+pub fn area(width, height) {
+    width * height
+}
+
+pub fn describe(width, height) {
+    format("{}x{} has area {}", width, height, area(width, height)) + units()
+}
+
+fn units() {
+    " square units"
+}
+
+defined_here = true
@@ -0,0 +1,58 @@
+// for, while, do-while, loop, break and continue, and block scoping
+total = 0
+for i in range(0, 5) {
+    total = total + i
+}
+print(total)
+
+for i in range(10, 0) step -3 {
+    write(i, "")
+}
+print()
+
+for i, x in ["a", "b", "c"].enumerate() {
+    print(i, x)
+}
+
+n = 0
+while n < 3 {
+    n = n + 1
+}
+print(n)
+
+do {
+    n = n - 1
+} while n > 0
+print(n)
+
+found = loop {
+    n = n + 1
+    if n % 7 == 0 {
+        break n
+    }
+}
+print(found)
+
+for i in range(0, 10) {
+    if i % 2 == 0 {
+        continue
+    }
+    if i > 7 {
+        break
+    }
+    write(i, "")
+}
+print()
+
+outer: for i in range(0, 3) {
+    for j in range(0, 3) {
+        if j == 2 {
+            continue outer
+        }
+        if i == 2 {
+            break outer
+        }
+        write(i, j, "|")
+    }
+}
+print()
@@ -0,0 +1,5 @@
+// Importing a file: only its pub definitions are visible
+use "lib/shapes.m"
+
+print(area(3, 4))
+print(describe(2, 5))
@@ -0,0 +1,32 @@
+// Splitting text into lines and pieces, and joining them back
+text = "name,age
+Misty, 12
+Tom,3
+"
+rows = []
+for line in text.lines() {
+    rows = rows + [line.split(",")]
+}
+print(rows)
+print(text.lines().join("|"))
+
+csv = "a,b,,c"
+print(csv.split(",").join(",") == csv, csv.split(",").join(";"))
+print([1, nil, 2.5, "x"].join(", "), range(0, 3).join(""))
+print("".lines(), "one".lines(), "
+".lines())
+
+// Strings count characters, not bytes
+s = "héllo 🐱"
+print(s.len(), s.chars(), s[1], s[6])
+print(ord("é"), ord("🐱"), chr(233), chr(ord("a") + 1))
+print([1, 2].len(), {"a": 1}.len(), set([1]).len(), (1, 2, 3).len(), range(2, 5).len(), "".len())
+
+// Triple-quoted strings keep quotes and newlines
+name = "Misty"
+page = """
+<p class="name">""" + name + """</p>
+<p>She said "mrrp"</p>
+"""
+print(page)
+print(""""quoted"""", """a""b""".len())
@@ -0,0 +1,29 @@
+// User-defined and builtin transformers, chaining and pipelines
+transformer shout() {
+    applied.upper() + "!"
+}
+
+transformer times(n) {
+    applied * n
+}
+
+print("hello".shout())
+print(3.times(4).times(2))
+print("[" + "  padded  ".trim() + "]")
+print("a,b,c".split(","))
+print(42.to_string() + "!", "3.5".to_number() + 1)
+
+fn add(x, n) {
+    x + n
+}
+print(5 |> add(3) |> add(1))
+
+word = "quiet"
+word .= shout()
+print(word)
+
+// The applied value can be given a name of its own
+transformer s.surround(edge) {
+    edge + s + edge
+}
+print("x".surround("*"))
@@ -0,0 +1,25 @@
+// Tuples group values without making a container
+fn divmod(a, b) {
+    return (a ~/ b, a % b)
+}
+
+(q, r) = divmod(17, 5)
+print(q, r)
+
+pair = divmod(9, 4)
+print(pair, typeof(pair), pair[1], (1,))
+print(pair == (2, 1), pair == [2, 1])
+
+x = 1
+y = x
+(x, y) = (y + 1, x)
+print(x, y)
+
+for name, score in [("Ann", 90), ("Bo", 85)] {
+    print(name, score)
+}
+
+(a, b) = [1, 2]
+print(a + b)
+
+pair[0] = 5
@@ -0,0 +1,45 @@
+use std::fs;
+use std::process::Command;
+
+#[test]
+fn run_uses_the_manifest_of_the_enclosing_project() {
+    let dir = std::env::temp_dir().join(format!("m_lang_project_{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+
+    let created = Command::new(env!("CARGO_BIN_EXE_m_lang")).args(["new", "hello"]).current_dir(&dir).output().unwrap();
+    assert!(created.status.success());
+
+    // Files in the module paths can be imported by name
+    let project = dir.join("hello");
+    fs::write(project.join("src/greeting.m"), "fn greeting() { \"Hi\" }\n").unwrap();
+    fs::write(project.join("src/main.m"), "use \"greeting.m\"\nprint(greeting())\n").unwrap();
+    fs::create_dir(project.join("notes")).unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_m_lang")).arg("run").current_dir(project.join("notes")).output().unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(String::from_utf8_lossy(&output.stderr), "");
+    assert!(String::from_utf8_lossy(&output.stdout).ends_with("\nHi\n"));
+}
+
+#[test]
+fn install_vendors_path_dependencies_for_use() {
+    let dir = std::env::temp_dir().join(format!("m_lang_install_{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(dir.join("app/src")).unwrap();
+    fs::create_dir_all(dir.join("shapes")).unwrap();
+    fs::write(dir.join("shapes/area.m"), "fn area(w, h) { w * h }\n").unwrap();
+    fs::write(dir.join("app/m.toml"), "[dependencies]\nshapes = { path = \"../shapes\" }\n").unwrap();
+    fs::write(dir.join("app/src/main.m"), "use \"shapes/area.m\"\nprint(area(3, 4))\n").unwrap();
+
+    let installed = Command::new(env!("CARGO_BIN_EXE_m_lang")).arg("install").current_dir(dir.join("app")).output().unwrap();
+    assert_eq!(String::from_utf8_lossy(&installed.stdout), "Installed shapes from ../shapes\n");
+    assert!(dir.join("app/m_modules/shapes/area.m").is_file());
+
+    // The copy is used, not the original
+    fs::remove_dir_all(dir.join("shapes")).unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_m_lang")).arg("run").current_dir(dir.join("app")).output().unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(String::from_utf8_lossy(&output.stderr), "");
+    assert!(String::from_utf8_lossy(&output.stdout).ends_with("\n12\n"));
+}
@@ -0,0 +1,85 @@
+use std::sync::Arc;
+
+use m_lang::host::Host;
+use m_lang::interpreter::Interpreter;
+use m_lang::lexer::Lexer;
+use m_lang::parser::Parser;
+use m_lang::value::Value;
+
+fn evaluate(interpreter: &mut Interpreter, source: &str) -> Result<(), String> {
+    let tokens = Lexer::new(source).tokenize()?;
+    let program = Parser::new(tokens).parse()?;
+    interpreter.evaluate(&program).map(|_| ())
+}
+
+const PROGRAM: &str = "
+    rolls = []
+    for i in range(0, 20) {
+        roll = random_int(1, 7)
+        assert(roll >= 1 and roll < 7)
+        push(rolls, roll)
+    }
+    x = random()
+    assert(x >= 0 and x < 1)
+    started = now()
+    sleep(5)
+    elapsed = now() - started
+";
+
+fn run_seeded(seed: u64) -> Interpreter {
+    let mut interpreter = Interpreter::new();
+    interpreter.set_seed(seed);
+    evaluate(&mut interpreter, PROGRAM).unwrap();
+    interpreter
+}
+
+#[test]
+fn seeded_runs_repeat_exactly() {
+    let first = run_seeded(42);
+    let again = run_seeded(42);
+    for name in ["rolls", "x"] {
+        assert_eq!(first.get_variable(name), again.get_variable(name));
+    }
+    assert_ne!(first.get_variable("rolls"), run_seeded(43).get_variable("rolls"));
+
+    // The simulated clock starts at zero and moves by what was slept
+    assert_eq!(first.get_variable("started"), Some(Value::Number(0.0)));
+    assert_eq!(first.get_variable("elapsed"), Some(Value::Number(5.0)));
+}
+
+#[test]
+fn random_int_needs_an_increasing_range() {
+    let mut interpreter = Interpreter::new();
+    let error = evaluate(&mut interpreter, "random_int(3, 3)").unwrap_err();
+    assert!(error.contains("start less than its end"), "{}", error);
+}
+
+// A host without a clock, like wasm32-unknown-unknown
+struct NoClock;
+
+impl Host for NoClock {
+    fn write(&self, _: &str) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn read_line(&self) -> Result<String, String> {
+        Err("no input".to_string())
+    }
+
+    fn now(&self) -> Option<f64> {
+        None
+    }
+}
+
+#[test]
+fn now_without_a_clock_is_an_error_unless_seeded() {
+    let mut interpreter = Interpreter::new();
+    interpreter.set_host(Arc::new(NoClock));
+    let error = evaluate(&mut interpreter, "now()").unwrap_err();
+    assert!(error.contains("needs a clock"), "{}", error);
+    evaluate(&mut interpreter, "x = random()").unwrap();
+
+    interpreter.set_seed(7);
+    evaluate(&mut interpreter, "t = now()").unwrap();
+    assert_eq!(interpreter.get_variable("t"), Some(Value::Number(0.0)));
+}
@@ -0,0 +1,106 @@
+mod common;
+
+use common::run;
+
+#[test]
+fn functions_mutate_the_callers_array() {
+    let source = "
+fn fill(arr, count, value) {
+    for i in range(0, count) {
+        arr[i] = value
+    }
+}
+scores = [0, 0, 0]
+fill(scores, 3, 7)
+print(scores)
+";
+    assert_eq!(run("fill", source), "[7, 7, 7]");
+}
+
+#[test]
+fn assignment_shares_containers() {
+    let source = "
+a = [1, 2]
+b = a
+push(b, 3)
+config = {server: {port: 80}}
+server = config[\"server\"]
+server[\"port\"] = 8080
+print(a, config)
+";
+    assert_eq!(run("share", source), "[1, 2, 3] {server: {port: 8080}}");
+}
+
+#[test]
+fn slice_copies_an_array() {
+    let source = "
+a = [1, 2, 3]
+b = a.slice(0)
+b[0] = 10
+print(a, b)
+";
+    assert_eq!(run("copy", source), "[1, 2, 3] [10, 2, 3]");
+}
+
+#[test]
+fn tasks_get_their_own_copy() {
+    let source = "
+a = [1, 2, 3]
+fn clear(arr) {
+    arr[0] = 0
+    return arr
+}
+t = spawn(clear, a)
+print(join(t), a)
+";
+    assert_eq!(run("task", source), "[0, 2, 3] [1, 2, 3]");
+}
+
+#[test]
+fn containers_cannot_contain_themselves() {
+    let source = "
+a = [1]
+a[0] = a
+";
+    assert_eq!(run("cycle", source), "Error: Cannot store an array or map inside itself");
+}
+
+#[test]
+fn appending_to_a_string_leaves_copies_alone() {
+    let source = "
+s = \"a\"
+t = s
+s = s + \"b\" + 1
+words = [s]
+for i in range(0, 3) {
+    s = s + i
+}
+print(t, words[0], s)
+";
+    assert_eq!(run("append", source), "a ab1 ab1012");
+}
+
+#[test]
+fn appending_to_an_array_leaves_copies_alone() {
+    let source = "
+a = [1]
+b = a
+a = a + [2] + [3]
+rows = {first: a}
+for i in range(0, 3) {
+    a = a + [i]
+}
+print(b, rows.first, a)
+";
+    assert_eq!(run("array_append", source), "[1] [1, 2, 3] [1, 2, 3, 0, 1, 2]");
+}
+
+#[test]
+fn array_of_copies_containers() {
+    let source = "
+grid = array_of(2, array_of(2, 0))
+grid[0][0] = 1
+print(grid, array_of(0, nil))
+";
+    assert_eq!(run("array_of", source), "[[1, 0], [0, 0]] []");
+}
@@ -0,0 +1,66 @@
+use m_lang::interpreter::Interpreter;
+use m_lang::lexer::Lexer;
+use m_lang::parser::Parser;
+use m_lang::value::Value;
+
+fn evaluate(interpreter: &mut Interpreter, source: &str) -> Result<(), String> {
+    let tokens = Lexer::new(source).tokenize()?;
+    let program = Parser::new(tokens).parse()?;
+    interpreter.evaluate(&program).map(|_| ())
+}
+
+#[test]
+fn globals_lists_what_is_in_scope() {
+    evaluate(&mut Interpreter::new(), "
+        fn cmd_add(a, b) { a + b }
+        transformer scaled(factor) { applied * factor }
+        count = 3
+
+        names = []
+        for name, value in globals() {
+            names = names + [name]
+        }
+        assert_eq(names, [\"cmd_add\", \"count\", \"names\", \"scaled\"])
+        handler = globals()[\"cmd_\" + \"add\"]
+        assert_eq(handler(2, 3), 5)
+
+        fn local_names() {
+            local = 1
+            globals()
+        }
+        assert_eq(local_names()[\"local\"], 1)
+        assert_eq(globals()[\"local\"], nil)
+    ").unwrap();
+}
+
+#[test]
+fn defined_and_fn_params_describe_names() {
+    evaluate(&mut Interpreter::new(), "
+        fn greet(name, greeting) { greeting + name }
+        transformer scaled(factor) { applied * factor }
+        assert_eq(fn_params(greet), [\"name\", \"greeting\"])
+        assert_eq(fn_params(scaled), [\"factor\"])
+        assert(defined(\"greet\") and defined(\"typeof\") and defined(\"print\"))
+        assert(not defined(\"missing\"))
+    ").unwrap();
+
+    let error = evaluate(&mut Interpreter::new(), "fn_params(1)").unwrap_err();
+    assert!(error.contains("fn_params() takes a function or transformer, got number"), "{}", error);
+}
+
+#[test]
+fn hosts_can_list_bindings() {
+    let mut interpreter = Interpreter::new();
+    evaluate(&mut interpreter, "
+        fn greet(name) { \"hi \" + name }
+        count = 3
+        if true {
+            let shadow = 1
+        }
+    ").unwrap();
+
+    let names: Vec<&str> = interpreter.globals().map(|(name, _)| name).collect();
+    assert_eq!(names, ["count", "greet"]);
+    assert_eq!(interpreter.globals().find(|(name, _)| *name == "count").map(|(_, value)| value.clone()), Some(Value::Int(3)));
+    assert_eq!(interpreter.scopes().count(), 1);
+}
@@ -0,0 +1,53 @@
+use std::fs;
+use m_lang::interpreter::Interpreter;
+use m_lang::lexer::Lexer;
+use m_lang::parser::Parser;
+
+fn evaluate(interpreter: &mut Interpreter, source: &str) -> Result<(), String> {
+    let tokens = Lexer::new(source).tokenize()?;
+    let program = Parser::new(tokens).parse()?;
+    interpreter.evaluate(&program).map(|_| ())
+}
+
+#[test]
+fn reloading_a_module_swaps_its_definitions() {
+    let dir = std::env::temp_dir().join(format!("m_lang_reload_{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("plugin.m"), "fn greet() { \"v1\" }\nold = true\n").unwrap();
+
+    let mut host = Interpreter::with_base_path(&dir);
+    evaluate(&mut host, "use \"plugin.m\"\ncount = 1\nassert_eq(greet(), \"v1\")").unwrap();
+
+    fs::write(dir.join("plugin.m"), "fn greet() { \"v2\" }\n").unwrap();
+    host.reload_module("plugin.m").unwrap();
+    evaluate(&mut host, "assert_eq(greet(), \"v2\")\nassert_eq(count, 1)").unwrap();
+    assert!(host.get_variable("old").is_none());
+
+    // A version that fails to load leaves the last good one in place
+    fs::write(dir.join("plugin.m"), "fn greet( {").unwrap();
+    assert!(host.reload_module("plugin.m").is_err());
+    evaluate(&mut host, "assert_eq(greet(), \"v2\")").unwrap();
+
+    fs::write(dir.join("other.m"), "").unwrap();
+    assert_eq!(host.reload_module("other.m"), Err("Module 'other.m' hasn't been imported".to_string()));
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn reloaded_functions_see_their_own_private_definitions() {
+    let dir = std::env::temp_dir().join(format!("m_lang_reload_private_{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let plugin = |version: usize| format!("pub fn api() {{ helper() }}\nfn helper() {{ {} }}\n", version);
+    fs::write(dir.join("plugin.m"), plugin(0)).unwrap();
+
+    let mut host = Interpreter::with_base_path(&dir);
+    evaluate(&mut host, "use \"plugin.m\"").unwrap();
+
+    // Each version's bodies may be allocated where an earlier version's were
+    for version in 1..200 {
+        fs::write(dir.join("plugin.m"), plugin(version)).unwrap();
+        host.reload_module("plugin.m").unwrap();
+        evaluate(&mut host, &format!("assert_eq(api(), {})", version)).unwrap();
+    }
+    fs::remove_dir_all(&dir).unwrap();
+}
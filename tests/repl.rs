@@ -0,0 +1,83 @@
+use std::fs;
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+// Types the input into `m_lang repl` and returns what it printed, errors included
+fn session(name: &str, input: &str) -> String {
+    // The history is written to the home directory
+    let home = std::env::temp_dir().join(format!("m_lang_repl_{}_{}", name, std::process::id()));
+    fs::create_dir_all(&home).unwrap();
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_m_lang"))
+        .arg("repl")
+        .current_dir(&home)
+        .env("HOME", &home)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(input.as_bytes()).unwrap();
+    let output = child.wait_with_output().unwrap();
+    fs::remove_dir_all(&home).unwrap();
+
+    String::from_utf8_lossy(&output.stdout).into_owned() + &String::from_utf8_lossy(&output.stderr)
+}
+
+#[test]
+fn definitions_carry_over_between_inputs() {
+    let printed = session("definitions", "\
+x = 2
+fn double(n) {
+    return n * x
+}
+print(double(3))
+print(y)
+print(x)
+:vars
+:quit
+print(\"never\")
+");
+    assert_eq!(printed, "6\n2\ndouble = <function>\nx = 2\nError: Undefined variable: y\n");
+}
+
+#[test]
+fn files_can_be_loaded() {
+    let printed = session("load", "\
+:load missing.m
+:load
+:unknown
+");
+    assert!(printed.contains("Error reading file"), "{}", printed);
+    assert!(printed.contains(":load expects a file name"), "{}", printed);
+    assert!(printed.contains("Unknown command ':unknown'"), "{}", printed);
+
+    let lib = std::env::temp_dir().join(format!("m_lang_repl_lib_{}.m", std::process::id()));
+    fs::write(&lib, "fn greet(name) { return \"hi \" + name }\n").unwrap();
+    let printed = session("loaded", &format!(":load {}\nprint(greet(\"M\"))\n", lib.display()));
+    fs::remove_file(&lib).unwrap();
+    assert_eq!(printed, "hi M\n");
+}
+
+#[test]
+fn values_are_shown_and_kept_in_underscore() {
+    let printed = session("echo", "\
+6 * 7
+_ + 1
+x = 10
+print(\"printed\")
+\"text\"
+[1, \"two\", (3,), {\"four\": ok(nil)}]
+");
+    assert_eq!(printed, "42\n43\nprinted\n\"text\"\n[1, \"two\", (3,), {\"four\": ok(nil)}]\n");
+}
+
+#[test]
+fn large_values_are_split_over_lines_and_cut_short() {
+    let printed = session("large", "\
+{\"short\": [1, 2], \"long\": range(0, 150).to_array()}
+");
+    let lines: Vec<_> = printed.lines().collect();
+    assert_eq!(lines[..4], ["{", "    \"long\": [", "        0,", "        1,"]);
+    assert_eq!(lines[lines.len() - 5..], ["        99,", "        ... 50 more", "    ],", "    \"short\": [1, 2]", "}"]);
+}
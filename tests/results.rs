@@ -0,0 +1,61 @@
+use m_lang::interpreter::Interpreter;
+use m_lang::lexer::Lexer;
+use m_lang::parser::Parser;
+
+fn evaluate(interpreter: &mut Interpreter, source: &str) -> Result<(), String> {
+    let tokens = Lexer::new(source).tokenize()?;
+    let program = Parser::new(tokens).parse()?;
+    interpreter.evaluate(&program).map(|_| ())
+}
+
+#[test]
+fn results_hold_a_value_or_an_error() {
+    evaluate(&mut Interpreter::new(), "
+        fn half(n) {
+            if n % 2 != 0 { return err(\"odd\") }
+            ok(n / 2)
+        }
+
+        assert_eq(half(4), ok(2))
+        assert(half(4).is_ok() and half(3).is_err())
+        assert_eq(half(4).unwrap(), 2)
+        assert_eq(half(3).unwrap_or(0), 0)
+        assert_eq(half(3).unwrap_err(), \"odd\")
+        assert_eq(typeof(half(3)), \"result\")
+        assert(half(4).to_bool() and not half(3).to_bool())
+        assert_eq(half(3).to_string(), \"err(odd)\")
+        assert_eq(ok([1]).to_json(), \"\"\"{\"ok\":[1]}\"\"\")
+    ").unwrap();
+
+    let error = evaluate(&mut Interpreter::new(), "err(\"odd\").unwrap()").unwrap_err();
+    assert!(error.contains("unwrap() of err(odd)"), "{}", error);
+
+    // Other values keep transformers of the same name
+    evaluate(&mut Interpreter::new(), "
+        transformer v.unwrap() { v[0] }
+        assert_eq([7].unwrap(), 7)
+        assert_eq(ok(7).unwrap(), 7)
+    ").unwrap();
+}
+
+#[test]
+fn try_turns_errors_into_results() {
+    let mut interpreter = Interpreter::new();
+    evaluate(&mut interpreter, "
+        transformer n.inverse() { 1 / n }
+        assert_eq(try(inverse, 0).is_err(), true)
+        assert_eq(try(inverse, 4), ok(0.25))
+        assert_eq(try(range, 0, 2), ok(range(0, 2)))
+    ").unwrap();
+
+    // The program carries on after a failure
+    evaluate(&mut interpreter, "
+        fn stop() {
+            for i in [1] { return err(\"inner\") }
+        }
+        assert_eq(try(stop), ok(err(\"inner\")))
+    ").unwrap();
+
+    let error = evaluate(&mut interpreter, "try(42)").unwrap_err();
+    assert!(error.contains("must be a function or transformer, got number"), "{}", error);
+}
@@ -0,0 +1,77 @@
+use m_lang::interpreter::Interpreter;
+use m_lang::lexer::Lexer;
+use m_lang::parser::Parser;
+
+fn evaluate(interpreter: &mut Interpreter, source: &str) -> Result<(), String> {
+    let tokens = Lexer::new(source).tokenize()?;
+    let program = Parser::new(tokens).parse()?;
+    interpreter.evaluate(&program).map(|_| ())
+}
+
+#[test]
+fn blocks_shadow_outer_names_without_changing_them() {
+    evaluate(&mut Interpreter::new(), "
+        let x = 1
+        if true {
+            let x = 2
+            assert_eq(x, 2)
+        } else {
+            let x = 3
+        }
+        assert_eq(x, 1)
+
+        for i in [1, 2] {
+            let x = i * 10
+            assert_eq(x, i * 10)
+        }
+        assert_eq(x, 1)
+
+        let n = 0
+        while n < 3 {
+            let x = \"inner\"
+            n = n + 1
+        }
+        assert_eq(x, 1)
+
+        // Each iteration starts without the previous one's definitions
+        for i in [1, 2] {
+            if i == 2 { assert_eq(defined(\"seen\"), false) }
+            let seen = i
+        }
+    ").unwrap();
+}
+
+#[test]
+fn inner_bindings_do_not_leak_but_assignments_persist() {
+    let mut interpreter = Interpreter::new();
+    evaluate(&mut interpreter, "
+        let total = 0
+        if true {
+            let inner = 5
+            total = total + inner
+        }
+        for i in [1, 2, 3] {
+            let step = i
+            total = total + step
+        }
+        let n = 0
+        while n < 2 {
+            n = n + 1
+            counted = n
+        }
+        if false { } else {
+            let other = 1
+            total = total * 2
+        }
+
+        assert_eq(total, 22)
+        assert_eq(n, 2)
+        assert_eq(defined(\"inner\"), false)
+        assert_eq(defined(\"step\"), false)
+        assert_eq(defined(\"i\"), false)
+        assert_eq(defined(\"counted\"), false)
+        assert_eq(defined(\"other\"), false)
+    ").unwrap();
+    assert!(interpreter.get_variable("inner").is_none());
+    assert!(interpreter.get_variable("total").is_some());
+}
@@ -0,0 +1,78 @@
+use m_lang::interpreter::Interpreter;
+use m_lang::lexer::Lexer;
+use m_lang::parser::Parser;
+
+fn evaluate(interpreter: &mut Interpreter, source: &str) -> Result<(), String> {
+    let tokens = Lexer::new(source).tokenize()?;
+    let program = Parser::new(tokens).parse()?;
+    interpreter.evaluate(&program).map(|_| ())
+}
+
+#[test]
+fn sets_hold_distinct_values_in_order() {
+    evaluate(&mut Interpreter::new(), "
+        s = set([3, \"b\", 1, 3, \"a\", 1.0, nil])
+        assert_eq(s.to_string(), \"set([nil, 1, 3, a, b])\")
+        assert_eq(typeof(s), \"set\")
+        assert(s.contains(1) and s.contains(3.0) and not s.contains(2) and not s.contains([1]))
+        assert_eq(set(\"hello\").to_array(), [\"e\", \"h\", \"l\", \"o\"])
+        assert(set() == set([]) and not set().to_bool() and set([2, 1]) == set(range(1, 3)))
+
+        alias = s
+        s.add(2).add(2)
+        assert_eq(alias.to_array(), [nil, 1, 2, 3, \"a\", \"b\"])
+
+        total = 0
+        for n in set([5, 5, 7]) {
+            total = total + n
+        }
+        assert_eq(total, 12)
+    ").unwrap();
+}
+
+#[test]
+fn set_operations_make_new_sets() {
+    evaluate(&mut Interpreter::new(), "
+        a = set([1, 2, 3])
+        b = set([2, 3, 4])
+        assert_eq(a.union(b), set([1, 2, 3, 4]))
+        assert_eq(a.intersect(b), set([2, 3]))
+        assert_eq(a.difference(b), set([1]))
+        assert_eq(a.difference([1, 2]), set([3]))
+        assert_eq(a, set([1, 2, 3]))
+    ").unwrap();
+}
+
+#[test]
+fn other_values_keep_transformers_named_like_set_operations() {
+    evaluate(&mut Interpreter::new(), "
+        transformer add(n) { return applied + n }
+        assert_eq(5.add(3), 8)
+    ").unwrap();
+}
+
+#[test]
+fn containers_cannot_be_set_elements() {
+    let error = evaluate(&mut Interpreter::new(), "set([[1]])").unwrap_err();
+    assert!(error.contains("Cannot add array to a set"), "{}", error);
+    let error = evaluate(&mut Interpreter::new(), "set().add({})").unwrap_err();
+    assert!(error.contains("Cannot add map to a set"), "{}", error);
+}
+
+#[test]
+fn saved_sets_stay_shared() {
+    let mut session = Interpreter::new();
+    evaluate(&mut session, "
+        tags = set([\"x\", \"y\"])
+        alias = tags
+    ").unwrap();
+    let state = session.save_state().unwrap();
+
+    let mut restored = Interpreter::new();
+    restored.load_state(&state).unwrap();
+    evaluate(&mut restored, "
+        alias.add(\"z\")
+        assert_eq(tags, set([\"x\", \"y\", \"z\"]))
+        assert_eq(tags.to_json(), [\"x\", \"y\", \"z\"].to_json())
+    ").unwrap();
+}
@@ -0,0 +1,149 @@
+use m_lang::ast::Expr;
+use m_lang::interpreter::Interpreter;
+use m_lang::lexer::Lexer;
+use m_lang::parser::Parser;
+
+fn parse(source: &str) -> Expr {
+    Parser::new(Lexer::new(source).tokenize().unwrap()).parse().unwrap()
+}
+
+fn evaluate(source: &str) -> Result<(), String> {
+    Interpreter::new().evaluate(&parse(source)).map(|_| ())
+}
+
+#[test]
+fn references_to_parameters_and_locals_are_bound_to_slots() {
+    let program = parse("
+fn f(a, b) {
+    let total = a
+    if b {
+        let a = 2
+        total = total + a
+    }
+    for i in [b] {
+        total = total + i
+    }
+    total + missing
+}
+transformer n.plus(k) { n + k }
+");
+    let Expr::Block(statements) = &program else { panic!("expected a program") };
+
+    let Expr::Function { locals, body, .. } = &statements[0] else { panic!("expected a function") };
+    assert_eq!(locals.iter().map(|name| name.as_str()).collect::<Vec<_>>(), ["total"]);
+    let body = format!("{:?}", body);
+    // `a` at the top of the body, `b` in the condition, `total` one block out
+    assert!(body.contains(r#"Local { name: "a", depth: 0, slot: 0 }"#), "{}", body);
+    assert!(body.contains(r#"Local { name: "b", depth: 0, slot: 1 }"#), "{}", body);
+    assert!(body.contains(r#"Local { name: "total", depth: 1, slot: 2 }"#), "{}", body);
+    // The block's own `a`, the loop variable and names the function doesn't bind stay by name
+    assert!(body.contains(r#"Variable("a")"#), "{}", body);
+    assert!(body.contains(r#"Variable("i")"#), "{}", body);
+    assert!(body.contains(r#"Variable("missing")"#), "{}", body);
+
+    let Expr::Transformer { body, .. } = &statements[1] else { panic!("expected a transformer") };
+    let body = format!("{:?}", body);
+    assert!(body.contains(r#"Local { name: "n", depth: 0, slot: 0 }"#), "{}", body);
+    assert!(body.contains(r#"Local { name: "k", depth: 0, slot: 1 }"#), "{}", body);
+
+    // Bound references print as the names they were written as
+    assert_eq!(parse(&program.to_source()).to_source(), program.to_source());
+
+    // Only references that run once the slot is set are bound
+    let program = parse("
+fn g(n) {
+    let early = late
+    let late = n
+    if true {
+        eval(\"let n = 1\")
+        n
+    }
+    late
+}
+");
+    let Expr::Function { body, .. } = &program else { panic!("expected a function") };
+    let body = format!("{:?}", body);
+    assert!(body.contains(r#"value: Some(Variable("late"))"#), "{}", body);
+    assert!(body.contains(r#"Local { name: "late", depth: 0, slot: 2 }"#), "{}", body);
+    assert!(body.contains(r#"Local { name: "n", depth: 0, slot: 0 }"#), "{}", body);
+    // eval() may define anything in the block
+    assert!(body.contains(r#"Variable("n")"#), "{}", body);
+}
+
+#[test]
+fn bound_references_see_what_name_lookups_would() {
+    evaluate("
+        fn shadowed(n) {
+            if true {
+                let n = 2
+                assert_eq(n, 2)
+            }
+            for n in [3] { assert_eq(n, 3) }
+            n
+        }
+        assert_eq(shadowed(1), 1)
+
+        fn assigned(n) {
+            n = n + 1
+            if true { n = n * 2 }
+            n
+        }
+        assert_eq(assigned(1), 4)
+
+        // Before its `let` runs, a local is still the caller's variable
+        let x = \"outer\"
+        fn later() {
+            let before = x
+            let x = \"inner\"
+            return [before, x]
+        }
+        assert_eq(later(), [\"outer\", \"inner\"])
+
+        // A call's changes to its caller's variables end with the call
+        count = 0
+        fn bump() {
+            count = count + 1
+            count
+        }
+        assert_eq(bump(), 1)
+        assert_eq(count, 0)
+
+        // Functions see their caller's parameters by name
+        fn inner() { n }
+        fn outer(n) { inner() }
+        assert_eq(outer(3), 3)
+
+        fn fib(n) {
+            if n < 2 { return n }
+            fib(n - 1) + fib(n - 2)
+        }
+        assert_eq(fib(15), 610)
+
+        transformer n.plus(k) { n + k }
+        transformer twice() { applied * 2 }
+        assert_eq(2.plus(3).twice(), 10)
+
+        fn listed(a) { [defined(\"a\"), globals()[\"a\"]] }
+        assert_eq(listed(7), [true, 7])
+    ").unwrap();
+}
+
+#[test]
+fn names_defined_at_runtime_hide_slots() {
+    evaluate("
+        fn evaluated(n) {
+            if true {
+                eval(\"let n = 5\")
+                assert_eq(n, 5)
+            }
+            n
+        }
+        assert_eq(evaluated(1), 1)
+
+        fn replaced(n) {
+            eval(\"let n = 6\")
+            n
+        }
+        assert_eq(replaced(1), 6)
+    ").unwrap();
+}
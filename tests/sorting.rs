@@ -0,0 +1,85 @@
+use m_lang::interpreter::Interpreter;
+use m_lang::lexer::Lexer;
+use m_lang::parser::Parser;
+
+fn evaluate(interpreter: &mut Interpreter, source: &str) -> Result<(), String> {
+    let tokens = Lexer::new(source).tokenize()?;
+    let program = Parser::new(tokens).parse()?;
+    interpreter.evaluate(&program).map(|_| ())
+}
+
+#[test]
+fn sorting_keeps_equal_elements_in_order() {
+    evaluate(&mut Interpreter::new(), "
+        people = [
+            {\"name\": \"Ann\", \"age\": 40},
+            {\"name\": \"Bob\", \"age\": 25},
+            {\"name\": \"Cy\", \"age\": 40},
+            {\"name\": \"Di\", \"age\": 31}
+        ]
+        fn age(person) { return person[\"age\"] }
+        fn by_age(a, b) { return a[\"age\"] - b[\"age\"] }
+
+        fn names(sorted) {
+            result = []
+            for person in sorted {
+                result = result + [person.name]
+            }
+            result
+        }
+
+        assert_eq(names(people.sort_key(age)), [\"Bob\", \"Di\", \"Ann\", \"Cy\"])
+        assert_eq(names(people.sort_by(by_age)), [\"Bob\", \"Di\", \"Ann\", \"Cy\"])
+        assert_eq(people[0][\"name\"], \"Ann\")
+    ").unwrap();
+}
+
+#[test]
+fn comparators_and_keys_can_be_any_callable() {
+    evaluate(&mut Interpreter::new(), "
+        fn descending(a, b) { return b - a }
+        transformer negate() { return -applied }
+        assert_eq([3, 1, 2].sort_by(descending), [3, 2, 1])
+        assert_eq(set([5, 2, 9]).sort_key(negate), [9, 5, 2])
+        assert_eq(range(0, 3).sort_by(descending), [2, 1, 0])
+        assert_eq([].sort_key(negate), [])
+    ").unwrap();
+}
+
+#[test]
+fn comparators_and_keys_can_be_written_inline() {
+    evaluate(&mut Interpreter::new(), "
+        assert_eq([3, 1, 2].sort_by(fn(a, b) { b - a }), [3, 2, 1])
+        assert_eq([\"ccc\", \"a\", \"bb\"].sort_key(fn(s) { s.len() }), [\"a\", \"bb\", \"ccc\"])
+
+        by_length = fn(a: string, b: string): number {
+            return a.len() - b.len()
+        }
+        assert_eq([\"ccc\", \"a\"].sort_by(by_length), [\"a\", \"ccc\"])
+        assert_eq(by_length(\"ab\", \"a\"), 1)
+        assert_eq(typeof(fn() {}), \"function\")
+    ").unwrap();
+
+    let program = Parser::new(Lexer::new("keys = [2, 1].sort_key(fn(n) { -n })\nasync fn(x) { x }").tokenize().unwrap()).parse().unwrap();
+    assert_eq!(program.to_source(), "keys = [2, 1].sort_key(fn(n) {\n    -n\n})\nasync fn(x) {\n    x\n}\n");
+    let reparsed = Parser::new(Lexer::new(&program.to_source()).tokenize().unwrap()).parse().unwrap();
+    assert_eq!(reparsed.to_source(), program.to_source());
+}
+
+#[test]
+fn bad_comparisons_are_errors() {
+    let error = evaluate(&mut Interpreter::new(), "
+        fn same(a, b) { return true }
+        [1, 2].sort_by(same)
+    ").unwrap_err();
+    assert!(error.contains("must return a number, got boolean"), "{}", error);
+
+    let error = evaluate(&mut Interpreter::new(), "
+        fn itself(x) { return x }
+        [1, \"a\"].sort_key(itself)
+    ").unwrap_err();
+    assert!(error.contains("sort_key() cannot compare"), "{}", error);
+
+    let error = evaluate(&mut Interpreter::new(), "5.sort_by(5)").unwrap_err();
+    assert!(error.contains("Cannot sort"), "{}", error);
+}
@@ -0,0 +1,75 @@
+use std::fs;
+use std::path::Path;
+use m_lang::ast::Expr;
+use m_lang::lexer::Lexer;
+use m_lang::parser::Parser;
+
+fn parse(source: &str) -> Expr {
+    let tokens = Lexer::new(source).tokenize().unwrap();
+    Parser::new(tokens).parse().unwrap_or_else(|e| panic!("{}\nin:\n{}", e, source))
+}
+
+// Printing parses back to a program that prints the same way
+fn round_trip(source: &str) -> String {
+    let printed = parse(source).to_source();
+    assert_eq!(parse(&printed).to_source(), printed, "printed from:\n{}", source);
+    printed
+}
+
+#[test]
+fn every_example_program_round_trips() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let dirs = ["stdlib", "tests/programs", "tests/programs/lib"];
+    let paths = dirs.iter().flat_map(|dir| fs::read_dir(root.join(dir)).unwrap()).map(|entry| entry.unwrap().path());
+    let mut count = 0;
+    for path in paths.chain([root.join("main.m")]) {
+        if path.extension().is_some_and(|extension| extension == "m") {
+            round_trip(&fs::read_to_string(&path).unwrap());
+            count += 1;
+        }
+    }
+    assert!(count > 10);
+}
+
+#[test]
+fn printing_keeps_precedence_and_statement_boundaries() {
+    let source = "
+/// Adds one
+pub fn inc(n: number): number { n + 1 }
+x = (a or b)
+y = (1 + 2) * -(3 - 4) - -5
+z = ((x = 2) + 1 and not y)
+`use` = [1, 2][0].inc()?.to_string()
+m = {name: 1, \"two words\": 2, \"if\": 3}
+for i in 0..10 step 2 { if i > 4 { break } else { continue } }
+f(1); (g)(2)
+fn early() { return; print(1) }
+";
+    let printed = round_trip(source);
+    assert_eq!(printed, "\
+/// Adds one
+pub fn inc(n: number): number {
+    n + 1
+}
+
+x = (a or b)
+y = (1 + 2) * -(3 - 4) - -5
+z = ((x = 2) + 1 and not y)
+`use` = [1, 2][0].inc()?.to_string()
+m = {name: 1, \"two words\": 2, \"if\": 3}
+for i in range(0, 10) step 2 {
+    if i > 4 {
+        break
+    } else {
+        continue
+    }
+}
+f(1)
+g(2)
+
+fn early() {
+    return;
+    print(1)
+}
+");
+}
@@ -0,0 +1,44 @@
+use m_lang::interpreter::Interpreter;
+use m_lang::lexer::Lexer;
+use m_lang::parser::Parser;
+
+fn evaluate(interpreter: &mut Interpreter, source: &str) -> Result<(), String> {
+    let tokens = Lexer::new(source).tokenize()?;
+    let program = Parser::new(tokens).parse()?;
+    interpreter.evaluate(&program).map(|_| ())
+}
+
+#[test]
+fn saved_state_restores_variables_and_functions() {
+    let mut session = Interpreter::new();
+    evaluate(&mut session, "
+fn double(n) { return n * 2 }
+transformer inc() { return applied + 1 }
+scores = [1, 2]
+alias = scores
+config = {\"scores\": scores, \"ratio\": 0.5}
+fn adder(n) { fn(x) { x + n } }
+add_scores = adder(scores)
+").unwrap();
+    let state = session.save_state().unwrap();
+
+    let mut restored = Interpreter::new();
+    restored.load_state(&state).unwrap();
+    evaluate(&mut restored, "
+push(alias, 3)
+assert_eq(scores, [1, 2, 3])
+assert_eq(config.scores, [1, 2, 3])
+assert_eq(double(config.ratio), 1.0)
+assert_eq(4.inc(), 5)
+// with what it captured from the call that made it
+assert_eq(add_scores([0]), [0, 1, 2, 3])
+").unwrap();
+}
+
+#[test]
+fn tasks_cannot_be_saved() {
+    let mut session = Interpreter::new();
+    evaluate(&mut session, "fn work() { return 1 }\ntask = spawn(work)\njoin(task)").unwrap();
+
+    assert_eq!(session.save_state(), Err("Cannot save 'task': a task can't be saved".to_string()));
+}
@@ -0,0 +1,35 @@
+use m_lang::interpreter::Interpreter;
+use m_lang::lexer::Lexer;
+use m_lang::parser::Parser;
+use m_lang::value::Value;
+
+fn evaluate(interpreter: &mut Interpreter, source: &str) -> Result<(), String> {
+    let tokens = Lexer::new(source).tokenize()?;
+    let program = Parser::new(tokens).parse()?;
+    interpreter.evaluate(&program).map(|_| ())
+}
+
+#[test]
+fn strict_rejects_extra_arguments() {
+    let mut interpreter = Interpreter::new();
+    interpreter.set_strict(true);
+    let error = evaluate(&mut interpreter, "fn f(a) { a }\nf(1, 2, 3)").unwrap_err();
+    assert!(error.contains("expects 1 argument, got 3"), "{}", error);
+
+    // Also when the function is called by a builtin
+    let error = evaluate(&mut interpreter, "fn one(a) { true }\n[2, 1].sort_by(one)").unwrap_err();
+    assert!(error.contains("expects 1 argument, got 2"), "{}", error);
+}
+
+#[test]
+fn extra_arguments_are_evaluated_and_ignored() {
+    let mut interpreter = Interpreter::new();
+    evaluate(&mut interpreter, "
+        seen = []
+        fn note() { push(seen, 1) }
+        fn f(a) { a }
+        x = f(1, note())
+    ").unwrap();
+    assert_eq!(interpreter.get_variable("x"), Some(Value::Int(1)));
+    assert_eq!(interpreter.get_variable("seen").map(|seen| seen.to_string()), Some("[1]".to_string()));
+}
@@ -0,0 +1,34 @@
+use m_lang::interpreter::Interpreter;
+use m_lang::lexer::Lexer;
+use m_lang::parser::Parser;
+use m_lang::symbol::Symbol;
+
+#[test]
+fn names_are_interned_once() {
+    let a = Symbol::new("interned_name");
+    let b = Symbol::new(&String::from("interned_name"));
+    assert_eq!(a, b);
+    assert!(std::ptr::eq(a.as_str(), b.as_str()));
+    assert_ne!(a, Symbol::new("another_name"));
+    assert_eq!(Symbol::existing("interned_name"), Some(a));
+}
+
+#[test]
+fn looking_up_names_does_not_intern_them() {
+    let interpreter = Interpreter::new();
+    assert!(interpreter.get_variable("never_written_anywhere").is_none());
+    assert!(Symbol::existing("never_written_anywhere").is_none());
+}
+
+#[test]
+fn names_made_at_runtime_find_parsed_bindings() {
+    let mut interpreter = Interpreter::new();
+    let program = Parser::new(Lexer::new("let counter = 41\ncounter = counter + 1").tokenize().unwrap()).parse().unwrap();
+    interpreter.evaluate(&program).unwrap();
+
+    let name: String = ["coun", "ter"].concat();
+    assert_eq!(interpreter.get_variable(&name).map(|value| value.to_string()), Some("42".to_string()));
+    interpreter.set_variable(&name, m_lang::value::Value::Int(1));
+    let program = Parser::new(Lexer::new("assert_eq(counter, 1)\nassert(defined(\"counter\"))").tokenize().unwrap()).parse().unwrap();
+    interpreter.evaluate(&program).unwrap();
+}
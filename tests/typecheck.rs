@@ -0,0 +1,78 @@
+use m_lang::lexer::Lexer;
+use m_lang::parser::Parser;
+use m_lang::typecheck::TypeChecker;
+
+fn check(source: &str) -> Vec<String> {
+    let tokens = Lexer::new(source).tokenize().unwrap();
+    let program = Parser::new(tokens).parse().unwrap();
+    TypeChecker::check(&program)
+}
+
+#[test]
+fn mismatches_are_reported() {
+    let source = "
+fn add(a: number, b: number): number {
+    return a + b
+}
+fn label(): string { return 1 }
+fn paint(c: colour) { c }
+add(1, \"two\")
+add(1, 2, 3)
+let s = \"a\" - 1
+if 3 { }
+";
+
+    assert_eq!(check(source), vec![
+        "Unknown type 'colour' in parameter 'c' of 'paint'",
+        "'label' is declared to return string, but returns number",
+        "Argument 2 of 'add' expects number, got string",
+        "'add' takes 2 arguments, but 3 were given",
+        "Operator '-' expects numbers, got string",
+        "Condition must be a boolean, got number",
+    ]);
+}
+
+#[test]
+fn inferred_types_flow_through_variables_and_calls() {
+    let source = "
+fn greet(name: string): string { \"hello \" + name }
+let count = 3
+greet(count)
+let message = greet(\"ana\")
+message * 2
+";
+
+    assert_eq!(check(source), vec![
+        "Argument 1 of 'greet' expects string, got number",
+        "Operator '*' expects numbers, got string",
+    ]);
+}
+
+#[test]
+fn well_typed_and_unannotated_programs_are_accepted() {
+    let source = "
+fn add(a: number, b: number): number {
+    return a + b
+}
+fn first(items: array, fallback: any) {
+    if len(items) > 0 { return items[0] }
+    fallback
+}
+async fn fetch(n: number): number { n * 2 }
+
+// Calls before the definition are checked against it too
+let total = scale(add(1, 2))
+fn scale(n) { n * 10 }
+
+let value = first([1, 2], nil)
+value = \"now a string\"
+let task = fetch(4)
+let doubled = await task
+for i in range(0, 3) {
+    total = total + i
+}
+print(total, value, doubled)
+";
+
+    assert_eq!(check(source), Vec::<String>::new());
+}
@@ -0,0 +1,29 @@
+use m_lang::interpreter::Interpreter;
+use m_lang::lexer::Lexer;
+use m_lang::parser::Parser;
+
+fn evaluate(interpreter: &mut Interpreter, source: &str) -> Result<(), String> {
+    let tokens = Lexer::new(source).tokenize()?;
+    let program = Parser::new(tokens).parse()?;
+    interpreter.evaluate(&program).map(|_| ())
+}
+
+#[test]
+fn strings_count_characters_and_encode_as_utf8() {
+    evaluate(&mut Interpreter::new(), "
+        s = \"naïve 🐱\"
+        assert_eq(s.len(), 7)
+        assert_eq(s.bytes(), s.to_bytes())
+        assert_eq(s.bytes().len(), 11)
+        assert_eq(s.chars().join(\"\"), s)
+        assert_eq(s[7 - 1], \"🐱\")
+        assert_eq(chr(ord(s[2])), \"ï\")
+    ").unwrap();
+
+    let error = evaluate(&mut Interpreter::new(), "\"ab\"[2]").unwrap_err();
+    assert!(error.contains("Index out of bounds: 2"), "{}", error);
+    let error = evaluate(&mut Interpreter::new(), "ord(\"ab\")").unwrap_err();
+    assert!(error.contains("got a string of 2 characters"), "{}", error);
+    let error = evaluate(&mut Interpreter::new(), "chr(55296)").unwrap_err();
+    assert!(error.contains("55296 is not a Unicode code point"), "{}", error);
+}